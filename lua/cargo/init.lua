@@ -36,6 +36,26 @@ local default_opts = {
 	-- 新規オプション
 	force_smart_detection = true, -- 常にスマート検出を使用
 
+	-- Target directory per command category, e.g. { check = "target/check" } so checks
+	-- never wait on the lock of a running build (costs disk space)
+	target_dirs = {},
+
+	-- --features and --target for the compiling commands (`target` is unset by default),
+	-- and commands that may not run; .cargo-nvim.toml can set them for a project
+	features = {},
+	denied_commands = {},
+
+	-- Grace period given to each signal when a command is interrupted or stopped, before
+	-- escalating to the next one (SIGINT -> SIGTERM -> SIGKILL)
+	kill_timeout_ms = 2000,
+	-- First signal sent to a stopped command, for all of them ("sigterm") or by command;
+	-- `cargo run` gets SIGINT so a server's Ctrl-C handler can shut it down cleanly, the
+	-- others SIGTERM
+	stop_signal = { run = "sigint" },
+
+	-- How often background jobs' events and completions are delivered to their callbacks
+	dispatch_interval_ms = 100,
+
 	commands = {
 		bench = { nargs = "*", desc = "Run benchmarks" },
 		build = { nargs = "*", desc = "Compile package" },
@@ -105,6 +125,28 @@ local default_opts = {
 	},
 }
 
+-- Delivers background jobs' callbacks; restarted by each setup
+local dispatch_timer = nil
+
+-- Call dispatch_completions every `interval_ms`, reporting errors raised by callbacks
+local function start_dispatch_timer(interval_ms)
+	if dispatch_timer then
+		dispatch_timer:stop()
+		dispatch_timer:close()
+	end
+	dispatch_timer = vim.loop.new_timer()
+	dispatch_timer:start(
+		interval_ms,
+		interval_ms,
+		vim.schedule_wrap(function()
+			local ok, err = pcall(cargo_lib.dispatch_completions)
+			if not ok then
+				vim.notify("cargo.nvim job callback failed: " .. tostring(err), vim.log.levels.ERROR)
+			end
+		end)
+	)
+end
+
 -- Load Cargo library
 local function load_cargo_lib()
 	local plugin_dir = vim.fn.fnamemodify(vim.fn.resolve(debug.getinfo(1, "S").source:sub(2)), ":h:h:h")
@@ -137,6 +179,42 @@ end
 -- Global cargo lib instance
 local cargo_lib = nil
 
+-- Options merged by setup
+local config = default_opts
+
+-- Commands that take --features and --target
+local compiling_commands = {
+	build = true,
+	check = true,
+	clippy = true,
+	test = true,
+	bench = true,
+	run = true,
+	doc = true,
+	fix = true,
+}
+
+-- Defaults of a project's .cargo-nvim.toml in the shape of setup's options; empty when
+-- there is none or the loaded module can't read it
+local function project_opts()
+	if not cargo_lib.project_config then
+		return {}
+	end
+	local project, err = cargo_lib.project_config()
+	if not project then
+		if err then
+			vim.notify("cargo.nvim: ignoring " .. err, vim.log.levels.WARN)
+		end
+		return {}
+	end
+	return {
+		timeouts = project.timeouts,
+		features = project.features,
+		target = project.target,
+		denied_commands = project.denied_commands,
+	}
+end
+
 -- Set up highlights
 local function setup_highlights()
 	local highlights = {
@@ -415,8 +493,99 @@ _G.create_input_field = function(bufnr, winnr, opts)
 	return input_bufnr
 end
 
+-- " --target-dir <dir>" when one is configured for the command and not given in args
+local function target_dir_arg(cmd_name, args)
+	for _, arg in ipairs(args) do
+		if arg == "--" then
+			break
+		end
+		if vim.startswith(arg, "--target-dir") then
+			return ""
+		end
+	end
+	local dir = cargo_lib and cargo_lib.target_dir_for and cargo_lib.target_dir_for(cmd_name)
+	return dir and (" --target-dir " .. vim.fn.shellescape(dir)) or ""
+end
+
+-- " --features ... --target ..." from the options for a compiling command, for each one
+-- args don't already have
+local function build_option_args(cmd_name, args)
+	if not compiling_commands[cmd_name] then
+		return ""
+	end
+	local given = {}
+	for _, arg in ipairs(args) do
+		if arg == "--" then
+			break
+		end
+		local flag = arg:match("^%-%-?[^=]+")
+		if flag then
+			given[flag] = true
+		end
+	end
+	local extra = ""
+	if #config.features > 0 and not (given["--features"] or given["-F"] or given["--all-features"]) then
+		extra = extra .. " --features " .. vim.fn.shellescape(table.concat(config.features, ","))
+	end
+	if config.target and not given["--target"] then
+		extra = extra .. " --target " .. vim.fn.shellescape(config.target)
+	end
+	return extra
+end
+
+-- Stop a job with `first` (SIGTERM by default), escalating to SIGTERM and then SIGKILL
+-- each time timeout_ms passes; on_stopped(forced) is called when it is gone
+-- Returns false if it wasn't running
+local function stop_job(job, timeout_ms, on_stopped, first)
+	local ok, pid = pcall(vim.fn.jobpid, job)
+	if not ok or vim.fn.jobwait({ job }, 0)[1] ~= -1 then
+		return false
+	end
+	local signals = { first or "sigterm" }
+	if signals[1] ~= "sigterm" then
+		table.insert(signals, "sigterm")
+	end
+	table.insert(signals, "sigkill")
+	vim.loop.kill(pid, signals[1])
+
+	local sent = 1
+	local started = vim.loop.now()
+	local timer = vim.loop.new_timer()
+	timer:start(
+		50,
+		50,
+		vim.schedule_wrap(function()
+			local exited = vim.fn.jobwait({ job }, 0)[1] ~= -1
+			if not exited and vim.loop.now() - started >= timeout_ms then
+				sent = sent + 1
+				vim.loop.kill(pid, signals[sent])
+				started = vim.loop.now()
+			end
+			if exited or sent == #signals then
+				timer:stop()
+				timer:close()
+				on_stopped(sent == #signals)
+			end
+		end)
+	)
+	return true
+end
+
+-- First signal for a stopped `cmd_name`, from the `stop_signal` option
+local function first_stop_signal(cmd_name, stop_signal)
+	if type(stop_signal) == "table" then
+		stop_signal = stop_signal[cmd_name]
+	end
+	return stop_signal and stop_signal:lower() or "sigterm"
+end
+
 -- Execute command using Neovim's native job system
 local function execute_command_native(cmd_name, args, opts)
+	if vim.tbl_contains(opts.denied_commands, cmd_name) then
+		vim.notify(string.format("cargo %s is denied in this project", cmd_name), vim.log.levels.ERROR)
+		return
+	end
+
 	-- Save all modified buffers before executing command
 	vim.cmd("wa")
 
@@ -436,7 +605,13 @@ local function execute_command_native(cmd_name, args, opts)
 
 	-- Create command string safely
 	local args_str = #args > 0 and (" " .. table.concat(args, " ")) or ""
-	local cmd_line = string.format("cargo %s%s", cmd_name, args_str)
+	local cmd_line = string.format(
+		"cargo %s%s%s%s",
+		cmd_name,
+		target_dir_arg(cmd_name, args),
+		build_option_args(cmd_name, args),
+		args_str
+	)
 
 	-- Initial buffer content
 	vim.api.nvim_buf_set_lines(bufnr, 0, -1, false, {
@@ -545,14 +720,21 @@ local function execute_command_native(cmd_name, args, opts)
 	-- Set up interrupt handler
 	vim.keymap.set("n", opts.keymaps.interrupt, function()
 		local job = vim.api.nvim_buf_get_var(bufnr, "cargo_job_id")
-		if job and vim.fn.jobstop(job) == 1 then
-			vim.api.nvim_buf_set_option(bufnr, "modifiable", true)
-			vim.api.nvim_buf_set_lines(bufnr, -1, -1, false, {
-				"",
-				"@warning@Process interrupted by user",
-			})
-			vim.api.nvim_buf_set_option(bufnr, "modifiable", false)
+		if not job then
+			return
 		end
+		local first = first_stop_signal(cmd_name, opts.stop_signal)
+		stop_job(job, opts.kill_timeout_ms, function(forced)
+			if vim.api.nvim_buf_is_valid(bufnr) then
+				vim.api.nvim_buf_set_option(bufnr, "modifiable", true)
+				vim.api.nvim_buf_set_lines(bufnr, -1, -1, false, {
+					"",
+					forced and "@warning@Process interrupted by user (force-killed)"
+						or "@warning@Process interrupted by user (exited gracefully)",
+				})
+				vim.api.nvim_buf_set_option(bufnr, "modifiable", false)
+			end
+		end, first)
 	end, { buffer = bufnr, noremap = true, silent = true })
 
 	-- Safety timer to prevent UI hangs
@@ -574,7 +756,7 @@ local function execute_command_native(cmd_name, args, opts)
 					vim.api.nvim_buf_set_option(bufnr, "modifiable", false)
 				else
 					-- Fallback: stop job
-					vim.fn.jobstop(job_id)
+					stop_job(job_id, opts.kill_timeout_ms, function() end)
 					safety_timer:stop()
 					safety_timer:close()
 				end
@@ -589,6 +771,20 @@ local function execute_command_native(cmd_name, args, opts)
 	return bufnr, winnr
 end
 
+-- Access the native module loaded by setup (nil before setup)
+function M.native()
+	return cargo_lib
+end
+
+-- Whether the loaded native module supports a feature (see capabilities() in the README);
+-- false before setup and for modules built before the feature existed
+function M.has(feature)
+	if not (cargo_lib and cargo_lib.capabilities) then
+		return false
+	end
+	return cargo_lib.capabilities().features[feature] == true
+end
+
 -- Interrupt running cargo command
 function M.interrupt()
 	-- Display message
@@ -613,8 +809,9 @@ function M.interrupt()
 end
 
 -- Initialize plugin
+-- Options passed here win over the project's .cargo-nvim.toml, which wins over the defaults
 function M.setup(opts)
-	opts = vim.tbl_deep_extend("force", default_opts, opts or {})
+	opts = opts or {}
 
 	if opts.debug then
 		vim.g.cargo_nvim_debug = true
@@ -623,6 +820,21 @@ function M.setup(opts)
 
 	debug_print("Loading cargo library...")
 	cargo_lib = load_cargo_lib()
+	opts = vim.tbl_deep_extend("force", default_opts, project_opts(), opts)
+	config = opts
+	if cargo_lib.set_target_dirs then
+		-- An unknown category is reported, and the commands are registered all the same
+		local ok, err = pcall(cargo_lib.set_target_dirs, opts.target_dirs)
+		if not ok then
+			vim.notify("cargo.nvim: ignoring target_dirs: " .. tostring(err), vim.log.levels.WARN)
+		end
+	end
+	if opts.on_command_done and cargo_lib.set_post_command_hook then
+		cargo_lib.set_post_command_hook(opts.on_command_done)
+	end
+	if cargo_lib.dispatch_completions then
+		start_dispatch_timer(opts.dispatch_interval_ms)
+	end
 
 	setup_highlights()
 
@@ -688,6 +900,11 @@ end
 
 -- cargo run using terminal mode (especially for proconio usage)
 function M.run_in_terminal(args)
+	if vim.tbl_contains(config.denied_commands, "run") then
+		vim.notify("cargo run is denied in this project", vim.log.levels.ERROR)
+		return
+	end
+
 	-- Get current window size
 	local width = math.floor(vim.o.columns * 0.8)
 	local height = math.floor(vim.o.lines * 0.8)
@@ -715,7 +932,7 @@ function M.run_in_terminal(args)
 
 	-- Start terminal with cargo run
 	local args_str = table.concat(args, " ")
-	local cmd = "cargo run " .. args_str
+	local cmd = "cargo run" .. target_dir_arg("run", args) .. build_option_args("run", args) .. " " .. args_str
 	local _ = vim.fn.termopen(cmd, {
 		on_exit = function()
 			vim.schedule(function()
@@ -0,0 +1,119 @@
+// src/add.rs
+//! Parsing of what `cargo add` reports about the dependencies it added
+use serde::Serialize;
+
+/// A dependency added by `cargo add`, with the features cargo listed for it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AddedDependency {
+    pub name: String,
+    /// Version requirement written to the manifest; `None` for path dependencies
+    pub version: Option<String>,
+    /// Where it was added, e.g. `dependencies`, `dev-dependencies`, `optional dependencies`
+    pub table: String,
+    /// Every feature of the crate, enabled or not
+    pub available_features: Vec<String>,
+    /// The features that are on (`+` in cargo's list)
+    pub enabled_features: Vec<String>,
+}
+
+/// Parse lines like `Adding serde v1.0.228 to dependencies` and the feature list
+/// indented under them (`+ std`, `- derive`)
+/// `Adding` lines without a table (packages locked along the way) are skipped
+pub fn parse_add_output(output: &str) -> Vec<AddedDependency> {
+    let mut added: Vec<AddedDependency> = Vec::new();
+    let mut in_features = false;
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("Adding ") {
+            in_features = false;
+            if let Some(dependency) = parse_adding(rest) {
+                added.push(dependency);
+            }
+            continue;
+        }
+        if line.starts_with("Features") && line.ends_with(':') {
+            in_features = !added.is_empty();
+            continue;
+        }
+        let feature = match (in_features, line.split_at_checked(2)) {
+            (true, Some(("+ ", name))) => Some((name, true)),
+            (true, Some(("- ", name))) => Some((name, false)),
+            _ => None,
+        };
+        let Some((name, enabled)) = feature else {
+            in_features = false;
+            continue;
+        };
+        // Cargo may annotate a feature after its name
+        let name = name.split_whitespace().next().unwrap_or(name).to_string();
+        if let Some(dependency) = added.last_mut() {
+            if enabled {
+                dependency.enabled_features.push(name.clone());
+            }
+            dependency.available_features.push(name);
+        }
+    }
+    added
+}
+
+// `serde v1.0.228 to dependencies` or `dep (local) to dev-dependencies`
+fn parse_adding(rest: &str) -> Option<AddedDependency> {
+    let (spec, table) = rest.split_once(" to ")?;
+    let (name, version) = spec.split_once(' ').unwrap_or((spec, ""));
+    let version = version
+        .strip_prefix('v')
+        .filter(|v| !v.is_empty() && !v.contains(' '))
+        .map(str::to_string);
+    Some(AddedDependency {
+        name: name.to_string(),
+        version,
+        table: table.to_string(),
+        available_features: Vec::new(),
+        enabled_features: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: &str = "    Updating crates.io index
+      Adding serde v1.0.228 to dependencies
+             Features:
+             + std
+             - alloc
+             - derive
+      Adding tokio v1 to dev-dependencies
+             Features as of v1.2.0:
+             - full
+             - macros
+      Adding dep (local) to optional dependencies
+             Features:
+             + a
+      Adding feature `dep`
+     Locking 2 packages to latest Rust 1.95.0 compatible versions
+      Adding serde v1.0.228
+";
+
+    #[test]
+    fn test_parse_add_output() {
+        let added = parse_add_output(OUTPUT);
+        assert_eq!(added.len(), 3);
+        assert_eq!(
+            added[0],
+            AddedDependency {
+                name: "serde".to_string(),
+                version: Some("1.0.228".to_string()),
+                table: "dependencies".to_string(),
+                available_features: vec!["std".into(), "alloc".into(), "derive".into()],
+                enabled_features: vec!["std".to_string()],
+            }
+        );
+        assert_eq!(added[1].version.as_deref(), Some("1"));
+        assert_eq!(added[1].table, "dev-dependencies");
+        assert!(added[1].enabled_features.is_empty());
+        assert_eq!(added[1].available_features, vec!["full", "macros"]);
+        assert_eq!(added[2].version, None);
+        assert_eq!(added[2].enabled_features, vec!["a"]);
+    }
+}
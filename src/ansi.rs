@@ -0,0 +1,71 @@
+// src/ansi.rs
+//! ANSI escape sequences in output printed with colors (the `color` option)
+
+use std::borrow::Cow;
+
+const ESC: char = '\x1b';
+const BEL: char = '\x07';
+
+/// `line` without its escape sequences: colors and other CSI sequences (`ESC [ ... m`),
+/// OSC sequences such as hyperlinks (`ESC ] ... BEL` or `ESC ] ... ESC \`) and two
+/// character escapes; borrowed when it has none
+pub fn strip_ansi(line: &str) -> Cow<'_, str> {
+    if !line.contains(ESC) {
+        return Cow::Borrowed(line);
+    }
+    let mut plain = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != ESC {
+            plain.push(c);
+            continue;
+        }
+        match chars.next() {
+            // Parameters and intermediates up to a final byte in `@`..=`~`
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == BEL || (c == ESC && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Cow::Owned(plain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_ansi() {
+        assert!(matches!(strip_ansi("plain"), Cow::Borrowed("plain")));
+        assert_eq!(
+            strip_ansi("\x1b[1m\x1b[32m   Compiling\x1b[0m app v0.1.0"),
+            "   Compiling app v0.1.0"
+        );
+        assert_eq!(
+            strip_ansi("\x1b[0m\x1b[1m\x1b[38;5;9merror[E0308]\x1b[0m: mismatched types"),
+            "error[E0308]: mismatched types"
+        );
+        assert_eq!(
+            strip_ansi("see \x1b]8;;https://doc.rust-lang.org\x1b\\the docs\x1b]8;;\x07 here"),
+            "see the docs here"
+        );
+        assert_eq!(
+            strip_ansi("\x1b[1mContinue? [Y/n]\x1b[0m "),
+            "Continue? [Y/n] "
+        );
+        // A sequence cut off at the end of the line is dropped
+        assert_eq!(strip_ansi("done\x1b[3"), "done");
+    }
+}
@@ -0,0 +1,271 @@
+// src/artifacts.rs
+//! Location of build artifacts, shared by the features that need to find binaries
+//!
+//! Target directories can be set per command category (see `set_target_dirs`), so
+//! background checks don't wait on the lock of an interactive build.
+use crate::options::CommandOptions;
+use mlua::prelude::*;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Subcommands that take `--target-dir`
+pub const TARGET_DIR_COMMANDS: &[&str] = &[
+    "build", "check", "clippy", "fix", "run", "test", "bench", "doc", "rustc", "clean",
+];
+
+static TARGET_DIRS: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// Category of a subcommand for per-category target directories: `check` for the
+/// commands that only check, `build` for the ones that build (and `clean`, which cleans
+/// their directory)
+pub fn target_dir_category(subcommand: &str) -> Option<&'static str> {
+    match subcommand {
+        "check" | "clippy" | "fix" => Some("check"),
+        "build" | "run" | "test" | "bench" | "doc" | "rustc" | "clean" => Some("build"),
+        _ => None,
+    }
+}
+
+/// Set the target directory of each category (`check`, `build`), replacing earlier ones
+pub fn set_target_dirs(dirs: BTreeMap<String, String>) -> LuaResult<()> {
+    if let Some(category) = dirs
+        .keys()
+        .find(|c| !matches!(c.as_str(), "check" | "build"))
+    {
+        return Err(LuaError::RuntimeError(format!(
+            "Unknown target dir category: {:?} (expected \"check\" or \"build\")",
+            category
+        )));
+    }
+    *TARGET_DIRS.lock().unwrap() = dirs;
+    Ok(())
+}
+
+/// Target directory set for the category of `subcommand`, if any
+pub fn configured_target_dir(subcommand: &str) -> Option<String> {
+    let category = target_dir_category(subcommand)?;
+    TARGET_DIRS.lock().unwrap().get(category).cloned()
+}
+
+/// Target directory passed to `subcommand` with `--target-dir`: the `target_dir`
+/// option, else the one configured for its category; `None` leaves it to cargo
+pub fn target_dir_override(subcommand: &str, options: &CommandOptions) -> Option<String> {
+    if !TARGET_DIR_COMMANDS.contains(&subcommand) {
+        return None;
+    }
+    options
+        .target_dir
+        .clone()
+        .or_else(|| configured_target_dir(subcommand))
+}
+
+/// Directory holding the artifacts of a build with the given options:
+/// `<target dir>[/<triple>]/<profile dir>`
+pub fn artifact_dir(options: &CommandOptions) -> LuaResult<PathBuf> {
+    resolve_artifact_dir(
+        options,
+        configured_target_dir("build"),
+        std::env::var_os("CARGO_TARGET_DIR"),
+    )
+}
+
+/// Cargo's target directory for `subcommand`: the `target_dir` option, else the one
+/// configured for its category, else `CARGO_TARGET_DIR`, else `target`; relative paths
+/// are relative to the directory commands run in (`cwd`)
+pub fn target_dir(subcommand: &str, options: &CommandOptions) -> PathBuf {
+    resolve_target_dir(
+        options,
+        configured_target_dir(subcommand),
+        std::env::var_os("CARGO_TARGET_DIR"),
+    )
+}
+
+fn resolve_target_dir(
+    options: &CommandOptions,
+    configured: Option<String>,
+    env_target_dir: Option<OsString>,
+) -> PathBuf {
+    let dir = match (options.target_dir.clone().or(configured), env_target_dir) {
+        (Some(dir), _) => PathBuf::from(dir),
+        (None, Some(dir)) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from("target"),
+    };
+    match &options.cwd {
+        Some(cwd) => Path::new(cwd).join(dir),
+        None => dir,
+    }
+}
+
+fn resolve_artifact_dir(
+    options: &CommandOptions,
+    configured: Option<String>,
+    env_target_dir: Option<OsString>,
+) -> LuaResult<PathBuf> {
+    let mut dir = resolve_target_dir(options, configured, env_target_dir);
+    if let Some(triple) = &options.target {
+        validate_component("target", triple)?;
+        dir.push(triple);
+    }
+    dir.push(profile_dir(options.profile.as_deref().unwrap_or("dev"))?);
+    Ok(dir)
+}
+
+/// Directory a build with `args` puts its artifacts in, under `target_dir`; `--target`,
+/// `--profile` and `--release` in the arguments win over the `target` and `profile` options
+pub fn build_artifact_dir(
+    target_dir: &Path,
+    args: &[&str],
+    options: &CommandOptions,
+) -> LuaResult<PathBuf> {
+    let args: Vec<&str> = args.iter().copied().take_while(|a| *a != "--").collect();
+    let mut dir = target_dir.to_path_buf();
+    let triple = flag_values(&args, "--target").first().copied();
+    if let Some(triple) = triple.or(options.target.as_deref()) {
+        validate_component("target", triple)?;
+        dir.push(triple);
+    }
+    let release = args.iter().any(|a| matches!(*a, "--release" | "-r"));
+    let profile = flag_values(&args, "--profile")
+        .first()
+        .copied()
+        .or(release.then_some("release"))
+        .or(options.profile.as_deref())
+        .unwrap_or("dev");
+    dir.push(profile_dir(profile)?);
+    Ok(dir)
+}
+
+/// Values of `--flag value` and `--flag=value` in `args`
+pub(crate) fn flag_values<'a>(args: &[&'a str], flag: &str) -> Vec<&'a str> {
+    args.iter()
+        .enumerate()
+        .filter_map(|(i, arg)| match arg.strip_prefix(flag)? {
+            "" => args.get(i + 1).copied(),
+            rest => rest.strip_prefix('='),
+        })
+        .collect()
+}
+
+// The built-in profiles share directories; custom profiles get one named after them
+pub(crate) fn profile_dir(profile: &str) -> LuaResult<&str> {
+    match profile {
+        "dev" | "test" => Ok("debug"),
+        "release" | "bench" => Ok("release"),
+        custom => {
+            validate_component("profile", custom)?;
+            Ok(custom)
+        }
+    }
+}
+
+// Profile names and target triples become path components, so they can't contain
+// separators or be `..`
+pub(crate) fn validate_component(what: &str, value: &str) -> LuaResult<()> {
+    let valid = !value.is_empty()
+        && !value.starts_with('.')
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(LuaError::RuntimeError(format!(
+            "Invalid {}: {:?}",
+            what, value
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_artifact_dir() {
+        let options = |profile: Option<&str>, target: Option<&str>| CommandOptions {
+            profile: profile.map(str::to_string),
+            target: target.map(str::to_string),
+            ..Default::default()
+        };
+
+        let dir = |options: &CommandOptions| resolve_artifact_dir(options, None, None).unwrap();
+        assert_eq!(dir(&options(None, None)), PathBuf::from("target/debug"));
+        assert_eq!(
+            dir(&options(Some("bench"), None)),
+            PathBuf::from("target/release")
+        );
+        assert_eq!(
+            dir(&options(
+                Some("profiling"),
+                Some("aarch64-unknown-linux-gnu")
+            )),
+            PathBuf::from("target/aarch64-unknown-linux-gnu/profiling")
+        );
+
+        assert!(resolve_artifact_dir(&options(Some("../x"), None), None, None).is_err());
+        assert!(resolve_artifact_dir(&options(None, Some("..")), None, None).is_err());
+    }
+
+    #[test]
+    fn test_build_artifact_dir() {
+        let options = CommandOptions {
+            target: Some("wasm32-unknown-unknown".to_string()),
+            ..Default::default()
+        };
+        let dir = |args: &[&str]| build_artifact_dir(Path::new("t"), args, &options).unwrap();
+        assert_eq!(dir(&[]), PathBuf::from("t/wasm32-unknown-unknown/debug"));
+        assert_eq!(
+            dir(&["--release", "--", "--profile", "x"]),
+            PathBuf::from("t/wasm32-unknown-unknown/release")
+        );
+        assert_eq!(
+            dir(&["--target=thumbv7em-none-eabihf", "--profile", "size"]),
+            PathBuf::from("t/thumbv7em-none-eabihf/size")
+        );
+    }
+
+    #[test]
+    fn test_target_dir_precedence() {
+        let env = Some(OsString::from("/tmp/shared-target"));
+        let dir = resolve_artifact_dir(&CommandOptions::default(), None, env.clone()).unwrap();
+        assert_eq!(dir, PathBuf::from("/tmp/shared-target/debug"));
+
+        // A directory configured for the category wins over the environment
+        let configured = Some("target/builds".to_string());
+        let dir = resolve_artifact_dir(&CommandOptions::default(), configured.clone(), env.clone())
+            .unwrap();
+        assert_eq!(dir, PathBuf::from("target/builds/debug"));
+
+        // The option wins over the environment
+        let options = CommandOptions {
+            target_dir: Some("build".to_string()),
+            profile: Some("release".to_string()),
+            ..Default::default()
+        };
+        let dir = resolve_artifact_dir(&options, configured, env).unwrap();
+        assert_eq!(dir, PathBuf::from("build/release"));
+    }
+
+    #[test]
+    fn test_target_dir_categories() {
+        assert_eq!(target_dir_category("clippy"), Some("check"));
+        assert_eq!(target_dir_category("test"), Some("build"));
+        assert_eq!(target_dir_category("clean"), Some("build"));
+        assert_eq!(target_dir_category("fmt"), None);
+
+        let options = CommandOptions {
+            target_dir: Some("elsewhere".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            target_dir_override("clean", &options),
+            Some("elsewhere".to_string())
+        );
+        assert_eq!(target_dir_override("fmt", &options), None);
+
+        let unknown = BTreeMap::from([("tests".to_string(), "target/t".to_string())]);
+        assert!(set_target_dirs(unknown).is_err());
+    }
+}
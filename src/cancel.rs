@@ -0,0 +1,116 @@
+// src/cancel.rs
+use crate::signal::Termination;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// Why a command was cancelled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CancelReason {
+    /// The user stopped it (`interrupt`, `job_cancel`, `search_cancel`, ...)
+    Interrupted,
+    /// A newer command took its place (a later fast check or search, a replacing run)
+    Superseded,
+}
+
+/// Shared flag for stopping a running command from another thread
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<CancelState>);
+
+#[derive(Debug, Default)]
+struct CancelState {
+    cancelled: AtomicBool,
+    notify: Notify,
+    reason: Mutex<Option<CancelReason>>,
+    termination: Mutex<Option<Termination>>,
+}
+
+impl CancelToken {
+    /// Request cancellation on behalf of the user and wake everyone waiting on `cancelled`
+    pub fn cancel(&self) {
+        self.cancel_with(CancelReason::Interrupted);
+    }
+
+    /// Request cancellation for the given reason; the first reason given is kept
+    pub fn cancel_with(&self, reason: CancelReason) {
+        self.0.reason.lock().unwrap().get_or_insert(reason);
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+
+    /// Why the command was cancelled, once it has been
+    pub fn reason(&self) -> Option<CancelReason> {
+        *self.0.reason.lock().unwrap()
+    }
+
+    /// Whether both tokens stop the same command
+    pub fn same_token(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Record how the cancelled command's process went down
+    pub fn set_termination(&self, termination: Termination) {
+        *self.0.termination.lock().unwrap() = Some(termination);
+    }
+
+    /// How the cancelled command's process went down, once it has
+    pub fn termination(&self) -> Option<Termination> {
+        *self.0.termination.lock().unwrap()
+    }
+
+    /// Resolves once `cancel` has been called
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.0.notify.notified();
+            tokio::pin!(notified);
+            // Register before checking the flag so a concurrent `cancel` isn't missed
+            notified.as_mut().enable();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_cancel_wakes_waiter() {
+        let token = CancelToken::default();
+        let waiter = tokio::spawn({
+            let token = token.clone();
+            async move { token.cancelled().await }
+        });
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiter.is_finished());
+        token.cancel();
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(token.is_cancelled());
+        assert_eq!(token.reason(), Some(CancelReason::Interrupted));
+    }
+
+    #[test]
+    fn test_first_reason_is_kept() {
+        let token = CancelToken::default();
+        assert_eq!(token.reason(), None);
+        token.cancel_with(CancelReason::Superseded);
+        token.cancel();
+        assert_eq!(token.reason(), Some(CancelReason::Superseded));
+        assert!(token.same_token(&token.clone()));
+        assert!(!token.same_token(&CancelToken::default()));
+    }
+}
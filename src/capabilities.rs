@@ -0,0 +1,142 @@
+// src/capabilities.rs
+//! What the loaded native module supports, so the Lua side can check for a feature
+//! instead of assuming it from the plugin version
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Features and their availability; new features are added as they land and
+/// existing names are never repurposed
+const FEATURES: &[(&str, bool)] = &[
+    // on_event/on_stdout/on_stderr get output line by line while a command runs
+    ("streaming", true),
+    // on_output gets the lines of both streams, with the stream's name
+    ("on_output", true),
+    // Commands run attached to a pseudo-terminal (not supported: output is piped)
+    ("pty", false),
+    // "diagnostic" events and quickfix entries from `--message-format=json`
+    ("json_diagnostics", true),
+    // "downloading" events while dependencies download, and fetch()
+    ("download_progress", true),
+    // details.stdout and details.stderr next to the interleaved output
+    ("separate_streams", true),
+    // The `color` option: output with cargo's ANSI colors
+    ("color", true),
+    // details.exit_code
+    ("exit_code", true),
+    // first_error next to quickfix lists
+    ("first_error", true),
+    // The `rendered_diagnostics` option: quickfix from JSON, rustc's text as output
+    ("rendered_diagnostics", true),
+    // start_job/job_status/job_cancel/dispatch_completions
+    ("job_api", true),
+    // Events of background jobs, delivered by dispatch_completions
+    ("job_streaming", true),
+    // start_interactive() sessions with write/close_stdin
+    ("interactive_session", true),
+    // kill_jobs_for(dir): stop the jobs of one project
+    ("kill_jobs_for", true),
+    // interrupt() returns the output so far with `interrupted` set instead of an error
+    ("interrupt_result", true),
+    // send_input/send_bytes/close_input for the running command
+    ("stdin", true),
+    // search_start/search_status/search_cancel
+    ("async_search", true),
+    // check_fast with debouncing
+    ("fast_check", true),
+    // run_sequence and check_then_build
+    ("sequence", true),
+    // examples() and run_example()
+    ("examples", true),
+    // details.clean.freed_bytes from clean
+    ("clean_freed_bytes", true),
+    // details.test_report from test with `format = "junit"`
+    ("junit_report", true),
+    // llvm_cov() with details.coverage
+    ("llvm_cov", true),
+    // unit_graph() with details.unit_graph (nightly cargo)
+    ("unit_graph", true),
+    // details.artifact_sizes from build with `report_size`
+    ("report_size", true),
+    // The `run_dir` option of run, with details.program_dir
+    ("run_dir", true),
+    // The `profile_with` option of run, with details.profile
+    ("profile_with", true),
+    // The `manifest_path` option and list_workspace_members()
+    ("manifest_path", true),
+    // `timeout = false` and `timeout_secs = 0` disable the timeout
+    ("no_timeout", true),
+    // The `cwd`, `stop_signal` and `strip_env` options
+    ("cwd", true),
+    // set_cwd(dir): the cwd of commands without one
+    ("set_cwd", true),
+    ("stop_signal", true),
+    ("strip_env", true),
+    // The `env` option and set_env(vars): variables set or removed for commands
+    ("env", true),
+    // `member/feature` entries in `features`, and the `all_features` option
+    ("member_features", true),
+    // details.created from new and init
+    ("created_package", true),
+    // The `only_packages` option for JSON diagnostics
+    ("only_packages", true),
+    // The `toolchain` option, and the toolchain_not_installed error kind
+    ("toolchain", true),
+    // set_toolchain(name): the toolchain of commands without one
+    ("set_toolchain", true),
+    // set_cargo_bin(path): the cargo binary commands run
+    ("set_cargo_bin", true),
+    // set_post_command_hook() and setup's on_command_done
+    ("post_command_hook", true),
+    // version_fallback events when the cargo in use is too old for a JSON flag
+    ("version_fallback", true),
+    // The `output_yield_ms` option
+    ("output_yield", true),
+    // details.suggestions and diagnostic events' machine-applicable fixes
+    ("suggestions", true),
+    // is_installed(name) from a cached `cargo --list`
+    ("is_installed", true),
+    // The `tool_args` option
+    ("tool_args", true),
+    // The `split_doctests` option of test
+    ("split_doctests", true),
+    // The `max_output_lines` option and details.dropped_lines
+    ("max_output_lines", true),
+    // tree_node events, with the tree parsed as cargo tree prints it
+    ("tree_events", true),
+    // project_config(): the .cargo-nvim.toml setup() merges under its options
+    ("project_config", true),
+];
+
+/// Returned by the `capabilities` export
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Capabilities {
+    /// Version of the cargo-nvim crate
+    pub version: &'static str,
+    pub features: BTreeMap<&'static str, bool>,
+}
+
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        features: FEATURES.iter().copied().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities() {
+        let caps = capabilities();
+        assert_eq!(caps.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(
+            caps.features.len(),
+            FEATURES.len(),
+            "duplicate feature name"
+        );
+        assert_eq!(caps.features.get("job_api"), Some(&true));
+        assert_eq!(caps.features.get("pty"), Some(&false));
+    }
+}
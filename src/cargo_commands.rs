@@ -1,19 +1,96 @@
 // src/cargo_commands.rs
-use crate::lua_exports::set_input_sender;
+use crate::add::parse_add_output;
+use crate::artifacts::{
+    build_artifact_dir, flag_values, target_dir, target_dir_override, validate_component,
+};
+use crate::cancel::{CancelReason, CancelToken};
+use crate::clean::{clean_scope, dir_size, CleanResult};
+use crate::compat::{self, KEEP_GOING_MIN_CARGO_VERSION, RENDERED_MIN_CARGO_VERSION};
+use crate::compile_failure::parse_compile_failures;
+use crate::coverage::parse_coverage_summary;
+use crate::diagnostics::{
+    count_warnings, parse_diagnostics, parse_suggestions, parse_text_diagnostics,
+};
+use crate::doc::{parse_generated, resolve_doc_index};
+use crate::doctest::parse_doctests;
+use crate::error::{spawn_error, CommandFailure, Error};
+use crate::event_log::spawn_event_log;
+use crate::events::{publish, Event, EventSender};
+use crate::examples::{list_examples, parse_missing_features, ExampleTarget};
+use crate::help::HelpText;
+use crate::hooks::command_finished;
+use crate::installed;
+use crate::jobs::SessionInput;
+use crate::lua_exports::{clear_input_sender, set_input_sender};
+use crate::manifest_error::{parse_manifest_error, ManifestError};
+use crate::members::{
+    find_manifest, has_package_selection, list_workspace_members, package_of_manifest,
+    targeted_members, WorkspaceMember,
+};
+use crate::messages::{
+    build_finished, is_cached_build, is_json_message_format, render_messages,
+    RENDERED_MESSAGE_FORMAT,
+};
+use crate::metadata::Metadata;
+use crate::options::{CommandOptions, CONFIG_MIN_CARGO_VERSION};
+use crate::outcome::CommandOutcome;
+use crate::panic::{has_backtrace, parse_panic};
+use crate::profile::{profiler_not_installed, ProfileOutput, Profiler};
+use crate::project_config::ProjectConfig;
+use crate::quickfix::{first_error, QuickfixEntry};
+use crate::rate_limit::{self, detect_rate_limit, REGISTRY_COMMANDS};
+use crate::responder::AutoResponder;
+use crate::scaffold::{init_path, parse_created, CreatedPackage};
+use crate::search::{parse_search_output, MAX_LIMIT};
+use crate::signal::parse_signal;
+use crate::size::{artifact_files, measure, ArtifactSize};
+use crate::stream::{
+    spawn_and_stream, wait_cancelled, Exit, StreamOptions, INTERACTIVE_TIMEOUT_FACTOR, NO_TIMEOUT,
+};
+use crate::test_report::{parse_junit, parse_libtest, TestReport};
+use crate::timings::{parse_cargo_version, TimingReport, MIN_CARGO_VERSION};
+use crate::toolchain::missing_toolchain;
+use crate::tree::DependencyTree;
+use crate::unit_graph::{is_nightly, parse_unit_graph};
+use crate::vendor::parse_vendor_output;
 use mlua::prelude::*;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::process::Command as TokioCommand;
 use tokio::runtime::Runtime;
-use tokio::sync::mpsc;
+
+// Commands that only compile: their exit status is the build's, so `build-finished` can
+// decide success, and any warning in their output is the compiler's
+const BUILD_ONLY_COMMANDS: &[&str] = &["build", "check", "clippy"];
+
+// Numbers the files commands write, so concurrent commands never share one
+static FILE_COUNT: AtomicU32 = AtomicU32::new(0);
+
+// Commands taking `--package`/`--workspace`, whose result names the members acted on
+const PACKAGE_SELECTING_COMMANDS: &[&str] = &[
+    "build", "check", "clippy", "test", "bench", "run", "doc", "fix",
+];
 
 /// Structure for handling Cargo commands
 /// Contains a runtime for async operations
 #[derive(Clone)]
 pub struct CargoCommands {
     runtime: Arc<Runtime>,
+    pub(crate) options: CommandOptions,
+    events: Vec<EventSender>,
+    cancel: Option<CancelToken>,
+    detached: bool,
+    /// Input of an interactive session, read by whichever of its commands is running
+    session_input: Option<SessionInput>,
+    /// Extra environment for the commands, on top of the wrapper's
+    env: Vec<(&'static str, &'static str)>,
+    /// Shell script run in place of cargo, for exercising timeouts and kills
+    #[cfg(test)]
+    fake_command: Option<String>,
 }
 
 impl CargoCommands {
@@ -26,9 +103,98 @@ impl CargoCommands {
                     .build()
                     .map_err(|e| LuaError::RuntimeError(e.to_string()))?,
             ),
+            options: CommandOptions::default(),
+            events: Vec::new(),
+            cancel: None,
+            detached: false,
+            session_input: None,
+            env: Vec::new(),
+            #[cfg(test)]
+            fake_command: None,
         })
     }
 
+    // The command cargo is started with, unless a test stands in a script for it
+    fn cargo_command(&self, program: &str) -> LuaResult<(TokioCommand, String)> {
+        #[cfg(test)]
+        if let Some(script) = &self.fake_command {
+            let mut fake = TokioCommand::new("sh");
+            fake.args(["-c", script, "cargo"]);
+            return Ok((fake, "sh".to_string()));
+        }
+        self.options.command(program)
+    }
+
+    /// Returns a handle that applies the given options to every command it runs
+    pub fn with_options(&self, options: CommandOptions) -> Self {
+        Self {
+            options,
+            ..self.clone()
+        }
+    }
+
+    /// Returns a handle that also publishes output and progress events to the given channel
+    pub fn with_events(&self, events: EventSender) -> Self {
+        let mut commands = self.clone();
+        commands.events.push(events);
+        commands
+    }
+
+    /// Returns a handle that also writes events to the NDJSON log set in its options
+    pub fn with_event_log(&self) -> LuaResult<Self> {
+        match self.options.event_log_target()? {
+            Some(target) => Ok(self.with_events(spawn_event_log(&target)?)),
+            None => Ok(self.clone()),
+        }
+    }
+
+    /// Returns a handle whose commands are killed when the token is cancelled
+    pub fn with_cancel(&self, cancel: CancelToken) -> Self {
+        Self {
+            cancel: Some(cancel),
+            ..self.clone()
+        }
+    }
+
+    // Whether the command was cancelled by the user rather than superseded
+    fn interrupted(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|c| c.reason() == Some(CancelReason::Interrupted))
+    }
+
+    /// Returns a handle for background work: its commands get no stdin and
+    /// leave `send_input` routed to the foreground command
+    pub(crate) fn detached(&self) -> Self {
+        Self {
+            detached: true,
+            ..self.clone()
+        }
+    }
+
+    /// Returns a handle for an interactive session: detached, but its commands read
+    /// their stdin from the session's channel
+    pub(crate) fn with_session_input(&self, input: SessionInput) -> Self {
+        Self {
+            detached: true,
+            session_input: Some(input),
+            ..self.clone()
+        }
+    }
+
+    /// Returns a handle whose commands also get `key=value` in their environment
+    fn with_env(&self, key: &'static str, value: &'static str) -> Self {
+        let mut commands = self.clone();
+        commands.env.push((key, value));
+        commands
+    }
+
+    /// Publish an event if anyone is listening
+    pub(crate) fn emit(&self, event: Event) {
+        // The receivers may already be gone; events are best-effort
+        publish(&self.events, event);
+    }
+
     /// Executes a future on the runtime
     pub fn execute<F, T>(&self, future: F) -> T
     where
@@ -44,439 +210,1748 @@ impl CargoCommands {
         &self,
         command: &str,
         args: &[&str],
-    ) -> LuaResult<(String, bool)> {
+    ) -> LuaResult<CommandOutcome> {
         self.execute_cargo_command_internal(command, args, None)
             .await
     }
 
+    /// Returns a handle that runs `script` with `sh -c` instead of cargo; cargo's
+    /// arguments become its positional parameters
+    #[cfg(test)]
+    pub fn with_fake_command(&self, script: &str) -> Self {
+        Self {
+            fake_command: Some(script.to_string()),
+            ..self.clone()
+        }
+    }
+
     /// Execute a Cargo command with the given arguments (public for testing)
     #[cfg(test)]
     pub async fn execute_cargo_command(
         &self,
         command: &str,
         args: &[&str],
-    ) -> LuaResult<(String, bool)> {
+    ) -> LuaResult<CommandOutcome> {
         self.execute_cargo_command_internal(command, args, None)
             .await
     }
 
     /// Execute a Cargo command with timeout and interactive mode support
+    /// A non-zero exit status is reported as an error; registry commands that were
+    /// rate limited fail with a `rate_limited` error, or are retried once the registry
+    /// allows it when `respect_rate_limit` is set
     async fn execute_cargo_command_internal(
         &self,
         command: &str,
         args: &[&str],
         timeout_duration: Option<Duration>,
-    ) -> LuaResult<(String, bool)> {
-        let mut cmd = TokioCommand::new("cargo");
-        cmd.arg(command)
-            .args(args)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
-
-        // Always set a timeout (with default values)
-        let command_timeout = timeout_duration.unwrap_or_else(|| {
-            match command {
-                "run" => Duration::from_secs(300),   // 5 minutes
-                "test" => Duration::from_secs(300),  // 5 minutes
-                "bench" => Duration::from_secs(600), // 10 minutes
-                _ => Duration::from_secs(120),       // 2 minutes
+    ) -> LuaResult<CommandOutcome> {
+        let mut attempt = 0;
+        loop {
+            let outcome = self
+                .spawn_cargo_command(command, args, timeout_duration)
+                .await?;
+
+            // Cargo refused to build the target; its hint can look like a prompt
+            let missing_features = (!outcome.success && !outcome.interrupted)
+                .then(|| parse_missing_features(&outcome.output))
+                .flatten();
+            if let Some((target, features)) = missing_features {
+                let error = Error::MissingFeatures {
+                    command: format!("cargo {}", command),
+                    target,
+                    features,
+                };
+                return Err(command_failure(error, outcome));
             }
-        });
 
-        let mut child = cmd.spawn().map_err(|e| {
-            LuaError::RuntimeError(format!("Failed to execute cargo {}: {}", command, e))
-        })?;
-
-        let stdout = child.stdout.take().unwrap();
-        let stderr = child.stderr.take().unwrap();
-        let stdin = child.stdin.take().unwrap();
-
-        // Create buffered streams
-        let mut stdout_reader = BufReader::new(stdout).lines();
-        let mut stderr_reader = BufReader::new(stderr).lines();
+            // Check if process failed
+            if outcome.success || outcome.interactive || outcome.interrupted {
+                return Ok(outcome);
+            }
+            // A failed build points at its first error, from rustc's text without JSON
+            let mut outcome = outcome;
+            if outcome.quickfix.is_empty() && BUILD_ONLY_COMMANDS.contains(&command) {
+                outcome.quickfix = parse_text_diagnostics(&outcome.output);
+            }
+            outcome.first_error = first_error(&outcome.quickfix);
+            let details = outcome.output.clone();
+            if let Some(signal) = outcome.signal {
+                let error = Error::KilledBySignal {
+                    command: format!("cargo {}", command),
+                    signal,
+                    details,
+                };
+                return Err(command_failure(error, outcome));
+            }
+            if let Some(toolchain) = missing_toolchain(&outcome.output) {
+                let error = Error::ToolchainNotInstalled {
+                    command: format!("cargo {}", command),
+                    toolchain,
+                    details,
+                };
+                return Err(command_failure(error, outcome));
+            }
+            if let Some(manifest_error) = self.manifest_error(&outcome.output) {
+                let error = Error::ManifestError {
+                    command: format!("cargo {}", command),
+                    error: manifest_error,
+                    details,
+                };
+                return Err(command_failure(error, outcome));
+            }
+            if !outcome.compile_failures.is_empty() {
+                let error = Error::CompileFailed {
+                    command: format!("cargo {}", command),
+                    failures: outcome.compile_failures.clone(),
+                    details,
+                };
+                return Err(command_failure(error, outcome));
+            }
+            let rate_limit = REGISTRY_COMMANDS
+                .contains(&command)
+                .then(|| detect_rate_limit(&outcome.output, SystemTime::now()))
+                .flatten();
+            let Some(rate_limit) = rate_limit else {
+                let error = Error::CommandFailed {
+                    command: format!("cargo {}", command),
+                    details,
+                };
+                return Err(command_failure(error, outcome));
+            };
+
+            let wait = rate_limit
+                .wait()
+                .filter(|_| self.options.respect_rate_limit && attempt < rate_limit::MAX_RETRIES);
+            let Some(wait) = wait else {
+                let error = Error::RateLimited {
+                    command: command.to_string(),
+                    retry_after_secs: rate_limit.retry_after_secs,
+                    details,
+                };
+                return Err(command_failure(error, outcome));
+            };
+
+            attempt += 1;
+            self.emit(Event::RateLimited {
+                command: command.to_string(),
+                wait_secs: wait.as_secs(),
+                attempt,
+            });
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = wait_cancelled(self.cancel.as_ref()) => {
+                    if self.interrupted() {
+                        return Ok(CommandOutcome {
+                            interrupted: true,
+                            ..CommandOutcome::new(outcome.output, false)
+                        });
+                    }
+                    return Err(LuaError::RuntimeError(format!(
+                        "cargo {} was cancelled",
+                        command
+                    )));
+                }
+            }
+        }
+    }
 
-        // Interactive mode detection flag
-        let mut is_interactive = false;
+    /// Run a Cargo command to completion
+    /// Unlike `execute_cargo_command_internal`, a non-zero exit status is only recorded
+    /// in `success`, for callers that parse results out of failing runs
+    async fn spawn_cargo_command(
+        &self,
+        command: &str,
+        args: &[&str],
+        timeout_duration: Option<Duration>,
+    ) -> LuaResult<CommandOutcome> {
+        validate_subcommand(command)?;
+        let user_args = args;
+        // Every option that maps to arguments is applied here, once per spawn
+        let mut args = self.options.command_args(command, args)?;
+        // A cargo too old for the rendered JSON format builds with human output, parsed
+        // for the quickfix list instead
+        let text_diagnostics = self.options.rendered_diagnostics
+            && args.iter().any(|a| a == RENDERED_MESSAGE_FORMAT)
+            && !user_args.contains(&RENDERED_MESSAGE_FORMAT)
+            && !self
+                .supports_or_fall_back(
+                    "rendered_diagnostics",
+                    RENDERED_MIN_CARGO_VERSION,
+                    "diagnostics are parsed from cargo's human output",
+                )
+                .await?;
+        if text_diagnostics {
+            args.retain(|a| a != RENDERED_MESSAGE_FORMAT);
+        }
+        let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+        let invocation = self.options.invocation()?;
+        let config_args = self.options.config_args()?;
+        if !config_args.is_empty() {
+            self.check_cargo_version("config", CONFIG_MIN_CARGO_VERSION)
+                .await?;
+        }
+        // An explicit `--target-dir` in the arguments wins
+        let explicit_target_dir = args
+            .iter()
+            .take_while(|a| **a != "--")
+            .any(|a| a.starts_with("--target-dir"));
+        let target_dir_args: Vec<String> = target_dir_override(command, &self.options)
+            .filter(|_| !explicit_target_dir)
+            .map(|dir| vec!["--target-dir".to_string(), dir])
+            .unwrap_or_default();
+        let responder = AutoResponder::new(&self.options.responses)?;
+        let (mut cmd, launcher) = self.cargo_command(invocation.program)?;
+        if command == "publish" {
+            // Kept out of the arguments so it never appears in output or errors
+            cmd.envs(self.options.registry_token_env()?);
+        }
+        cmd.envs(invocation.env)
+            .envs(self.env.iter().copied())
+            .args(&config_args)
+            .arg(command)
+            .args(&target_dir_args)
+            .args(&args);
+
+        // Always set a timeout (explicit, then per-call option, then default values),
+        // unless the caller disabled it
+        let disabled = self.options.timeout_disabled().then_some(NO_TIMEOUT);
+        let command_timeout = timeout_duration
+            .or(disabled)
+            .or(self.options.timeout())
+            .unwrap_or_else(|| {
+                match command {
+                    "run" => Duration::from_secs(300),      // 5 minutes
+                    "test" => Duration::from_secs(300),     // 5 minutes
+                    "nextest" => Duration::from_secs(300),  // 5 minutes
+                    "llvm-cov" => Duration::from_secs(300), // 5 minutes
+                    "bench" => Duration::from_secs(600),    // 10 minutes
+                    "vendor" => Duration::from_secs(600),   // 10 minutes
+                    "fetch" => Duration::from_secs(600),    // 10 minutes
+                    _ => Duration::from_secs(120),          // 2 minutes
+                }
+            });
+
+        // Diagnostics are shown through their rendered text rather than as JSON
+        let rendered = args
+            .iter()
+            .take_while(|a| **a != "--")
+            .any(|a| *a == RENDERED_MESSAGE_FORMAT);
+        // JSON asked for in the arguments is kept as the output, with the quickfix list
+        // taken from it too
+        let json = is_json_message_format(&args);
+        // The `interactive` option overrides detection; run is interactive by default
+        let forced_interactive = self.options.interactive;
+        let mut handle = spawn_and_stream(
+            cmd,
+            StreamOptions {
+                timeout: command_timeout,
+                interactive: forced_interactive.unwrap_or(command == "run"),
+                detect_interactive: forced_interactive.is_none(),
+                stdin: !self.detached || self.session_input.is_some(),
+                responder,
+                events: !self.events.is_empty(),
+                errors_only: self.options.errors_only,
+                only_packages: self.options.only_packages.clone(),
+                rendered,
+                output_yield: self.options.output_yield(),
+                stop_signal: self.options.stop_signal(command),
+                kill_timeout: self.options.kill_timeout(),
+                tree: command == "tree",
+                max_output_lines: self.options.max_output_lines(command),
+                color: self.options.color,
+            },
+        )
+        .map_err(|e| spawn_error(&launcher, e))?;
+        // Looked up alongside the command, from the same directory and environment
+        let toolchain_handle = tokio::spawn(active_toolchain(self.options.command("rustc")?.0));
+
+        let input = handle.input();
+        if !self.detached {
+            set_input_sender(input.clone());
+        }
+        let session_forwarder = self.session_input.clone().map(|source| {
+            let input = input.clone();
+            tokio::spawn(async move {
+                let mut source = source.lock().await;
+                while let Some(message) = source.recv().await {
+                    if input.send(message).is_err() {
+                        break;
+                    }
+                }
+            })
+        });
+        let forwarder = handle.take_events().map(|mut stream| {
+            let events = self.events.clone();
+            tokio::spawn(async move {
+                while let Some(event) = stream.recv().await {
+                    publish(&events, event);
+                }
+            })
+        });
 
-        // Assume interactive mode based on command name
-        if command == "run" {
-            // Treat run command as interactive by default
-            is_interactive = true;
+        let finished = handle.wait(self.cancel.as_ref()).await;
+        // Releases the session's input for the next command, e.g. a backtrace rerun
+        if let Some(forwarder) = session_forwarder {
+            forwarder.abort();
+        }
+        if let Some(forwarder) = forwarder {
+            let _ = forwarder.await;
+        }
+        if !self.detached {
+            clear_input_sender(&input);
         }
 
-        // Output buffer
-        let output = String::new();
+        let (process_success, exit_code, process_signal) = match finished.exit {
+            Exit::Exited {
+                success,
+                code,
+                signal,
+            } => (success, code, signal),
+            _ => (false, None, None),
+        };
+        let final_output = finished.output;
+        let is_interactive_mode = finished.interactive;
+        let dropped_lines = finished.dropped_lines;
+        // Parsed as it was read, so an interrupted tree keeps the packages listed so far
+        let tree = finished
+            .tree
+            .map(|roots| DependencyTree::from_roots(roots, self.options.invert.is_some()));
+        // The JSON is kept out of the output once the quickfix list is taken from it
+        let human_output = |outcome: &mut CommandOutcome| {
+            if text_diagnostics {
+                outcome.quickfix = parse_text_diagnostics(&outcome.output);
+            }
+            if json {
+                outcome.quickfix = parse_diagnostics(&outcome.output, &self.options.only_packages);
+                outcome.suggestions = parse_suggestions(
+                    &outcome.output,
+                    &self.options.only_packages,
+                    self.options.errors_only,
+                );
+            }
+            if rendered {
+                outcome.edit_output(render_messages);
+            }
+        };
 
-        // Channel for standard input
-        let (tx, mut rx) = mpsc::channel::<String>(32);
-        set_input_sender(tx.clone());
+        if let Exit::Cancelled(termination) = finished.exit {
+            if let Some(cancel) = &self.cancel {
+                cancel.set_termination(termination);
+            }
+            // Stopped by the user: not a failure, so the output so far is returned
+            if self.interrupted() {
+                let mut outcome = CommandOutcome {
+                    interrupted: true,
+                    termination: Some(termination),
+                    stdout: finished.stdout,
+                    stderr: finished.stderr,
+                    ansi_output: finished.ansi_output,
+                    dropped_lines,
+                    tree,
+                    ..CommandOutcome::new(final_output, is_interactive_mode)
+                };
+                human_output(&mut outcome);
+                return Ok(outcome);
+            }
+            return Err(LuaError::RuntimeError(format!(
+                "cargo {} was cancelled; {}",
+                command, termination
+            )));
+        }
 
-        // Task to handle standard input
-        let stdin_handle = tokio::spawn(async move {
-            let mut stdin = stdin;
-            while let Some(input) = rx.recv().await {
-                match stdin.write_all(input.as_bytes()).await {
-                    Ok(_) => {
-                        if let Err(e) = stdin.flush().await {
-                            eprintln!("Failed to flush stdin: {}", e);
-                            break;
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to write to stdin: {}", e);
-                        break;
-                    }
+        // An interactive program still running at its deadline was most likely
+        // waiting for input that never came
+        if let Exit::TimedOut(termination) = finished.exit {
+            if is_interactive_mode {
+                return Err(Error::AwaitingInputTimeout {
+                    command: command.to_string(),
+                    timeout_secs: (command_timeout * INTERACTIVE_TIMEOUT_FACTOR).as_secs(),
+                    termination,
                 }
+                .into());
             }
-        });
+            // The output up to the timeout comes with the error
+            let mut outcome = CommandOutcome {
+                termination: Some(termination),
+                stdout: finished.stdout,
+                stderr: finished.stderr,
+                ansi_output: finished.ansi_output,
+                dropped_lines,
+                tree,
+                ..CommandOutcome::new(final_output, is_interactive_mode)
+            };
+            human_output(&mut outcome);
+            let error = Error::TimedOut {
+                command: command.to_string(),
+                timeout_secs: command_timeout.as_secs(),
+                termination,
+            };
+            return Err(command_failure(error, outcome));
+        }
+
+        let mut outcome = CommandOutcome {
+            exit_code,
+            stdout: finished.stdout,
+            stderr: finished.stderr,
+            ansi_output: finished.ansi_output,
+            dropped_lines,
+            tree,
+            ..CommandOutcome::new(final_output, is_interactive_mode)
+        };
+        outcome.build_finished = build_finished(&outcome.output);
+        // For commands that only build, cargo's JSON verdict is the more reliable one
+        // (e.g. with `--keep-going`); run and test also exit with the program's status
+        outcome.success = match outcome.build_finished {
+            Some(success) if BUILD_ONLY_COMMANDS.contains(&command) => success,
+            _ => process_success,
+        };
+        outcome.cached = is_cached_build(&outcome.output);
+        if BUILD_ONLY_COMMANDS.contains(&command) {
+            outcome.warning_count = count_warnings(&outcome.output);
+        }
+        if !outcome.success {
+            outcome.signal = process_signal.or_else(|| parse_signal(&outcome.output));
+            outcome.compile_failures = parse_compile_failures(&outcome.output);
+        }
+        outcome.toolchain = toolchain_handle.await.ok().flatten();
+        human_output(&mut outcome);
+        Ok(outcome)
+    }
 
-        // Asynchronous IO processing and timeout control
-        let output_handle = tokio::spawn(async move {
-            let mut combined_output = String::new();
-            let start_time = std::time::Instant::now();
-
-            // Output reading loop
-            loop {
-                let timeout_remaining = command_timeout
-                    .checked_sub(start_time.elapsed())
-                    .unwrap_or_else(|| Duration::from_secs(1));
-
-                // Monitor both stdout and stderr simultaneously
-                tokio::select! {
-                    // Reading standard output
-                    stdout_result = stdout_reader.next_line() => {
-                        match stdout_result {
-                            Ok(Some(line)) => {
-                                // Detect interactive mode based on specific patterns
-                                if !is_interactive && (
-                                    line.contains("? [Y/n]") ||
-                                    line.contains("Enter password:") ||
-                                    line.contains("> ") ||
-                                    line.contains("[1/3]") ||
-                                    line.ends_with("? ") ||
-                                    line.trim().is_empty() // Empty line may indicate interactive mode
-                                ) {
-                                    is_interactive = true;
-                                }
-
-                                combined_output.push_str(&line);
-                                combined_output.push('\n');
-                            },
-                            Ok(None) => break, // EOF
-                            Err(_) => break,
-                        }
-                    },
-
-                    // Reading standard error
-                    stderr_result = stderr_reader.next_line() => {
-                        match stderr_result {
-                            Ok(Some(line)) => {
-                                combined_output.push_str(&line);
-                                combined_output.push('\n');
-                            },
-                            Ok(None) => {}, // Stdout might still have data
-                            Err(_) => {},
-                        }
-                    },
-
-                    // Timeout processing (only for non-interactive mode)
-                    _ = tokio::time::sleep(timeout_remaining), if !is_interactive => {
-                        return (combined_output, is_interactive, true); // Timeout
+    /// Returns a handle with the defaults of the project's `.cargo-nvim.toml` filled in,
+    /// or an error if that config denies `subcommand`
+    /// A malformed config is ignored with a `config_warning` event
+    pub(crate) fn with_project_config(&self, subcommand: &str) -> LuaResult<Self> {
+        let Ok(dir) = std::env::current_dir() else {
+            return Ok(self.clone());
+        };
+        let dir = match &self.options.cwd {
+            Some(cwd) => dir.join(cwd),
+            None => dir,
+        };
+        match ProjectConfig::find(&dir) {
+            Ok(Some((_, config))) => {
+                if config.denies(subcommand) {
+                    return Err(Error::CommandDenied {
+                        command: subcommand.to_string(),
                     }
+                    .into());
                 }
+                let mut options = self.options.clone();
+                config.apply(subcommand, &mut options);
+                Ok(self.with_options(options))
+            }
+            Ok(None) => Ok(self.clone()),
+            Err((path, message)) => {
+                self.emit(Event::ConfigWarning {
+                    path: path.to_string_lossy().into_owned(),
+                    message,
+                });
+                Ok(self.clone())
+            }
+        }
+    }
 
-                // Check timeout (even for interactive mode)
-                if start_time.elapsed() >= command_timeout {
-                    return (combined_output, is_interactive, true);
-                }
+    /// Run a subcommand by name, dispatching to its dedicated method
+    /// The project config is applied first, and the post-command hooks are called with
+    /// the result; its output is the one with colors with the `color` option
+    pub async fn run_subcommand(
+        &self,
+        subcommand: &str,
+        args: &[&str],
+    ) -> LuaResult<CommandOutcome> {
+        let result = self
+            .run_subcommand_internal(subcommand, args)
+            .await
+            .map(CommandOutcome::with_colors);
+        command_finished(subcommand, &result);
+        result
+    }
 
-                // For interactive mode, use an extended timeout (3x normal timeout)
-                // but still terminate after excessive inactivity
-                if is_interactive
-                    && start_time.elapsed() >= Duration::from_secs(command_timeout.as_secs() * 3)
-                {
-                    return (combined_output, is_interactive, true);
-                }
-            }
+    async fn run_subcommand_internal(
+        &self,
+        subcommand: &str,
+        args: &[&str],
+    ) -> LuaResult<CommandOutcome> {
+        validate_subcommand(subcommand)?;
+        let commands = self.with_project_config(subcommand)?;
+        let args = commands.options.with_raw_args(args)?;
+        let args: &[&str] = &args.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+        let mut outcome = commands.dispatch_subcommand(subcommand, args).await?;
+        if PACKAGE_SELECTING_COMMANDS.contains(&subcommand)
+            && !outcome.interrupted
+            && !has_package_selection(args)
+        {
+            outcome.members = commands.targeted_members(args).await;
+        }
+        outcome.first_error = first_error(&outcome.quickfix);
+        Ok(outcome)
+    }
 
-            (combined_output, is_interactive, false)
-        });
+    // Members cargo acted on without a package selection, from the manifest it used;
+    // only a workspace root needs `cargo metadata`
+    async fn targeted_members(&self, args: &[&str]) -> Option<Vec<String>> {
+        let manifest = self.manifest_for(args)?;
+        if let Some(package) = package_of_manifest(&manifest) {
+            return Some(vec![package]);
+        }
+        let metadata = self.quiet_metadata(args).await?;
+        Some(targeted_members(&metadata, &manifest))
+    }
 
-        // Wait for process completion
-        let process_status = tokio::select! {
-            status = child.wait() => {
-                match status {
-                    Ok(s) => (s.success(), false), // (succeeded, timed out)
-                    Err(_) => (false, false),
+    // The manifest cargo reads: `--manifest-path`, else the closest one to `cwd`
+    fn manifest_for(&self, args: &[&str]) -> Option<PathBuf> {
+        match manifest_path_args(args).as_slice() {
+            [_, path] => Some(PathBuf::from(path)),
+            [flag] => Some(PathBuf::from(flag.strip_prefix("--manifest-path=")?)),
+            _ => {
+                let dir = self.cargo_dir()?;
+                match &self.options.manifest_path {
+                    Some(manifest) => Some(dir.join(manifest)),
+                    None => find_manifest(&dir),
                 }
-            },
-            _ = tokio::time::sleep(command_timeout) => {
-                // Timeout occurred
-                child.kill().await.ok(); // Force terminate the process
-                (false, true)
             }
-        };
+        }
+    }
 
-        // Get results from output processing task
-        let output_result = match tokio::time::timeout(Duration::from_secs(5), output_handle).await
-        {
-            Ok(Ok((out, interactive, _))) => (out, interactive),
-            _ => (output, is_interactive),
+    // `cargo metadata --no-deps` for the manifest in `args`, without publishing its
+    // JSON as output events
+    async fn quiet_metadata(&self, args: &[&str]) -> Option<Metadata> {
+        let mut metadata_args = vec!["--no-deps"];
+        metadata_args.extend(manifest_path_args(args));
+        let quiet = Self {
+            events: Vec::new(),
+            ..self.without_tool_args()
         };
+        quiet
+            .cargo_metadata(&metadata_args)
+            .await
+            .ok()
+            .and_then(|outcome| outcome.metadata)
+    }
 
-        // Resource cleanup
-        stdin_handle.abort();
-        drop(tx);
-        // rx is already moved into the stdin_handle task
-        // and will be dropped when the task is aborted
-
-        // Process the results
-        let (process_success, process_timeout) = process_status;
-        let (final_output, is_interactive_mode) = output_result;
+    // For the cargo commands run on the side of a subcommand (metadata, reports):
+    // `tool_args` are the subcommand's own
+    fn without_tool_args(&self) -> Self {
+        self.with_options(CommandOptions {
+            tool_args: Vec::new(),
+            ..self.options.clone()
+        })
+    }
 
-        // Check if process timed out
-        if process_timeout && !is_interactive_mode {
-            return Err(LuaError::RuntimeError(format!(
-                "cargo {} timed out after {} seconds",
-                command,
-                command_timeout.as_secs()
-            )));
+    async fn dispatch_subcommand(
+        &self,
+        subcommand: &str,
+        args: &[&str],
+    ) -> LuaResult<CommandOutcome> {
+        match subcommand {
+            "bench" => self.cargo_bench(args).await,
+            "build" => self.cargo_build(args).await,
+            "clean" => self.cargo_clean(args).await,
+            "doc" => self.cargo_doc(args).await,
+            "fmt" => self.cargo_fmt(args).await,
+            "help" => self.cargo_help(args).await,
+            "new" => match args.split_first() {
+                Some((name, remaining)) => self.cargo_new(name, remaining).await,
+                None => Err(LuaError::RuntimeError(
+                    "Project name is required".to_string(),
+                )),
+            },
+            "run" => self.cargo_run(args).await,
+            "test" => self.cargo_test(args).await,
+            "test_doc" => self.cargo_test_doc(args).await,
+            "update" => self.cargo_update(args).await,
+            "check" => self.cargo_check(args).await,
+            "init" => self.cargo_init(args).await,
+            "add" => self.cargo_add(args).await,
+            "remove" => self.cargo_remove(args).await,
+            "clippy" => self.cargo_clippy(args).await,
+            "fix" => self.cargo_fix(args).await,
+            "publish" => self.cargo_publish(args).await,
+            "install" => self.cargo_install(args).await,
+            "uninstall" => self.cargo_uninstall(args).await,
+            "search" => self.cargo_search(args).await,
+            "tree" => self.cargo_tree(args).await,
+            "vendor" => self.cargo_vendor(args).await,
+            "fetch" => self.cargo_fetch(args).await,
+            "metadata" => self.cargo_metadata(args).await,
+            "audit" => self.cargo_audit(args).await,
+            "outdated" => self.cargo_outdated(args).await,
+            "autodd" => self.cargo_autodd(args).await,
+            "llvm_cov" => self.cargo_llvm_cov(args).await,
+            "unit_graph" => self.cargo_unit_graph(args).await,
+            other => Err(LuaError::RuntimeError(format!(
+                "Unknown cargo subcommand: {}",
+                other
+            ))),
         }
+    }
 
-        // Check if process failed
-        if !process_success && !is_interactive_mode {
-            return Err(LuaError::RuntimeError(format!(
-                "cargo {} failed: {}",
-                command, final_output
-            )));
+    /// Cargo's unit graph for a build, without building: `cargo build --unit-graph
+    /// -Z unstable-options`, which needs a nightly cargo; returned in `unit_graph`, with
+    /// the JSON left out of the output
+    pub async fn cargo_unit_graph(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        self.require_nightly("unit_graph").await?;
+        let mut full_args = vec!["--unit-graph", "-Z", "unstable-options"];
+        full_args.extend_from_slice(args);
+        let mut outcome = self
+            .execute_cargo_command_internal("build", &full_args, None)
+            .await?;
+        outcome.unit_graph = parse_unit_graph(&outcome.output);
+        if outcome.unit_graph.is_some() {
+            outcome.edit_output(|output| {
+                output
+                    .lines()
+                    .filter(|line| !line.starts_with("{\"version\""))
+                    .map(|line| format!("{}\n", line))
+                    .collect()
+            });
         }
+        Ok(outcome)
+    }
 
-        Ok((final_output, is_interactive_mode))
+    // Fail with what to do unless cargo, as this command would run it, is a nightly
+    async fn require_nightly(&self, feature: &str) -> LuaResult<()> {
+        let version = self.cargo_version().await?;
+        if is_nightly(&version) {
+            return Ok(());
+        }
+        Err(Error::RequiresNightly {
+            command: feature.to_string(),
+            found: version.trim().to_string(),
+        }
+        .into())
     }
 
     /// Check the project for errors
-    pub async fn cargo_check(&self, args: &[&str]) -> LuaResult<(String, bool)> {
+    pub async fn cargo_check(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
         let result = self
             .execute_cargo_command_internal("check", args, None)
             .await;
 
         // If the command executed successfully but the output is empty, provide a default message
         match result {
-            Ok((output, interactive)) if output.trim().is_empty() => Ok((
-                "Finished `dev` profile [unoptimized + debuginfo] target(s) in 0.00s".to_string(),
-                interactive,
-            )),
+            Ok(mut outcome) if outcome.output.trim().is_empty() => {
+                outcome.output =
+                    "Finished `dev` profile [unoptimized + debuginfo] target(s) in 0.00s"
+                        .to_string();
+                Ok(outcome)
+            }
             other => other,
         }
     }
 
+    /// Check the project for diagnostics only, as fast as possible
+    /// Uses JSON output and `--keep-going` so every error is found in one pass,
+    /// and `--all-targets` so tests and examples are covered; the result carries
+    /// just the quickfix list, failing only when cargo didn't get to report diagnostics
+    pub async fn cargo_check_fast(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        let mut full_args: Vec<&str> = Vec::new();
+        for flag in ["--message-format=json", "--all-targets", "--keep-going"] {
+            let flag_name = flag.split('=').next().unwrap_or(flag);
+            if !args.iter().any(|a| a.starts_with(flag_name)) {
+                full_args.push(flag);
+            }
+        }
+        // Without `--keep-going`, cargo stops at the first crate that fails
+        if full_args.contains(&"--keep-going")
+            && !self
+                .supports_or_fall_back(
+                    "keep_going",
+                    KEEP_GOING_MIN_CARGO_VERSION,
+                    "checked without --keep-going, stopping at the first crate with errors",
+                )
+                .await?
+        {
+            full_args.retain(|a| *a != "--keep-going");
+        }
+        full_args.extend_from_slice(args);
+
+        let outcome = self.spawn_cargo_command("check", &full_args, None).await?;
+        if outcome.interrupted {
+            return Ok(outcome);
+        }
+        let mut quickfix = parse_diagnostics(&outcome.output, &self.options.only_packages);
+        if self.options.errors_only {
+            quickfix.retain(|entry| entry.kind == "E");
+        }
+        // A manifest that can't be read is shown where the compile errors would be
+        if !outcome.success && !quickfix.iter().any(|entry| entry.kind == "E") {
+            if let Some(error) = self.manifest_error(&outcome.output) {
+                quickfix.push(error.to_quickfix());
+            }
+        }
+        let reported = |quickfix: &[QuickfixEntry]| quickfix.iter().any(|entry| entry.kind == "E");
+        // Errors of the packages left out by `only_packages` were reported all the same
+        let failed_unreported = !outcome.success
+            && !reported(&quickfix)
+            && !reported(&parse_diagnostics(&outcome.output, &[]));
+        let fast = CommandOutcome {
+            success: outcome.success,
+            exit_code: outcome.exit_code,
+            signal: outcome.signal,
+            build_finished: outcome.build_finished,
+            cached: outcome.cached,
+            warning_count: outcome.warning_count,
+            compile_failures: outcome.compile_failures,
+            first_error: first_error(&quickfix),
+            quickfix,
+            suggestions: parse_suggestions(
+                &outcome.output,
+                &self.options.only_packages,
+                self.options.errors_only,
+            ),
+            ..Default::default()
+        };
+
+        let command = "cargo check".to_string();
+        let details = outcome.output.clone();
+        let missing_toolchain = (!outcome.success)
+            .then(|| missing_toolchain(&outcome.output))
+            .flatten();
+        // A killed compiler leaves the diagnostics incomplete, whatever else was reported
+        let error = if let Some(signal) = outcome.signal {
+            Error::KilledBySignal {
+                command,
+                signal,
+                details,
+            }
+        } else if let Some(toolchain) = missing_toolchain {
+            Error::ToolchainNotInstalled {
+                command,
+                toolchain,
+                details,
+            }
+        } else if failed_unreported {
+            Error::CommandFailed { command, details }
+        } else {
+            return Ok(fast);
+        };
+        // Failures come with the output, like those of `check`
+        let outcome = CommandOutcome {
+            output: outcome.output,
+            stdout: outcome.stdout,
+            stderr: outcome.stderr,
+            ..fast
+        };
+        Err(command_failure(error, outcome))
+    }
+
     /// Execute a Cargo command with automatic interactive mode detection
     async fn execute_cargo_command_smart(
         &self,
         command: &str,
         args: &[&str],
-    ) -> LuaResult<(String, bool)> {
+    ) -> LuaResult<CommandOutcome> {
         // 特定のコマンドは常にインタラクティブモードとして扱う
-        let result = self
+        let mut outcome = self
             .execute_cargo_command_internal(command, args, None)
             .await?;
 
         // run コマンドは常にインタラクティブモードとして扱う
-        if command == "run" {
-            return Ok((result.0, true));
+        if command == "run" && self.options.interactive.is_none() {
+            outcome.interactive = true;
         }
 
-        Ok(result)
+        Ok(outcome)
     }
 
     /// Run benchmarks
-    pub async fn cargo_bench(&self, args: &[&str]) -> LuaResult<(String, bool)> {
+    pub async fn cargo_bench(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
         self.execute_cargo_command_smart("bench", args).await
     }
 
     /// Build the project
-    pub async fn cargo_build(&self, args: &[&str]) -> LuaResult<(String, bool)> {
-        self.execute_cargo_command_smart("build", args).await
+    /// With the `timings` option, also writes cargo's timing report and returns its
+    /// path with the slowest crates
+    /// With `report_size`, also returns the size of each binary and library built
+    pub async fn cargo_build(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        let mut outcome = if self.options.timings {
+            // `--timings` itself is added with the other options
+            self.check_cargo_version("timings", MIN_CARGO_VERSION)
+                .await?;
+            let mut outcome = self.execute_cargo_command_smart("build", args).await?;
+            outcome.timings = Some(TimingReport::from_build(
+                &outcome.output,
+                &target_dir("build", &self.options),
+            ));
+            outcome
+        } else {
+            self.execute_cargo_command_smart("build", args).await?
+        };
+        if self.options.report_size && outcome.success && !outcome.interrupted {
+            outcome.artifact_sizes = self.artifact_sizes(args).await?;
+        }
+        Ok(outcome)
     }
 
-    /// Run the project
-    pub async fn cargo_run(&self, args: &[&str]) -> LuaResult<(String, bool)> {
-        // Designed to support interactive programs
-        let result = self
-            .execute_cargo_command_internal("run", args, None)
-            .await?;
+    // Sizes of the bin, cdylib and staticlib files of the packages built; files that
+    // aren't there (a target that wasn't built) are left out
+    async fn artifact_sizes(&self, args: &[&str]) -> LuaResult<Vec<ArtifactSize>> {
+        let Some(metadata) = self.quiet_metadata(args).await else {
+            return Ok(Vec::new());
+        };
+        let selection: Vec<&str> = args.iter().copied().take_while(|a| *a != "--").collect();
+        let packages: Vec<String> = if selection
+            .iter()
+            .any(|a| matches!(*a, "--workspace" | "--all"))
+        {
+            metadata.workspace_members.clone()
+        } else if has_package_selection(&selection) {
+            [
+                flag_values(&selection, "-p"),
+                flag_values(&selection, "--package"),
+            ]
+            .concat()
+            .into_iter()
+            .map(|spec| spec.split('@').next().unwrap_or(spec).to_string())
+            .collect()
+        } else {
+            match self.manifest_for(args) {
+                Some(manifest) => targeted_members(&metadata, &manifest),
+                None => metadata.workspace_members.clone(),
+            }
+        };
 
-        // Check if proconio is likely being used by examining Cargo.toml
-        // This is important for competitive programming scenarios where proconio::input! is common
-        let has_proconio = std::fs::read_to_string("Cargo.toml")
-            .map(|content| content.contains("proconio"))
-            .unwrap_or(false);
+        let target_dir = self
+            .explicit_target_dir("build", args)
+            .or_else(|| metadata.target_directory.as_deref().map(PathBuf::from))
+            .unwrap_or_else(|| target_dir("build", &self.options));
+        let dir = build_artifact_dir(&target_dir, args, &self.options)?;
+        let triple = flag_values(&selection, "--target")
+            .first()
+            .copied()
+            .or(self.options.target.as_deref());
+        let files = artifact_files(&metadata, &packages, &dir, triple);
+        Ok(tokio::task::spawn_blocking(move || {
+            files.iter().filter_map(|file| measure(file)).collect()
+        })
+        .await
+        .unwrap_or_default())
+    }
 
-        // If proconio is used, force interactive mode
-        if has_proconio {
-            return Ok((result.0, true));
+    // Options mapping to flags that older cargo releases reject fail early with the
+    // version they need
+    async fn check_cargo_version(&self, option: &str, required: (u32, u32)) -> LuaResult<()> {
+        let version = self.cargo_version().await?;
+        match parse_cargo_version(&version) {
+            Some(found) if found >= required => Ok(()),
+            _ => Err(LuaError::RuntimeError(format!(
+                "The {} option requires cargo {}.{} or newer (found: {})",
+                option,
+                required.0,
+                required.1,
+                version.trim()
+            ))),
         }
-
-        Ok(result)
     }
 
-    /// Run the tests
-    pub async fn cargo_test(&self, args: &[&str]) -> LuaResult<(String, bool)> {
-        self.execute_cargo_command_smart("test", args).await
+    // Whether the cargo in use has a flag of `feature`; when it doesn't, a
+    // `version_fallback` event says the command runs without it and how
+    async fn supports_or_fall_back(
+        &self,
+        feature: &str,
+        required: (u32, u32),
+        fallback: &str,
+    ) -> LuaResult<bool> {
+        let version = self.cargo_version().await?;
+        if compat::supports(&version, required) {
+            return Ok(true);
+        }
+        self.emit(Event::VersionFallback {
+            feature: feature.to_string(),
+            required: format!("{}.{}", required.0, required.1),
+            found: version,
+            message: fallback.to_string(),
+        });
+        Ok(false)
     }
 
-    /// Clean the target directory
-    pub async fn cargo_clean(&self, args: &[&str]) -> LuaResult<(String, bool)> {
-        self.execute_cargo_command_internal("clean", args, None)
+    // `cargo --version` of the cargo commands run, read once per cargo (its `CargoKey`,
+    // with the wrapper's environment)
+    async fn cargo_version(&self) -> LuaResult<String> {
+        let mut key = self.options.cargo_key(self.cargo_dir());
+        key.env.extend(
+            self.env
+                .iter()
+                .map(|(name, value)| (name.to_string(), Some(value.to_string()))),
+        );
+        if let Some(version) = compat::cached(&key) {
+            return Ok(version);
+        }
+        let (mut cmd, launcher) = self.options.command("cargo")?;
+        let output = cmd
+            .envs(self.env.iter().copied())
+            .arg("--version")
+            .output()
             .await
+            .map_err(|e| spawn_error(&launcher, e))?;
+        let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if output.status.success() {
+            compat::store(key, version.clone());
+        }
+        Ok(version)
     }
 
-    /// Generate documentation
-    pub async fn cargo_doc(&self, args: &[&str]) -> LuaResult<(String, bool)> {
-        self.execute_cargo_command_internal("doc", args, None).await
+    // Directory cargo runs in: `cwd`, taken from the current directory
+    pub(crate) fn cargo_dir(&self) -> Option<PathBuf> {
+        let dir = std::env::current_dir().ok()?;
+        Some(match &self.options.cwd {
+            Some(cwd) => dir.join(cwd),
+            None => dir,
+        })
     }
 
-    /// Create a new package
-    pub async fn cargo_new(&self, name: &str, args: &[&str]) -> LuaResult<(String, bool)> {
-        let mut full_args = vec![name];
-        full_args.extend_from_slice(args);
-        self.execute_cargo_command_internal("new", &full_args, None)
-            .await
+    // The manifest error in a failed command's output, with its path made absolute
+    fn manifest_error(&self, output: &str) -> Option<ManifestError> {
+        let error = parse_manifest_error(output)?;
+        Some(match self.cargo_dir() {
+            Some(dir) => error.resolve(&dir),
+            None => error,
+        })
     }
 
-    /// Update dependencies
-    pub async fn cargo_update(&self, args: &[&str]) -> LuaResult<(String, bool)> {
-        self.execute_cargo_command_internal("update", args, None)
-            .await
-    }
+    // With `run_dir` or `profile_with`, the program is started by a runner set for the
+    // target with `--config`: `sh -c 'cd ...'` moves to `run_dir` while cargo stays in
+    // `cwd`, and the profiler goes in front of the program
+    // Returns the commands to run with, the directory the program runs in and the
+    // profile it will write
+    async fn with_runner(
+        &self,
+        args: &[&str],
+    ) -> LuaResult<(Self, Option<String>, Option<ProfileOutput>)> {
+        let cargo_dir = self.cargo_dir();
+        if self.options.run_dir.is_none() && self.options.profile_with.is_none() {
+            let cargo_dir = cargo_dir.map(|dir| dir.to_string_lossy().into_owned());
+            return Ok((self.clone(), cargo_dir, None));
+        }
+        let mut runner: Vec<String> = Vec::new();
+        let mut program_dir = cargo_dir.clone();
+        if let Some(run_dir) = &self.options.run_dir {
+            if !cfg!(unix) {
+                return Err(LuaError::RuntimeError(
+                    "The run_dir option is only supported on Unix".to_string(),
+                ));
+            }
+            let dir = cargo_dir.clone().unwrap_or_default().join(run_dir);
+            if !dir.is_dir() {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid run_dir: {:?} is not a directory",
+                    run_dir
+                )));
+            }
+            // `$0` is the directory; cargo appends the program and its arguments
+            let dir_arg = dir.to_string_lossy().into_owned();
+            runner.extend(["sh", "-c", "cd \"$0\" && exec \"$@\""].map(String::from));
+            runner.push(dir_arg);
+            program_dir = Some(dir);
+        }
+        let mut profile = None;
+        if let Some(profiler) = self.options.profile_with {
+            profiler.check_platform()?;
+            self.ensure_profiler(profiler).await?;
+            let output = self.profile_path(profiler, args).await?;
+            runner.extend(profiler.runner(&output));
+            profile = Some(ProfileOutput {
+                profiler: profiler.name().to_string(),
+                path: output.to_string_lossy().into_owned(),
+            });
+        }
 
-    // Additional Cargo Commands
+        let before_separator: Vec<&str> = args.iter().copied().take_while(|a| *a != "--").collect();
+        let explicit_target = flag_values(&before_separator, "--target")
+            .first()
+            .map(|t| t.to_string())
+            .or_else(|| self.options.target.clone());
+        let triple = match explicit_target {
+            Some(triple) => triple,
+            None => host_triple(self.options.command("rustc")?.0)
+                .await
+                .ok_or_else(|| {
+                    LuaError::RuntimeError(
+                        "Couldn't determine the host target for the runner".to_string(),
+                    )
+                })?,
+        };
+        let runner =
+            serde_json::to_string(&runner).map_err(|e| LuaError::RuntimeError(e.to_string()))?;
+        let mut options = self.options.clone();
+        options
+            .config
+            .push(format!("target.\"{}\".runner={}", triple, runner));
+        let program_dir = program_dir.map(|dir| dir.to_string_lossy().into_owned());
+        Ok((self.with_options(options), program_dir, profile))
+    }
 
-    /// Initialize a new package in an existing directory
-    pub async fn cargo_init(&self, args: &[&str]) -> LuaResult<(String, bool)> {
-        self.execute_cargo_command_internal("init", args, None)
+    // Fails with `not_found` when the profiler doesn't start
+    async fn ensure_profiler(&self, profiler: Profiler) -> LuaResult<()> {
+        let (mut cmd, _) = self.options.command(profiler.name())?;
+        let runs = cmd
+            .arg(profiler.version_flag())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
             .await
+            .is_ok_and(|status| status.success());
+        if runs {
+            Ok(())
+        } else {
+            Err(profiler_not_installed(profiler))
+        }
     }
 
-    /// Add dependencies to a manifest file
-    pub async fn cargo_add(&self, args: &[&str]) -> LuaResult<(String, bool)> {
-        self.execute_cargo_command_internal("add", args, None).await
+    // New file under `<target dir>/profiles/` for a run, absolute since the program may
+    // run in another directory; the directory is created
+    async fn profile_path(&self, profiler: Profiler, args: &[&str]) -> LuaResult<PathBuf> {
+        let dir = self.resolved_target_dir("run", args).await.join("profiles");
+        std::fs::create_dir_all(&dir).map_err(|e| {
+            LuaError::RuntimeError(format!("Failed to create {}: {}", dir.display(), e))
+        })?;
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        // Runs started within the same second, from this or another editor, get their own
+        Ok(dir.join(format!(
+            "{}-{}-{}-{}.{}",
+            profiler.name(),
+            secs,
+            std::process::id(),
+            FILE_COUNT.fetch_add(1, Ordering::Relaxed),
+            profiler.extension()
+        )))
     }
 
-    /// Remove dependencies from a manifest file
-    pub async fn cargo_remove(&self, args: &[&str]) -> LuaResult<(String, bool)> {
-        self.execute_cargo_command_internal("remove", args, None)
-            .await
-    }
+    /// Run the project
+    /// With `run_dir`, the program runs in that directory instead of cargo's
+    /// With `profile_with`, it runs under that profiler and details.profile has the path
+    /// With `backtrace_on_panic`, a panic without a backtrace is followed by a second run
+    /// with `RUST_BACKTRACE=1`, whose output is returned instead
+    pub async fn cargo_run(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        let (commands, program_dir, profile) = self.with_runner(args).await?;
+        // Designed to support interactive programs
+        let mut outcome = commands
+            .execute_cargo_command_internal("run", args, None)
+            .await?;
 
-    /// Format Rust code
-    pub async fn cargo_fmt(&self, args: &[&str]) -> LuaResult<(String, bool)> {
-        self.execute_cargo_command_internal("fmt", args, None).await
-    }
+        let needs_backtrace = self.options.backtrace_on_panic
+            && !outcome.interrupted
+            && parse_panic(&outcome.output).is_some()
+            && !has_backtrace(&outcome.output);
+        if needs_backtrace {
+            outcome = commands
+                .with_env("RUST_BACKTRACE", "1")
+                .execute_cargo_command_internal("run", args, None)
+                .await?;
+            outcome.backtrace_rerun = true;
+        }
+        outcome.program_dir = program_dir;
+        outcome.profile = profile;
 
-    /// Run the Clippy linter
-    pub async fn cargo_clippy(&self, args: &[&str]) -> LuaResult<(String, bool)> {
-        self.execute_cargo_command_internal("clippy", args, None)
-            .await
+        // Surface a panic of the program as a structured location
+        outcome.panic = parse_panic(&outcome.output);
+        if let Some(panic) = &outcome.panic {
+            outcome.quickfix.push(panic.to_quickfix());
+        }
+
+        // Check if proconio is likely being used by examining Cargo.toml
+        // This is important for competitive programming scenarios where proconio::input! is common
+        // The manifest is the one cargo used: `--manifest-path` in args, the
+        // `manifest_path` option, or the one found from `cwd`
+        let has_proconio = self
+            .manifest_for(args)
+            .and_then(|manifest| std::fs::read_to_string(manifest).ok())
+            .is_some_and(|content| content.contains("proconio"));
+
+        // If proconio is used, force interactive mode (unless the option says otherwise)
+        if has_proconio && self.options.interactive.is_none() {
+            outcome.interactive = true;
+        }
+
+        Ok(outcome)
     }
 
-    /// Automatically fix lint warnings
-    pub async fn cargo_fix(&self, args: &[&str]) -> LuaResult<(String, bool)> {
-        self.execute_cargo_command_internal("fix", args, None).await
+    /// Run the tests
+    /// With the `format` option the result carries a JUnit-shaped `test_report`, from
+    /// cargo-nextest when it's installed (args are then passed to `cargo nextest run`);
+    /// failing tests are reported there, and the call only fails when no test ran
+    /// With `split_doctests` the doctests run as a second step, see `cargo_test_split`
+    pub async fn cargo_test(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        if self.options.split_doctests {
+            return self.cargo_test_split(args).await;
+        }
+        self.run_tests(args).await
     }
 
-    /// Package and upload crate to registry
-    pub async fn cargo_publish(&self, args: &[&str]) -> LuaResult<(String, bool)> {
-        self.execute_cargo_command_internal("publish", args, None)
-            .await
+    // `cargo test`, or nextest for a report, with the options of `cargo_test`
+    async fn run_tests(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        if self.options.format.is_none() {
+            return self.execute_cargo_command_smart("test", args).await;
+        }
+        if self.is_installed("nextest", false).await {
+            return self.cargo_nextest_report(args).await;
+        }
+
+        let outcome = self.spawn_cargo_command("test", args, None).await?;
+        let limitations = vec![
+            "cargo-nextest isn't installed: per-test times aren't available, and a failing \
+             test binary stops the run unless --no-fail-fast is passed"
+                .to_string(),
+        ];
+        let report = TestReport::new("libtest", parse_libtest(&outcome.output), limitations);
+        with_test_report(outcome, "cargo test", report)
     }
 
-    /// Install a Rust binary
-    pub async fn cargo_install(&self, args: &[&str]) -> LuaResult<(String, bool)> {
-        self.execute_cargo_command_internal("install", args, None)
-            .await
+    /// Whether `cargo <name>` is available, built in or as a `cargo-<name>` plugin, in the
+    /// directory and toolchain the options select; plugin names such as `llvm_cov` are
+    /// accepted for their subcommand (`llvm-cov`)
+    /// The `cargo --list` read for the directory and toolchain is reused unless `refresh`
+    pub async fn is_installed(&self, name: &str, refresh: bool) -> bool {
+        let name = name.replace('_', "-");
+        self.installed_subcommands(refresh).await.contains(&name)
     }
 
-    /// Uninstall a Rust binary
-    pub async fn cargo_uninstall(&self, args: &[&str]) -> LuaResult<(String, bool)> {
-        self.execute_cargo_command_internal("uninstall", args, None)
-            .await
+    // The subcommands `cargo --list` shows, less the rustup proxies whose component is
+    // missing; cached, see `installed`
+    async fn installed_subcommands(&self, refresh: bool) -> BTreeSet<String> {
+        let key = self.options.cargo_key(self.cargo_dir());
+        if !refresh {
+            if let Some(list) = installed::cached(&key) {
+                return list;
+            }
+        }
+        let Ok((mut cmd, _)) = self.options.command("cargo") else {
+            return BTreeSet::new();
+        };
+        let output = match cmd.arg("--list").stderr(Stdio::null()).output().await {
+            Ok(output) if output.status.success() => output,
+            // Not kept, so the next call tries again
+            _ => return BTreeSet::new(),
+        };
+        let mut list = installed::parse_list(&String::from_utf8_lossy(&output.stdout));
+        for proxy in installed::RUSTUP_PROXIES {
+            if list.contains(*proxy) && !self.subcommand_runs(proxy).await {
+                list.remove(*proxy);
+            }
+        }
+        installed::store(key, list.clone());
+        list
     }
 
-    /// Search packages in registry
-    pub async fn cargo_search(&self, args: &[&str]) -> LuaResult<(String, bool)> {
-        self.execute_cargo_command_internal("search", args, None)
+    // Whether `cargo <name> --version` succeeds
+    async fn subcommand_runs(&self, name: &str) -> bool {
+        let Ok((mut cmd, _)) = self.options.command("cargo") else {
+            return false;
+        };
+        cmd.args([name, "--version"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
             .await
+            .is_ok_and(|status| status.success())
+    }
+
+    // Fails with the install hint when the cargo subcommand `name` isn't installed; a
+    // tool missing from the cached list is looked up again, as it may have been
+    // installed since
+    async fn ensure_subcommand(&self, name: &str) -> LuaResult<()> {
+        if self.is_installed(name, false).await || self.is_installed(name, true).await {
+            Ok(())
+        } else {
+            Err(subcommand_not_installed(name))
+        }
+    }
+
+    // `cargo nextest run` with its JUnit output enabled through a tool config file, which
+    // is layered over the project's own nextest config
+    async fn cargo_nextest_report(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        let profile = nextest_profile(args).unwrap_or("default");
+        validate_component("nextest profile", profile)?;
+        let target_dir = self.resolved_target_dir("test", args).await;
+        let junit_path = target_dir.join("nextest").join(profile).join("junit.xml");
+        // A report left from an earlier run must not pass for this one's
+        let _ = std::fs::remove_file(&junit_path);
+
+        let config = std::env::temp_dir().join(format!(
+            "cargo-nvim-nextest-{}-{}.toml",
+            std::process::id(),
+            FILE_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(
+            &config,
+            format!("[profile.{:?}.junit]\npath = \"junit.xml\"\n", profile),
+        )
+        .map_err(|e| {
+            LuaError::RuntimeError(format!("Failed to write the nextest config: {}", e))
+        })?;
+        let tool_config = format!("cargo-nvim:{}", config.display());
+        let target_dir = target_dir.to_string_lossy().into_owned();
+        let mut full_args = vec!["run", "--tool-config-file", tool_config.as_str()];
+        if !args.iter().any(|a| a.starts_with("--target-dir")) {
+            full_args.extend(["--target-dir", target_dir.as_str()]);
+        }
+        full_args.extend_from_slice(args);
+
+        let outcome = self.spawn_cargo_command("nextest", &full_args, None).await;
+        let _ = std::fs::remove_file(&config);
+        let outcome = outcome?;
+        let suites = std::fs::read_to_string(&junit_path)
+            .map(|xml| parse_junit(&xml))
+            .unwrap_or_default();
+        with_test_report(
+            outcome,
+            "cargo nextest run",
+            TestReport::new("nextest", suites, Vec::new()),
+        )
+    }
+
+    /// Run only the documentation tests
+    /// Failing doctests don't make this an error: they are reported in `doctests`
+    /// and `quickfix`, and the call only fails when no doctest could be run
+    pub async fn cargo_test_doc(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        let mut full_args = vec!["--doc"];
+        full_args.extend_from_slice(args);
+        let mut outcome = self.spawn_cargo_command("test", &full_args, None).await?;
+        if outcome.interrupted {
+            return Ok(outcome);
+        }
+
+        outcome.doctests = parse_doctests(&outcome.output);
+        if !outcome.success && outcome.doctests.is_empty() {
+            return Err(Error::CommandFailed {
+                command: "cargo test --doc".to_string(),
+                details: outcome.output,
+            }
+            .into());
+        }
+        outcome.quickfix = outcome
+            .doctests
+            .iter()
+            .filter_map(|doctest| doctest.to_quickfix())
+            .collect();
+        Ok(outcome)
+    }
+
+    /// Run the unit and integration tests (`--tests`), then the doctests, as two steps
+    /// (`step_start`/`step_finish` events), for `split_doctests`
+    /// The result is that of the tests, with the doctests in `doctests` and their output
+    /// in `doctest_output`; their quickfix entries come after those of the tests. As with
+    /// `cargo test`, the doctests don't run once the other tests have failed
+    async fn cargo_test_split(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        self.emit(Event::StepStart {
+            index: 0,
+            subcommand: "test".to_string(),
+        });
+        let mut test_args = vec!["--tests"];
+        test_args.extend_from_slice(args);
+        let tests = self.run_tests(&test_args).await;
+        self.emit(Event::StepFinish {
+            index: 0,
+            subcommand: "test".to_string(),
+            success: tests.as_ref().is_ok_and(|outcome| outcome.success),
+        });
+        let mut outcome = tests?;
+        if !outcome.success || outcome.interrupted {
+            return Ok(outcome);
+        }
+
+        self.emit(Event::StepStart {
+            index: 1,
+            subcommand: "test_doc".to_string(),
+        });
+        let doctests = match self.cargo_test_doc(args).await {
+            // A package of binaries only has no doctests
+            Err(err) if err.to_string().contains("no library targets found") => {
+                Ok(CommandOutcome {
+                    success: true,
+                    ..Default::default()
+                })
+            }
+            result => result,
+        };
+        self.emit(Event::StepFinish {
+            index: 1,
+            subcommand: "test_doc".to_string(),
+            success: doctests.as_ref().is_ok_and(|outcome| outcome.success),
+        });
+        let doctests = doctests?;
+
+        outcome.success = doctests.success;
+        outcome.interrupted = doctests.interrupted;
+        outcome.termination = doctests.termination;
+        outcome.quickfix.extend(doctests.quickfix);
+        outcome.doctests = doctests.doctests;
+        outcome.doctest_output = Some(doctests.output);
+        Ok(outcome)
+    }
+
+    /// Clean the target directory
+    /// The result reports how much space was freed, measured on the part of the target
+    /// directory the clean applies to
+    pub async fn cargo_clean(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        let scope = clean_scope(&self.resolved_target_dir("clean", args).await, args)?;
+        let measure = |dir: PathBuf| async move {
+            tokio::task::spawn_blocking(move || dir_size(&dir))
+                .await
+                .unwrap_or(0)
+        };
+        let before = measure(scope.clone()).await;
+        let mut outcome = self
+            .execute_cargo_command_internal("clean", args, None)
+            .await?;
+        let after = measure(scope.clone()).await;
+        outcome.clean = Some(CleanResult {
+            dir: scope.to_string_lossy().into_owned(),
+            freed_bytes: before.saturating_sub(after),
+        });
+        Ok(outcome)
+    }
+
+    // The target directory `subcommand` works on: `--target-dir` in the arguments, the
+    // configured one, else the one cargo metadata reports (which knows the workspace root,
+    // `CARGO_TARGET_DIR` and cargo config); absolute unless the current directory is gone
+    async fn resolved_target_dir(&self, subcommand: &str, args: &[&str]) -> PathBuf {
+        if let Some(dir) = self.explicit_target_dir(subcommand, args) {
+            return dir;
+        }
+        self.quiet_metadata(args)
+            .await
+            .and_then(|metadata| metadata.target_directory)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| self.in_cargo_dir(target_dir(subcommand, &Default::default())))
+    }
+
+    // `path` relative to the directory cargo runs in
+    fn in_cargo_dir(&self, path: impl AsRef<Path>) -> PathBuf {
+        match (self.cargo_dir(), &self.options.cwd) {
+            (Some(dir), _) => dir.join(path),
+            (None, Some(cwd)) => Path::new(cwd).join(path),
+            (None, None) => path.as_ref().to_path_buf(),
+        }
+    }
+
+    // `--target-dir` in the arguments, else the configured one, in the directory cargo
+    // runs in
+    fn explicit_target_dir(&self, subcommand: &str, args: &[&str]) -> Option<PathBuf> {
+        let dir = args
+            .iter()
+            .take_while(|a| **a != "--")
+            .position(|a| *a == "--target-dir")
+            .and_then(|at| args.get(at + 1))
+            .map(|dir| dir.to_string())
+            .or_else(|| target_dir_override(subcommand, &self.options))?;
+        Some(self.in_cargo_dir(dir))
+    }
+
+    /// Generate documentation
+    /// The result lists each workspace member's index and picks the one to open; the
+    /// `package` option selects a member
+    pub async fn cargo_doc(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        let mut outcome = self
+            .execute_cargo_command_internal("doc", args, None)
+            .await?;
+        if outcome.interrupted {
+            return Ok(outcome);
+        }
+        let generated = parse_generated(&outcome.output);
+        let doc_dir = match generated.as_deref().and_then(|p| p.parent()?.parent()) {
+            Some(dir) => dir.to_path_buf(),
+            None => target_dir("doc", &self.options).join("doc"),
+        };
+
+        // Members and their target names
+        let metadata = self.quiet_metadata(args).await;
+        outcome.doc = Some(resolve_doc_index(
+            &doc_dir,
+            metadata.as_ref(),
+            generated.as_deref(),
+            self.options.package.as_deref(),
+        )?);
+        Ok(outcome)
+    }
+
+    /// Examples of the workspace members, from `cargo metadata --no-deps`
+    pub async fn cargo_examples(&self, args: &[&str]) -> LuaResult<Vec<ExampleTarget>> {
+        let mut metadata_args = vec!["--no-deps"];
+        metadata_args.extend(manifest_path_args(args));
+        let outcome = self
+            .without_tool_args()
+            .cargo_metadata(&metadata_args)
+            .await?;
+        Ok(outcome
+            .metadata
+            .as_ref()
+            .map(list_examples)
+            .unwrap_or_default())
+    }
+
+    /// Members of the workspace with their manifests, from `cargo metadata --no-deps`,
+    /// to pick one for the `manifest_path` option
+    pub async fn cargo_workspace_members(&self, args: &[&str]) -> LuaResult<Vec<WorkspaceMember>> {
+        let mut metadata_args = vec!["--no-deps"];
+        metadata_args.extend(manifest_path_args(args));
+        let outcome = self
+            .without_tool_args()
+            .cargo_metadata(&metadata_args)
+            .await?;
+        Ok(outcome
+            .metadata
+            .as_ref()
+            .map(list_workspace_members)
+            .unwrap_or_default())
+    }
+
+    /// Run an example with `cargo run --example <name>`; the `package` option picks the
+    /// member it belongs to
+    /// An example whose required features aren't enabled fails with `missing_features`
+    pub async fn cargo_run_example(&self, name: &str, args: &[&str]) -> LuaResult<CommandOutcome> {
+        // Joined so a name starting with `-` can't be taken for a flag
+        let example = format!("--example={}", name);
+        let mut full_args = vec![example.as_str()];
+        if let Some(package) = &self.options.package {
+            full_args.extend(["--package", package.as_str()]);
+        }
+        full_args.extend_from_slice(args);
+        self.cargo_run(&full_args)
+            .await
+            .map(CommandOutcome::with_colors)
+    }
+
+    /// Create a new package
+    pub async fn cargo_new(&self, name: &str, args: &[&str]) -> LuaResult<CommandOutcome> {
+        let mut full_args = vec![name];
+        full_args.extend_from_slice(args);
+        let mut outcome = self
+            .execute_cargo_command_internal("new", &full_args, None)
+            .await?;
+        outcome.created = self.created_package(&outcome.output, init_path(&full_args), args);
+        Ok(outcome)
+    }
+
+    /// Update dependencies
+    pub async fn cargo_update(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        self.execute_cargo_command_internal("update", args, None)
+            .await
+    }
+
+    // Additional Cargo Commands
+
+    /// Initialize a new package in an existing directory
+    pub async fn cargo_init(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        let mut outcome = self
+            .execute_cargo_command_internal("init", args, None)
+            .await?;
+        outcome.created = self.created_package(&outcome.output, init_path(args), args);
+        Ok(outcome)
+    }
+
+    // The package new or init created at `path`, relative to the directory cargo ran in
+    fn created_package(&self, output: &str, path: &str, args: &[&str]) -> Option<CreatedPackage> {
+        let dir = self.cargo_dir()?.join(path);
+        let dir = dir.canonicalize().unwrap_or(dir);
+        parse_created(output, &dir, args)
+    }
+
+    /// Add dependencies to a manifest file
+    /// The result lists each added dependency with the features cargo reported for it
+    pub async fn cargo_add(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        let mut outcome = self
+            .execute_cargo_command_internal("add", args, None)
+            .await?;
+        outcome.added = parse_add_output(&outcome.output);
+        Ok(outcome)
+    }
+
+    /// Remove dependencies from a manifest file
+    pub async fn cargo_remove(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        self.execute_cargo_command_internal("remove", args, None)
+            .await
+    }
+
+    /// Format Rust code
+    /// With the `files` option, only those files are formatted (see `format_files`)
+    pub async fn cargo_fmt(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        if !self.options.files.is_empty() {
+            return self.format_files(&self.options.files, args).await;
+        }
+        self.execute_cargo_command_internal("fmt", args, None).await
+    }
+
+    /// Run the Clippy linter
+    /// Lint options (`deny_warnings`, `warn`, `allow`) are appended after `--`
+    pub async fn cargo_clippy(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        self.execute_cargo_command_internal("clippy", args, None)
+            .await
+    }
+
+    /// Automatically fix lint warnings
+    pub async fn cargo_fix(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        self.execute_cargo_command_internal("fix", args, None).await
+    }
+
+    /// Package and upload crate to registry
+    pub async fn cargo_publish(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        self.execute_cargo_command_internal("publish", args, None)
+            .await
+    }
+
+    /// Install a Rust binary
+    /// The installed subcommands are looked up again afterwards
+    pub async fn cargo_install(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        let outcome = self
+            .execute_cargo_command_internal("install", args, None)
+            .await;
+        installed::forget();
+        outcome
+    }
+
+    /// Uninstall a Rust binary
+    /// The installed subcommands are looked up again afterwards
+    pub async fn cargo_uninstall(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        let outcome = self
+            .execute_cargo_command_internal("uninstall", args, None)
+            .await;
+        installed::forget();
+        outcome
+    }
+
+    /// Search packages in registry
+    pub async fn cargo_search(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        let mut outcome = self
+            .execute_cargo_command_internal("search", args, None)
+            .await?;
+        let limit = self.options.limit.map(|l| l.min(MAX_LIMIT) as usize);
+        outcome.search = Some(parse_search_output(&outcome.output, limit));
+        Ok(outcome)
     }
 
     /// Display dependency tree
-    pub async fn cargo_tree(&self, args: &[&str]) -> LuaResult<(String, bool)> {
+    /// With the `invert` option, shows what depends on a package instead and reports
+    /// the top-level dependencies that pull it in
+    /// The tree is parsed as the output is read and published as `tree_node` events
+    pub async fn cargo_tree(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
         self.execute_cargo_command_internal("tree", args, None)
             .await
     }
 
-    /// Vendor all dependencies locally
-    pub async fn cargo_vendor(&self, args: &[&str]) -> LuaResult<(String, bool)> {
-        self.execute_cargo_command_internal("vendor", args, None)
+    /// Download the dependencies without building, publishing `downloading` events
+    pub async fn cargo_fetch(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        self.execute_cargo_command_internal("fetch", args, None)
             .await
     }
 
+    /// Vendor all dependencies locally
+    /// Extra manifests from the `sync` option are vendored too; the config snippet
+    /// cargo prints is returned separately so it can be written automatically
+    pub async fn cargo_vendor(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        let mut outcome = self
+            .execute_cargo_command_internal("vendor", args, None)
+            .await?;
+        // The same arguments cargo got, to find the vendor directory in
+        let full_args = self.options.command_args("vendor", args)?;
+        let full_args: Vec<&str> = full_args.iter().map(|s| s.as_str()).collect();
+        outcome.vendor = Some(parse_vendor_output(&outcome.output, &full_args));
+        Ok(outcome)
+    }
+
+    /// Describe the packages of the workspace, including their features
+    /// `--format-version 1` is added; pass `--no-deps` to skip the dependencies
+    pub async fn cargo_metadata(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        let mut full_args = vec!["--format-version", "1"];
+        full_args.extend_from_slice(args);
+        // The JSON can contain anything, including what looks like a prompt
+        let commands = self.with_options(CommandOptions {
+            interactive: Some(false),
+            ..self.options.clone()
+        });
+        let mut outcome = commands
+            .execute_cargo_command_internal("metadata", &full_args, None)
+            .await?;
+        outcome.metadata = Metadata::from_output(&outcome.output);
+        // The JSON is returned structured; keep only what cargo printed around it
+        outcome.edit_output(|output| {
+            output
+                .lines()
+                .filter(|line| !line.starts_with('{'))
+                .collect::<Vec<_>>()
+                .join("\n")
+        });
+        Ok(outcome)
+    }
+
     /// Audit dependencies for security vulnerabilities
-    pub async fn cargo_audit(&self, args: &[&str]) -> LuaResult<(String, bool)> {
+    pub async fn cargo_audit(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
         self.execute_cargo_command_internal("audit", args, None)
             .await
     }
 
     /// Show outdated dependencies
-    pub async fn cargo_outdated(&self, args: &[&str]) -> LuaResult<(String, bool)> {
+    pub async fn cargo_outdated(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
         self.execute_cargo_command_internal("outdated", args, None)
             .await
     }
 
     /// Get Cargo help
-    pub async fn cargo_help(&self, args: &[&str]) -> LuaResult<(String, bool)> {
+    pub async fn cargo_help(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
         self.execute_cargo_command_internal("help", args, None)
             .await
     }
 
+    /// Get the flags of a subcommand parsed from its `--help` output
+    /// Falls back to the raw text when the output can't be parsed
+    pub async fn cargo_help_options(&self, subcommand: Option<&str>) -> LuaResult<HelpText> {
+        let outcome = match subcommand {
+            Some(name) => {
+                self.execute_cargo_command_internal(name, &["--help"], None)
+                    .await?
+            }
+            None => {
+                self.execute_cargo_command_internal("help", &[], None)
+                    .await?
+            }
+        };
+        Ok(HelpText::from_output(subcommand, &outcome.output))
+    }
+
+    /// Run the tests with coverage through `cargo llvm-cov`, returning the line, region
+    /// and function percentages in `coverage`
+    /// With the `html` option, the HTML report is also written, from the same run's
+    /// profile data, and its index returned as `coverage.html`
+    pub async fn cargo_llvm_cov(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        self.ensure_subcommand("llvm-cov").await?;
+        let mut outcome = self
+            .execute_cargo_command_internal("llvm-cov", args, None)
+            .await?;
+        outcome.coverage = parse_coverage_summary(&outcome.output);
+        if !self.options.html {
+            return Ok(outcome);
+        }
+
+        let mut report_args = vec!["report", "--html"];
+        report_args.extend(manifest_path_args(args));
+        // The report runs no tests
+        self.without_tool_args()
+            .execute_cargo_command_internal("llvm-cov", &report_args, None)
+            .await?;
+        let index = self
+            .resolved_target_dir("llvm-cov", args)
+            .await
+            .join("llvm-cov")
+            .join("html")
+            .join("index.html");
+        if let Some(coverage) = &mut outcome.coverage {
+            coverage.html = Some(index.to_string_lossy().into_owned());
+        }
+        Ok(outcome)
+    }
+
     /// Run cargo-autodd command
-    pub async fn cargo_autodd(&self, _args: &[&str]) -> LuaResult<(String, bool)> {
-        // テスト環境では常にエラーを返す
-        #[cfg(test)]
-        return Err(LuaError::RuntimeError(
-            "cargo-autodd is not installed. Please install it with 'cargo install cargo-autodd'"
-                .to_string(),
-        ));
+    pub async fn cargo_autodd(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        self.ensure_subcommand("autodd").await?;
+        self.execute_cargo_command_internal("autodd", args, None)
+            .await
+    }
+}
 
-        // 実環境ではインストール確認を行う
-        #[cfg(not(test))]
-        {
-            // Check if cargo-autodd is installed
-            let check_output = std::process::Command::new("cargo")
-                .arg("--list")
-                .output()
-                .map_err(|e| {
-                    LuaError::RuntimeError(format!("Failed to check cargo commands: {}", e))
-                })?;
-
-            let output_str = String::from_utf8_lossy(&check_output.stdout);
-            if !output_str.contains("autodd") {
-                return Err(LuaError::RuntimeError(
-                    "cargo-autodd is not installed. Please install it with 'cargo install cargo-autodd'"
-                        .to_string(),
-                ));
-            }
+// `--manifest-path <path>` from a command's arguments, to run another command on the
+// same workspace
+fn manifest_path_args<'a>(args: &[&'a str]) -> Vec<&'a str> {
+    let args: Vec<&str> = args.iter().copied().take_while(|a| *a != "--").collect();
+    match args.iter().position(|a| a.starts_with("--manifest-path")) {
+        Some(at) if args[at] == "--manifest-path" => args[at..].iter().take(2).copied().collect(),
+        Some(at) => vec![args[at]],
+        None => Vec::new(),
+    }
+}
 
-            self.execute_cargo_command_internal("autodd", _args, None)
-                .await
+// Attach a test report; a run that reported no tests failed before testing (a build
+// error), which stays an error
+fn with_test_report(
+    mut outcome: CommandOutcome,
+    command: &str,
+    report: TestReport,
+) -> LuaResult<CommandOutcome> {
+    if !outcome.success && !outcome.interrupted && report.tests == 0 {
+        return Err(Error::CommandFailed {
+            command: command.to_string(),
+            details: outcome.output,
         }
+        .into());
+    }
+    outcome.test_report = Some(report);
+    Ok(outcome)
+}
+
+// nextest's `--profile`/`-P`, whose directory holds its JUnit file
+fn nextest_profile<'a>(args: &[&'a str]) -> Option<&'a str> {
+    let args: Vec<&str> = args.iter().copied().take_while(|a| *a != "--").collect();
+    args.iter().enumerate().find_map(|(i, arg)| match *arg {
+        "--profile" | "-P" => args.get(i + 1).copied(),
+        _ => arg.strip_prefix("--profile="),
+    })
+}
+
+// The subcommand is cargo's first argument, so it is limited to `[a-zA-Z0-9_-]+` and
+// can't start with `-`: a flag or a path there would change what cargo runs
+fn validate_subcommand(name: &str) -> LuaResult<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(LuaError::RuntimeError(format!(
+            "Invalid subcommand name: {:?}",
+            name
+        )))
+    }
+}
+
+// The error for a command that ran and failed, carrying its result
+fn command_failure(error: Error, outcome: CommandOutcome) -> LuaError {
+    CommandFailure {
+        error,
+        outcome: Box::new(outcome),
+    }
+    .into()
+}
+
+fn subcommand_not_installed(name: &str) -> LuaError {
+    Error::NotFound {
+        program: format!("cargo-{}", name),
+        details: format!("Please install it with 'cargo install cargo-{}'", name),
     }
+    .into()
+}
+
+// The toolchain cargo runs under, as reported by `rustc --version`; through rustup this
+// honors `rust-toolchain.toml` and `RUSTUP_TOOLCHAIN` just like the command does
+async fn active_toolchain(mut rustc: TokioCommand) -> Option<String> {
+    let output = rustc.arg("--version").output().await.ok()?;
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !version.is_empty()).then_some(version)
+}
+
+// Target triple of the host, from `rustc -vV`
+async fn host_triple(mut rustc: TokioCommand) -> Option<String> {
+    let output = rustc.arg("-vV").output().await.ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .map(|host| host.trim().to_string())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::signal::Termination;
+    use crate::test_report::TestFormat;
+    use crate::test_support::ScratchCrate;
+    use tokio::sync::mpsc;
 
     fn setup_test_commands() -> CargoCommands {
         CargoCommands::new().unwrap()
@@ -497,6 +1972,44 @@ mod tests {
         let result =
             rt.block_on(async { cargo_commands.execute_cargo_command("invalid", &[]).await });
         assert!(result.is_err());
+        // cargo itself ran, so this is a failed command rather than a missing program
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("[command_failed] cargo invalid failed"));
+    }
+
+    #[test]
+    fn test_rejects_invalid_subcommand_names() {
+        for name in [
+            "",
+            "--config=x",
+            "-Zunstable",
+            "../bin/sh",
+            "build;ls",
+            "test run",
+            "bü",
+        ] {
+            assert!(validate_subcommand(name).is_err(), "{:?}", name);
+        }
+        for name in ["build", "test_doc", "nextest", "llvm-cov", "fmt2"] {
+            assert!(validate_subcommand(name).is_ok(), "{:?}", name);
+        }
+
+        // Rejected before cargo is started, by every entry point taking a name
+        let cargo_commands = setup_test_commands();
+        let err = cargo_commands
+            .execute(cargo_commands.run_subcommand("--list", &[]))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("Invalid subcommand name: \"--list\""),
+            "{}",
+            err
+        );
+        assert!(cargo_commands
+            .execute(cargo_commands.cargo_help_options(Some("/usr/bin/env")))
+            .is_err());
     }
 
     #[test]
@@ -512,18 +2025,17 @@ mod tests {
     fn test_cargo_autodd() {
         let rt = tokio::runtime::Runtime::new().unwrap();
         let cargo_commands = setup_test_commands();
+        // Only checkable where cargo-autodd is missing
+        if rt.block_on(cargo_commands.is_installed("autodd", false)) {
+            return;
+        }
         let result = rt.block_on(async { cargo_commands.cargo_autodd(&[]).await });
         assert!(result.is_err());
         let err_msg = result.unwrap_err().to_string().to_lowercase();
 
-        // More flexible error message checking
+        // A missing cargo-autodd is reported as such, not as a failed command
         assert!(
-            err_msg.contains("failed to check cargo commands")
-                || err_msg.contains("cargo-autodd is not installed")
-                || err_msg.contains("no valid version found")
-                || err_msg.contains("cargo autodd failed")
-                || err_msg.contains("command not found") // For Docker environment
-                || err_msg.contains("no such file or directory"), // For Docker environment
+            err_msg.contains("[not_found] cargo-autodd is not installed"),
             "Unexpected error message: {}",
             err_msg
         );
@@ -533,6 +2045,9 @@ mod tests {
     fn test_cargo_autodd_with_args() {
         let rt = tokio::runtime::Runtime::new().unwrap();
         let cargo_commands = setup_test_commands();
+        if rt.block_on(cargo_commands.is_installed("autodd", false)) {
+            return;
+        }
         let test_args = vec![
             vec!["update"],
             vec!["report"],
@@ -547,16 +2062,1490 @@ mod tests {
             let err_msg = result.unwrap_err().to_string().to_lowercase();
 
             assert!(
-                err_msg.contains("failed to check cargo commands")
-                    || err_msg.contains("cargo-autodd is not installed")
-                    || err_msg.contains("no valid version found")
-                    || err_msg.contains("cargo autodd failed")
-                    || err_msg.contains("command not found") // For Docker environment
-                    || err_msg.contains("no such file or directory") // For Docker environment
-                    || err_msg.contains("status code 404"),
+                err_msg.contains("[not_found] cargo-autodd is not installed"),
                 "Unexpected error message: {}",
                 err_msg
             );
         }
     }
+
+    #[test]
+    fn test_llvm_cov_not_installed() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let cargo_commands = setup_test_commands();
+        // Only checkable where cargo-llvm-cov is missing
+        if rt.block_on(cargo_commands.is_installed("llvm-cov", false)) {
+            return;
+        }
+        let err = cargo_commands
+            .execute(cargo_commands.run_subcommand("llvm_cov", &[]))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains(
+                "[not_found] cargo-llvm-cov is not installed: Please install it with \
+                 'cargo install cargo-llvm-cov'"
+            ),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_nocapture_output_streams_incrementally() {
+        // A throwaway crate whose only test prints a line every 300ms
+        let scratch = ScratchCrate::new(
+            "ticker",
+            &[(
+                "src/lib.rs",
+                "#[test]\nfn ticks() {\n    for i in 0..3 {\n        println!(\"progress {}\", i);\n        std::thread::sleep(std::time::Duration::from_millis(300));\n    }\n}\n",
+            )],
+        );
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let collector = std::thread::spawn(move || {
+            let mut ticks = Vec::new();
+            while let Some(event) = rx.blocking_recv() {
+                if let Event::Stdout { line } = event {
+                    // The first print shares a line with libtest's `test ticks ... `
+                    if line.contains("progress ") {
+                        ticks.push(std::time::Instant::now());
+                    }
+                }
+            }
+            ticks
+        });
+
+        let manifest = scratch.manifest_path();
+        let cargo_commands = setup_test_commands().with_events(tx);
+        let result = cargo_commands.execute(cargo_commands.cargo_test(&[
+            "--manifest-path",
+            &manifest,
+            "--",
+            "--nocapture",
+        ]));
+        drop(cargo_commands);
+        let ticks = collector.join().unwrap();
+
+        assert!(result.is_ok(), "{:?}", result.err());
+        assert_eq!(ticks.len(), 3);
+        // Lines arrive as they are printed, not all at once when the test exits
+        assert!(ticks[2].duration_since(ticks[0]) >= Duration::from_millis(400));
+    }
+
+    #[test]
+    fn test_invalid_utf8_output_does_not_truncate() {
+        let scratch = ScratchCrate::new(
+            "binary_output",
+            &[(
+                "src/main.rs",
+                "use std::io::Write;\n\nfn main() {\n    std::io::stdout().write_all(b\"start\\n\\xff\\xfe raw\\n\").unwrap();\n    println!(\"end\");\n}\n",
+            )],
+        );
+
+        let manifest = scratch.manifest_path();
+        let cargo_commands = setup_test_commands();
+        let outcome = cargo_commands
+            .execute(
+                cargo_commands.execute_cargo_command("run", &["-q", "--manifest-path", &manifest]),
+            )
+            .unwrap();
+
+        assert!(outcome
+            .output
+            .contains("start\n\u{fffd}\u{fffd} raw\nend\n"));
+    }
+
+    #[test]
+    fn test_is_installed() {
+        let cargo_commands = setup_test_commands();
+        let installed = |name: &str, refresh: bool| {
+            cargo_commands.execute(cargo_commands.is_installed(name, refresh))
+        };
+        assert!(installed("build", false));
+        assert!(installed("b", false));
+        assert!(!installed("no-such-tool", false));
+        // From the cached list, which a refresh reads again
+        assert!(installed("build", false));
+        assert!(!installed("no-such-tool", true));
+        // Listed by cargo through rustup's proxy either way, but only there when the
+        // component is
+        let has_clippy = cargo_commands.execute(cargo_commands.subcommand_runs("clippy"));
+        assert_eq!(installed("clippy", false), has_clippy);
+    }
+
+    #[test]
+    fn test_split_doctests() {
+        let scratch = ScratchCrate::new(
+            "split_doctests",
+            &[(
+                "src/lib.rs",
+                "/// ```\n/// assert_eq!(split_doctests::two(), 3);\n/// ```\npub fn two() -> u32 {\n    2\n}\n\n#[test]\nfn unit() {\n    assert_eq!(two(), 2);\n}\n",
+            )],
+        );
+        let manifest = scratch.manifest_path();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let cargo_commands = setup_test_commands()
+            .with_options(CommandOptions {
+                split_doctests: true,
+                ..Default::default()
+            })
+            .with_events(tx);
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_test(&["--manifest-path", &manifest]))
+            .unwrap();
+
+        assert!(!outcome.success);
+        assert!(
+            outcome.output.contains("test unit ... ok"),
+            "{}",
+            outcome.output
+        );
+        assert!(!outcome.output.contains("Doc-tests"), "{}", outcome.output);
+        let doctest_output = outcome.doctest_output.unwrap();
+        assert!(doctest_output.contains("Doc-tests"), "{}", doctest_output);
+        assert_eq!(outcome.doctests.len(), 1);
+        assert!(outcome.doctests[0].failed());
+        assert_eq!(outcome.quickfix.len(), 1);
+
+        drop(cargo_commands);
+        let mut steps = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            if let Event::StepFinish {
+                subcommand,
+                success,
+                ..
+            } = event
+            {
+                steps.push((subcommand, success));
+            }
+        }
+        assert_eq!(
+            steps,
+            vec![("test".to_string(), true), ("test_doc".to_string(), false)]
+        );
+
+        // A package of binaries only has no doctests to run
+        let scratch = ScratchCrate::new("split_bin", &[("src/main.rs", "fn main() {}\n")]);
+        let manifest = scratch.manifest_path();
+        let cargo_commands = setup_test_commands().with_options(CommandOptions {
+            split_doctests: true,
+            ..Default::default()
+        });
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_test(&["--manifest-path", &manifest]))
+            .unwrap();
+        assert!(outcome.success);
+        assert!(outcome.doctests.is_empty());
+    }
+
+    #[test]
+    fn test_build_timings_report() {
+        let scratch = ScratchCrate::new("timed", &[("src/lib.rs", "pub fn f() {}\n")]);
+        let manifest = scratch.manifest_path();
+        let cargo_commands = setup_test_commands().with_options(CommandOptions {
+            timings: true,
+            ..Default::default()
+        });
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_build(&["--manifest-path", &manifest]))
+            .unwrap();
+
+        let timings = outcome.timings.unwrap();
+        assert!(timings.html_path.unwrap().ends_with(".html"));
+        assert_eq!(timings.crates.len(), 1);
+        assert_eq!(timings.crates[0].name, "timed");
+    }
+
+    #[test]
+    fn test_interrupted_command_keeps_output() {
+        let scratch = ScratchCrate::new(
+            "interrupted",
+            &[(
+                "src/main.rs",
+                "fn main() {\n    println!(\"started\");\n    std::thread::sleep(std::time::Duration::from_secs(60));\n}\n",
+            )],
+        );
+        let manifest = scratch.manifest_path();
+        let cargo_commands = setup_test_commands();
+        cargo_commands
+            .execute(cargo_commands.cargo_build(&["-q", "--manifest-path", &manifest]))
+            .unwrap();
+        let cargo_commands = cargo_commands.with_options(CommandOptions {
+            interactive: Some(false),
+            ..Default::default()
+        });
+        let run = |reason: CancelReason| {
+            let cancel = CancelToken::default();
+            let stopper = cancel.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_secs(2));
+                stopper.cancel_with(reason);
+            });
+            let commands = cargo_commands.with_cancel(cancel);
+            commands.execute(commands.cargo_run(&["-q", "--manifest-path", &manifest]))
+        };
+
+        // Stopped by the user: a result, neither failed nor timed out
+        let outcome = run(CancelReason::Interrupted).unwrap();
+        assert!(outcome.interrupted);
+        assert!(!outcome.success);
+        assert_eq!(outcome.termination, Some(Termination::Graceful));
+        assert!(outcome.output.contains("started"), "{}", outcome.output);
+
+        // Replaced by a newer command: still an error
+        let err = run(CancelReason::Superseded).unwrap_err().to_string();
+        assert!(err.contains("was cancelled"), "{}", err);
+    }
+
+    #[test]
+    fn test_examples() {
+        let scratch = ScratchCrate::new(
+            "examples",
+            &[
+                (
+                    "Cargo.toml",
+                    "[package]\nname = \"examples\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[workspace]\n\n[features]\nfancy = []\n\n[[example]]\nname = \"fancy\"\nrequired-features = [\"fancy\"]\n",
+                ),
+                ("src/lib.rs", ""),
+            ],
+        );
+        let manifest = scratch.manifest_path();
+        let dir = std::path::Path::new(&manifest)
+            .parent()
+            .unwrap()
+            .join("examples");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("fancy.rs"), "fn main() { println!(\"fancy\"); }\n").unwrap();
+        std::fs::write(dir.join("plain.rs"), "fn main() { println!(\"plain\"); }\n").unwrap();
+
+        let cargo_commands = setup_test_commands();
+        let mut examples = cargo_commands
+            .execute(cargo_commands.cargo_examples(&["--manifest-path", &manifest]))
+            .unwrap();
+        examples.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(examples.len(), 2);
+        assert_eq!(examples[0].name, "fancy");
+        assert_eq!(examples[0].required_features, vec!["fancy"]);
+        assert!(examples[1].src_path.ends_with("plain.rs"));
+
+        let run = |name: &str, args: &[&str]| {
+            let mut full_args = vec!["-q", "--manifest-path", &manifest];
+            full_args.extend_from_slice(args);
+            cargo_commands.execute(cargo_commands.cargo_run_example(name, &full_args))
+        };
+        assert!(run("plain", &[]).unwrap().output.contains("plain"));
+        let err = run("fancy", &[]).unwrap_err().to_string();
+        assert!(
+            err.contains(
+                "[missing_features] cargo run: target `fancy` requires the features fancy"
+            ),
+            "{}",
+            err
+        );
+        assert!(run("fancy", &["--features", "fancy"])
+            .unwrap()
+            .output
+            .contains("fancy"));
+    }
+
+    // Whether the process is gone (or a zombie waiting to be reaped by init)
+    fn process_gone(pid: &str) -> bool {
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid.trim()));
+        stat.map_or(true, |stat| stat.contains(") Z "))
+    }
+
+    #[test]
+    fn test_fake_command_timeout_kills_group() {
+        let pid_file = std::env::temp_dir().join(format!("cargo-nvim-fake-{}", std::process::id()));
+        let script = format!(
+            "sleep 30 & echo \"$$ $!\" > {}; echo partial; wait",
+            pid_file.display()
+        );
+        let cargo_commands = setup_test_commands()
+            .with_fake_command(&script)
+            .with_options(CommandOptions {
+                timeout_secs: Some(1),
+                ..Default::default()
+            });
+        let err = cargo_commands
+            .execute(cargo_commands.execute_cargo_command("build", &[]))
+            .unwrap_err();
+        assert!(
+            err.to_string().contains(
+                "[timed_out] cargo build timed out after 1 seconds; it exited gracefully"
+            ),
+            "{}",
+            err
+        );
+        // What was printed before the timeout comes back with the error
+        let failure = err.downcast_ref::<CommandFailure>().unwrap();
+        assert_eq!(failure.outcome.output, "partial\n");
+        // Both the stand-in for cargo and the process it started are gone
+        let pids = std::fs::read_to_string(&pid_file).unwrap();
+        let _ = std::fs::remove_file(&pid_file);
+        for pid in pids.split_whitespace() {
+            assert!(process_gone(pid), "process {} survived", pid);
+        }
+    }
+
+    #[test]
+    fn test_check_fast_failure_details() {
+        // A failure without diagnostics comes back like one of `check`, with its result
+        let cargo_commands = setup_test_commands()
+            .with_fake_command("echo 'error: failed to load source for dependency'; exit 101");
+        let err = cargo_commands
+            .execute(cargo_commands.cargo_check_fast(&["--keep-going"]))
+            .unwrap_err();
+        let failure = err.downcast_ref::<CommandFailure>().unwrap();
+        assert_eq!(failure.error.kind(), "command_failed");
+        assert_eq!(failure.outcome.exit_code, Some(101));
+        assert!(!failure.outcome.success);
+        assert_eq!(
+            failure.outcome.output,
+            "error: failed to load source for dependency\n"
+        );
+    }
+
+    #[test]
+    fn test_fake_command_interrupt_and_forced_kill() {
+        let stop = |commands: CargoCommands, reason: CancelReason| {
+            let cancel = CancelToken::default();
+            let stopper = cancel.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(500));
+                stopper.cancel_with(reason);
+            });
+            let commands = commands.with_cancel(cancel);
+            commands.execute(commands.execute_cargo_command("build", &["--release"]))
+        };
+
+        // The output up to the interrupt is returned, including the arguments given
+        let commands = setup_test_commands().with_fake_command("echo \"partial $*\"; sleep 30");
+        let outcome = stop(commands, CancelReason::Interrupted).unwrap();
+        assert!(outcome.interrupted);
+        assert_eq!(outcome.output, "partial build --release\n");
+
+        // Ignoring SIGTERM gets it killed once kill_timeout_ms is over
+        let commands = setup_test_commands()
+            .with_fake_command("trap '' TERM; echo partial; sleep 30; :")
+            .with_options(CommandOptions {
+                kill_timeout_ms: Some(200),
+                ..Default::default()
+            });
+        let outcome = stop(commands, CancelReason::Interrupted).unwrap();
+        assert_eq!(outcome.termination, Some(Termination::Forced));
+    }
+
+    #[test]
+    fn test_cwd_option() {
+        let scratch = ScratchCrate::new("cwd_option", &[("src/lib.rs", "")]);
+        let manifest = scratch.manifest_path();
+        let dir = std::path::Path::new(&manifest).parent().unwrap();
+        let cargo_commands = setup_test_commands().with_options(CommandOptions {
+            cwd: Some(dir.to_string_lossy().into_owned()),
+            ..Default::default()
+        });
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_metadata(&["--no-deps"]))
+            .unwrap();
+        assert_eq!(outcome.metadata.unwrap().packages[0].name, "cwd_option");
+        assert_eq!(
+            target_dir("build", &cargo_commands.options),
+            dir.join("target")
+        );
+    }
+
+    #[test]
+    fn test_report_size() {
+        let scratch = ScratchCrate::new("report_size", &[("src/main.rs", "fn main() {}\n")]);
+        let manifest = scratch.manifest_path();
+        let cargo_commands = setup_test_commands().with_options(CommandOptions {
+            report_size: true,
+            ..Default::default()
+        });
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_build(&["--manifest-path", &manifest, "--release"]))
+            .unwrap();
+        let [size] = outcome.artifact_sizes.as_slice() else {
+            panic!("expected one artifact: {:?}", outcome.artifact_sizes);
+        };
+        assert!(size.artifact.ends_with(&format!(
+            "release/report_size{}",
+            std::env::consts::EXE_SUFFIX
+        )));
+        assert!(size.bytes > 0 && size.gzip_bytes < size.bytes);
+    }
+
+    #[test]
+    fn test_junit_report() {
+        let scratch = ScratchCrate::new(
+            "junit_report",
+            &[(
+                "src/lib.rs",
+                "#[test]\nfn passes() {}\n\n#[test]\nfn fails() {\n    assert_eq!(1, 2);\n}\n",
+            )],
+        );
+        let manifest = scratch.manifest_path();
+        let cargo_commands = setup_test_commands().with_options(CommandOptions {
+            format: Some(TestFormat::Junit),
+            ..Default::default()
+        });
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_test(&["--manifest-path", &manifest, "--lib"]))
+            .unwrap();
+        assert!(!outcome.success);
+        let report = outcome.test_report.unwrap();
+        assert_eq!((report.tests, report.failures), (2, 1));
+        assert!(report.junit.contains("<failure"));
+        if report.source == "libtest" {
+            assert_eq!(report.limitations.len(), 1);
+        }
+
+        // A build error isn't a report
+        std::fs::write(
+            std::path::Path::new(&manifest).with_file_name("src/lib.rs"),
+            "fn broken(",
+        )
+        .unwrap();
+        let result = cargo_commands.execute(cargo_commands.cargo_test(&[
+            "--manifest-path",
+            &manifest,
+            "--lib",
+        ]));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_default_members_are_reported() {
+        let scratch = ScratchCrate::new("default_members", &[]);
+        let manifest = scratch.manifest_path();
+        let root = std::path::Path::new(&manifest).parent().unwrap();
+        std::fs::write(
+            &manifest,
+            "[workspace]\nmembers = [\"a\", \"b\"]\ndefault-members = [\"a\"]\nresolver = \"2\"\n",
+        )
+        .unwrap();
+        for member in ["a", "b"] {
+            std::fs::create_dir_all(root.join(member).join("src")).unwrap();
+            std::fs::write(
+                root.join(member).join("Cargo.toml"),
+                format!(
+                    "[package]\nname = \"{member}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n"
+                ),
+            )
+            .unwrap();
+            std::fs::write(root.join(member).join("src/lib.rs"), "").unwrap();
+        }
+
+        let cargo_commands = setup_test_commands();
+        let check = |args: &[&str]| {
+            cargo_commands
+                .execute(cargo_commands.run_subcommand("check", args))
+                .unwrap()
+        };
+        let outcome = check(&["--manifest-path", &manifest]);
+        assert_eq!(outcome.members, Some(vec!["a".to_string()]));
+        let metadata = cargo_commands
+            .execute(cargo_commands.cargo_metadata(&["--no-deps", "--manifest-path", &manifest]))
+            .unwrap()
+            .metadata
+            .unwrap();
+        assert_eq!(metadata.workspace_default_members, vec!["a"]);
+
+        // An explicit selection is the user's own
+        let outcome = check(&["--manifest-path", &manifest, "--workspace"]);
+        assert_eq!(outcome.members, None);
+
+        // tool_args are the test binaries' own, not those of the metadata behind `members`
+        let with_tool_args = cargo_commands.with_options(CommandOptions {
+            tool_args: vec!["--list".to_string()],
+            ..Default::default()
+        });
+        let outcome = with_tool_args
+            .execute(with_tool_args.run_subcommand("test", &["--manifest-path", &manifest]))
+            .unwrap();
+        assert!(outcome.success);
+        assert_eq!(outcome.members, Some(vec!["a".to_string()]));
+
+        // A member picked from the list, run through the manifest_path option
+        let members = cargo_commands
+            .execute(cargo_commands.cargo_workspace_members(&["--manifest-path", &manifest]))
+            .unwrap();
+        let names: Vec<&str> = members.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "b"]);
+        let in_member = cargo_commands.with_options(CommandOptions {
+            manifest_path: Some(members[1].manifest_path.clone()),
+            ..Default::default()
+        });
+        let outcome = in_member
+            .execute(in_member.run_subcommand("check", &[]))
+            .unwrap();
+        assert!(outcome.success);
+        assert_eq!(outcome.members, Some(vec!["b".to_string()]));
+
+        let missing = cargo_commands.with_options(CommandOptions {
+            manifest_path: Some(root.join("c/Cargo.toml").to_string_lossy().into_owned()),
+            ..Default::default()
+        });
+        let err = missing
+            .execute(missing.run_subcommand("check", &[]))
+            .unwrap_err();
+        assert!(err.to_string().contains("Invalid manifest_path"));
+    }
+
+    #[test]
+    fn test_project_config_applies_to_run() {
+        let scratch = ScratchCrate::new(
+            "project_config_run",
+            &[
+                (
+                    "src/main.rs",
+                    "fn main() { println!(\"greet: {}\", cfg!(feature = \"greet\")); }",
+                ),
+                (
+                    ".cargo-nvim.toml",
+                    "features = [\"greet\"]\ndenied_commands = [\"publish\"]\n",
+                ),
+            ],
+        );
+        let manifest = scratch.manifest_path();
+        let contents = std::fs::read_to_string(&manifest).unwrap();
+        std::fs::write(&manifest, contents + "\n[features]\ngreet = []\n").unwrap();
+        let root = scratch.path("").to_string_lossy().into_owned();
+
+        let in_project = setup_test_commands().with_options(CommandOptions {
+            cwd: Some(root),
+            ..Default::default()
+        });
+        let outcome = in_project
+            .execute(in_project.run_subcommand("run", &[]))
+            .unwrap();
+        assert!(outcome.output.contains("greet: true"), "{}", outcome.output);
+
+        let err = in_project
+            .execute(in_project.run_subcommand("publish", &["--dry-run"]))
+            .unwrap_err();
+        assert!(err.to_string().contains("[command_denied]"), "{}", err);
+    }
+
+    #[test]
+    fn test_program_waiting_for_input_times_out_with_hint() {
+        let scratch = ScratchCrate::new(
+            "waits_for_input",
+            &[(
+                "src/main.rs",
+                "fn main() {\n    let mut line = String::new();\n    std::io::stdin().read_line(&mut line).unwrap();\n}\n",
+            )],
+        );
+        let manifest = scratch.manifest_path();
+
+        // Build first so compiling doesn't count against the short timeout
+        let cargo_commands = setup_test_commands();
+        cargo_commands
+            .execute(cargo_commands.cargo_build(&["-q", "--manifest-path", &manifest]))
+            .unwrap();
+
+        let cargo_commands = cargo_commands.with_options(CommandOptions {
+            timeout_secs: Some(1),
+            ..Default::default()
+        });
+        let err = cargo_commands
+            .execute(cargo_commands.cargo_run(&["-q", "--manifest-path", &manifest]))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("[awaiting_input_timeout]"), "{}", err);
+        assert!(err.contains("after 3 seconds"), "{}", err);
+
+        // With detection off, run is a batch command with the normal timeout
+        let cargo_commands = cargo_commands.with_options(CommandOptions {
+            timeout_secs: Some(1),
+            interactive: Some(false),
+            ..Default::default()
+        });
+        let err = cargo_commands
+            .execute(cargo_commands.cargo_run(&["-q", "--manifest-path", &manifest]))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("timed out after 1 seconds"), "{}", err);
+        // Blocked on stdin, the program goes down with the SIGTERM
+        assert!(err.contains("it exited gracefully"), "{}", err);
+    }
+
+    #[test]
+    fn test_build_finished_decides_success() {
+        let scratch = ScratchCrate::new("build_finished", &[("src/lib.rs", "pub fn f() {}\n")]);
+        let manifest = scratch.manifest_path();
+        let cargo_commands = setup_test_commands();
+
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_build(&[
+                "--message-format=json",
+                "--manifest-path",
+                &manifest,
+            ]))
+            .unwrap();
+        assert_eq!(outcome.build_finished, Some(true));
+        assert!(outcome.success);
+
+        // Human-readable output has no verdict; the exit status is used
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_build(&["--manifest-path", &manifest]))
+            .unwrap();
+        assert_eq!(outcome.build_finished, None);
+        assert!(outcome.success);
+
+        std::fs::write(
+            std::path::Path::new(&manifest)
+                .parent()
+                .unwrap()
+                .join("src/lib.rs"),
+            "pub fn f() -> i32 {\n    \"s\"\n}\n",
+        )
+        .unwrap();
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_check_fast(&["--manifest-path", &manifest]))
+            .unwrap();
+        assert_eq!(outcome.build_finished, Some(false));
+        assert!(!outcome.success);
+    }
+
+    #[test]
+    fn test_compile_failures() {
+        let scratch = ScratchCrate::new(
+            "compile_failures",
+            &[("src/lib.rs", "pub fn f() -> i32 {\n    \"s\"\n}\n")],
+        );
+        let manifest = scratch.manifest_path();
+        let args = ["--manifest-path", manifest.as_str()];
+        let cargo_commands = setup_test_commands();
+
+        let err = cargo_commands
+            .execute(cargo_commands.cargo_build(&args))
+            .unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.contains(
+                "[compile_failed] cargo build could not compile `compile_failures` (lib, 1 error)"
+            ),
+            "{}",
+            message
+        );
+        // The error's details list them structurally
+        let failure = err.downcast_ref::<CommandFailure>().unwrap();
+        let failures: Vec<_> = failure
+            .outcome
+            .compile_failures
+            .iter()
+            .map(|f| (f.failed_crate.as_str(), f.target.as_deref(), f.error_count))
+            .collect();
+        assert_eq!(failures, vec![("compile_failures", Some("lib"), 1)]);
+
+        // Results that are returned for failed builds carry them too
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_check_fast(&args))
+            .unwrap();
+        // `--all-targets` fails the library and its test build separately
+        let mut targets: Vec<_> = outcome
+            .compile_failures
+            .iter()
+            .map(|f| (f.failed_crate.as_str(), f.target.as_deref(), f.error_count))
+            .collect();
+        targets.sort();
+        assert_eq!(
+            targets,
+            vec![
+                ("compile_failures", Some("lib"), 1),
+                ("compile_failures", Some("lib test"), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unit_graph_requires_nightly() {
+        let scratch = ScratchCrate::new("unit_graph", &[("src/lib.rs", "")]);
+        let manifest = scratch.manifest_path();
+        let args = ["--manifest-path", manifest.as_str()];
+        let stable = setup_test_commands().with_env("RUSTUP_TOOLCHAIN", "stable");
+        let err = stable
+            .execute(stable.run_subcommand("unit_graph", &args))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("[requires_nightly] unit_graph requires a nightly cargo"),
+            "{}",
+            err
+        );
+
+        // Where a nightly toolchain is installed
+        let nightly = setup_test_commands().with_env("RUSTUP_TOOLCHAIN", "nightly");
+        if nightly.execute(nightly.require_nightly("test")).is_err() {
+            return;
+        }
+        let outcome = nightly
+            .execute(nightly.run_subcommand("unit_graph", &args))
+            .unwrap();
+        let graph = outcome.unit_graph.unwrap();
+        assert_eq!(graph.units[graph.roots[0]].target.name, "unit_graph");
+        assert!(!outcome.output.contains("\"units\""), "{}", outcome.output);
+    }
+
+    #[test]
+    fn test_disabled_timeout() {
+        for options in [
+            CommandOptions {
+                timeout: Some(false),
+                ..Default::default()
+            },
+            CommandOptions {
+                timeout_secs: Some(0),
+                interactive: Some(true),
+                ..Default::default()
+            },
+        ] {
+            let commands = setup_test_commands()
+                .with_fake_command("sleep 0.2; echo done")
+                .with_options(options);
+            let outcome = commands
+                .execute(commands.spawn_cargo_command("doc", &[], None))
+                .unwrap();
+            assert!(outcome.success);
+            assert_eq!(outcome.stdout, "done\n");
+        }
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let commands = setup_test_commands().with_fake_command("echo 'test failed'; exit 101");
+        let err = commands
+            .execute(commands.run_subcommand("test", &[]))
+            .unwrap_err();
+        // The failure carries the result, exit code included
+        let failure = err.downcast_ref::<CommandFailure>().unwrap();
+        assert_eq!(failure.error.kind(), "command_failed");
+        assert!(!failure.outcome.success);
+        assert_eq!(failure.outcome.exit_code, Some(101));
+        assert!(err
+            .to_string()
+            .starts_with("[command_failed] cargo test failed: test failed"));
+
+        let commands = setup_test_commands().with_fake_command("exit 0");
+        let outcome = commands
+            .execute(commands.spawn_cargo_command("build", &[], None))
+            .unwrap();
+        assert_eq!(outcome.exit_code, Some(0));
+    }
+
+    #[test]
+    fn test_toolchain_not_installed() {
+        let commands = setup_test_commands().with_fake_command(
+            "echo \"error: toolchain 'nightly-x86_64-unknown-linux-gnu' is not installed\" >&2; exit 1",
+        );
+        let err = commands
+            .execute(commands.run_subcommand("build", &[]))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("[toolchain_not_installed] cargo build needs the toolchain nightly-x86_64-unknown-linux-gnu"),
+            "{}",
+            err
+        );
+        assert!(err.contains("`rustup toolchain install nightly-x86_64-unknown-linux-gnu`"));
+    }
+
+    #[test]
+    fn test_malformed_manifest() {
+        let scratch = ScratchCrate::new("malformed_manifest", &[("src/lib.rs", "")]);
+        let manifest = scratch.manifest_path();
+        std::fs::write(&manifest, "[package]\nname = \"app\"\nversion = 0.1.0\"\n").unwrap();
+        let cargo_commands = setup_test_commands();
+        // Reported absolute, however cargo printed it
+        let resolved = std::path::Path::new(&manifest)
+            .canonicalize()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+
+        let err = cargo_commands
+            .execute(cargo_commands.run_subcommand("check", &["--manifest-path", &manifest]))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains(&format!(
+                "[manifest_error] cargo check failed to read {}:3:",
+                resolved
+            )),
+            "{}",
+            err
+        );
+
+        // check_fast lists it with the diagnostics
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_check_fast(&["--manifest-path", &manifest]))
+            .unwrap();
+        assert!(!outcome.success);
+        let first_error = outcome.first_error.unwrap();
+        assert_eq!((first_error.file, first_error.line), (resolved, 3));
+    }
+
+    #[test]
+    fn test_json_message_format_fills_quickfix() {
+        let scratch = ScratchCrate::new(
+            "json_message_format",
+            &[("src/lib.rs", "use std::fmt;\n\npub fn f() {}\n")],
+        );
+        let manifest = scratch.manifest_path();
+        let cargo_commands = setup_test_commands();
+        let outcome = cargo_commands
+            .execute(cargo_commands.run_subcommand(
+                "check",
+                &["--manifest-path", &manifest, "--message-format=json"],
+            ))
+            .unwrap();
+        assert_eq!(outcome.quickfix.len(), 1, "{:?}", outcome.quickfix);
+        let warning = &outcome.quickfix[0];
+        assert!(
+            warning.filename.ends_with("src/lib.rs"),
+            "{}",
+            warning.filename
+        );
+        assert_eq!(
+            (warning.lnum, warning.col, warning.kind.as_str()),
+            (1, 5, "W")
+        );
+        assert!(warning.text.contains("unused import"), "{}", warning.text);
+        // Lines that aren't JSON, like cargo's own status lines, are left alone
+        assert!(outcome.output.contains("\"reason\":\"compiler-message\""));
+        assert!(outcome.output.contains("Finished"));
+
+        // A failed build has them in the details of its error
+        std::fs::write(
+            scratch.path("src/lib.rs"),
+            "use std::fmt;\n\npub fn f() -> i32 {\n    \"s\"\n}\n",
+        )
+        .unwrap();
+        let err = cargo_commands
+            .execute(cargo_commands.run_subcommand(
+                "build",
+                &["--manifest-path", &manifest, "--message-format=json"],
+            ))
+            .unwrap_err();
+        let failure = err.downcast_ref::<CommandFailure>().unwrap();
+        assert_eq!(failure.error.kind(), "compile_failed");
+        let kinds: Vec<(u32, &str)> = failure
+            .outcome
+            .quickfix
+            .iter()
+            .map(|entry| (entry.lnum, entry.kind.as_str()))
+            .collect();
+        assert_eq!(kinds, vec![(1, "W"), (4, "E")]);
+        assert_eq!(failure.outcome.first_error.as_ref().unwrap().line, 4);
+    }
+
+    #[test]
+    fn test_color_output() {
+        let scratch = ScratchCrate::new(
+            "color_output",
+            &[("src/lib.rs", "use std::fmt;\n\npub fn f() {}\n")],
+        );
+        let manifest = scratch.manifest_path();
+        let cargo_commands = setup_test_commands().with_options(CommandOptions {
+            color: true,
+            ..Default::default()
+        });
+        let outcome = cargo_commands
+            .execute(cargo_commands.run_subcommand(
+                "check",
+                &["--manifest-path", &manifest, "--message-format=json"],
+            ))
+            .unwrap();
+        assert!(outcome.output.contains("\x1b["), "{}", outcome.output);
+        assert_eq!(outcome.ansi_output, None);
+        // Read without the colors
+        assert!(!outcome.stderr.contains('\x1b'), "{}", outcome.stderr);
+        assert_eq!(outcome.build_finished, Some(true));
+        assert_eq!(outcome.quickfix.len(), 1, "{:?}", outcome.quickfix);
+    }
+
+    #[test]
+    fn test_failed_build_has_first_error() {
+        let scratch = ScratchCrate::new(
+            "failed_first_error",
+            &[(
+                "src/lib.rs",
+                "pub fn f() -> i32 {\n    \"s\"\n}\n\npub fn g() -> u8 {\n    \"t\"\n}\n",
+            )],
+        );
+        let manifest = scratch.manifest_path();
+        let cargo_commands = setup_test_commands();
+        let err = cargo_commands
+            .execute(cargo_commands.run_subcommand("check", &["--manifest-path", &manifest]))
+            .unwrap_err();
+        let failure = err.downcast_ref::<CommandFailure>().unwrap();
+        assert_eq!(failure.error.kind(), "compile_failed");
+        let first_error = failure.outcome.first_error.as_ref().unwrap();
+        assert!(
+            first_error.file.ends_with("src/lib.rs"),
+            "{}",
+            first_error.file
+        );
+        assert_eq!((first_error.line, first_error.column), (2, 5));
+        assert_eq!(failure.outcome.quickfix.len(), 2);
+    }
+
+    #[test]
+    fn test_check_fast_errors_only() {
+        let scratch = ScratchCrate::new(
+            "errors_only",
+            &[(
+                "src/lib.rs",
+                "use std::fmt;\n\npub fn f() -> i32 {\n    \"s\"\n}\n",
+            )],
+        );
+        let manifest = scratch.manifest_path();
+        let args = ["--manifest-path", manifest.as_str()];
+
+        let cargo_commands = setup_test_commands();
+        let all = cargo_commands
+            .execute(cargo_commands.cargo_check_fast(&args))
+            .unwrap();
+        assert!(all.quickfix.iter().any(|entry| entry.kind == "W"));
+        assert_eq!(all.warning_count, 1);
+
+        let cargo_commands = cargo_commands.with_options(CommandOptions {
+            errors_only: true,
+            ..Default::default()
+        });
+        let errors = cargo_commands
+            .execute(cargo_commands.cargo_check_fast(&args))
+            .unwrap();
+        assert!(!errors.success);
+        assert_eq!(errors.quickfix.len(), 1);
+        assert_eq!(
+            (errors.quickfix[0].lnum, errors.quickfix[0].kind.as_str()),
+            (4, "E")
+        );
+        // The warning comes first in the file, but only errors are jumped to
+        assert_eq!(all.first_error.unwrap().line, 4);
+        assert_eq!(errors.first_error.unwrap().line, 4);
+    }
+
+    #[test]
+    fn test_new_reports_created_package() {
+        let dir = std::env::temp_dir().join(format!("cargo-nvim-new-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cargo_commands = setup_test_commands().with_options(CommandOptions {
+            cwd: Some(dir.to_string_lossy().into_owned()),
+            ..Default::default()
+        });
+
+        let outcome = cargo_commands
+            .execute(
+                cargo_commands
+                    .run_subcommand("new", &["my-dir", "--name", "real_name", "--vcs", "none"]),
+            )
+            .unwrap();
+        let created = outcome.created.unwrap();
+        let dir = dir.canonicalize().unwrap();
+        assert_eq!(created.kind, "bin");
+        assert_eq!(created.name, "real_name");
+        assert_eq!(created.path, dir.join("my-dir").to_string_lossy());
+        assert_eq!(
+            created.entry_file,
+            dir.join("my-dir/src/main.rs").to_string_lossy()
+        );
+
+        std::fs::create_dir_all(dir.join("lib-dir")).unwrap();
+        let outcome = cargo_commands
+            .execute(cargo_commands.run_subcommand("init", &["--lib", "--vcs", "none", "lib-dir"]))
+            .unwrap();
+        let created = outcome.created.unwrap();
+        assert_eq!(
+            (created.kind.as_str(), created.name.as_str()),
+            ("lib", "lib-dir")
+        );
+        assert!(std::path::Path::new(&created.entry_file).is_file());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_check_fast_only_packages() {
+        let scratch = ScratchCrate::new(
+            "only_packages",
+            &[
+                ("src/lib.rs", "pub fn f() {\n    let unused = 1;\n}\n"),
+                (
+                    "dep/Cargo.toml",
+                    "[package]\nname = \"dep\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+                ),
+                ("dep/src/lib.rs", "pub fn g() -> i32 {\n    \"s\"\n}\n"),
+            ],
+        );
+        let manifest = scratch.manifest_path();
+        let contents = std::fs::read_to_string(&manifest).unwrap();
+        std::fs::write(
+            &manifest,
+            contents.replace(
+                "[workspace]",
+                "[dependencies]\ndep = { path = \"dep\" }\n\n[workspace]",
+            ),
+        )
+        .unwrap();
+        let args = ["--manifest-path", manifest.as_str()];
+        let only = |packages: &[&str]| {
+            let commands = setup_test_commands().with_options(CommandOptions {
+                only_packages: packages.iter().map(|p| p.to_string()).collect(),
+                ..Default::default()
+            });
+            commands.execute(commands.cargo_check_fast(&args)).unwrap()
+        };
+
+        let dep = only(&["dep"]);
+        assert!(!dep.success);
+        assert_eq!(dep.quickfix.len(), 1);
+        assert_eq!(dep.quickfix[0].kind, "E");
+        // The error is in a dependency that is filtered out; the check still reports
+        // its result instead of failing
+        let own = only(&["only_packages"]);
+        assert!(!own.success);
+        assert!(own.quickfix.is_empty(), "{:?}", own.quickfix);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_build_killed_by_signal() {
+        let scratch = ScratchCrate::new(
+            "killed_by_signal",
+            &[
+                ("src/lib.rs", ""),
+                ("build.rs", "fn main() {\n    std::process::abort();\n}\n"),
+            ],
+        );
+        let manifest = scratch.manifest_path();
+        let cargo_commands = setup_test_commands();
+        let err = cargo_commands
+            .execute(cargo_commands.cargo_build(&["--manifest-path", &manifest]))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("[killed_by_signal] cargo build was killed by signal 6 (SIGABRT)"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_config_overrides() {
+        let scratch = ScratchCrate::new("config_overrides", &[("src/lib.rs", "")]);
+        let manifest = scratch.manifest_path();
+        let cargo_commands = setup_test_commands().with_options(CommandOptions {
+            config: vec!["term.verbose=true".to_string()],
+            ..Default::default()
+        });
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_build(&["--manifest-path", &manifest]))
+            .unwrap();
+        assert!(outcome.output.contains("Running `"), "{}", outcome.output);
+    }
+
+    #[test]
+    fn test_target_dir_option() {
+        let scratch = ScratchCrate::new("target_dir_option", &[("src/lib.rs", "")]);
+        let manifest = scratch.manifest_path();
+        let check_dir = std::path::Path::new(&manifest).with_file_name("target-check");
+        let cargo_commands = setup_test_commands().with_options(CommandOptions {
+            target_dir: Some(check_dir.to_string_lossy().into_owned()),
+            ..Default::default()
+        });
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_check(&["--manifest-path", &manifest]))
+            .unwrap();
+        assert!(outcome.success, "{}", outcome.output);
+        assert!(check_dir.join("debug").is_dir());
+        assert!(!check_dir.with_file_name("target").exists());
+    }
+
+    #[test]
+    fn test_toolchain_reported() {
+        let scratch = ScratchCrate::new("toolchain", &[("src/lib.rs", "")]);
+        let manifest = scratch.manifest_path();
+        let cargo_commands = setup_test_commands();
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_check(&["--manifest-path", &manifest]))
+            .unwrap();
+        let toolchain = outcome.toolchain.unwrap();
+        assert!(toolchain.starts_with("rustc 1."), "{}", toolchain);
+    }
+
+    #[test]
+    fn test_metadata_features() {
+        let scratch = ScratchCrate::new("metadata_features", &[("src/lib.rs", "")]);
+        let manifest = scratch.manifest_path();
+        std::fs::write(
+            &manifest,
+            "[package]\nname = \"metadata_features\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[features]\ndefault = [\"fast\"]\nfast = []\nslow = [\"fast\"]\n\n[workspace]\n",
+        )
+        .unwrap();
+        let cargo_commands = setup_test_commands();
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_metadata(&["--no-deps", "--manifest-path", &manifest]))
+            .unwrap();
+
+        let metadata = outcome.metadata.unwrap();
+        let package = &metadata.packages[0];
+        assert_eq!(package.default_features, vec!["fast"]);
+        assert_eq!(package.features["slow"], vec!["fast"]);
+        assert_eq!(metadata.workspace_members, vec!["metadata_features"]);
+        assert!(!outcome.output.contains("\"packages\""));
+    }
+
+    #[test]
+    fn test_add_reports_features() {
+        let scratch = ScratchCrate::new("add_features", &[("src/lib.rs", "")]);
+        let manifest = scratch.manifest_path();
+        // A path dependency, so nothing is fetched from a registry
+        let dep = std::path::Path::new(&manifest).with_file_name("dep");
+        std::fs::create_dir_all(dep.join("src")).unwrap();
+        std::fs::write(dep.join("src/lib.rs"), "").unwrap();
+        std::fs::write(
+            dep.join("Cargo.toml"),
+            "[package]\nname = \"dep\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[features]\ndefault = [\"json\"]\njson = []\nyaml = []\n",
+        )
+        .unwrap();
+
+        let cargo_commands = setup_test_commands();
+        let dep_path = dep.to_string_lossy();
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_add(&[
+                "--offline",
+                "--manifest-path",
+                &manifest,
+                "--path",
+                &dep_path,
+            ]))
+            .unwrap();
+        assert_eq!(outcome.added.len(), 1, "{}", outcome.output);
+        let added = &outcome.added[0];
+        assert_eq!(added.name, "dep");
+        assert_eq!(added.table, "dependencies");
+        assert_eq!(added.available_features, vec!["json", "yaml"]);
+        assert_eq!(added.enabled_features, vec!["json"]);
+    }
+
+    #[test]
+    fn test_backtrace_on_panic_reruns() {
+        // The first run panics without a backtrace, whatever RUST_BACKTRACE is here
+        let scratch = ScratchCrate::new(
+            "backtrace_rerun",
+            &[(
+                "src/main.rs",
+                r#"fn main() {
+    let marker = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("ran");
+    if !marker.exists() {
+        std::fs::write(&marker, "").unwrap();
+        std::panic::set_hook(Box::new(|info| eprintln!("thread 'main' {}", info)));
+    }
+    panic!("boom");
+}
+"#,
+            )],
+        );
+        let manifest = scratch.manifest_path();
+        let cargo_commands = setup_test_commands().with_options(CommandOptions {
+            backtrace_on_panic: true,
+            ..Default::default()
+        });
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_run(&["-q", "--manifest-path", &manifest]))
+            .unwrap();
+
+        assert!(outcome.backtrace_rerun);
+        assert!(has_backtrace(&outcome.output), "{}", outcome.output);
+        assert_eq!(outcome.panic.unwrap().message, "boom");
+    }
+
+    #[test]
+    fn test_run_dir() {
+        let scratch = ScratchCrate::new(
+            "run_dir",
+            &[(
+                "src/main.rs",
+                "fn main() {\n    println!(\"in {}\", std::env::current_dir().unwrap().display());\n}\n",
+            )],
+        );
+        let manifest = scratch.manifest_path();
+        let run_dir = std::path::Path::new(&manifest)
+            .parent()
+            .unwrap()
+            .join("data dir");
+        std::fs::create_dir_all(&run_dir).unwrap();
+        let cargo_commands = setup_test_commands().with_options(CommandOptions {
+            run_dir: Some(run_dir.to_string_lossy().into_owned()),
+            ..Default::default()
+        });
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_run(&["-q", "--manifest-path", &manifest]))
+            .unwrap();
+
+        let run_dir = run_dir.canonicalize().unwrap();
+        assert!(
+            outcome
+                .output
+                .contains(&format!("in {}", run_dir.display())),
+            "{}",
+            outcome.output
+        );
+        let program_dir = std::path::PathBuf::from(outcome.program_dir.unwrap());
+        assert_eq!(program_dir.canonicalize().unwrap(), run_dir);
+
+        let missing = setup_test_commands().with_options(CommandOptions {
+            run_dir: Some("no/such/dir".to_string()),
+            ..Default::default()
+        });
+        let err = missing
+            .execute(missing.cargo_run(&["--manifest-path", &manifest]))
+            .unwrap_err();
+        assert!(err.to_string().contains("Invalid run_dir"), "{}", err);
+    }
+
+    #[test]
+    fn test_proconio_manifest_from_args() {
+        let scratch = ScratchCrate::new("proconio", &[("src/main.rs", "fn main() {}\n")]);
+        let manifest = scratch.manifest_path();
+        let mut content = std::fs::read_to_string(&manifest).unwrap();
+        content.push_str("# input with proconio\n");
+        std::fs::write(&manifest, content).unwrap();
+
+        let cargo_commands = setup_test_commands();
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_run(&["-q", "--manifest-path", &manifest]))
+            .unwrap();
+        assert!(outcome.interactive);
+    }
+
+    #[test]
+    fn test_diagnostics_streamed_as_events() {
+        let scratch = ScratchCrate::new(
+            "streamed_diagnostics",
+            &[(
+                "src/lib.rs",
+                "pub fn f() -> i32 {\n    \"s\"\n}\n\n#[cfg(test)]\nmod tests {}\n",
+            )],
+        );
+        let manifest = scratch.manifest_path();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let cargo_commands = setup_test_commands().with_events(tx);
+        // The result itself doesn't matter, only the events sent while it ran
+        let _ = cargo_commands.execute(cargo_commands.execute_cargo_command(
+            "check",
+            &[
+                "--message-format=json",
+                "--all-targets",
+                "--manifest-path",
+                &manifest,
+            ],
+        ));
+
+        let mut diagnostics = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            if let Event::Diagnostic { quickfix, .. } = event {
+                diagnostics.extend(quickfix);
+            }
+        }
+        // Reported for both the lib and its test build, but sent once
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            (diagnostics[0].lnum, diagnostics[0].kind.as_str()),
+            (2, "E")
+        );
+    }
+
+    #[test]
+    fn test_rendered_diagnostics() {
+        let scratch = ScratchCrate::new(
+            "rendered_diagnostics",
+            &[("src/lib.rs", "pub fn f() {\n    let unused = 1;\n}\n")],
+        );
+        let manifest = scratch.manifest_path();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let cargo_commands = setup_test_commands()
+            .with_events(tx)
+            .with_options(CommandOptions {
+                rendered_diagnostics: true,
+                ..Default::default()
+            });
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_check(&["--all-targets", "--manifest-path", &manifest]))
+            .unwrap();
+
+        assert_eq!(outcome.quickfix.len(), 1);
+        assert_eq!(outcome.quickfix[0].lnum, 2);
+        assert_eq!(outcome.warning_count, 1);
+        assert!(outcome.success);
+        assert!(!outcome.output.lines().any(|line| line.starts_with('{')));
+        assert_eq!(outcome.output.matches("unused variable").count(), 1);
+        assert!(!outcome.stdout.lines().any(|line| line.starts_with('{')));
+        assert!(
+            outcome.stdout.contains("unused variable"),
+            "{}",
+            outcome.stdout
+        );
+
+        let mut stderr = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            match event {
+                Event::Stdout { line } => assert!(!line.starts_with('{'), "{}", line),
+                Event::Stderr { line } => stderr.push(line),
+                _ => {}
+            }
+        }
+        assert!(stderr.iter().any(|line| line.contains("unused variable")));
+    }
+
+    #[test]
+    fn test_old_cargo_falls_back() {
+        let scratch = ScratchCrate::new(
+            "old_cargo",
+            &[("src/lib.rs", "pub fn f() {\n    let unused = 1;\n}\n")],
+        );
+        let manifest = scratch.manifest_path();
+        // Reports an old version, and runs the real cargo otherwise
+        let old_cargo =
+            "if [ \"$2\" = --version ]; then echo 'cargo 1.37.0 (9edd08916 2019-08-02)'; \
+                         else exec \"$@\"; fi";
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let cargo_commands = setup_test_commands()
+            .with_events(tx)
+            .with_options(CommandOptions {
+                rendered_diagnostics: true,
+                shell_prefix: Some(vec![
+                    "sh".to_string(),
+                    "-c".to_string(),
+                    old_cargo.to_string(),
+                    "sh".to_string(),
+                ]),
+                ..Default::default()
+            });
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_check(&["--manifest-path", &manifest]))
+            .unwrap();
+        assert!(outcome.success);
+        assert_eq!(outcome.quickfix.len(), 1);
+        assert_eq!(
+            (outcome.quickfix[0].lnum, outcome.quickfix[0].kind.as_str()),
+            (2, "W")
+        );
+        assert!(outcome.output.contains("unused variable"));
+
+        let fast = cargo_commands
+            .execute(cargo_commands.cargo_check_fast(&["--manifest-path", &manifest]))
+            .unwrap();
+        assert_eq!(fast.quickfix.len(), 1);
+
+        let mut fallbacks = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            if let Event::VersionFallback {
+                feature, required, ..
+            } = event
+            {
+                fallbacks.push((feature, required));
+            }
+        }
+        assert_eq!(
+            fallbacks,
+            vec![
+                ("rendered_diagnostics".to_string(), "1.38".to_string()),
+                ("keep_going".to_string(), "1.74".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_doc_workspace_indexes() {
+        let scratch = ScratchCrate::new("doc_workspace", &[("src/main.rs", "fn main() {}\n")]);
+        let manifest = scratch.manifest_path();
+        let root = std::path::Path::new(&manifest)
+            .parent()
+            .unwrap()
+            .to_path_buf();
+        std::fs::write(
+            &manifest,
+            "[package]\nname = \"doc_workspace\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[workspace]\nmembers = [\"my-lib\"]\n",
+        )
+        .unwrap();
+        std::fs::create_dir_all(root.join("my-lib/src")).unwrap();
+        std::fs::write(root.join("my-lib/src/lib.rs"), "").unwrap();
+        std::fs::write(
+            root.join("my-lib/Cargo.toml"),
+            "[package]\nname = \"my-lib\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        )
+        .unwrap();
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let cargo_commands = setup_test_commands()
+            .with_events(tx)
+            .with_options(CommandOptions {
+                package: Some("my-lib".to_string()),
+                ..Default::default()
+            });
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_doc(&["--workspace", "--manifest-path", &manifest]))
+            .unwrap();
+
+        let doc = outcome.doc.unwrap();
+        let mut crate_names: Vec<&str> = doc.crates.iter().map(|c| c.crate_name.as_str()).collect();
+        crate_names.sort();
+        assert_eq!(crate_names, vec!["doc_workspace", "my_lib"]);
+        assert!(doc.index.unwrap().ends_with("doc/my_lib/index.html"));
+
+        let mut documenting = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            match event {
+                Event::Progress {
+                    action, package, ..
+                } if action == "Documenting" => documenting.push(package),
+                Event::Stdout { line } => assert!(!line.starts_with('{'), "{}", line),
+                _ => {}
+            }
+        }
+        documenting.sort();
+        assert_eq!(documenting, vec!["doc_workspace", "my-lib"]);
+    }
+
+    #[test]
+    fn test_shell_prefix() {
+        // Only builds with the prefix's environment
+        let scratch = ScratchCrate::new(
+            "shell_prefix",
+            &[(
+                "src/lib.rs",
+                "#[cfg(not(prefixed))]\ncompile_error!(\"no prefix\");\n",
+            )],
+        );
+        let manifest = scratch.manifest_path();
+        let prefixed = |prefix: &[&str]| {
+            setup_test_commands().with_options(CommandOptions {
+                shell_prefix: Some(prefix.iter().map(|s| s.to_string()).collect()),
+                ..Default::default()
+            })
+        };
+
+        let cargo_commands = prefixed(&["env", "RUSTFLAGS=--cfg prefixed"]);
+        let outcome = cargo_commands
+            .execute(cargo_commands.cargo_check(&["--manifest-path", &manifest]))
+            .unwrap();
+        assert!(outcome.success, "{}", outcome.output);
+        assert!(outcome.toolchain.is_some());
+
+        let cargo_commands = prefixed(&["cargo-nvim-no-such-shell", "-c"]);
+        let err = cargo_commands
+            .execute(cargo_commands.cargo_check(&["--manifest-path", &manifest]))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("[not_found] cargo-nvim-no-such-shell is not installed"),
+            "{}",
+            err
+        );
+    }
 }
@@ -0,0 +1,82 @@
+// src/clean.rs
+//! Space freed by `cargo clean`, measured on disk since older cargo doesn't report it
+use crate::artifacts::{flag_values, profile_dir, validate_component};
+use mlua::prelude::*;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// What `cargo clean` removed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CleanResult {
+    /// Directory measured before and after: the target directory, or the part of it a
+    /// selective clean (`--release`, `--profile`, `--target`, `--doc`) removes from
+    pub dir: String,
+    pub freed_bytes: u64,
+}
+
+/// The part of `target_dir` that a clean with `args` removes from
+pub fn clean_scope(target_dir: &Path, args: &[&str]) -> LuaResult<PathBuf> {
+    let args: Vec<&str> = args.iter().copied().take_while(|a| *a != "--").collect();
+    let mut dir = target_dir.to_path_buf();
+    let triples = flag_values(&args, "--target");
+    if let [triple] = triples.as_slice() {
+        validate_component("target", triple)?;
+        dir.push(triple);
+    } else if triples.len() > 1 {
+        return Ok(dir);
+    }
+    if args.contains(&"--doc") {
+        dir.push("doc");
+    } else if let Some(profile) = flag_values(&args, "--profile").first() {
+        dir.push(profile_dir(profile)?);
+    } else if args.contains(&"--release") || args.contains(&"-r") {
+        dir.push("release");
+    }
+    Ok(dir)
+}
+
+/// Total size of the files under `path`, without following symlinks; 0 when it
+/// doesn't exist
+pub fn dir_size(path: &Path) -> u64 {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    std::fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|entry| dir_size(&entry.path())).sum())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_scope() {
+        let target = Path::new("/ws/target");
+        let scope = |args: &[&str]| clean_scope(target, args).unwrap();
+        assert_eq!(scope(&[]), target);
+        assert_eq!(scope(&["-p", "app"]), target);
+        assert_eq!(scope(&["--release"]), target.join("release"));
+        assert_eq!(scope(&["--profile=test"]), target.join("debug"));
+        assert_eq!(scope(&["--profile", "ci"]), target.join("ci"));
+        assert_eq!(
+            scope(&["--target", "wasm32-unknown-unknown", "--doc"]),
+            target.join("wasm32-unknown-unknown").join("doc")
+        );
+        assert!(clean_scope(target, &["--target", "../x"]).is_err());
+    }
+
+    #[test]
+    fn test_dir_size() {
+        let dir = std::env::temp_dir().join(format!("cargo-nvim-dir-size-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(dir.join("a"), [0u8; 100]).unwrap();
+        std::fs::write(dir.join("nested").join("b"), [0u8; 23]).unwrap();
+        assert_eq!(dir_size(&dir), 123);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(dir_size(&dir), 0);
+    }
+}
@@ -0,0 +1,58 @@
+// src/compat.rs
+//! Which flags of the JSON-oriented methods the cargo in use supports
+//!
+//! `cargo --version` is read once per cargo (see `CargoKey`: its directory, program,
+//! toolchain, `shell_prefix` and environment), so the methods can check it on every run. Where a flag is too new the
+//! method runs without it and says so with a `version_fallback` event instead of failing.
+
+use crate::options::CargoKey;
+use crate::timings::parse_cargo_version;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// `--message-format=json-diagnostic-rendered-ansi` (the `rendered_diagnostics` option)
+pub const RENDERED_MIN_CARGO_VERSION: (u32, u32) = (1, 38);
+/// `--keep-going` (check_fast)
+pub const KEEP_GOING_MIN_CARGO_VERSION: (u32, u32) = (1, 74);
+
+/// The cargo a version was read from
+pub type VersionKey = CargoKey;
+
+static VERSIONS: Mutex<BTreeMap<VersionKey, String>> = Mutex::new(BTreeMap::new());
+
+/// The version read for `key`, if any
+pub fn cached(key: &VersionKey) -> Option<String> {
+    VERSIONS.lock().unwrap().get(key).cloned()
+}
+
+pub fn store(key: VersionKey, version: String) {
+    VERSIONS.lock().unwrap().insert(key, version);
+}
+
+/// Forget every version, e.g. once cargo was replaced
+pub fn forget() {
+    VERSIONS.lock().unwrap().clear();
+}
+
+/// Whether `version` (`cargo --version` output) is at least `required`; a version that
+/// can't be read, e.g. from a wrapper, is taken to support everything
+pub fn supports(version: &str, required: (u32, u32)) -> bool {
+    parse_cargo_version(version).is_none_or(|found| found >= required)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_supports() {
+        let old = "cargo 1.70.0 (ec8a8a0ca 2023-04-25)";
+        assert!(supports(old, RENDERED_MIN_CARGO_VERSION));
+        assert!(!supports(old, KEEP_GOING_MIN_CARGO_VERSION));
+        assert!(supports(
+            "cargo 1.97.0-nightly (4d1f98451 2026-05-15)",
+            KEEP_GOING_MIN_CARGO_VERSION
+        ));
+        assert!(supports("cross 0.2.5", KEEP_GOING_MIN_CARGO_VERSION));
+    }
+}
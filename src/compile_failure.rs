@@ -0,0 +1,105 @@
+// src/compile_failure.rs
+//! Cargo's closing `error: could not compile ...` lines, which name each crate that
+//! failed and how many errors it had, in human and JSON output alike
+use serde::Serialize;
+
+/// A crate that failed to compile
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CompileFailure {
+    pub failed_crate: String,
+    /// The target that failed, e.g. `lib`, `bin "app"` or `lib test`; older cargo
+    /// releases don't print it
+    pub target: Option<String>,
+    /// Errors rustc reported; 0 when rustc failed without reporting any (e.g. a crash)
+    pub error_count: usize,
+}
+
+/// Every `could not compile` line of the output, one per crate and target that failed
+pub fn parse_compile_failures(output: &str) -> Vec<CompileFailure> {
+    output.lines().filter_map(parse_line).collect()
+}
+
+// `error: could not compile `app` (bin "app") due to 2 previous errors; 1 warning emitted`,
+// or `due to previous error` before cargo 1.71
+fn parse_line(line: &str) -> Option<CompileFailure> {
+    let (prefix, rest) = line.split_once("could not compile `")?;
+    if !prefix.contains("error") {
+        return None;
+    }
+    let (name, rest) = rest.split_once('`')?;
+    let rest = rest.trim_start();
+    let (target, rest) = match rest.strip_prefix('(') {
+        Some(inner) => {
+            let (target, rest) = inner.split_once(')')?;
+            (Some(target.to_string()), rest.trim_start())
+        }
+        None => (None, rest),
+    };
+    let error_count = match rest.strip_prefix("due to ") {
+        Some(reason) if reason.starts_with("previous error") => 1,
+        Some(reason) => reason.split_whitespace().next()?.parse().ok()?,
+        None => 0,
+    };
+    Some(CompileFailure {
+        failed_crate: name.to_string(),
+        target,
+        error_count,
+    })
+}
+
+/// `` `app` (bin "app", 2 errors), `core` (1 error) ``
+pub fn summarize(failures: &[CompileFailure]) -> String {
+    failures
+        .iter()
+        .map(|failure| {
+            let errors = match failure.error_count {
+                1 => "1 error".to_string(),
+                n => format!("{} errors", n),
+            };
+            match &failure.target {
+                Some(target) => format!("`{}` ({}, {})", failure.failed_crate, target, errors),
+                None => format!("`{}` ({})", failure.failed_crate, errors),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_compile_failures() {
+        let output = "\
+   Compiling core v0.1.0 (/ws/core)
+error[E0308]: mismatched types
+error: could not compile `core` (lib) due to 2 previous errors; 1 warning emitted
+warning: build failed, waiting for other jobs to finish...
+error: could not compile `app` (bin \"app\" test) due to 1 previous error
+error: could not compile `old` due to previous error
+error: could not compile `crashed`
+";
+        let failures = parse_compile_failures(output);
+        assert_eq!(failures.len(), 4);
+        assert_eq!(
+            failures[0],
+            CompileFailure {
+                failed_crate: "core".to_string(),
+                target: Some("lib".to_string()),
+                error_count: 2,
+            }
+        );
+        assert_eq!(failures[1].target.as_deref(), Some("bin \"app\" test"));
+        assert_eq!(
+            (failures[2].target.as_deref(), failures[2].error_count),
+            (None, 1)
+        );
+        assert_eq!(failures[3].error_count, 0);
+        assert_eq!(
+            summarize(&failures[..2]),
+            "`core` (lib, 2 errors), `app` (bin \"app\" test, 1 error)"
+        );
+        assert!(parse_compile_failures("note: could not compile `x`").is_empty());
+    }
+}
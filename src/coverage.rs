@@ -0,0 +1,103 @@
+// src/coverage.rs
+//! Coverage totals from the summary table `cargo llvm-cov` prints
+
+use serde::Serialize;
+
+/// Coverage of a test run, as percentages of the table's `TOTAL` row
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CoverageReport {
+    /// `None` when llvm-cov shows `-`: there was nothing of that kind to cover
+    pub lines: Option<f64>,
+    pub regions: Option<f64>,
+    pub functions: Option<f64>,
+    /// Path of the HTML report's index (`html` option)
+    pub html: Option<String>,
+}
+
+/// Parse the totals from llvm-cov's summary table:
+///
+/// ```text
+/// Filename   Regions    Missed Regions     Cover   Functions  Missed Functions  Executed  Lines ...
+/// TOTAL           10                 2    80.00%           3                 0   100.00%     20 ...
+/// ```
+///
+/// Columns are found from the header, so tables with extra ones (branches,
+/// instantiations) parse the same way
+pub fn parse_coverage_summary(output: &str) -> Option<CoverageReport> {
+    let lines: Vec<&str> = output.lines().collect();
+    let header = lines
+        .iter()
+        .rposition(|line| line.trim_start().starts_with("Filename"))?;
+    // Header names are separated by runs of spaces, and may contain single ones
+    let columns: Vec<&str> = lines[header]
+        .split("  ")
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .collect();
+    let totals: Vec<&str> = lines[header..]
+        .iter()
+        .find(|line| line.trim_start().starts_with("TOTAL"))?
+        .split_whitespace()
+        .collect();
+    if totals.len() != columns.len() {
+        return None;
+    }
+
+    let mut report = CoverageReport {
+        lines: None,
+        regions: None,
+        functions: None,
+        html: None,
+    };
+    // A percentage column (`Cover`, or `Executed` for functions) follows the counts of
+    // the metric it belongs to
+    let mut metric = "";
+    for (column, value) in columns.iter().zip(&totals) {
+        match *column {
+            "Cover" | "Executed" => {
+                let percent = value.strip_suffix('%').and_then(|v| v.parse().ok());
+                match metric {
+                    "Lines" => report.lines = percent,
+                    "Regions" => report.regions = percent,
+                    "Functions" => report.functions = percent,
+                    _ => {}
+                }
+            }
+            name if !name.starts_with("Missed") => metric = name,
+            _ => {}
+        }
+    }
+    Some(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_coverage_summary() {
+        let output = "\
+running 2 tests
+test tests::a ... ok
+
+Filename                      Regions    Missed Regions     Cover   Functions  Missed Functions  Executed       Lines      Missed Lines     Cover    Branches   Missed Branches     Cover
+-----------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+src/lib.rs                         10                 2    80.00%           3                 0   100.00%          20                 5    75.00%           0                 0         -
+-----------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+TOTAL                              10                 2    80.00%           3                 0   100.00%          20                 5    75.00%           0                 0         -
+";
+        let report = parse_coverage_summary(output).unwrap();
+        assert_eq!(report.regions, Some(80.0));
+        assert_eq!(report.functions, Some(100.0));
+        assert_eq!(report.lines, Some(75.0));
+        assert_eq!(report.html, None);
+
+        // Nothing to cover
+        let empty = "Filename  Regions  Missed Regions  Cover  Lines  Missed Lines  Cover\n\
+                     TOTAL           0               0      -      0             0      -\n";
+        let report = parse_coverage_summary(empty).unwrap();
+        assert_eq!((report.lines, report.regions), (None, None));
+
+        assert_eq!(parse_coverage_summary("error: no tests"), None);
+    }
+}
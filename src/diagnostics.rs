@@ -0,0 +1,419 @@
+// src/diagnostics.rs
+//! rustc diagnostics as reported in cargo's JSON output
+use crate::messages::CargoMessage;
+use crate::quickfix::QuickfixEntry;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// A rustc diagnostic (`message` of a `compiler-message`)
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct Diagnostic {
+    pub message: String,
+    /// `error`, `warning`, `note`, `help` or `failure-note`
+    pub level: String,
+    pub code: Option<DiagnosticCode>,
+    #[serde(default)]
+    pub spans: Vec<DiagnosticSpan>,
+    /// The diagnostic as rustc would print it
+    pub rendered: Option<String>,
+    /// Notes and help attached to it, which is where rustc puts its suggestions; read
+    /// for `suggestions`, but not passed on
+    #[serde(default, skip_serializing)]
+    pub children: Vec<Diagnostic>,
+}
+
+/// Error or lint code, e.g. `E0308` or `unused_variables`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct DiagnosticCode {
+    pub code: String,
+}
+
+/// A source location a diagnostic refers to
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct DiagnosticSpan {
+    pub file_name: String,
+    pub line_start: u32,
+    pub column_start: u32,
+    #[serde(default)]
+    pub line_end: u32,
+    #[serde(default)]
+    pub column_end: u32,
+    #[serde(default)]
+    pub byte_start: u32,
+    #[serde(default)]
+    pub byte_end: u32,
+    pub is_primary: bool,
+    /// Text rustc suggests in place of the span
+    #[serde(default)]
+    pub suggested_replacement: Option<String>,
+    /// How sure rustc is of that text: `MachineApplicable`, `MaybeIncorrect`,
+    /// `HasPlaceholders` or `Unspecified`
+    #[serde(default)]
+    pub suggestion_applicability: Option<String>,
+}
+
+/// A fix rustc is sure of (`MachineApplicable`), for an "apply suggestion" code action;
+/// its replacements belong together and are applied all at once
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Suggestion {
+    /// The help that comes with it, e.g. "remove this `mut`"
+    pub message: String,
+    pub applicability: String,
+    pub replacements: Vec<Replacement>,
+}
+
+/// Text to put in place of a span of a file
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Replacement {
+    /// As rustc reports it, relative to the workspace root
+    pub file: String,
+    pub span: SourceSpan,
+    pub replacement: String,
+}
+
+/// Lines and columns are 1-based, columns counting characters; the end is exclusive.
+/// Byte offsets are from the start of the file
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct SourceSpan {
+    pub line_start: u32,
+    pub column_start: u32,
+    pub line_end: u32,
+    pub column_end: u32,
+    pub byte_start: u32,
+    pub byte_end: u32,
+}
+
+const MACHINE_APPLICABLE: &str = "MachineApplicable";
+
+impl Diagnostic {
+    /// Whether this is an error (including an ICE) rather than a warning or note
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self.level.as_str(),
+            "error" | "error: internal compiler error"
+        )
+    }
+
+    /// The machine-applicable suggestions of this diagnostic and its children; those
+    /// rustc isn't sure of are left out, as applying them could break the code
+    pub fn suggestions(&self) -> Vec<Suggestion> {
+        let mut suggestions = Vec::new();
+        self.collect_suggestions(&mut suggestions);
+        suggestions
+    }
+
+    fn collect_suggestions(&self, suggestions: &mut Vec<Suggestion>) {
+        let replacements: Vec<Replacement> = self
+            .spans
+            .iter()
+            .filter(|span| span.suggestion_applicability.as_deref() == Some(MACHINE_APPLICABLE))
+            .filter_map(|span| {
+                Some(Replacement {
+                    file: span.file_name.clone(),
+                    span: SourceSpan {
+                        line_start: span.line_start,
+                        column_start: span.column_start,
+                        line_end: span.line_end,
+                        column_end: span.column_end,
+                        byte_start: span.byte_start,
+                        byte_end: span.byte_end,
+                    },
+                    replacement: span.suggested_replacement.clone()?,
+                })
+            })
+            .collect();
+        if !replacements.is_empty() {
+            suggestions.push(Suggestion {
+                message: self.message.clone(),
+                applicability: MACHINE_APPLICABLE.to_string(),
+                replacements,
+            });
+        }
+        for child in &self.children {
+            child.collect_suggestions(suggestions);
+        }
+    }
+
+    /// Quickfix entry at the primary span; `None` for diagnostics without a location
+    /// such as the closing "aborting due to previous error"
+    pub fn to_quickfix(&self) -> Option<QuickfixEntry> {
+        let span = self.spans.iter().find(|s| s.is_primary)?;
+        let kind = match self.level.as_str() {
+            _ if self.is_error() => "E",
+            "warning" => "W",
+            _ => "N",
+        };
+        let text = match &self.code {
+            Some(code) => format!("[{}] {}", code.code, self.message),
+            None => self.message.clone(),
+        };
+        Some(QuickfixEntry::new(
+            &span.file_name,
+            span.line_start,
+            span.column_start,
+            &text,
+            kind,
+        ))
+    }
+}
+
+/// Collect quickfix entries for the diagnostics in JSON output: all of them, or those of
+/// the packages in `only_packages` when it isn't empty
+///
+/// With `--all-targets` the same diagnostic is reported once per target,
+/// so duplicates are dropped.
+pub fn parse_diagnostics(output: &str, only_packages: &[String]) -> Vec<QuickfixEntry> {
+    let mut entries: Vec<QuickfixEntry> = Vec::new();
+    let mut seen = HashSet::new();
+    for line in output.lines() {
+        if let Some(CargoMessage::CompilerMessage(message)) = CargoMessage::parse(line) {
+            if !message.of_packages(only_packages) {
+                continue;
+            }
+            if let Some(entry) = message.message.to_quickfix() {
+                if seen.insert(entry.clone()) {
+                    entries.push(entry);
+                }
+            }
+        }
+    }
+    entries
+}
+
+/// Collect quickfix entries from human output, for a cargo too old for the JSON format
+/// asked for: each `error[E0308]: ...` or `warning: ...` header at the ` --> file:line:col`
+/// line that follows it, without duplicates
+pub fn parse_text_diagnostics(output: &str) -> Vec<QuickfixEntry> {
+    let mut entries: Vec<QuickfixEntry> = Vec::new();
+    let mut seen = HashSet::new();
+    let mut header: Option<(&str, String)> = None;
+    for line in output.lines() {
+        if let Some(location) = line.trim_start().strip_prefix("--> ") {
+            let Some((kind, text)) = header.take() else {
+                continue;
+            };
+            let mut parts = location.rsplitn(3, ':');
+            let (Some(col), Some(lnum), Some(file)) = (parts.next(), parts.next(), parts.next())
+            else {
+                continue;
+            };
+            if let (Ok(lnum), Ok(col)) = (lnum.parse(), col.parse()) {
+                let entry = QuickfixEntry::new(file, lnum, col, &text, kind);
+                if seen.insert(entry.clone()) {
+                    entries.push(entry);
+                }
+            }
+            continue;
+        }
+        header = text_header(line);
+    }
+    entries
+}
+
+// Kind and text of a diagnostic header line, with the code in brackets like JSON's
+fn text_header(line: &str) -> Option<(&'static str, String)> {
+    let (kind, rest) = if let Some(rest) = line.strip_prefix("error") {
+        ("E", rest)
+    } else if let Some(rest) = line.strip_prefix("warning") {
+        ("W", rest)
+    } else {
+        return None;
+    };
+    if let Some(message) = rest.strip_prefix(": ") {
+        return Some((kind, message.to_string()));
+    }
+    let (code, message) = rest.strip_prefix('[')?.split_once("]: ")?;
+    Some((kind, format!("[{}] {}", code, message)))
+}
+
+/// The machine-applicable suggestions in JSON output, of the packages in
+/// `only_packages` when it isn't empty and of errors alone with `errors_only`, without
+/// the duplicates of `--all-targets`
+pub fn parse_suggestions(
+    output: &str,
+    only_packages: &[String],
+    errors_only: bool,
+) -> Vec<Suggestion> {
+    let mut suggestions: Vec<Suggestion> = Vec::new();
+    let mut seen = HashSet::new();
+    for line in output.lines() {
+        if let Some(CargoMessage::CompilerMessage(message)) = CargoMessage::parse(line) {
+            if !message.of_packages(only_packages) || (errors_only && !message.message.is_error()) {
+                continue;
+            }
+            for suggestion in message.message.suggestions() {
+                if seen.insert(suggestion.clone()) {
+                    suggestions.push(suggestion);
+                }
+            }
+        }
+    }
+    suggestions
+}
+
+/// Number of warnings in build output
+///
+/// JSON output is counted by `warning`-level diagnostics, human output by the `warning:`
+/// headers followed by a ` --> file:line:col` location; both without duplicates. Either
+/// way the summaries (`generated 3 warnings`, `3 warnings emitted`) aren't warnings
+/// themselves, and neither are cargo's own warnings (e.g. `unused manifest key`), which
+/// point at no source.
+pub fn count_warnings(output: &str) -> usize {
+    let mut json_warnings: HashSet<Diagnostic> = HashSet::new();
+    let mut json = false;
+    let mut human: HashSet<(String, &str)> = HashSet::new();
+    let mut header: Option<String> = None;
+    for line in output.lines() {
+        if let Some(CargoMessage::CompilerMessage(message)) = CargoMessage::parse(line) {
+            json = true;
+            let diagnostic = message.message;
+            if diagnostic.level == "warning" && !is_warning_summary(&diagnostic.message) {
+                json_warnings.insert(diagnostic);
+            }
+        } else if let Some(location) = line.trim_start().strip_prefix("--> ") {
+            if let Some(text) = header.take() {
+                human.insert((text, location));
+            }
+        } else {
+            header = match text_header(line) {
+                Some(("W", text)) if !is_warning_summary(&text) => Some(text),
+                _ => None,
+            };
+        }
+    }
+    if json {
+        json_warnings.len()
+    } else {
+        human.len()
+    }
+}
+
+// `` `app` (lib) generated 3 warnings (...)`` from cargo, `3 warnings emitted` from rustc
+fn is_warning_summary(message: &str) -> bool {
+    let words: Vec<&str> = message.split_whitespace().collect();
+    words.windows(3).any(|w| {
+        w[0] == "generated" && w[1].parse::<usize>().is_ok() && w[2].starts_with("warning")
+    }) || (words.len() == 3
+        && words[0].parse::<usize>().is_ok()
+        && words[1].starts_with("warning")
+        && words[2] == "emitted")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ERROR: &str = r#"{"reason":"compiler-message","package_id":"path+file:///tmp/dt#0.1.0","manifest_path":"/tmp/dt/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"dt","src_path":"/tmp/dt/src/lib.rs","edition":"2021","doc":true,"doctest":true,"test":true},"message":{"rendered":"error[E0308]: mismatched types\n","$message_type":"diagnostic","children":[],"level":"error","message":"mismatched types","spans":[{"byte_end":29,"byte_start":26,"column_end":30,"column_start":27,"expansion":null,"file_name":"src/lib.rs","is_primary":true,"label":"expected `i32`, found `&str`","line_end":1,"line_start":1,"suggested_replacement":null,"suggestion_applicability":null,"text":[]},{"byte_end":23,"byte_start":20,"column_end":24,"column_start":21,"expansion":null,"file_name":"src/lib.rs","is_primary":false,"label":"expected due to this","line_end":1,"line_start":1,"suggested_replacement":null,"suggestion_applicability":null,"text":[]}],"code":{"code":"E0308","explanation":"Expected type did not match the received type."}}}"#;
+
+    const FAILURE_NOTE: &str = r#"{"reason":"compiler-message","package_id":"path+file:///tmp/dt#0.1.0","manifest_path":"/tmp/dt/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"dt","src_path":"/tmp/dt/src/lib.rs","edition":"2021","doc":true,"doctest":true,"test":true},"message":{"rendered":"For more information about this error, try `rustc --explain E0308`.\n","$message_type":"diagnostic","children":[],"level":"failure-note","message":"For more information about this error, try `rustc --explain E0308`.","spans":[],"code":null}}"#;
+
+    #[test]
+    fn test_parse_diagnostics() {
+        let output = format!(
+            "{}\n   Compiling dt v0.1.0\n{}\n{}\n",
+            ERROR, ERROR, FAILURE_NOTE
+        );
+        let entries = parse_diagnostics(&output, &[]);
+        assert_eq!(
+            entries,
+            vec![QuickfixEntry::new(
+                "src/lib.rs",
+                1,
+                27,
+                "[E0308] mismatched types",
+                "E"
+            )]
+        );
+        assert_eq!(parse_diagnostics(&output, &["dt".to_string()]).len(), 1);
+        assert!(parse_diagnostics(&output, &["other".to_string()]).is_empty());
+    }
+
+    // An unused `mut`, whose fix rustc is sure of, and a removal it isn't sure of
+    const UNUSED_MUT: &str = r#"{"reason":"compiler-message","package_id":"path+file:///tmp/sugg#0.1.0","manifest_path":"/tmp/sugg/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"sugg","src_path":"/tmp/sugg/src/lib.rs","edition":"2024","doc":true,"doctest":true,"test":true},"message":{"rendered":"warning: variable does not need to be mutable\n","$message_type":"diagnostic","children":[{"children":[],"code":null,"level":"note","message":"`#[warn(unused_mut)]` (part of `#[warn(unused)]`) on by default","rendered":null,"spans":[]},{"children":[],"code":null,"level":"help","message":"remove this `mut`","rendered":null,"spans":[{"byte_end":64,"byte_start":60,"column_end":13,"column_start":9,"expansion":null,"file_name":"src/lib.rs","is_primary":true,"label":null,"line_end":4,"line_start":4,"suggested_replacement":"","suggestion_applicability":"MachineApplicable","text":[]}]},{"children":[],"code":null,"level":"help","message":"rename it","rendered":null,"spans":[{"byte_end":65,"byte_start":64,"column_end":14,"column_start":13,"expansion":null,"file_name":"src/lib.rs","is_primary":true,"label":null,"line_end":4,"line_start":4,"suggested_replacement":"_x","suggestion_applicability":"MaybeIncorrect","text":[]}]}],"level":"warning","message":"variable does not need to be mutable","spans":[{"byte_end":65,"byte_start":60,"column_end":14,"column_start":9,"expansion":null,"file_name":"src/lib.rs","is_primary":true,"label":null,"line_end":4,"line_start":4,"suggested_replacement":null,"suggestion_applicability":null,"text":[]}],"code":{"code":"unused_mut","explanation":null}}}"#;
+
+    #[test]
+    fn test_machine_applicable_suggestions() {
+        let output = format!("{}\n{}\n{}\n", UNUSED_MUT, UNUSED_MUT, ERROR);
+        assert_eq!(
+            parse_suggestions(&output, &[], false),
+            vec![Suggestion {
+                message: "remove this `mut`".to_string(),
+                applicability: "MachineApplicable".to_string(),
+                replacements: vec![Replacement {
+                    file: "src/lib.rs".to_string(),
+                    span: SourceSpan {
+                        line_start: 4,
+                        column_start: 9,
+                        line_end: 4,
+                        column_end: 13,
+                        byte_start: 60,
+                        byte_end: 64,
+                    },
+                    replacement: String::new(),
+                }],
+            }]
+        );
+        assert!(parse_suggestions(&output, &[], true).is_empty());
+        assert!(parse_suggestions(&output, &["other".to_string()], false).is_empty());
+    }
+
+    #[test]
+    fn test_parse_text_diagnostics() {
+        let output = "   Compiling td v0.1.0 (/tmp/td)
+warning: unused variable: `x`
+ --> src/main.rs:2:9
+  |
+2 |     let x = 1;
+  |         ^ help: if this is intentional, prefix it with an underscore: `_x`
+error[E0425]: cannot find value `y` in this scope
+  --> src/main.rs:10:5
+   |
+error: could not compile `td` (bin \"td\") due to 1 previous error; 1 warning emitted";
+        assert_eq!(
+            parse_text_diagnostics(output),
+            vec![
+                QuickfixEntry::new("src/main.rs", 2, 9, "unused variable: `x`", "W"),
+                QuickfixEntry::new(
+                    "src/main.rs",
+                    10,
+                    5,
+                    "[E0425] cannot find value `y` in this scope",
+                    "E"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_count_warnings() {
+        let human = "warning: unused manifest key: package.autor
+   Compiling wc v0.1.0 (/tmp/wc)
+warning: unused variable: `x`
+ --> src/main.rs:1:14
+  |
+warning: function `a` is never used
+ --> src/main.rs:1:4
+warning: `wc` (bin \"wc\" test) generated 2 warnings (run `cargo fix --bin \"wc\" -p wc --tests` to apply 1 suggestion)
+warning: `wc` (bin \"wc\") generated 2 warnings (2 duplicates)
+warning: 2 warnings emitted
+warning[E0170]: pattern binding `A` is named the same as one of the variants
+ --> src/lib.rs:3:9
+warning: unused variable: `x`
+ --> src/main.rs:1:14
+    Finished `dev` profile [unoptimized + debuginfo] target(s) in 0.13s";
+        assert_eq!(count_warnings(human), 3);
+
+        let warning = ERROR
+            .replace(r#""level":"error""#, r#""level":"warning""#)
+            .replace("mismatched types", "unused variable");
+        let summary = FAILURE_NOTE
+            .replace(r#""level":"failure-note""#, r#""level":"warning""#)
+            .replace(
+                "For more information about this error, try `rustc --explain E0308`.",
+                "1 warning emitted",
+            );
+        let json = format!("{}\n{}\n{}\n{}", warning, warning, ERROR, summary);
+        assert_eq!(count_warnings(&json), 1);
+        assert_eq!(count_warnings("    Finished `dev` profile"), 0);
+    }
+}
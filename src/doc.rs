@@ -0,0 +1,194 @@
+// src/doc.rs
+//! Which `index.html` to open after `cargo doc`
+//!
+//! In a workspace cargo documents every member but has no index of its own (unless
+//! rustdoc's unstable `--enable-index-page` made one), so each member's index is listed
+//! and one is picked: the requested package, the workspace index, the only member, or
+//! the one cargo itself named.
+
+use crate::metadata::{Metadata, PackageInfo};
+use mlua::prelude::*;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Documentation generated by `cargo doc`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DocResult {
+    /// The index to open, if one exists
+    pub index: Option<String>,
+    /// Index of each workspace member that has docs
+    pub crates: Vec<DocCrate>,
+}
+
+/// Docs of one package
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DocCrate {
+    pub package: String,
+    /// Name rustdoc used for the crate's directory (`my-lib` -> `my_lib`)
+    pub crate_name: String,
+    pub index: String,
+}
+
+/// The index cargo names in `Generated /ws/target/doc/app/index.html and 1 other file`
+pub fn parse_generated(output: &str) -> Option<PathBuf> {
+    output.lines().rev().find_map(|line| {
+        let path = line.trim().strip_prefix("Generated ")?;
+        let path = path.split(" and ").next().unwrap_or(path);
+        path.ends_with("index.html").then(|| PathBuf::from(path))
+    })
+}
+
+// Directory rustdoc uses for a package: its library target, else its first binary,
+// with dashes turned into underscores like crate names
+fn crate_name(package: &PackageInfo) -> String {
+    let target = package
+        .targets
+        .iter()
+        .find(|t| {
+            t.kind
+                .iter()
+                .any(|k| k.ends_with("lib") || k == "proc-macro")
+        })
+        .or_else(|| {
+            package
+                .targets
+                .iter()
+                .find(|t| t.kind.iter().any(|k| k == "bin"))
+        });
+    target
+        .map(|t| t.name.as_str())
+        .unwrap_or(&package.name)
+        .replace('-', "_")
+}
+
+/// List the members' indexes under `doc_dir` and pick the one to open
+///
+/// `package` selects a member by package or crate name; it is an error when that
+/// member has no docs.
+pub fn resolve_doc_index(
+    doc_dir: &Path,
+    metadata: Option<&Metadata>,
+    generated: Option<&Path>,
+    package: Option<&str>,
+) -> LuaResult<DocResult> {
+    let crates: Vec<DocCrate> = metadata
+        .map(|m| m.packages.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|package| {
+            let crate_name = crate_name(package);
+            let index = doc_dir.join(&crate_name).join("index.html");
+            index.is_file().then(|| DocCrate {
+                package: package.name.clone(),
+                crate_name,
+                index: index.to_string_lossy().into_owned(),
+            })
+        })
+        .collect();
+
+    let index = if let Some(wanted) = package {
+        let found = crates
+            .iter()
+            .find(|c| c.package == wanted || c.crate_name == wanted);
+        match found {
+            Some(found) => Some(found.index.clone()),
+            None => {
+                let available: Vec<&str> = crates.iter().map(|c| c.package.as_str()).collect();
+                return Err(LuaError::RuntimeError(format!(
+                    "No docs for package {:?} (documented: {})",
+                    wanted,
+                    available.join(", ")
+                )));
+            }
+        }
+    } else {
+        let workspace_index = doc_dir.join("index.html");
+        if workspace_index.is_file() {
+            Some(workspace_index.to_string_lossy().into_owned())
+        } else if let [only] = crates.as_slice() {
+            Some(only.index.clone())
+        } else {
+            generated
+                .filter(|path| path.is_file())
+                .map(|path| path.to_string_lossy().into_owned())
+                .or_else(|| crates.first().map(|c| c.index.clone()))
+        }
+    };
+
+    Ok(DocResult { index, crates })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::TargetInfo;
+    use std::collections::BTreeMap;
+
+    fn package(name: &str, targets: &[(&str, &str)]) -> PackageInfo {
+        PackageInfo {
+            name: name.to_string(),
+            version: "0.1.0".to_string(),
+            manifest_path: format!("/ws/{}/Cargo.toml", name),
+            targets: targets
+                .iter()
+                .map(|(name, kind)| TargetInfo {
+                    name: name.to_string(),
+                    kind: vec![kind.to_string()],
+                    src_path: String::new(),
+                    required_features: Vec::new(),
+                })
+                .collect(),
+            features: BTreeMap::new(),
+            default_features: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_generated() {
+        let output = "    Finished `dev` profile [unoptimized + debuginfo] target(s) in 1.14s\n   Generated /ws/target/doc/my_app/index.html and 1 other file\n";
+        assert_eq!(
+            parse_generated(output),
+            Some(PathBuf::from("/ws/target/doc/my_app/index.html"))
+        );
+        assert_eq!(parse_generated("error: could not compile"), None);
+    }
+
+    #[test]
+    fn test_resolve_doc_index() {
+        let doc_dir =
+            std::env::temp_dir().join(format!("cargo-nvim-doc-index-{}", std::process::id()));
+        for name in ["my_app", "mylib"] {
+            std::fs::create_dir_all(doc_dir.join(name)).unwrap();
+            std::fs::write(doc_dir.join(name).join("index.html"), "").unwrap();
+        }
+        let metadata = Metadata {
+            packages: vec![
+                package("my-app", &[("my-app", "bin")]),
+                package("my-lib", &[("mylib", "lib")]),
+                package("undocumented", &[("undocumented", "lib")]),
+            ],
+            workspace_members: Vec::new(),
+            workspace_default_members: Vec::new(),
+            workspace_root: None,
+            target_directory: None,
+        };
+        let index = |name: &str| doc_dir.join(name).join("index.html");
+
+        // Without a workspace index, cargo's choice is kept
+        let result =
+            resolve_doc_index(&doc_dir, Some(&metadata), Some(&index("mylib")), None).unwrap();
+        assert_eq!(result.crates.len(), 2);
+        assert_eq!(result.crates[0].crate_name, "my_app");
+        assert_eq!(result.index.as_deref(), index("mylib").to_str());
+
+        let result = resolve_doc_index(&doc_dir, Some(&metadata), None, Some("my-app")).unwrap();
+        assert_eq!(result.index.as_deref(), index("my_app").to_str());
+        assert!(resolve_doc_index(&doc_dir, Some(&metadata), None, Some("undocumented")).is_err());
+
+        std::fs::write(doc_dir.join("index.html"), "").unwrap();
+        let result = resolve_doc_index(&doc_dir, Some(&metadata), None, None).unwrap();
+        assert_eq!(result.index.as_deref(), doc_dir.join("index.html").to_str());
+
+        std::fs::remove_dir_all(&doc_dir).unwrap();
+    }
+}
@@ -0,0 +1,209 @@
+// src/doctest.rs
+//! Parsing of `cargo test --doc` results
+//!
+//! Doctests are named after their location rather than a test function:
+//! `src/lib.rs - Foo::bar (line 10)`, where the line is the opening fence.
+
+use crate::panic::parse_panic;
+use crate::quickfix::QuickfixEntry;
+use serde::Serialize;
+
+/// Result of a single doctest
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DoctestResult {
+    pub file: String,
+    /// Documented item, e.g. `Foo::bar`
+    pub item: String,
+    /// Line of the code block's opening fence
+    pub line: u32,
+    /// `ok`, `failed` or `ignored`
+    pub status: String,
+    /// Failure output (compiler errors or the panic), for failed doctests
+    pub message: Option<String>,
+}
+
+impl DoctestResult {
+    pub fn failed(&self) -> bool {
+        self.status == "failed"
+    }
+
+    /// Quickfix entry for a failed doctest
+    ///
+    /// Compile errors carry a real file location, so the first one is used; panics
+    /// report lines of the generated test program, so those point at the code block.
+    pub fn to_quickfix(&self) -> Option<QuickfixEntry> {
+        if !self.failed() {
+            return None;
+        }
+        let message = self.message.as_deref().unwrap_or_default();
+        let (lnum, col) = compile_error_location(message, &self.file).unwrap_or((self.line, 1));
+        let reason = match parse_panic(message) {
+            Some(panic) => Some(panic.message),
+            None => message
+                .lines()
+                .find(|l| l.starts_with("error"))
+                .map(str::to_string),
+        };
+        let text = match reason {
+            Some(reason) => format!("{}: {}", self.item, reason),
+            None => format!("doctest {} failed", self.item),
+        };
+        Some(QuickfixEntry::error(&self.file, lnum, col, &text))
+    }
+}
+
+/// Parse the per-test status lines and failure sections of doctest output
+pub fn parse_doctests(output: &str) -> Vec<DoctestResult> {
+    let mut results: Vec<DoctestResult> = output
+        .lines()
+        .filter_map(|line| {
+            let rest = line.strip_prefix("test ")?;
+            let (name, status) = rest.rsplit_once(" ... ")?;
+            let (file, item, line) = parse_doctest_name(name)?;
+            let status = match status.trim() {
+                "ok" => "ok",
+                "FAILED" => "failed",
+                s if s.starts_with("ignored") => "ignored",
+                _ => return None,
+            };
+            Some(DoctestResult {
+                file,
+                item,
+                line,
+                status: status.to_string(),
+                message: None,
+            })
+        })
+        .collect();
+
+    // Failure output follows `---- <name> stdout ----` headers
+    let mut current: Option<(String, u32, Vec<&str>)> = None;
+    let mut flush = |current: &mut Option<(String, u32, Vec<&str>)>| {
+        if let Some((file, line, body)) = current.take() {
+            if let Some(result) = results
+                .iter_mut()
+                .find(|r| r.file == file && r.line == line)
+            {
+                result.message = Some(body.join("\n").trim().to_string());
+            }
+        }
+    };
+    for line in output.lines() {
+        if let Some(name) = line
+            .strip_prefix("---- ")
+            .and_then(|l| l.strip_suffix(" stdout ----"))
+        {
+            flush(&mut current);
+            current = parse_doctest_name(name).map(|(file, _, line)| (file, line, Vec::new()));
+        } else if line == "failures:" {
+            flush(&mut current);
+        } else if let Some((_, _, body)) = current.as_mut() {
+            body.push(line);
+        }
+    }
+    flush(&mut current);
+
+    results
+}
+
+/// Split `src/lib.rs - Foo::bar (line 10)`, ignoring suffixes like ` - compile fail`
+fn parse_doctest_name(name: &str) -> Option<(String, String, u32)> {
+    let (file, rest) = name.split_once(" - ")?;
+    let start = rest.find(" (line ")?;
+    let line_part = &rest[start + " (line ".len()..];
+    let line = line_part[..line_part.find(')')?].parse().ok()?;
+    Some((file.to_string(), rest[..start].to_string(), line))
+}
+
+// First `--> file:line:col` in the failure output that points into `file`
+fn compile_error_location(message: &str, file: &str) -> Option<(u32, u32)> {
+    message.lines().find_map(|l| {
+        let location = l.trim_start().strip_prefix("--> ")?;
+        let (path, rest) = location.split_once(':')?;
+        if path != file {
+            return None;
+        }
+        let (line, col) = rest.split_once(':')?;
+        Some((line.parse().ok()?, col.trim().parse().ok()?))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: &str = "\
+   Doc-tests dt
+
+running 4 tests
+test src/lib.rs - add (line 1) ... ok
+test src/lib.rs - bad (line 6) ... FAILED
+test src/lib.rs - ign (line 16) ... ignored
+test src/lib.rs - nocompile (line 11) ... FAILED
+
+failures:
+
+---- src/lib.rs - bad (line 6) stdout ----
+Test executable failed (exit status: 101).
+
+stderr:
+
+thread 'main' (16460) panicked at src/lib.rs:5:1:
+assertion `left == right` failed
+
+---- src/lib.rs - nocompile (line 11) stdout ----
+error[E0308]: mismatched types
+  --> src/lib.rs:12:14
+   |
+12 | let x: i32 = \"s\";
+   |        ---   ^^^ expected `i32`, found `&str`
+
+Couldn't compile the test.
+
+failures:
+    src/lib.rs - bad (line 6)
+    src/lib.rs - nocompile (line 11)
+
+test result: FAILED. 1 passed; 2 failed; 1 ignored; 0 measured; 0 filtered out; finished in 0.17s
+";
+
+    #[test]
+    fn test_parse_doctest_statuses() {
+        let results = parse_doctests(OUTPUT);
+        let summary: Vec<(&str, u32, &str)> = results
+            .iter()
+            .map(|r| (r.item.as_str(), r.line, r.status.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("add", 1, "ok"),
+                ("bad", 6, "failed"),
+                ("ign", 16, "ignored"),
+                ("nocompile", 11, "failed"),
+            ]
+        );
+        assert!(results[0].message.is_none());
+        assert!(results[1]
+            .message
+            .as_deref()
+            .unwrap()
+            .contains("assertion `left == right` failed"));
+    }
+
+    #[test]
+    fn test_doctest_quickfix_locations() {
+        let results = parse_doctests(OUTPUT);
+        assert!(results[0].to_quickfix().is_none());
+
+        // Panics point at the code block
+        let entry = results[1].to_quickfix().unwrap();
+        assert_eq!((entry.filename.as_str(), entry.lnum), ("src/lib.rs", 6));
+        assert_eq!(entry.text, "bad: assertion `left == right` failed");
+
+        // Compile errors point at the offending line
+        let entry = results[3].to_quickfix().unwrap();
+        assert_eq!((entry.lnum, entry.col), (12, 14));
+        assert_eq!(entry.text, "nocompile: error[E0308]: mismatched types");
+    }
+}
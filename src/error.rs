@@ -1,19 +1,208 @@
 // src/error.rs
+use crate::compile_failure::{summarize, CompileFailure};
+use crate::manifest_error::ManifestError;
+use crate::outcome::CommandOutcome;
+use crate::signal::{signal_name, Termination, SIGKILL};
 use std::fmt;
 
 #[derive(Debug)]
 pub enum Error {
-    CommandFailed { command: String, details: String },
+    /// A command ran but exited unsuccessfully
+    CommandFailed {
+        /// The command as typed, e.g. `cargo build`
+        command: String,
+        details: String,
+    },
+    /// The program to run isn't installed or isn't on `PATH`
+    NotFound {
+        program: String,
+        details: String,
+    },
+    /// The registry refused the request because of rate limiting
+    RateLimited {
+        command: String,
+        retry_after_secs: Option<u64>,
+        details: String,
+    },
+    /// The project config doesn't allow running this subcommand
+    CommandDenied {
+        command: String,
+    },
+    /// A command was stopped at its timeout (`timeout_secs`)
+    TimedOut {
+        command: String,
+        timeout_secs: u64,
+        termination: Termination,
+    },
+    /// An interactive program was stopped at its extended timeout, most likely
+    /// while waiting for input
+    AwaitingInputTimeout {
+        command: String,
+        timeout_secs: u64,
+        termination: Termination,
+    },
+    /// The command, or a process it started (e.g. rustc), was killed by a signal
+    KilledBySignal {
+        command: String,
+        signal: i32,
+        details: String,
+    },
+    /// Crates failed to compile; `failures` comes from cargo's `could not compile` lines
+    CompileFailed {
+        command: String,
+        failures: Vec<CompileFailure>,
+        details: String,
+    },
+    /// A `Cargo.toml` couldn't be parsed; `error` has the location cargo reported
+    ManifestError {
+        command: String,
+        error: ManifestError,
+        details: String,
+    },
+    /// rustup was asked for a toolchain that isn't installed (`toolchain` option,
+    /// `rust-toolchain.toml`, `RUSTUP_TOOLCHAIN`)
+    ToolchainNotInstalled {
+        command: String,
+        toolchain: String,
+        details: String,
+    },
+    /// The target can't be built without features that aren't enabled
+    MissingFeatures {
+        command: String,
+        target: String,
+        features: Vec<String>,
+    },
+    /// A command that needs `-Z unstable-options` was run with a stable or beta cargo
+    RequiresNightly {
+        command: String,
+        /// `cargo --version` of the cargo that would have run it
+        found: String,
+    },
     RuntimeError(String),
     IoError(std::io::Error),
 }
 
+impl Error {
+    /// Stable name of the kind of error, for callers that react to specific failures
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::CommandFailed { .. } => "command_failed",
+            Error::NotFound { .. } => "not_found",
+            Error::RateLimited { .. } => "rate_limited",
+            Error::CommandDenied { .. } => "command_denied",
+            Error::TimedOut { .. } => "timed_out",
+            Error::AwaitingInputTimeout { .. } => "awaiting_input_timeout",
+            Error::KilledBySignal { .. } => "killed_by_signal",
+            Error::CompileFailed { .. } => "compile_failed",
+            Error::ManifestError { .. } => "manifest_error",
+            Error::ToolchainNotInstalled { .. } => "toolchain_not_installed",
+            Error::MissingFeatures { .. } => "missing_features",
+            Error::RequiresNightly { .. } => "requires_nightly",
+            Error::RuntimeError(_) => "runtime_error",
+            Error::IoError(_) => "io_error",
+        }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::CommandFailed { command, details } => {
-                write!(f, "Cargo command '{}' failed: {}", command, details)
+                write!(f, "{} failed: {}", command, details)
+            }
+            Error::NotFound { program, details } => {
+                write!(f, "{} is not installed: {}", program, details)
+            }
+            Error::RateLimited {
+                command,
+                retry_after_secs,
+                details,
+            } => {
+                write!(f, "cargo {} was rate limited by the registry", command)?;
+                if let Some(secs) = retry_after_secs {
+                    write!(f, " (retry after {} seconds)", secs)?;
+                }
+                write!(f, ": {}", details)
+            }
+            Error::CommandDenied { command } => {
+                write!(f, "cargo {} is denied by the project config", command)
+            }
+            Error::TimedOut {
+                command,
+                timeout_secs,
+                termination,
+            } => write!(
+                f,
+                "cargo {} timed out after {} seconds; {}",
+                command, timeout_secs, termination
+            ),
+            Error::AwaitingInputTimeout {
+                command,
+                timeout_secs,
+                termination,
+            } => write!(
+                f,
+                "cargo {} was stopped after {} seconds ({}): the program was waiting for input; use send_input",
+                command, timeout_secs, termination
+            ),
+            Error::KilledBySignal {
+                command,
+                signal,
+                details,
+            } => {
+                write!(f, "{} was killed by signal {}", command, signal)?;
+                if let Some(name) = signal_name(*signal) {
+                    write!(f, " ({})", name)?;
+                }
+                if *signal == SIGKILL {
+                    write!(f, "; the system may have run out of memory")?;
+                }
+                write!(f, ": {}", details)
             }
+            Error::CompileFailed {
+                command,
+                failures,
+                details,
+            } => write!(
+                f,
+                "{} could not compile {}: {}",
+                command,
+                summarize(failures),
+                details
+            ),
+            Error::ManifestError {
+                command,
+                error,
+                details,
+            } => write!(f, "{} failed to read {}: {}", command, error, details),
+            Error::ToolchainNotInstalled {
+                command,
+                toolchain,
+                details,
+            } => write!(
+                f,
+                "{} needs the toolchain {}, which is not installed; install it with \
+                 `rustup toolchain install {}`: {}",
+                command, toolchain, toolchain, details
+            ),
+            Error::MissingFeatures {
+                command,
+                target,
+                features,
+            } => write!(
+                f,
+                "{}: target `{}` requires the features {}; pass --features \"{}\"",
+                command,
+                target,
+                features.join(", "),
+                features.join(" ")
+            ),
+            Error::RequiresNightly { command, found } => write!(
+                f,
+                "{} requires a nightly cargo, for -Z unstable-options (found: {}); select one \
+                 with rust-toolchain.toml or RUSTUP_TOOLCHAIN=nightly",
+                command, found
+            ),
             Error::RuntimeError(msg) => write!(f, "Runtime error: {}", msg),
             Error::IoError(err) => write!(f, "IO error: {}", err),
         }
@@ -28,8 +217,109 @@ impl From<std::io::Error> for Error {
     }
 }
 
+/// Lua only sees the message, so it starts with the kind in brackets
+/// (e.g. `[rate_limited] cargo publish was rate limited ...`)
 impl From<Error> for mlua::Error {
     fn from(err: Error) -> Self {
-        mlua::Error::RuntimeError(err.to_string())
+        mlua::Error::RuntimeError(format!("[{}] {}", err.kind(), err))
+    }
+}
+
+/// A command that ran and failed, with the result it would have returned otherwise (exit
+/// code, quickfix list, ...); Lua gets it as an error table whose `details` is that result
+#[derive(Debug)]
+pub struct CommandFailure {
+    pub error: Error,
+    pub outcome: Box<CommandOutcome>,
+}
+
+impl fmt::Display for CommandFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.error.kind(), self.error)
+    }
+}
+
+impl std::error::Error for CommandFailure {}
+
+/// Kept whole, so the Lua exports can find the result with `downcast_ref`
+impl From<CommandFailure> for mlua::Error {
+    fn from(failure: CommandFailure) -> Self {
+        mlua::Error::external(failure)
+    }
+}
+
+/// Error for a program that couldn't be started; a missing program gets the
+/// `not_found` kind so callers can offer to install it, or says where it was set when it
+/// is the cargo of `set_cargo_bin`
+pub fn spawn_error(program: &str, err: std::io::Error) -> mlua::Error {
+    if err.kind() == std::io::ErrorKind::NotFound
+        && crate::options::cargo_bin().as_deref() == Some(program)
+    {
+        crate::options::cargo_bin_not_found(program, &err.to_string())
+    } else if err.kind() == std::io::ErrorKind::NotFound {
+        Error::NotFound {
+            program: program.to_string(),
+            details: err.to_string(),
+        }
+        .into()
+    } else {
+        mlua::Error::RuntimeError(format!("Failed to execute {}: {}", program, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_program_is_not_found() {
+        let err = std::process::Command::new("cargo-nvim-no-such-program")
+            .output()
+            .unwrap_err();
+        let message = spawn_error("cargo-nvim-no-such-program", err).to_string();
+        assert!(
+            message.contains("[not_found] cargo-nvim-no-such-program is not installed"),
+            "{}",
+            message
+        );
+
+        let err = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        assert!(!spawn_error("cargo", err)
+            .to_string()
+            .contains("[not_found]"));
+    }
+
+    #[test]
+    fn test_toolchain_not_installed() {
+        let err: mlua::Error = Error::ToolchainNotInstalled {
+            command: "cargo build".to_string(),
+            toolchain: "nightly".to_string(),
+            details: "error: toolchain 'nightly' is not installed".to_string(),
+        }
+        .into();
+        let message = err.to_string();
+        assert!(
+            message.contains("[toolchain_not_installed] cargo build needs the toolchain nightly"),
+            "{}",
+            message
+        );
+        assert!(message.contains("`rustup toolchain install nightly`"));
+    }
+
+    #[test]
+    fn test_killed_by_signal_hints_at_oom() {
+        let err: mlua::Error = Error::KilledBySignal {
+            command: "cargo build".to_string(),
+            signal: SIGKILL,
+            details: String::new(),
+        }
+        .into();
+        let message = err.to_string();
+        assert!(
+            message.contains("[killed_by_signal] cargo build was killed by signal 9 (SIGKILL)"),
+            "{}",
+            message
+        );
+        assert!(message.contains("out of memory"), "{}", message);
     }
 }
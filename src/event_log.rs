@@ -0,0 +1,128 @@
+// src/event_log.rs
+//! Newline-delimited JSON transport for events, for consumers outside Neovim
+use crate::events::{Event, EventSender};
+use mlua::prelude::*;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use tokio::sync::mpsc;
+
+/// Where to write the event log
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventLogTarget {
+    /// Appended to, so consecutive commands share one log
+    File(String),
+    /// An already open descriptor inherited by Neovim (e.g. a pipe)
+    Fd(i32),
+}
+
+/// Start writing events to `target`, one JSON object per line
+///
+/// Writing happens on a dedicated thread so a slow consumer never holds up reading the
+/// command's output; the thread finishes once every clone of the returned sender is gone.
+pub fn spawn_event_log(target: &EventLogTarget) -> LuaResult<EventSender> {
+    let file = open_target(target)?;
+    let (tx, mut rx) = mpsc::unbounded_channel::<Event>();
+
+    std::thread::spawn(move || {
+        let mut writer = BufWriter::new(file);
+        while let Some(event) = rx.blocking_recv() {
+            let written = serde_json::to_writer(&mut writer, &event)
+                .map_err(std::io::Error::from)
+                .and_then(|_| writer.write_all(b"\n"))
+                // Flush per event so readers see it as it happens
+                .and_then(|_| writer.flush());
+            if written.is_err() {
+                // The reader is gone; stop logging rather than failing the command
+                break;
+            }
+        }
+    });
+
+    Ok(tx)
+}
+
+fn open_target(target: &EventLogTarget) -> LuaResult<File> {
+    match target {
+        EventLogTarget::File(path) => OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| {
+                LuaError::RuntimeError(format!("Failed to open event log {}: {}", path, e))
+            }),
+        EventLogTarget::Fd(fd) => open_fd(*fd),
+    }
+}
+
+#[cfg(unix)]
+fn open_fd(fd: i32) -> LuaResult<File> {
+    use std::os::fd::BorrowedFd;
+
+    // 0 is stdin, never a log destination
+    if fd < 1 {
+        return Err(LuaError::RuntimeError(format!(
+            "Invalid event log fd: {}",
+            fd
+        )));
+    }
+    // Duplicate the descriptor so dropping the log doesn't close the caller's fd.
+    // SAFETY: the borrow only lives for the `dup` call, which fails cleanly with
+    // EBADF if the descriptor isn't open.
+    let borrowed = unsafe { BorrowedFd::borrow_raw(fd) };
+    borrowed
+        .try_clone_to_owned()
+        .map(File::from)
+        .map_err(|e| LuaError::RuntimeError(format!("Invalid event log fd {}: {}", fd, e)))
+}
+
+#[cfg(not(unix))]
+fn open_fd(_fd: i32) -> LuaResult<File> {
+    Err(LuaError::RuntimeError(
+        "event_fd is only supported on Unix".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::publish;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_events_written_as_ndjson() {
+        let path =
+            std::env::temp_dir().join(format!("cargo-nvim-events-{}.ndjson", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let log =
+            spawn_event_log(&EventLogTarget::File(path.to_string_lossy().into_owned())).unwrap();
+        publish(
+            &[log],
+            Event::Stdout {
+                line: "hello \"world\"".to_string(),
+            },
+        );
+
+        // The writer thread flushes on its own schedule
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let contents = loop {
+            let contents = std::fs::read_to_string(&path).unwrap_or_default();
+            if contents.ends_with('\n') || Instant::now() > deadline {
+                break contents;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(
+            contents,
+            "{\"type\":\"stdout\",\"data\":\"hello \\\"world\\\"\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_invalid_fd_rejected() {
+        assert!(spawn_event_log(&EventLogTarget::Fd(0)).is_err());
+        assert!(spawn_event_log(&EventLogTarget::Fd(-1)).is_err());
+    }
+}
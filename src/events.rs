@@ -0,0 +1,91 @@
+// src/events.rs
+use crate::diagnostics::{Diagnostic, Suggestion};
+use crate::quickfix::QuickfixEntry;
+use crate::tree::TreeNode;
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+/// Events published while a command is running
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// A line read from the command's standard output, as `data`
+    Stdout {
+        #[serde(rename = "data")]
+        line: String,
+    },
+    /// A line read from the command's standard error, as `data`
+    Stderr {
+        #[serde(rename = "data")]
+        line: String,
+    },
+    /// Cargo started compiling, checking or documenting a package (`action` is
+    /// `Compiling`, `Checking` or `Documenting`), read from its status lines
+    Progress {
+        action: String,
+        package: String,
+        version: String,
+    },
+    /// Cargo is downloading dependencies, before compiling; sent when the download
+    /// starts and for each crate downloaded. `total` is only known from cargo's closing
+    /// `Downloaded N crates` line (or a `Downloading N crates` line, where printed)
+    Downloading {
+        current: usize,
+        total: Option<usize>,
+    },
+    /// A build produced an executable (`--message-format=json` only)
+    /// `fresh` is true when the binary was reused from a previous build
+    Artifact {
+        target: String,
+        path: String,
+        fresh: bool,
+    },
+    /// rustc reported a diagnostic (`--message-format=json` only), sent as soon as it
+    /// is read; `quickfix` is absent for diagnostics without a location, and
+    /// `suggestions` holds its machine-applicable fixes
+    Diagnostic {
+        diagnostic: Diagnostic,
+        quickfix: Option<QuickfixEntry>,
+        suggestions: Vec<Suggestion>,
+    },
+    /// A step of a sequence is about to start
+    StepStart { index: usize, subcommand: String },
+    /// A step of a sequence has finished
+    StepFinish {
+        index: usize,
+        subcommand: String,
+        success: bool,
+    },
+    /// The registry rate limited a command, which is retried after `wait_secs`
+    RateLimited {
+        command: String,
+        wait_secs: u64,
+        attempt: u32,
+    },
+    /// The project's `.cargo-nvim.toml` couldn't be read and was ignored
+    ConfigWarning { path: String, message: String },
+    /// The cargo in use is older than `required` for `feature`, so the command ran
+    /// without it; `message` says what was done instead
+    VersionFallback {
+        feature: String,
+        required: String,
+        found: String,
+        message: String,
+    },
+    /// `cargo tree` printed a package (without its children, which follow); it is a
+    /// child of the last package sent at `depth - 1`, and a root at depth 0
+    TreeNode { depth: usize, node: TreeNode },
+}
+
+/// Channel used to publish events from the runtime to the Lua side
+pub type EventSender = mpsc::UnboundedSender<Event>;
+
+/// Send an event to every listener; listeners that went away are skipped
+pub fn publish(listeners: &[EventSender], event: Event) {
+    if let Some((last, rest)) = listeners.split_last() {
+        for listener in rest {
+            let _ = listener.send(event.clone());
+        }
+        let _ = last.send(event);
+    }
+}
@@ -0,0 +1,72 @@
+// src/examples.rs
+//! Examples of the workspace, for a run-example picker, and the features they require
+use crate::metadata::Metadata;
+use serde::Serialize;
+
+/// An example target (`examples/*.rs` or an `[[example]]` entry)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExampleTarget {
+    pub name: String,
+    pub package: String,
+    pub src_path: String,
+    /// Features that must be enabled (`--features`) to build it
+    pub required_features: Vec<String>,
+}
+
+/// Examples of the workspace members, in metadata order
+pub fn list_examples(metadata: &Metadata) -> Vec<ExampleTarget> {
+    metadata
+        .packages
+        .iter()
+        .filter(|p| metadata.workspace_members.contains(&p.name))
+        .flat_map(|package| {
+            package
+                .targets
+                .iter()
+                .filter(|t| t.kind.iter().any(|k| k == "example"))
+                .map(|target| ExampleTarget {
+                    name: target.name.clone(),
+                    package: package.name.clone(),
+                    src_path: target.src_path.clone(),
+                    required_features: target.required_features.clone(),
+                })
+        })
+        .collect()
+}
+
+/// The target and features named by cargo's
+/// ``error: target `fancy` in package `app` requires the features: `a`, `b` ``
+pub fn parse_missing_features(output: &str) -> Option<(String, Vec<String>)> {
+    output.lines().find_map(|line| {
+        let rest = line.trim().strip_prefix("error: target `")?;
+        let (target, rest) = rest.split_once('`')?;
+        let (_, features) = rest.split_once("requires the features: ")?;
+        let features: Vec<String> = features
+            .split(", ")
+            .map(|f| f.trim().trim_matches('`').to_string())
+            .filter(|f| !f.is_empty())
+            .collect();
+        (!features.is_empty()).then(|| (target.to_string(), features))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_missing_features() {
+        let output = "error: target `fancy` in package `exf` requires the features: `fancy`, `extra`\nConsider enabling them by passing, e.g., `--features=\"fancy extra\"`\n";
+        assert_eq!(
+            parse_missing_features(output),
+            Some((
+                "fancy".to_string(),
+                vec!["fancy".to_string(), "extra".to_string()]
+            ))
+        );
+        assert_eq!(
+            parse_missing_features("error: no example target named `x`"),
+            None
+        );
+    }
+}
@@ -0,0 +1,152 @@
+// src/fast_check.rs
+//! Debounced background `cargo check` for feedback on save
+//!
+//! Every call supersedes the previous one: a check still waiting out its debounce
+//! delay never starts, and one already running is killed. Results are polled by id.
+
+use crate::cancel::{CancelReason, CancelToken};
+use crate::options::CommandOptions;
+use crate::quickfix::{ErrorLocation, QuickfixEntry};
+use crate::CargoCommands;
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// State of a fast check, as returned to Lua
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum FastCheckStatus {
+    /// Waiting out the debounce delay or still running
+    Pending,
+    /// A newer check was started before this one finished
+    Superseded,
+    Done {
+        quickfix: Vec<QuickfixEntry>,
+        /// The first error of `quickfix` in source order
+        first_error: Option<ErrorLocation>,
+        /// Wall time of the cargo invocation itself
+        elapsed_ms: u64,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+struct FastCheckState {
+    latest: u64,
+    cancel: Option<CancelToken>,
+    finished: Option<(u64, FastCheckStatus)>,
+}
+
+static FAST_CHECK: Mutex<FastCheckState> = Mutex::new(FastCheckState {
+    latest: 0,
+    cancel: None,
+    finished: None,
+});
+
+/// Start a check after `debounce`, cancelling any earlier one; returns its id
+pub fn start(options: CommandOptions, args: Vec<String>, debounce: Duration) -> u64 {
+    let cancel = CancelToken::default();
+    let id = {
+        let mut state = FAST_CHECK.lock().unwrap();
+        if let Some(previous) = state.cancel.replace(cancel.clone()) {
+            previous.cancel_with(CancelReason::Superseded);
+        }
+        state.latest += 1;
+        state.latest
+    };
+
+    std::thread::spawn(move || {
+        std::thread::sleep(debounce);
+        if cancel.is_cancelled() {
+            return;
+        }
+
+        let started = Instant::now();
+        // A runtime of its own, so the check never holds up commands run from Lua
+        let result = CargoCommands::new().and_then(|commands| {
+            let commands = commands
+                .with_options(options)
+                .with_cancel(cancel.clone())
+                .detached();
+            let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+            commands.execute(commands.cargo_check_fast(&args))
+        });
+        let status = match result {
+            Ok(outcome) => FastCheckStatus::Done {
+                first_error: outcome.first_error,
+                quickfix: outcome.quickfix,
+                elapsed_ms: started.elapsed().as_millis() as u64,
+            },
+            Err(err) => FastCheckStatus::Failed {
+                error: err.to_string(),
+            },
+        };
+
+        let mut state = FAST_CHECK.lock().unwrap();
+        if state.latest == id {
+            state.finished = Some((id, status));
+            state.cancel = None;
+        }
+    });
+
+    id
+}
+
+/// Current state of the check with the given id
+pub fn status(id: u64) -> FastCheckStatus {
+    let state = FAST_CHECK.lock().unwrap();
+    match &state.finished {
+        Some((finished, status)) if *finished == id => status.clone(),
+        _ if id < state.latest => FastCheckStatus::Superseded,
+        _ if id == state.latest => FastCheckStatus::Pending,
+        _ => FastCheckStatus::Failed {
+            error: format!("Unknown check id: {}", id),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::ScratchCrate;
+
+    fn wait_for(id: u64) -> FastCheckStatus {
+        let deadline = Instant::now() + Duration::from_secs(120);
+        loop {
+            let status = status(id);
+            if status != FastCheckStatus::Pending || Instant::now() > deadline {
+                return status;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn test_newer_check_supersedes_older() {
+        // A throwaway crate with one type error
+        let scratch = ScratchCrate::new(
+            "broken",
+            &[("src/lib.rs", "pub fn f() -> i32 {\n    \"s\"\n}\n")],
+        );
+        let args = vec!["--manifest-path".to_string(), scratch.manifest_path()];
+
+        let first = start(
+            CommandOptions::default(),
+            args.clone(),
+            Duration::from_millis(200),
+        );
+        let second = start(CommandOptions::default(), args, Duration::from_millis(10));
+        assert!(second > first);
+        assert_eq!(status(first), FastCheckStatus::Superseded);
+
+        match wait_for(second) {
+            FastCheckStatus::Done { quickfix, .. } => {
+                assert_eq!(quickfix.len(), 1);
+                assert_eq!((quickfix[0].lnum, quickfix[0].kind.as_str()), (2, "E"));
+            }
+            other => panic!("unexpected status: {:?}", other),
+        }
+        assert!(matches!(status(second + 1), FastCheckStatus::Failed { .. }));
+    }
+}
@@ -0,0 +1,211 @@
+// src/fmt.rs
+//! Formatting individual files with rustfmt, for format-on-save
+//!
+//! `cargo fmt` always formats the whole package. Running rustfmt directly on the files
+//! keeps the project's `rustfmt.toml`, which rustfmt finds from each file's directory;
+//! the edition `cargo fmt` would pass is read from the file's manifest.
+
+use crate::error::{spawn_error, Error};
+use crate::outcome::CommandOutcome;
+use crate::CargoCommands;
+use mlua::prelude::*;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+impl CargoCommands {
+    /// Format only `files`, returning the ones rustfmt changed in `formatted`; relative
+    /// paths are taken from the directory commands run in (`cwd`)
+    /// `args` are passed to rustfmt; with `--check`, files that need formatting make the
+    /// outcome unsuccessful (the diff is its output) rather than an error
+    pub async fn format_files(&self, files: &[String], args: &[&str]) -> LuaResult<CommandOutcome> {
+        let dir = self.cargo_dir().unwrap_or_default();
+        let paths: Vec<PathBuf> = files.iter().map(|file| dir.join(file)).collect();
+        // rustfmt needs the edition, which differs between packages of a workspace
+        let mut by_edition: BTreeMap<Option<String>, Vec<PathBuf>> = BTreeMap::new();
+        let mut outside = Vec::new();
+        for (file, path) in files.iter().zip(&paths) {
+            let path = path.clone();
+            match package_manifest(&path) {
+                Some(manifest) if path.is_file() => by_edition
+                    .entry(manifest_edition(&manifest))
+                    .or_default()
+                    .push(path),
+                _ => outside.push(file.as_str()),
+            }
+        }
+        if !outside.is_empty() {
+            return Err(LuaError::RuntimeError(format!(
+                "Not files of a Cargo package: {}",
+                outside.join(", ")
+            )));
+        }
+
+        let before: Vec<(&String, &PathBuf, Option<String>)> = files
+            .iter()
+            .zip(&paths)
+            .map(|(file, path)| (file, path, std::fs::read_to_string(path).ok()))
+            .collect();
+
+        let mut output = String::new();
+        let mut success = true;
+        let mut exit_code = Some(0);
+        for (edition, paths) in &by_edition {
+            let (mut cmd, launcher) = self.options.command("rustfmt")?;
+            if let Some(edition) = edition {
+                cmd.args(["--edition", edition]);
+            }
+            let result = cmd
+                .args(args)
+                .args(&self.options.tool_args)
+                .args(paths)
+                .output()
+                .await
+                .map_err(|e| spawn_error(&launcher, e))?;
+            output.push_str(&String::from_utf8_lossy(&result.stdout));
+            output.push_str(&String::from_utf8_lossy(&result.stderr));
+            if !result.status.success() {
+                success = false;
+                exit_code = result.status.code();
+            }
+        }
+        // rustfmt --check prints the diff of each file it would change and exits with 1
+        let unformatted = args.contains(&"--check") && output.contains("Diff in ");
+        if !success && !unformatted {
+            return Err(Error::CommandFailed {
+                command: "rustfmt".to_string(),
+                details: output,
+            }
+            .into());
+        }
+
+        let formatted = before
+            .into_iter()
+            .filter(|(_, path, contents)| std::fs::read_to_string(path).ok() != *contents)
+            .map(|(file, _, _)| file.clone())
+            .collect();
+        Ok(CommandOutcome {
+            output,
+            success,
+            exit_code,
+            formatted,
+            ..Default::default()
+        })
+    }
+}
+
+// The manifest of the package a file belongs to: the nearest `Cargo.toml` above it
+fn package_manifest(file: &Path) -> Option<PathBuf> {
+    let file = std::fs::canonicalize(file).ok()?;
+    file.ancestors()
+        .skip(1)
+        .map(|dir| dir.join("Cargo.toml"))
+        .find(|manifest| manifest.is_file())
+}
+
+// `package.edition`, following `edition.workspace = true` to the workspace manifest
+fn manifest_edition(manifest: &Path) -> Option<String> {
+    let table = read_manifest(manifest)?;
+    let edition = table.get("package")?.get("edition")?;
+    if let Some(edition) = edition.as_str() {
+        return Some(edition.to_string());
+    }
+    if edition.get("workspace")?.as_bool()? {
+        return manifest
+            .parent()?
+            .ancestors()
+            .skip(1)
+            .map(|dir| dir.join("Cargo.toml"))
+            .filter_map(|m| read_manifest(&m))
+            .find_map(|t| {
+                let edition = t.get("workspace")?.get("package")?.get("edition")?;
+                edition.as_str().map(str::to_string)
+            });
+    }
+    None
+}
+
+fn read_manifest(manifest: &Path) -> Option<toml::Table> {
+    std::fs::read_to_string(manifest).ok()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::ScratchCrate;
+
+    #[test]
+    fn test_format_files_reports_changes() {
+        let scratch = ScratchCrate::new(
+            "fmt_files",
+            &[
+                ("src/lib.rs", "pub mod tidy;\npub fn messy( )->i32{1}\n"),
+                ("src/tidy.rs", "pub fn tidy() {}\n"),
+                ("rustfmt.toml", "tab_spaces = 2\n"),
+            ],
+        );
+        let root = Path::new(&scratch.manifest_path())
+            .parent()
+            .unwrap()
+            .to_path_buf();
+        let lib = root.join("src/lib.rs").to_string_lossy().into_owned();
+        let tidy = root.join("src/tidy.rs").to_string_lossy().into_owned();
+
+        let commands = CargoCommands::new().unwrap();
+        // Files that need formatting fail the check without being touched
+        let in_root = commands.with_options(crate::CommandOptions {
+            cwd: Some(root.to_string_lossy().into_owned()),
+            ..Default::default()
+        });
+        let checked = in_root
+            .execute(in_root.format_files(&["src/lib.rs".to_string()], &["--check"]))
+            .unwrap();
+        assert!(!checked.success);
+        assert_eq!(checked.exit_code, Some(1));
+        assert!(checked.output.contains("Diff in "), "{}", checked.output);
+        assert!(checked.formatted.is_empty());
+
+        let outcome = commands
+            .execute(commands.format_files(&[lib.clone(), tidy], &[]))
+            .unwrap();
+        assert_eq!(outcome.formatted, vec![lib.clone()]);
+        // The project's rustfmt.toml was used
+        assert_eq!(
+            std::fs::read_to_string(&lib).unwrap(),
+            "pub mod tidy;\npub fn messy() -> i32 {\n  1\n}\n"
+        );
+        let checked = in_root
+            .execute(in_root.format_files(&["src/lib.rs".to_string()], &["--check"]))
+            .unwrap();
+        assert!(checked.success);
+
+        let outside = std::env::temp_dir().join("cargo-nvim-no-package.rs");
+        let result =
+            commands.execute(commands.format_files(&[outside.to_string_lossy().into_owned()], &[]));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_workspace_edition() {
+        let scratch = ScratchCrate::new("fmt_edition", &[]);
+        let root = Path::new(&scratch.manifest_path())
+            .parent()
+            .unwrap()
+            .to_path_buf();
+        std::fs::create_dir_all(root.join("member")).unwrap();
+        std::fs::write(
+            root.join("member/Cargo.toml"),
+            "[package]\nname = \"member\"\nedition.workspace = true\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"member\"]\n\n[workspace.package]\nedition = \"2024\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            manifest_edition(&root.join("member/Cargo.toml")).as_deref(),
+            Some("2024")
+        );
+    }
+}
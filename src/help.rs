@@ -0,0 +1,186 @@
+// src/help.rs
+use serde::Serialize;
+
+/// A single flag parsed from `cargo <subcommand> --help`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HelpOption {
+    /// Long flag (e.g. `--release`), or the short flag when no long form exists
+    pub flag: String,
+    /// Short alias (e.g. `-r`), if any
+    pub short: Option<String>,
+    /// Whether the flag expects a value (`--jobs <N>`)
+    pub takes_value: bool,
+    pub description: String,
+}
+
+/// Help for a subcommand, parsed when possible
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HelpText {
+    pub subcommand: Option<String>,
+    /// Parsed flags; empty when the help text could not be parsed
+    pub options: Vec<HelpOption>,
+    /// Raw help text, only kept when parsing found no options
+    pub raw: Option<String>,
+}
+
+impl HelpText {
+    /// Parse help output, falling back to the raw text
+    pub fn from_output(subcommand: Option<&str>, output: &str) -> Self {
+        let options = parse_help_options(output);
+        let raw = options.is_empty().then(|| output.to_string());
+        Self {
+            subcommand: subcommand.map(str::to_string),
+            options,
+            raw,
+        }
+    }
+}
+
+/// Parse the option sections of clap-style help output
+///
+/// Cargo groups flags under headings such as `Options:` or `Feature Selection:`;
+/// every indented line starting with `-` inside those sections is an option,
+/// and deeper-indented lines that follow continue its description.
+pub fn parse_help_options(text: &str) -> Vec<HelpOption> {
+    let mut options: Vec<HelpOption> = Vec::new();
+    let mut in_section = false;
+    // Whether the next indented non-flag line continues the last option
+    let mut continuing = false;
+
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            continuing = false;
+            continue;
+        }
+
+        // Section headings are unindented and end with a colon
+        if !line.starts_with(char::is_whitespace) {
+            in_section = line.trim_end().ends_with(':') && !line.starts_with("Usage:");
+            continuing = false;
+            continue;
+        }
+
+        if !in_section {
+            continue;
+        }
+
+        let trimmed = line.trim_start();
+        if trimmed.starts_with('-') {
+            if let Some(option) = parse_option_line(trimmed) {
+                options.push(option);
+                continuing = true;
+            }
+        } else if let Some(last) = options.last_mut().filter(|_| continuing) {
+            // Wrapped description line
+            if !last.description.is_empty() {
+                last.description.push(' ');
+            }
+            last.description.push_str(trimmed.trim_end());
+        }
+    }
+
+    options
+}
+
+/// Parse a single `-p, --package [<SPEC>]  Description` line
+fn parse_option_line(line: &str) -> Option<HelpOption> {
+    // The flag spec and description are separated by a run of at least two spaces
+    let (spec, description) = match line.find("  ") {
+        Some(idx) => (&line[..idx], line[idx..].trim()),
+        None => (line.trim_end(), ""),
+    };
+
+    let mut short = None;
+    let mut long = None;
+    for part in spec.split(", ") {
+        let name = part
+            .split(|c: char| c.is_whitespace() || c == '=')
+            .next()
+            .unwrap_or_default()
+            .trim_end_matches("...");
+        if name.starts_with("--") {
+            long = Some(name.to_string());
+        } else if name.starts_with('-') {
+            short = Some(name.to_string());
+        }
+    }
+
+    let flag = match (long, &short) {
+        (Some(long), _) => long,
+        (None, Some(short)) => short.clone(),
+        (None, None) => return None,
+    };
+    let short = short.filter(|s| *s != flag);
+
+    Some(HelpOption {
+        flag,
+        short,
+        takes_value: spec.contains('<'),
+        description: description.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUILD_HELP: &str = "\
+Compile a local package and all of its dependencies
+
+Usage: cargo build [OPTIONS]
+
+Options:
+      --message-format <FMT>     Error format [possible values: human, short, json,
+                                 json-diagnostic-short]
+  -v, --verbose...               Use verbose output (-vv very verbose/build.rs output)
+  -Z <FLAG>                      Unstable (nightly-only) flags to Cargo
+  -h, --help                     Print help
+
+Package Selection:
+  -p, --package [<SPEC>]  Package to build (see `cargo help pkgid`)
+      --workspace         Build all packages in the workspace
+
+Run `cargo help build` for more detailed information.
+";
+
+    #[test]
+    fn test_parse_help_options() {
+        let options = parse_help_options(BUILD_HELP);
+        let flags: Vec<&str> = options.iter().map(|o| o.flag.as_str()).collect();
+        assert_eq!(
+            flags,
+            vec![
+                "--message-format",
+                "--verbose",
+                "-Z",
+                "--help",
+                "--package",
+                "--workspace"
+            ]
+        );
+
+        let format = &options[0];
+        assert!(format.takes_value);
+        assert_eq!(format.short, None);
+        assert!(format.description.ends_with("json-diagnostic-short]"));
+
+        let verbose = &options[1];
+        assert_eq!(verbose.short.as_deref(), Some("-v"));
+        assert!(!verbose.takes_value);
+
+        let package = &options[4];
+        assert_eq!(package.short.as_deref(), Some("-p"));
+        assert!(package.takes_value);
+    }
+
+    #[test]
+    fn test_unparseable_help_falls_back_to_raw() {
+        let help = HelpText::from_output(Some("autodd"), "autodd: manage dependencies\n");
+        assert!(help.options.is_empty());
+        assert_eq!(help.raw.as_deref(), Some("autodd: manage dependencies\n"));
+
+        let help = HelpText::from_output(Some("build"), BUILD_HELP);
+        assert!(!help.options.is_empty());
+        assert!(help.raw.is_none());
+    }
+}
@@ -0,0 +1,112 @@
+// src/hooks.rs
+//! Global observers called after every subcommand, however it was started: a direct
+//! call, a background job or a step of a sequence. Unlike the callbacks of one call,
+//! they are registered once (at setup) and see every command.
+
+use crate::outcome::CommandOutcome;
+use mlua::prelude::*;
+use serde::Serialize;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Once};
+
+/// Called with the subcommand and its result once it has finished
+pub type PostCommandHook = Arc<dyn Fn(&str, Result<&CommandOutcome, &LuaError>) + Send + Sync>;
+
+static POST_COMMAND_HOOKS: Mutex<Vec<PostCommandHook>> = Mutex::new(Vec::new());
+
+/// Register `hook` to be called after every subcommand, on the thread that ran it
+pub fn add_post_command_hook(hook: PostCommandHook) {
+    POST_COMMAND_HOOKS.lock().unwrap().push(hook);
+}
+
+/// Call the registered hooks; a hook that panics is skipped, so the command's result
+/// is returned all the same
+pub(crate) fn command_finished(subcommand: &str, result: &LuaResult<CommandOutcome>) {
+    // Copied out, so a hook may register another
+    let hooks = POST_COMMAND_HOOKS.lock().unwrap().clone();
+    for hook in hooks {
+        let _ = std::panic::catch_unwind(AssertUnwindSafe(|| hook(subcommand, result.as_ref())));
+    }
+}
+
+/// A finished subcommand, as the Lua hook receives it
+#[derive(Debug, Clone, Serialize)]
+pub struct FinishedCommand {
+    pub subcommand: String,
+    pub ok: bool,
+    /// The details table a direct call returns, when it succeeded
+    pub outcome: Option<CommandOutcome>,
+    pub error: Option<String>,
+}
+
+// Commands that finished since the Lua hook was last run; Lua can only be called from
+// the main thread, so jobs leave their results here
+static FINISHED: Mutex<Vec<FinishedCommand>> = Mutex::new(Vec::new());
+static QUEUE_FINISHED: AtomicBool = AtomicBool::new(false);
+static QUEUE_HOOK: Once = Once::new();
+
+/// Start or stop keeping finished commands for `take_finished`
+pub(crate) fn queue_finished(enabled: bool) {
+    QUEUE_HOOK.call_once(|| {
+        add_post_command_hook(Arc::new(|subcommand, result| {
+            if QUEUE_FINISHED.load(Ordering::Relaxed) {
+                FINISHED.lock().unwrap().push(FinishedCommand {
+                    subcommand: subcommand.to_string(),
+                    ok: result.is_ok(),
+                    outcome: result.ok().cloned(),
+                    error: result.err().map(|err| err.to_string()),
+                });
+            }
+        }))
+    });
+    QUEUE_FINISHED.store(enabled, Ordering::Relaxed);
+    if !enabled {
+        FINISHED.lock().unwrap().clear();
+    }
+}
+
+/// Commands that finished while queueing was on, oldest first
+pub(crate) fn take_finished() -> Vec<FinishedCommand> {
+    std::mem::take(&mut *FINISHED.lock().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_panicking_hook_is_skipped() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        add_post_command_hook(Arc::new(|subcommand, _| {
+            if subcommand == "hook-test-panic" {
+                panic!("hook failed");
+            }
+        }));
+        let recorder = seen.clone();
+        add_post_command_hook(Arc::new(move |subcommand, result| {
+            if subcommand.starts_with("hook-test") {
+                recorder
+                    .lock()
+                    .unwrap()
+                    .push((subcommand.to_string(), result.is_ok()));
+            }
+        }));
+
+        command_finished(
+            "hook-test-panic",
+            &Ok(CommandOutcome::new(String::new(), false)),
+        );
+        command_finished(
+            "hook-test-error",
+            &Err(LuaError::RuntimeError("failed".to_string())),
+        );
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                ("hook-test-panic".to_string(), true),
+                ("hook-test-error".to_string(), false)
+            ]
+        );
+    }
+}
@@ -0,0 +1,66 @@
+// src/installed.rs
+//! Which cargo subcommands are installed, from `cargo --list`
+//!
+//! The list is read once per cargo (see `CargoKey`: a `rust-toolchain.toml`, the
+//! `toolchain` option, `PATH` or `CARGO_HOME` change which tools there are) and kept
+//! until it is refreshed or a tool is installed or uninstalled through the plugin, so a
+//! menu can ask about every tool without starting cargo each time.
+
+use crate::options::CargoKey;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+
+/// The cargo a list was read from
+pub type ListKey = CargoKey;
+
+/// Subcommands rustup installs as proxies, which `cargo --list` shows whether or not
+/// their component is installed; they are asked for their version instead
+pub const RUSTUP_PROXIES: &[&str] = &["clippy", "fmt", "miri"];
+
+static LISTS: Mutex<BTreeMap<ListKey, BTreeSet<String>>> = Mutex::new(BTreeMap::new());
+
+/// The subcommands in `cargo --list` output, aliases included
+pub fn parse_list(output: &str) -> BTreeSet<String> {
+    output
+        .lines()
+        // Commands are indented under the `Installed Commands:` header
+        .filter(|line| line.starts_with(char::is_whitespace))
+        .filter_map(|line| line.split_whitespace().next())
+        .map(str::to_string)
+        .collect()
+}
+
+/// The list read for `key`, if any
+pub fn cached(key: &ListKey) -> Option<BTreeSet<String>> {
+    LISTS.lock().unwrap().get(key).cloned()
+}
+
+pub fn store(key: ListKey, list: BTreeSet<String>) {
+    LISTS.lock().unwrap().insert(key, list);
+}
+
+/// Forget every list, e.g. once a tool was installed
+pub fn forget() {
+    LISTS.lock().unwrap().clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_list() {
+        let output = "\
+Installed Commands:
+    b                    alias: build
+    build                Compile a local package and all of its dependencies
+    llvm-cov             Cargo subcommand to easily use LLVM source-based code coverage (-C instrument-coverage).
+    miri
+";
+        let list = parse_list(output);
+        assert_eq!(
+            list.into_iter().collect::<Vec<_>>(),
+            vec!["b", "build", "llvm-cov", "miri"]
+        );
+    }
+}
@@ -0,0 +1,720 @@
+// src/jobs.rs
+//! Commands run in the background, for long builds that shouldn't block the editor
+//!
+//! Each job runs on a thread with a runtime of its own and is polled by id. Lua can't be
+//! called from those threads, so completion callbacks are kept on the Lua side and run
+//! by `dispatch_completions` once their job has finished. Jobs started with `stream`
+//! also queue their events, which `dispatch_completions` delivers before the result.
+//!
+//! A new `run` job replaces a running one for the same target of the same project (its
+//! directory and manifest) unless `replace = false`, so rapid edit-run cycles don't pile
+//! up program instances (or fight over a port).
+//!
+//! Each job records the directory it runs in and the manifest it targets, so
+//! `kill_jobs_for` can stop the jobs of one project when its buffers are closed.
+//!
+//! An interactive session is a job with an input channel of its own, so a REPL-like
+//! program keeps taking input (`session_write`) while its output streams, whatever runs
+//! in the foreground. Writing fails once the job has finished.
+
+use crate::cancel::{CancelReason, CancelToken};
+use crate::events::Event;
+use crate::lua_exports::InputMessage;
+use crate::options::CommandOptions;
+use crate::outcome::CommandOutcome;
+use crate::signal::Termination;
+use crate::CargoCommands;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// Finished jobs kept for `job_status`; older ones are forgotten, except held ones
+pub const MAX_FINISHED_JOBS: usize = 32;
+
+/// The receiving end of a session's input, held by the command that is running
+pub type SessionInput = Arc<tokio::sync::Mutex<mpsc::UnboundedReceiver<InputMessage>>>;
+
+/// Timeout of a session's commands unless `timeout_secs` is given; as an interactive
+/// command, the program gets three times as long
+pub const SESSION_TIMEOUT_SECS: u64 = 3600;
+
+/// How long a replacing `run` job waits for the one it replaces to exit
+const REPLACE_WAIT: Duration = Duration::from_secs(10);
+
+/// State of a background job, as returned to Lua
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    /// `termination` tells whether the process exited after SIGTERM or had to be killed;
+    /// it is missing when the job was cancelled before its process started
+    Cancelled {
+        termination: Option<Termination>,
+    },
+    Done {
+        outcome: Box<CommandOutcome>,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+impl JobStatus {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, JobStatus::Running)
+    }
+}
+
+struct Job {
+    cancel: CancelToken,
+    status: JobStatus,
+    /// For `run` jobs, the cargo arguments that select what runs
+    run_target: Option<Vec<String>>,
+    /// Directory the command runs in (the `cwd` option, resolved); `None` when the
+    /// current directory couldn't be read
+    cwd: Option<PathBuf>,
+    /// Directory of the manifest the command targets (a `--manifest-path` argument or the
+    /// `manifest_path` option, resolved); `None` when it has neither
+    manifest_dir: Option<PathBuf>,
+    /// Events not yet taken by `take_events`; `None` unless the job streams
+    events: Option<Vec<Event>>,
+    /// Input of a session; `None` for other jobs
+    input: Option<mpsc::UnboundedSender<InputMessage>>,
+    /// Kept past `MAX_FINISHED_JOBS` until released: its result is still to be delivered
+    held: bool,
+}
+
+struct JobState {
+    next_id: u64,
+    jobs: BTreeMap<u64, Job>,
+}
+
+static JOBS: Mutex<JobState> = Mutex::new(JobState {
+    next_id: 1,
+    jobs: BTreeMap::new(),
+});
+
+/// Run `subcommand` in the background; returns the job's id
+/// With `stream`, the job's events are queued for `take_events`; all of them are queued
+/// by the time the job is finished
+pub fn start(subcommand: String, args: Vec<String>, options: CommandOptions, stream: bool) -> u64 {
+    spawn_job(subcommand, args, options, stream, None)
+}
+
+/// Start an interactive session: a job whose program reads what `session_write` sends,
+/// until it exits; interactive detection is off, and the timeout defaults to
+/// `SESSION_TIMEOUT_SECS`
+pub fn start_interactive(
+    subcommand: String,
+    args: Vec<String>,
+    mut options: CommandOptions,
+    stream: bool,
+) -> u64 {
+    options.interactive = Some(true);
+    options.timeout_secs.get_or_insert(SESSION_TIMEOUT_SECS);
+    let (tx, rx) = mpsc::unbounded_channel();
+    spawn_job(subcommand, args, options, stream, Some((tx, rx)))
+}
+
+fn spawn_job(
+    subcommand: String,
+    args: Vec<String>,
+    options: CommandOptions,
+    stream: bool,
+    input: Option<(
+        mpsc::UnboundedSender<InputMessage>,
+        mpsc::UnboundedReceiver<InputMessage>,
+    )>,
+) -> u64 {
+    let (input, session_input) = match input {
+        Some((tx, rx)) => (Some(tx), Some(Arc::new(tokio::sync::Mutex::new(rx)))),
+        None => (None, None),
+    };
+    let cancel = CancelToken::default();
+    let run_target = (subcommand == "run").then(|| run_target(&args));
+    let cwd = job_cwd(&options);
+    let manifest_dir = job_manifest_dir(&args, &options, cwd.as_deref());
+    let (id, replaced) = {
+        let mut state = JOBS.lock().unwrap();
+        let replaced: Vec<u64> = if run_target.is_some() && options.replace != Some(false) {
+            state
+                .jobs
+                .iter()
+                .filter(|(_, job)| {
+                    !job.status.is_finished()
+                        && job.run_target == run_target
+                        && job.cwd == cwd
+                        && job.manifest_dir == manifest_dir
+                })
+                .map(|(id, job)| {
+                    job.cancel.cancel_with(CancelReason::Superseded);
+                    *id
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let id = state.next_id;
+        state.next_id += 1;
+        state.jobs.insert(
+            id,
+            Job {
+                cancel: cancel.clone(),
+                status: JobStatus::Running,
+                run_target,
+                cwd,
+                manifest_dir,
+                events: stream.then(Vec::new),
+                input,
+                held: false,
+            },
+        );
+        (id, replaced)
+    };
+
+    std::thread::spawn(move || {
+        wait_for_exit(&replaced);
+        let result = CargoCommands::new().and_then(|commands| {
+            let commands = commands
+                .with_options(options)
+                .with_event_log()?
+                .with_cancel(cancel.clone());
+            let commands = match session_input {
+                Some(input) => commands.with_session_input(input),
+                None => commands.detached(),
+            };
+            let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+            if !stream {
+                return commands.execute(commands.run_subcommand(&subcommand, &args));
+            }
+
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            let commands = commands.with_events(tx);
+            commands.execute(async {
+                let run = commands.run_subcommand(&subcommand, &args);
+                tokio::pin!(run);
+                let result = loop {
+                    tokio::select! {
+                        result = &mut run => break result,
+                        Some(event) = rx.recv() => queue_event(id, event),
+                    }
+                };
+                while let Ok(event) = rx.try_recv() {
+                    queue_event(id, event);
+                }
+                result
+            })
+        });
+        let status = match result {
+            _ if cancel.is_cancelled() => JobStatus::Cancelled {
+                termination: cancel.termination(),
+            },
+            Ok(outcome) => JobStatus::Done {
+                outcome: Box::new(outcome),
+            },
+            Err(err) => JobStatus::Failed {
+                error: err.to_string(),
+            },
+        };
+        finish(id, status);
+    });
+
+    id
+}
+
+// The arguments before `--`: two runs with the same ones start the same program
+fn run_target(args: &[String]) -> Vec<String> {
+    args.iter()
+        .take_while(|arg| arg.as_str() != "--")
+        .cloned()
+        .collect()
+}
+
+// Where the job's command runs, resolved the way it will be when spawned
+fn job_cwd(options: &CommandOptions) -> Option<PathBuf> {
+    let dir = std::env::current_dir().ok()?;
+    let dir = match &options.cwd {
+        Some(cwd) => dir.join(cwd),
+        None => dir,
+    };
+    Some(canonical(&dir))
+}
+
+// The directory of the manifest the command targets: a `--manifest-path` argument wins
+// over the `manifest_path` option, both relative to `cwd`
+fn job_manifest_dir(
+    args: &[String],
+    options: &CommandOptions,
+    cwd: Option<&Path>,
+) -> Option<PathBuf> {
+    let args: Vec<&str> = args
+        .iter()
+        .map(|s| s.as_str())
+        .take_while(|a| *a != "--")
+        .collect();
+    let from_args = args.iter().enumerate().find_map(|(i, arg)| match *arg {
+        "--manifest-path" => args.get(i + 1).copied(),
+        arg => arg.strip_prefix("--manifest-path="),
+    });
+    let manifest = from_args.or(options.manifest_path.as_deref())?;
+    let manifest = match cwd {
+        Some(cwd) => cwd.join(manifest),
+        None => PathBuf::from(manifest),
+    };
+    Some(canonical(manifest.parent()?))
+}
+
+// Symlinked paths would otherwise not match
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+// Wait until the replaced jobs have exited and released what they held, such as a port
+fn wait_for_exit(ids: &[u64]) {
+    let deadline = Instant::now() + REPLACE_WAIT;
+    while Instant::now() < deadline && ids.iter().any(|id| !status(*id).is_finished()) {
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+fn queue_event(id: u64, event: Event) {
+    let mut state = JOBS.lock().unwrap();
+    if let Some(events) = state.jobs.get_mut(&id).and_then(|job| job.events.as_mut()) {
+        events.push(event);
+    }
+}
+
+/// Events of a streaming job published since the last call, oldest first
+pub fn take_events(id: u64) -> Vec<Event> {
+    let mut state = JOBS.lock().unwrap();
+    state
+        .jobs
+        .get_mut(&id)
+        .and_then(|job| job.events.as_mut())
+        .map(std::mem::take)
+        .unwrap_or_default()
+}
+
+// Record the final status, forgetting the oldest finished jobs beyond the limit
+fn finish(id: u64, status: JobStatus) {
+    let mut state = JOBS.lock().unwrap();
+    if let Some(job) = state.jobs.get_mut(&id) {
+        job.status = status;
+    }
+    forget_finished(&mut state);
+}
+
+/// Keep a job, even once finished, until `release`; called right after starting it, when
+/// it is the newest job and so can't have been forgotten yet
+pub fn hold(id: u64) {
+    if let Some(job) = JOBS.lock().unwrap().jobs.get_mut(&id) {
+        job.held = true;
+    }
+}
+
+/// Let a held job be forgotten like any other finished one
+pub fn release(id: u64) {
+    let mut state = JOBS.lock().unwrap();
+    if let Some(job) = state.jobs.get_mut(&id) {
+        job.held = false;
+    }
+    forget_finished(&mut state);
+}
+
+fn forget_finished(state: &mut JobState) {
+    let finished: Vec<u64> = state
+        .jobs
+        .iter()
+        .filter(|(_, job)| job.status.is_finished() && !job.held)
+        .map(|(id, _)| *id)
+        .collect();
+    for id in finished
+        .iter()
+        .take(finished.len().saturating_sub(MAX_FINISHED_JOBS))
+    {
+        state.jobs.remove(id);
+    }
+}
+
+/// Queue input for a session's program
+pub fn session_write(id: u64, message: InputMessage) -> Result<(), &'static str> {
+    let state = JOBS.lock().unwrap();
+    let job = state.jobs.get(&id).ok_or("unknown session")?;
+    let input = job.input.as_ref().ok_or("not a session")?;
+    // Unbounded, so input is never dropped for arriving faster than it is read; the
+    // receiver is gone once the job's commands have finished
+    input.send(message).map_err(|_| "session ended")
+}
+
+/// Current state of the job with the given id
+pub fn status(id: u64) -> JobStatus {
+    let state = JOBS.lock().unwrap();
+    match state.jobs.get(&id) {
+        Some(job) => job.status.clone(),
+        None => JobStatus::Failed {
+            error: format!("Unknown job id: {}", id),
+        },
+    }
+}
+
+/// Stop a running job; returns whether it was still running
+pub fn cancel(id: u64) -> bool {
+    let state = JOBS.lock().unwrap();
+    match state.jobs.get(&id) {
+        Some(job) if !job.status.is_finished() => {
+            job.cancel.cancel();
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Stop every running job whose directory, or the manifest it targets, is in `dir` or
+/// under it; returns their ids
+/// A relative `dir` is taken from the current directory
+pub fn kill_jobs_for(dir: &str) -> Vec<u64> {
+    let dir = match std::env::current_dir() {
+        Ok(current) => canonical(&current.join(dir)),
+        Err(_) => canonical(Path::new(dir)),
+    };
+    let state = JOBS.lock().unwrap();
+    state
+        .jobs
+        .iter()
+        .filter(|(_, job)| !job.status.is_finished())
+        .filter(|(_, job)| {
+            [&job.cwd, &job.manifest_dir]
+                .into_iter()
+                .any(|path| path.as_deref().is_some_and(|path| path.starts_with(&dir)))
+        })
+        .map(|(id, job)| {
+            job.cancel.cancel();
+            *id
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::ScratchCrate;
+
+    fn wait_for(id: u64) -> JobStatus {
+        let deadline = Instant::now() + Duration::from_secs(120);
+        loop {
+            let status = status(id);
+            if status.is_finished() || Instant::now() > deadline {
+                return status;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn test_background_job() {
+        let scratch = ScratchCrate::new("background_job", &[("src/lib.rs", "")]);
+        let args = vec!["--manifest-path".to_string(), scratch.manifest_path()];
+
+        let id = start(
+            "check".to_string(),
+            args.clone(),
+            CommandOptions::default(),
+            false,
+        );
+        match wait_for(id) {
+            JobStatus::Done { outcome } => assert!(outcome.success),
+            other => panic!("unexpected status: {:?}", other),
+        }
+        assert!(!cancel(id));
+
+        let id = start("build".to_string(), args, CommandOptions::default(), false);
+        assert!(cancel(id));
+        assert!(matches!(wait_for(id), JobStatus::Cancelled { .. }));
+        assert!(matches!(status(0), JobStatus::Failed { .. }));
+    }
+
+    #[test]
+    fn test_held_jobs_are_kept() {
+        // A state of its own, so the jobs of other tests aren't forgotten
+        let mut state = JobState {
+            next_id: 1,
+            jobs: BTreeMap::new(),
+        };
+        for id in 0..=MAX_FINISHED_JOBS as u64 + 1 {
+            state.jobs.insert(
+                id,
+                Job {
+                    cancel: CancelToken::default(),
+                    status: JobStatus::Failed {
+                        error: "failed".to_string(),
+                    },
+                    run_target: None,
+                    cwd: None,
+                    manifest_dir: None,
+                    events: None,
+                    input: None,
+                    held: id == 0,
+                },
+            );
+        }
+        forget_finished(&mut state);
+        assert_eq!(state.jobs.len(), MAX_FINISHED_JOBS + 1);
+        assert!(state.jobs.contains_key(&0));
+        assert!(!state.jobs.contains_key(&1));
+
+        state.jobs.get_mut(&0).unwrap().held = false;
+        forget_finished(&mut state);
+        assert_eq!(state.jobs.len(), MAX_FINISHED_JOBS);
+        assert!(!state.jobs.contains_key(&0));
+    }
+
+    #[test]
+    fn test_interactive_session() {
+        // Answers each line as it comes, until stdin is closed
+        let main = r#"use std::io::BufRead;
+fn main() {
+    for line in std::io::stdin().lock().lines() {
+        println!("got {}", line.unwrap());
+    }
+    println!("bye");
+}
+"#;
+        let scratch = ScratchCrate::new("interactive_session", &[("src/main.rs", main)]);
+        let args = vec!["--manifest-path".to_string(), scratch.manifest_path()];
+        let id = start_interactive("run".to_string(), args, CommandOptions::default(), true);
+
+        // Every answer arrives while the session is still running
+        let mut stdout = Vec::new();
+        for guess in ["50", "25", "37"] {
+            let data = format!("{}\n", guess).into_bytes();
+            assert_eq!(session_write(id, InputMessage::Data(data)), Ok(()));
+            let expected = format!("got {}", guess);
+            let deadline = Instant::now() + Duration::from_secs(120);
+            while !stdout.contains(&expected) {
+                assert!(Instant::now() < deadline, "no answer to {}", guess);
+                for event in take_events(id) {
+                    if let Event::Stdout { line } = event {
+                        stdout.push(line);
+                    }
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            assert!(!status(id).is_finished());
+        }
+
+        assert_eq!(session_write(id, InputMessage::Eof), Ok(()));
+        match wait_for(id) {
+            JobStatus::Done { outcome } => {
+                assert!(outcome.success);
+                assert!(outcome.output.contains("bye"), "{}", outcome.output);
+            }
+            other => panic!("unexpected status: {:?}", other),
+        }
+        let ended = session_write(id, InputMessage::Data(b"1\n".to_vec()));
+        assert_eq!(ended, Err("session ended"));
+        assert_eq!(session_write(0, InputMessage::Eof), Err("unknown session"));
+    }
+
+    #[test]
+    fn test_streaming_job() {
+        let main = "fn main() {\n    println!(\"out\");\n    eprintln!(\"err\");\n}\n";
+        let scratch = ScratchCrate::new("streaming_job", &[("src/main.rs", main)]);
+        let args = vec!["--manifest-path".to_string(), scratch.manifest_path()];
+
+        let id = start("run".to_string(), args, CommandOptions::default(), true);
+        let JobStatus::Done { outcome } = wait_for(id) else {
+            panic!("job didn't finish");
+        };
+        // The streamed lines add up to the output of the result
+        let streamed: String = take_events(id)
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::Stdout { line } | Event::Stderr { line } => Some(line + "\n"),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(streamed, outcome.output);
+        assert!(streamed.contains("out\n"), "{}", streamed);
+        assert!(take_events(id).is_empty());
+    }
+
+    #[test]
+    fn test_kill_jobs_for() {
+        let scratch = ScratchCrate::new("kill_jobs_for", &[("src/lib.rs", "")]);
+        let manifest = scratch.manifest_path();
+        let project = Path::new(&manifest).parent().unwrap().to_path_buf();
+        let in_project = |dir: &Path| CommandOptions {
+            cwd: Some(dir.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        std::fs::create_dir_all(project.join("src/nested")).unwrap();
+        let other_dir = std::env::temp_dir();
+
+        let at_root = start("build".to_string(), Vec::new(), in_project(&project), false);
+        let nested = start(
+            "build".to_string(),
+            Vec::new(),
+            in_project(&project.join("src/nested")),
+            false,
+        );
+        // Run from elsewhere, on the project's manifest
+        let by_argument = start(
+            "build".to_string(),
+            vec!["--manifest-path".to_string(), manifest.clone()],
+            in_project(&other_dir),
+            false,
+        );
+        let by_option = start(
+            "build".to_string(),
+            Vec::new(),
+            CommandOptions {
+                manifest_path: Some(manifest.clone()),
+                ..in_project(&other_dir)
+            },
+            false,
+        );
+        let other = ScratchCrate::new("kill_jobs_for_other", &[("src/lib.rs", "")]);
+        let elsewhere = start(
+            "build".to_string(),
+            vec!["--manifest-path".to_string(), other.manifest_path()],
+            in_project(&other_dir),
+            false,
+        );
+
+        let stopped = kill_jobs_for(&project.to_string_lossy());
+        assert_eq!(stopped, vec![at_root, nested, by_argument, by_option]);
+        for id in stopped {
+            assert!(matches!(wait_for(id), JobStatus::Cancelled { .. }));
+        }
+        // A path that merely shares a prefix isn't under the project
+        let sibling = format!("{}-other", project.to_string_lossy());
+        assert!(kill_jobs_for(&sibling).is_empty());
+        assert!(cancel(elsewhere));
+        wait_for(elsewhere);
+    }
+
+    #[test]
+    fn test_run_job_keeps_other_projects() {
+        let main = r#"fn main() {
+    let path = std::env::args().nth(1).unwrap();
+    std::fs::write(path, std::process::id().to_string()).unwrap();
+    std::thread::sleep(std::time::Duration::from_secs(60));
+}"#;
+        let first = ScratchCrate::new("run_project_a", &[("src/main.rs", main)]);
+        let second = ScratchCrate::new("run_project_b", &[("src/main.rs", main)]);
+        // The same arguments in two projects: the pid goes to a file in the project
+        let run_in = |scratch: &ScratchCrate| {
+            let options = CommandOptions {
+                cwd: Some(scratch.path("").to_string_lossy().into_owned()),
+                ..Default::default()
+            };
+            let args = vec!["--".to_string(), "pid".to_string()];
+            let id = start("run".to_string(), args, options, false);
+            wait_for_pid(&scratch.path("pid"));
+            id
+        };
+        let a = run_in(&first);
+        let b = run_in(&second);
+        assert!(!status(a).is_finished(), "{:?}", status(a));
+
+        // With the manifest given as an option, the manifest tells them apart too
+        let pid_file = std::env::temp_dir().join(format!("run-manifest-{}", std::process::id()));
+        let by_manifest = |scratch: &ScratchCrate| {
+            let options = CommandOptions {
+                manifest_path: Some(scratch.manifest_path()),
+                ..Default::default()
+            };
+            let args = vec!["--".to_string(), pid_file.to_string_lossy().into_owned()];
+            let id = start("run".to_string(), args, options, false);
+            wait_for_pid(&pid_file);
+            std::fs::remove_file(&pid_file).unwrap();
+            id
+        };
+        let c = by_manifest(&first);
+        let d = by_manifest(&second);
+        assert!(!status(c).is_finished(), "{:?}", status(c));
+
+        for id in [a, b, c, d] {
+            assert!(cancel(id));
+            wait_for(id);
+        }
+    }
+
+    fn wait_for_pid(path: &std::path::Path) -> u32 {
+        let deadline = Instant::now() + Duration::from_secs(120);
+        loop {
+            if let Some(pid) = std::fs::read_to_string(path)
+                .ok()
+                .and_then(|pid| pid.trim().parse().ok())
+            {
+                return pid;
+            }
+            assert!(Instant::now() < deadline, "program never started");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    // Needs /proc to see that the worker is gone
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_run_job_replaces_previous() {
+        // A server-like program with a worker process of its own
+        let main = r#"fn main() {
+    let path = std::env::args().nth(1).unwrap();
+    let worker = std::process::Command::new("sleep").arg("60").spawn().unwrap();
+    std::fs::write(path, worker.id().to_string()).unwrap();
+    std::thread::sleep(std::time::Duration::from_secs(60));
+}"#;
+        let scratch = ScratchCrate::new("replace_run", &[("src/main.rs", main)]);
+        let pid_file =
+            |name: &str| std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        let run_args = |pid_file: &std::path::Path| {
+            vec![
+                "--manifest-path".to_string(),
+                scratch.manifest_path(),
+                "--".to_string(),
+                pid_file.to_string_lossy().into_owned(),
+            ]
+        };
+        let (first_pid, second_pid) = (pid_file("replace-first"), pid_file("replace-second"));
+
+        let first = start(
+            "run".to_string(),
+            run_args(&first_pid),
+            CommandOptions::default(),
+            false,
+        );
+        let pid = wait_for_pid(&first_pid);
+
+        let second = start(
+            "run".to_string(),
+            run_args(&second_pid),
+            CommandOptions::default(),
+            false,
+        );
+        assert!(matches!(wait_for(first), JobStatus::Cancelled { .. }));
+        wait_for_pid(&second_pid);
+        // The worker went down with the program, not just cargo and the program
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap_or_default();
+        assert!(
+            stat.is_empty() || stat.contains(") Z "),
+            "worker still alive"
+        );
+
+        let options = CommandOptions {
+            replace: Some(false),
+            ..Default::default()
+        };
+        let third = start("run".to_string(), run_args(&first_pid), options, false);
+        assert!(!status(second).is_finished());
+        assert!(cancel(second));
+        assert!(cancel(third));
+        wait_for(second);
+        wait_for(third);
+        let _ = std::fs::remove_file(first_pid);
+        let _ = std::fs::remove_file(second_pid);
+    }
+}
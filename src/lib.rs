@@ -3,12 +3,96 @@
 //! This module provides a bridge between Neovim and Cargo commands
 //! allowing users to run Cargo commands directly from Neovim.
 
+mod add;
+mod ansi;
+mod artifacts;
+mod cancel;
+mod capabilities;
 mod cargo_commands;
+mod clean;
+mod compat;
+mod compile_failure;
+mod coverage;
+mod diagnostics;
+mod doc;
+mod doctest;
 mod error;
+mod event_log;
+mod events;
+mod examples;
+mod fast_check;
+mod fmt;
+mod help;
+mod hooks;
+mod installed;
+mod jobs;
+mod lines;
 mod lua_exports;
+mod manifest_error;
+mod members;
+mod messages;
+mod metadata;
+mod options;
+mod outcome;
+mod panic;
+mod profile;
+mod project_config;
+mod quickfix;
+mod rate_limit;
+mod responder;
+mod scaffold;
+mod search;
+mod sequence;
+mod signal;
+mod size;
+mod snippet;
+mod stream;
+mod test_report;
+#[cfg(test)]
+mod test_support;
+mod timings;
+mod toolchain;
+mod tree;
+mod unit_graph;
+mod vendor;
 
+pub use add::AddedDependency;
+pub use cancel::CancelToken;
+pub use capabilities::Capabilities;
 pub use cargo_commands::CargoCommands;
+pub use clean::CleanResult;
+pub use compile_failure::CompileFailure;
+pub use coverage::CoverageReport;
+pub use diagnostics::{
+    Diagnostic, DiagnosticCode, DiagnosticSpan, Replacement, SourceSpan, Suggestion,
+};
+pub use doc::{DocCrate, DocResult};
+pub use doctest::DoctestResult;
 pub use error::Error;
+pub use events::Event;
+pub use examples::ExampleTarget;
+pub use fast_check::FastCheckStatus;
+pub use help::{HelpOption, HelpText};
+pub use hooks::{add_post_command_hook, FinishedCommand, PostCommandHook};
+pub use jobs::JobStatus;
+pub use manifest_error::ManifestError;
+pub use members::WorkspaceMember;
+pub use metadata::{Metadata, PackageInfo, TargetInfo};
+pub use options::CommandOptions;
+pub use outcome::CommandOutcome;
+pub use panic::{BacktraceFrame, PanicInfo};
+pub use profile::{ProfileOutput, Profiler};
+pub use quickfix::{ErrorLocation, QuickfixEntry};
+pub use scaffold::CreatedPackage;
+pub use search::{SearchHit, SearchResult, SearchStatus};
+pub use sequence::{CheckThenBuild, SequenceStep, StepResult};
+pub use size::ArtifactSize;
+pub use snippet::SnippetResult;
+pub use test_report::{TestCase, TestReport, TestSuite};
+pub use timings::{CrateTiming, TimingReport};
+pub use tree::{CrateVersion, DependencyTree, TreeDiff, TreeNode, VersionChange};
+pub use unit_graph::{Unit, UnitDependency, UnitGraph, UnitTarget};
+pub use vendor::VendorResult;
 
 /// Main module registration for Neovim
 #[mlua::lua_module]
@@ -0,0 +1,78 @@
+// src/lines.rs
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+/// Line reader that tolerates invalid UTF-8
+///
+/// `tokio::io::Lines` fails on the first invalid byte, which would cut the output
+/// short; here invalid sequences become U+FFFD and reading carries on.
+pub struct LossyLines<R> {
+    reader: R,
+    buf: Vec<u8>,
+}
+
+impl<R: AsyncBufRead + Unpin> LossyLines<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Next line without its line ending, or `None` at end of stream
+    ///
+    /// Cancel safe: bytes read before cancellation stay buffered for the next call.
+    pub async fn next_line(&mut self) -> std::io::Result<Option<String>> {
+        let read = self.reader.read_until(b'\n', &mut self.buf).await?;
+        if read == 0 && self.buf.is_empty() {
+            return Ok(None);
+        }
+
+        let mut bytes = &self.buf[..];
+        if let Some(rest) = bytes.strip_suffix(b"\n") {
+            bytes = rest.strip_suffix(b"\r").unwrap_or(rest);
+        }
+        let line = String::from_utf8_lossy(bytes).into_owned();
+        self.buf.clear();
+        Ok(Some(line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_invalid_utf8_is_replaced() {
+        let input: &[u8] = b"before\n\xff\xfebinary\xc3\r\nafter";
+        let mut lines = LossyLines::new(input);
+
+        assert_eq!(lines.next_line().await.unwrap().as_deref(), Some("before"));
+        assert_eq!(
+            lines.next_line().await.unwrap().as_deref(),
+            Some("\u{fffd}\u{fffd}binary\u{fffd}")
+        );
+        // A last line without a newline is still returned
+        assert_eq!(lines.next_line().await.unwrap().as_deref(), Some("after"));
+        assert_eq!(lines.next_line().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_line_split_across_reads_is_buffered() {
+        use tokio::io::{AsyncWriteExt, BufReader};
+        use tokio::time::{timeout, Duration};
+
+        let (mut writer, reader) = tokio::io::duplex(64);
+        let mut lines = LossyLines::new(BufReader::new(reader));
+
+        // Half a JSON message is not a line yet
+        writer.write_all(br#"{"reason":"compiler-"#).await.unwrap();
+        let pending = timeout(Duration::from_millis(50), lines.next_line()).await;
+        assert!(pending.is_err());
+
+        writer.write_all(b"message\"}\n").await.unwrap();
+        assert_eq!(
+            lines.next_line().await.unwrap().as_deref(),
+            Some(r#"{"reason":"compiler-message"}"#)
+        );
+    }
+}
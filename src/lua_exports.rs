@@ -1,245 +1,965 @@
 // src/lua_exports.rs
-use crate::CargoCommands;
+use crate::artifacts::{artifact_dir, set_target_dirs, target_dir_override};
+use crate::cancel::CancelToken;
+use crate::capabilities::capabilities;
+use crate::error::CommandFailure;
+use crate::events::Event;
+use crate::fast_check;
+use crate::hooks::{queue_finished, take_finished};
+use crate::jobs;
+use crate::options::{set_cargo_bin, set_default_cwd, set_default_env, set_default_toolchain};
+use crate::outcome::CommandOutcome;
+use crate::project_config::ProjectConfig;
+use crate::search;
+use crate::snippet::compile_snippet;
+use crate::{CargoCommands, CommandOptions, DependencyTree, SequenceStep};
 use mlua::prelude::*;
+use mlua::{DeserializeOptions, SerializeOptions};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
 use std::sync::Mutex;
+use std::time::Duration;
 use tokio::sync::mpsc;
 
+/// Data forwarded to the running command's standard input
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputMessage {
+    /// Raw bytes, written verbatim (no newline is appended)
+    Data(Vec<u8>),
+    /// Close the program's stdin so it observes end-of-file
+    Eof,
+}
+
+// Delay before a check_fast call starts, so bursts of saves run a single check
+const DEFAULT_DEBOUNCE_MS: u64 = 200;
+
+// Stops the command running in the foreground (`execute_with_events`) on `interrupt`
+static FOREGROUND_CANCEL: Mutex<Option<CancelToken>> = Mutex::new(None);
+
 // 標準入力を送信するためのチャネル
-static INPUT_SENDER: Mutex<Option<mpsc::Sender<String>>> = Mutex::new(None);
+static INPUT_SENDER: Mutex<Option<mpsc::UnboundedSender<InputMessage>>> = Mutex::new(None);
 
 // 標準入力送信用のチャネルを設定
-pub fn set_input_sender(sender: mpsc::Sender<String>) {
+pub fn set_input_sender(sender: mpsc::UnboundedSender<InputMessage>) {
     let mut guard = INPUT_SENDER.lock().unwrap();
     *guard = Some(sender);
 }
 
+// Stop routing input to a finished command, unless a newer command has taken over
+pub fn clear_input_sender(sender: &mpsc::UnboundedSender<InputMessage>) {
+    let mut guard = INPUT_SENDER.lock().unwrap();
+    if guard
+        .as_ref()
+        .is_some_and(|current| current.same_channel(sender))
+    {
+        *guard = None;
+    }
+}
+
+// Queue input for the command registered in `slot`
+fn send_to(
+    slot: &Mutex<Option<mpsc::UnboundedSender<InputMessage>>>,
+    message: InputMessage,
+) -> Result<(), &'static str> {
+    let guard = slot.lock().unwrap();
+    let sender = guard.as_ref().ok_or("no active job")?;
+    // Unbounded, so input is never dropped for arriving faster than it is read
+    sender.send(message).map_err(|_| "no active job")
+}
+
+// Forget the command registered in `slot`; its input channel is dropped
+fn detach_input(slot: &Mutex<Option<mpsc::UnboundedSender<InputMessage>>>) {
+    slot.lock().unwrap().take();
+}
+
+// Cancel the command in `cancel_slot` and forget the input channel in `input_slot`;
+// with nothing running this does nothing and returns false
+fn interrupt_foreground(
+    cancel_slot: &Mutex<Option<CancelToken>>,
+    input_slot: &Mutex<Option<mpsc::UnboundedSender<InputMessage>>>,
+) -> bool {
+    let running = match cancel_slot.lock().unwrap().as_ref() {
+        Some(cancel) => {
+            cancel.cancel();
+            true
+        }
+        None => false,
+    };
+    detach_input(input_slot);
+    running
+}
+
+// Callbacks of background jobs, by job id; run by `dispatch_completions`
+#[derive(Default)]
+struct JobCallbacks(Vec<(u64, JobCallback)>);
+
+struct JobCallback {
+    on_complete: Option<LuaRegistryKey>,
+    events: EventCallbacks,
+}
+
+// Keep a job's callbacks for `dispatch_completions`, if it has any; the job is held until
+// they have been called
+fn register_job_callbacks(
+    lua: &Lua,
+    id: u64,
+    on_complete: Option<LuaFunction>,
+    events: EventCallbacks,
+) -> LuaResult<()> {
+    if on_complete.is_none() && events.is_empty() {
+        return Ok(());
+    }
+    jobs::hold(id);
+    let on_complete = on_complete
+        .map(|callback| lua.create_registry_value(callback))
+        .transpose()?;
+    if lua.app_data_ref::<JobCallbacks>().is_none() {
+        lua.set_app_data(JobCallbacks::default());
+    }
+    if let Some(mut callbacks) = lua.app_data_mut::<JobCallbacks>() {
+        callbacks.0.push((
+            id,
+            JobCallback {
+                on_complete,
+                events,
+            },
+        ));
+    }
+    Ok(())
+}
+
+// The handle start_interactive returns; its functions take the table as `self`, so
+// they are called as methods (`session:write("1\n")`)
+fn session_table(lua: &Lua, id: u64) -> LuaResult<LuaTable> {
+    let session = lua.create_table()?;
+    session.set("id", id)?;
+    // Returns true, or false and the reason the input could not be delivered
+    let write =
+        lua.create_function(
+            move |_, (_, data): (LuaValue, LuaString)| match jobs::session_write(
+                id,
+                InputMessage::Data(data.as_bytes().to_vec()),
+            ) {
+                Ok(()) => Ok((true, None)),
+                Err(reason) => Ok((false, Some(reason))),
+            },
+        )?;
+    session.set("write", write)?;
+    let close_stdin = lua.create_function(move |_, _: LuaValue| {
+        match jobs::session_write(id, InputMessage::Eof) {
+            Ok(()) => Ok((true, None)),
+            Err(reason) => Ok((false, Some(reason))),
+        }
+    })?;
+    session.set("close_stdin", close_stdin)?;
+    let status =
+        lua.create_function(move |lua, _: LuaValue| to_lua_value(lua, &jobs::status(id)))?;
+    session.set("status", status)?;
+    let cancel = lua.create_function(move |_, _: LuaValue| Ok(jobs::cancel(id)))?;
+    session.set("cancel", cancel)?;
+    Ok(session)
+}
+
+// Convert a structured result into a Lua value, mapping `None` to nil
+fn to_lua_value<T: Serialize>(lua: &Lua, value: &T) -> LuaResult<LuaValue> {
+    let options = SerializeOptions::new()
+        .serialize_none_to_null(false)
+        .serialize_unit_to_null(false);
+    lua.to_value_with(value, options)
+}
+
+// Marks a failure returned by a command export for `RAISE_FAILURES` to raise
+static FAILURE_MARKER: u8 = 0;
+
+// Raises the failure tables of the functions it wraps: an error returned from Rust
+// reaches `pcall` as an opaque value, so the table is returned and raised from Lua
+const RAISE_FAILURES: &str = r#"
+local marker = ...
+local function raise(first, ...)
+  if first == marker then
+    error((...), 0)
+  end
+  return first, ...
+end
+return function(f)
+  return function(...)
+    return raise(f(...))
+  end
+end
+"#;
+
+// The values a command export returns: `output, interactive, details`, or for a command
+// that ran and failed the marker and its error table, `{ kind, message, details }`, which
+// prints as its message
+fn command_result(lua: &Lua, result: LuaResult<CommandOutcome>) -> LuaResult<LuaMultiValue> {
+    let outcome = match result {
+        Ok(outcome) => outcome,
+        Err(err) => {
+            let Some(failure) = err.downcast_ref::<CommandFailure>() else {
+                return Err(err);
+            };
+            let message = failure.to_string();
+            let error = lua.create_table()?;
+            error.set("kind", failure.error.kind())?;
+            error.set("message", message.as_str())?;
+            let outcome = failure.outcome.as_ref().clone().with_colors();
+            error.set("details", to_lua_value(lua, &outcome)?)?;
+            let metatable = lua.create_table()?;
+            metatable.set(
+                "__tostring",
+                lua.create_function(move |_, _: LuaValue| Ok(message.clone()))?,
+            )?;
+            error.set_metatable(Some(metatable));
+            return (failure_marker(), error).into_lua_multi(lua);
+        }
+    };
+    let details = to_lua_value(lua, &outcome)?;
+    (outcome.output, outcome.interactive, details).into_lua_multi(lua)
+}
+
+fn failure_marker() -> LuaValue {
+    LuaValue::LightUserData(LuaLightUserData(
+        &FAILURE_MARKER as *const u8 as *mut std::ffi::c_void,
+    ))
+}
+
+// `f` as a function raising the failures `command_result` returns
+fn raising_failures(lua: &Lua, f: LuaFunction) -> LuaResult<LuaFunction> {
+    let wrap: LuaFunction = lua.load(RAISE_FAILURES).call(failure_marker())?;
+    wrap.call(f)
+}
+
+// The Lua function set with `set_post_command_hook`
+struct LuaPostCommandHook(LuaRegistryKey);
+
+// Call the Lua post-command hook with each command that finished since the last call
+// Like the Rust hooks, its errors are ignored: they never change a command's result
+fn run_post_command_hook(lua: &Lua) {
+    let Some(hook) = lua
+        .app_data_ref::<LuaPostCommandHook>()
+        .and_then(|hook| lua.registry_value::<LuaFunction>(&hook.0).ok())
+    else {
+        return;
+    };
+    for finished in take_finished() {
+        if let Ok(result) = to_lua_value(lua, &finished) {
+            let _ = hook.call::<()>((finished.subcommand.as_str(), result));
+        }
+    }
+}
+
+// Lua callbacks for the events of a command, from its options table
+// `on_event` gets every event; `on_stdout`/`on_stderr` get just the lines of one stream,
+// and `on_output` the lines of both, with the stream's name
+#[derive(Default)]
+struct EventCallbacks {
+    on_event: Option<LuaFunction>,
+    on_stdout: Option<LuaFunction>,
+    on_stderr: Option<LuaFunction>,
+    on_output: Option<LuaFunction>,
+}
+
+impl EventCallbacks {
+    fn is_empty(&self) -> bool {
+        self.on_event.is_none()
+            && self.on_stdout.is_none()
+            && self.on_stderr.is_none()
+            && self.on_output.is_none()
+    }
+
+    fn from_opts(opts: Option<&LuaTable>) -> LuaResult<Self> {
+        let Some(opts) = opts else {
+            return Ok(Self::default());
+        };
+        Ok(Self {
+            on_event: opts.get("on_event")?,
+            on_stdout: opts.get("on_stdout")?,
+            on_stderr: opts.get("on_stderr")?,
+            on_output: opts.get("on_output")?,
+        })
+    }
+
+    fn deliver(&self, lua: &Lua, event: &Event) -> LuaResult<()> {
+        if let Some(callback) = &self.on_event {
+            callback.call::<()>(to_lua_value(lua, event)?)?;
+        }
+        if let Some(callback) = &self.on_output {
+            match event {
+                Event::Stdout { line } => callback.call::<()>((line.as_str(), "stdout"))?,
+                Event::Stderr { line } => callback.call::<()>((line.as_str(), "stderr"))?,
+                _ => {}
+            }
+        }
+        match event {
+            Event::Stdout { line } => match &self.on_stdout {
+                Some(callback) => callback.call::<()>(line.as_str()),
+                None => Ok(()),
+            },
+            Event::Stderr { line } => match &self.on_stderr {
+                Some(callback) => callback.call::<()>(line.as_str()),
+                None => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+// Run a future on the main thread, passing each published event to the Lua callbacks
+// Callback errors don't abort the command; the first one is returned once it completes
+// While it runs, `interrupt` (e.g. from a callback) stops it
+fn execute_with_events<F, T>(
+    lua: &Lua,
+    commands: &CargoCommands,
+    callbacks: &EventCallbacks,
+    run: impl FnOnce(CargoCommands) -> F,
+) -> LuaResult<T>
+where
+    F: Future<Output = T>,
+{
+    let (tx, mut rx) = mpsc::unbounded_channel::<Event>();
+    let cancel = CancelToken::default();
+    let previous = FOREGROUND_CANCEL.lock().unwrap().replace(cancel.clone());
+    let future = run(commands.with_events(tx).with_cancel(cancel.clone()));
+
+    commands.execute(async move {
+        let mut callback_error = None;
+        let mut deliver = |event: Event| {
+            if let Err(err) = callbacks.deliver(lua, &event) {
+                callback_error.get_or_insert(err);
+            }
+        };
+
+        tokio::pin!(future);
+        let result = loop {
+            tokio::select! {
+                result = &mut future => break result,
+                Some(event) = rx.recv() => deliver(event),
+            }
+        };
+        // Flush events published right before completion
+        while let Ok(event) = rx.try_recv() {
+            deliver(event);
+        }
+
+        // A callback may have run another command in the meantime; only restore
+        // the slot if it still holds this one
+        let mut foreground = FOREGROUND_CANCEL.lock().unwrap();
+        if foreground.as_ref().is_some_and(|c| c.same_token(&cancel)) {
+            *foreground = previous;
+        }
+        drop(foreground);
+
+        run_post_command_hook(lua);
+        match callback_error {
+            Some(err) => Err(err),
+            None => Ok(result),
+        }
+    })
+}
+
+// Cargo subcommands exported to Lua under their own names
+const COMMANDS: &[&str] = &[
+    "bench",
+    "build",
+    "clean",
+    "doc",
+    "fmt",
+    "help",
+    "new",
+    "run",
+    "test",
+    "test_doc",
+    "update",
+    "check",
+    "init",
+    "add",
+    "remove",
+    "clippy",
+    "fix",
+    "publish",
+    "install",
+    "uninstall",
+    "search",
+    "tree",
+    "vendor",
+    "fetch",
+    "metadata",
+    "audit",
+    "outdated",
+    "autodd",
+    "llvm_cov",
+    "unit_graph",
+];
+
 pub fn register_commands(lua: &Lua) -> LuaResult<LuaTable> {
     let exports = lua.create_table()?;
     let cargo_commands = CargoCommands::new()?;
 
-    // Define all available commands with their implementations
-    let commands = vec![
-        (
-            "bench",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_bench(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "build",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_build(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "clean",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_clean(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "doc",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_doc(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "fmt",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_fmt(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "help",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_help(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "new",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async {
-                    if let Some(name) = args.first() {
-                        let remaining = &args[1..];
-                        cmd.cargo_new(name, remaining).await
-                    } else {
-                        Err(LuaError::RuntimeError(
-                            "Project name is required".to_string(),
-                        ))
-                    }
-                })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "run",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_run(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "test",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_test(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "update",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_update(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "check",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_check(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "init",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_init(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "add",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_add(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "remove",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_remove(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "clippy",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_clippy(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "fix",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_fix(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "publish",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_publish(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "install",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_install(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "uninstall",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_uninstall(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "search",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_search(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "tree",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_tree(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "vendor",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_vendor(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "audit",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_audit(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "outdated",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_outdated(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-        (
-            "autodd",
-            Box::new(move |cmd: &CargoCommands, args: &[&str]| {
-                cmd.execute(async { cmd.cargo_autodd(args).await })
-            })
-                as Box<dyn Fn(&CargoCommands, &[&str]) -> LuaResult<(String, bool)> + Send>,
-        ),
-    ];
-
     // Register all commands to the Lua environment
-    for (name, cmd_fn) in commands {
+    // Each accepts an optional args list and an optional options table
+    for &name in COMMANDS {
         let cargo_commands = cargo_commands.clone();
-        let cmd = lua.create_function(move |_, args: Option<Vec<String>>| {
+        let cmd = lua.create_function(
+            move |lua, (args, opts): (Option<Vec<String>>, Option<LuaTable>)| {
+                let args = args.unwrap_or_default();
+                let args_ref: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+                let callbacks = EventCallbacks::from_opts(opts.as_ref())?;
+                let options = match opts {
+                    Some(opts) => CommandOptions::from_lua(LuaValue::Table(opts), lua)?,
+                    None => CommandOptions::from_lua(LuaValue::Nil, lua)?,
+                };
+
+                let result = execute_with_events(
+                    lua,
+                    &cargo_commands.with_options(options).with_event_log()?,
+                    &callbacks,
+                    |commands| async move { commands.run_subcommand(name, &args_ref).await },
+                )?;
+                command_result(lua, result)
+            },
+        )?;
+        exports.set(name, raising_failures(lua, cmd)?)?;
+    }
+
+    // Register help_options function for per-subcommand flag discovery
+    let help_commands = cargo_commands.clone();
+    let help_options = lua.create_function(move |lua, subcommand: Option<String>| {
+        let help = help_commands.execute(async {
+            help_commands
+                .cargo_help_options(subcommand.as_deref())
+                .await
+        })?;
+        to_lua_value(lua, &help)
+    })?;
+    exports.set("help_options", help_options)?;
+
+    // Register is_installed function: whether a subcommand or cargo plugin is available,
+    // e.g. to gray out menu entries; `opts` are command options (`cwd`, `toolchain`) plus
+    // `refresh` to read `cargo --list` again
+    let installed_commands = cargo_commands.clone();
+    let is_installed =
+        lua.create_function(move |lua, (name, opts): (String, Option<LuaTable>)| {
+            let (options, refresh) = match opts {
+                Some(opts) => (
+                    CommandOptions::from_lua(LuaValue::Table(opts.clone()), lua)?,
+                    opts.get::<Option<bool>>("refresh")?.unwrap_or(false),
+                ),
+                None => (CommandOptions::from_lua(LuaValue::Nil, lua)?, false),
+            };
+            let commands = installed_commands.with_options(options);
+            Ok(commands.execute(commands.is_installed(&name, refresh)))
+        })?;
+    exports.set("is_installed", is_installed)?;
+
+    // Register tree_diff function: compare two `details.tree` results of cargo.tree
+    let tree_diff = lua.create_function(move |lua, (before, after): (LuaValue, LuaValue)| {
+        let before: DependencyTree = lua.from_value(before)?;
+        let after: DependencyTree = lua.from_value(after)?;
+        to_lua_value(lua, &before.diff(&after))
+    })?;
+    exports.set("tree_diff", tree_diff)?;
+
+    // Register compile_file function: compile a single file with rustc, outside any project
+    let snippet_commands = cargo_commands.clone();
+    let compile_file =
+        lua.create_function(move |lua, (file, opts): (String, Option<LuaTable>)| {
+            let (edition, args) = match opts {
+                Some(opts) => (
+                    opts.get::<Option<String>>("edition")?,
+                    opts.get::<Option<Vec<String>>>("args")?.unwrap_or_default(),
+                ),
+                None => (None, Vec::new()),
+            };
+            let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+            let result =
+                snippet_commands.execute(compile_snippet(&file, edition.as_deref(), &args))?;
+            to_lua_value(lua, &result)
+        })?;
+    exports.set("compile_file", compile_file)?;
+
+    // Register artifact_dir function: where builds with the given options put binaries
+    let artifact_dir = lua.create_function(move |lua, opts: Option<LuaTable>| {
+        let options = match opts {
+            Some(opts) => CommandOptions::from_lua(LuaValue::Table(opts), lua)?,
+            None => CommandOptions::from_lua(LuaValue::Nil, lua)?,
+        };
+        Ok(artifact_dir(&options)?.to_string_lossy().into_owned())
+    })?;
+    exports.set("artifact_dir", artifact_dir)?;
+
+    // Register set_target_dirs function: target directories per command category
+    // (`check`, `build`), e.g. so background checks never wait on a build's lock
+    let set_dirs = lua.create_function(|_, dirs: Option<BTreeMap<String, String>>| {
+        set_target_dirs(dirs.unwrap_or_default())
+    })?;
+    exports.set("set_target_dirs", set_dirs)?;
+
+    // Register set_cwd function: the directory commands run in when they have no `cwd`
+    // option, e.g. the crate of the current buffer; nil clears it. Returns it made absolute
+    let set_cwd = lua.create_function(|_, dir: Option<String>| set_default_cwd(dir.as_deref()))?;
+    exports.set("set_cwd", set_cwd)?;
+
+    // Register set_toolchain function: the rustup toolchain of commands without a
+    // `toolchain` option, like `cargo +nightly`; nil or "" clears it
+    let set_toolchain = lua.create_function(|_, toolchain: Option<String>| {
+        set_default_toolchain(toolchain.as_deref())
+    })?;
+    exports.set("set_toolchain", set_toolchain)?;
+
+    // Register set_cargo_bin function: the cargo every command runs, e.g. a rustup shim
+    // outside PATH; nil goes back to `cargo` from PATH
+    let set_cargo_bin =
+        lua.create_function(|_, path: Option<String>| set_cargo_bin(path.as_deref()))?;
+    exports.set("set_cargo_bin", set_cargo_bin)?;
+
+    // Register set_env function: variables of every command under its `env` option, e.g.
+    // { RUST_BACKTRACE = "full", RUSTFLAGS = false }; nil clears them
+    let set_env =
+        lua.create_function(|lua, env: LuaValue| set_default_env(lua.from_value(env)?))?;
+    exports.set("set_env", set_env)?;
+
+    // Register project_config function: the `.cargo-nvim.toml` found from `dir` (by default
+    // the directory commands run in) upwards, with its `path`; nil when there is none, and
+    // nil plus a message when it is malformed
+    let project_config = lua.create_function(|lua, dir: Option<String>| {
+        let options = CommandOptions::from_lua(LuaValue::Nil, lua)?;
+        let base = std::env::current_dir().unwrap_or_default();
+        let dir = match dir.or(options.cwd) {
+            Some(dir) => base.join(dir),
+            None => base,
+        };
+        match ProjectConfig::find(&dir) {
+            Ok(Some((path, config))) => {
+                let table = to_lua_value(lua, &config)?;
+                if let LuaValue::Table(table) = &table {
+                    table.set("path", path.to_string_lossy())?;
+                }
+                Ok((table, LuaValue::Nil))
+            }
+            Ok(None) => Ok((LuaValue::Nil, LuaValue::Nil)),
+            Err((path, message)) => Ok((
+                LuaValue::Nil,
+                format!("{}: {}", path.display(), message).into_lua(lua)?,
+            )),
+        }
+    })?;
+    exports.set("project_config", project_config)?;
+
+    // Register target_dir_for function: the `--target-dir` a subcommand gets, if any
+    let target_dir_for = lua.create_function(|_, subcommand: String| {
+        Ok(target_dir_override(&subcommand, &CommandOptions::default()))
+    })?;
+    exports.set("target_dir_for", target_dir_for)?;
+
+    // Register run_sequence function: run steps in order, optionally stopping on failure
+    let sequence_commands = cargo_commands.clone();
+    let run_sequence =
+        lua.create_function(move |lua, (steps, opts): (LuaValue, Option<LuaTable>)| {
+            let steps: Vec<SequenceStep> = lua.from_value_with(
+                steps,
+                DeserializeOptions::new().deny_unsupported_types(false),
+            )?;
+            let callbacks = EventCallbacks::from_opts(opts.as_ref())?;
+            let (stop_on_failure, options) = match &opts {
+                Some(opts) => (
+                    opts.get::<Option<bool>>("stop_on_failure")?.unwrap_or(true),
+                    CommandOptions::from_lua(LuaValue::Table(opts.clone()), lua)?,
+                ),
+                None => (true, CommandOptions::from_lua(LuaValue::Nil, lua)?),
+            };
+
+            // Steps bring their own options; the sequence's only choose the event log
+            let results = execute_with_events(
+                lua,
+                &sequence_commands.with_options(options).with_event_log()?,
+                &callbacks,
+                |commands| async move { commands.run_sequence(&steps, stop_on_failure).await },
+            )?;
+            to_lua_value(lua, &results)
+        })?;
+    exports.set("run_sequence", run_sequence)?;
+
+    // Register check_then_build function: build only when a check passes
+    let staged_commands = cargo_commands.clone();
+    let check_then_build = lua.create_function(
+        move |lua, (args, opts): (Option<Vec<String>>, Option<LuaTable>)| {
             let args = args.unwrap_or_default();
             let args_ref: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
-            cmd_fn(&cargo_commands, &args_ref)
-        })?;
-        exports.set(name, cmd)?;
-    }
+            let callbacks = EventCallbacks::from_opts(opts.as_ref())?;
+            let (build, options) = match &opts {
+                Some(opts) => (
+                    opts.get::<Option<bool>>("build")?.unwrap_or(true),
+                    CommandOptions::from_lua(LuaValue::Table(opts.clone()), lua)?,
+                ),
+                None => (true, CommandOptions::from_lua(LuaValue::Nil, lua)?),
+            };
+
+            let result = execute_with_events(
+                lua,
+                &staged_commands.with_options(options).with_event_log()?,
+                &callbacks,
+                |commands| async move { commands.check_then_build(&args_ref, build).await },
+            )?;
+            to_lua_value(lua, &result)
+        },
+    )?;
+    exports.set("check_then_build", check_then_build)?;
+
+    // Register examples function: example targets of the workspace, for a picker
+    let example_commands = cargo_commands.clone();
+    let examples = lua.create_function(
+        move |lua, (args, opts): (Option<Vec<String>>, Option<LuaTable>)| {
+            let args = args.unwrap_or_default();
+            let args_ref: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+            let options = match opts {
+                Some(opts) => CommandOptions::from_lua(LuaValue::Table(opts), lua)?,
+                None => CommandOptions::from_lua(LuaValue::Nil, lua)?,
+            };
+            let commands = example_commands.with_options(options);
+            let examples = commands.execute(commands.cargo_examples(&args_ref))?;
+            to_lua_value(lua, &examples)
+        },
+    )?;
+    exports.set("examples", examples)?;
+
+    // Register list_workspace_members function: members and their manifests, for the
+    // manifest_path option
+    let member_commands = cargo_commands.clone();
+    let list_workspace_members = lua.create_function(
+        move |lua, (args, opts): (Option<Vec<String>>, Option<LuaTable>)| {
+            let args = args.unwrap_or_default();
+            let args_ref: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+            let options = match opts {
+                Some(opts) => CommandOptions::from_lua(LuaValue::Table(opts), lua)?,
+                None => CommandOptions::from_lua(LuaValue::Nil, lua)?,
+            };
+            let commands = member_commands.with_options(options);
+            let members = commands.execute(commands.cargo_workspace_members(&args_ref))?;
+            to_lua_value(lua, &members)
+        },
+    )?;
+    exports.set("list_workspace_members", list_workspace_members)?;
+
+    // Register run_example function: `cargo run --example <name>`, returning like run
+    let run_example_commands = cargo_commands.clone();
+    let run_example = lua.create_function(
+        move |lua, (name, args, opts): (String, Option<Vec<String>>, Option<LuaTable>)| {
+            let args = args.unwrap_or_default();
+            let args_ref: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+            let callbacks = EventCallbacks::from_opts(opts.as_ref())?;
+            let options = match opts {
+                Some(opts) => CommandOptions::from_lua(LuaValue::Table(opts), lua)?,
+                None => CommandOptions::from_lua(LuaValue::Nil, lua)?,
+            };
+
+            let result = execute_with_events(
+                lua,
+                &run_example_commands
+                    .with_options(options)
+                    .with_event_log()?,
+                &callbacks,
+                |commands| async move {
+                    commands
+                        .with_project_config("run")?
+                        .cargo_run_example(&name, &args_ref)
+                        .await
+                },
+            )?;
+            command_result(lua, result)
+        },
+    )?;
+    exports.set("run_example", raising_failures(lua, run_example)?)?;
+
+    // Register check_fast function: debounced background check for on-save feedback
+    // Returns an id immediately; poll check_fast_status(id) for the quickfix list
+    let check_fast = lua.create_function(
+        move |lua, (args, opts): (Option<Vec<String>>, Option<LuaTable>)| {
+            let (options, debounce_ms) = match opts {
+                Some(opts) => (
+                    CommandOptions::from_lua(LuaValue::Table(opts.clone()), lua)?,
+                    opts.get::<Option<u64>>("debounce_ms")?,
+                ),
+                None => (CommandOptions::from_lua(LuaValue::Nil, lua)?, None),
+            };
+            let debounce = Duration::from_millis(debounce_ms.unwrap_or(DEFAULT_DEBOUNCE_MS));
+            Ok(fast_check::start(
+                options,
+                args.unwrap_or_default(),
+                debounce,
+            ))
+        },
+    )?;
+    exports.set("check_fast", check_fast)?;
+
+    let check_fast_status =
+        lua.create_function(move |lua, id: u64| to_lua_value(lua, &fast_check::status(id)))?;
+    exports.set("check_fast_status", check_fast_status)?;
+
+    // Register start_job function: run a subcommand in the background, returning its id
+    // An `on_complete` callback is called with the final status by dispatch_completions,
+    // after the job's events have gone to its `on_event`/`on_stdout`/`on_stderr` callbacks
+    let start_job = lua.create_function(
+        move |lua, (subcommand, args, opts): (String, Option<Vec<String>>, Option<LuaTable>)| {
+            let events = EventCallbacks::from_opts(opts.as_ref())?;
+            let (options, on_complete) = match opts {
+                Some(opts) => (
+                    CommandOptions::from_lua(LuaValue::Table(opts.clone()), lua)?,
+                    opts.get::<Option<LuaFunction>>("on_complete")?,
+                ),
+                None => (CommandOptions::from_lua(LuaValue::Nil, lua)?, None),
+            };
+            let id = jobs::start(
+                subcommand,
+                args.unwrap_or_default(),
+                options,
+                !events.is_empty(),
+            );
+            register_job_callbacks(lua, id, on_complete, events)?;
+            Ok(id)
+        },
+    )?;
+    exports.set("start_job", start_job)?;
+
+    // Register start_interactive function: run a subcommand as an interactive session,
+    // e.g. a REPL that takes input for as long as it runs. Returns a session table with
+    // `id` and the methods write(bytes), close_stdin(), status() and cancel(); output
+    // and the final status go to the callbacks of start_job, through dispatch_completions
+    let start_interactive = lua.create_function(
+        move |lua, (subcommand, args, opts): (String, Option<Vec<String>>, Option<LuaTable>)| {
+            let events = EventCallbacks::from_opts(opts.as_ref())?;
+            let (options, on_complete) = match opts {
+                Some(opts) => (
+                    CommandOptions::from_lua(LuaValue::Table(opts.clone()), lua)?,
+                    opts.get::<Option<LuaFunction>>("on_complete")?,
+                ),
+                None => (CommandOptions::from_lua(LuaValue::Nil, lua)?, None),
+            };
+            let id = jobs::start_interactive(
+                subcommand,
+                args.unwrap_or_default(),
+                options,
+                !events.is_empty(),
+            );
+            register_job_callbacks(lua, id, on_complete, events)?;
+            session_table(lua, id)
+        },
+    )?;
+    exports.set("start_interactive", start_interactive)?;
+
+    let job_status =
+        lua.create_function(move |lua, id: u64| to_lua_value(lua, &jobs::status(id)))?;
+    exports.set("job_status", job_status)?;
+
+    let job_cancel = lua.create_function(move |_, id: u64| Ok(jobs::cancel(id)))?;
+    exports.set("job_cancel", job_cancel)?;
+
+    // Register kill_jobs_for function: stop the jobs running in a project's directory
+    let kill_jobs_for = lua.create_function(move |_, dir: String| Ok(jobs::kill_jobs_for(&dir)))?;
+    exports.set("kill_jobs_for", kill_jobs_for)?;
+
+    // Register dispatch_completions function: deliver the events streamed by jobs since
+    // the last call, then call the on_complete callbacks of jobs that have finished;
+    // returns how many finished
+    let dispatch_completions = lua.create_function(move |lua, _: ()| {
+        // Taken out first, so callbacks can start new jobs
+        let callbacks = match lua.app_data_mut::<JobCallbacks>() {
+            Some(mut callbacks) => std::mem::take(&mut callbacks.0),
+            None => return Ok(0),
+        };
+
+        let mut pending = Vec::new();
+        let mut count = 0;
+        let mut first_error = None;
+        for (id, callback) in callbacks {
+            // The status is read first: a finished job has queued all of its events
+            let status = jobs::status(id);
+            for event in jobs::take_events(id) {
+                if let Err(err) = callback.events.deliver(lua, &event) {
+                    first_error.get_or_insert(err);
+                }
+            }
+            if !status.is_finished() {
+                pending.push((id, callback));
+                continue;
+            }
+            count += 1;
+            jobs::release(id);
+            if let Some(key) = callback.on_complete {
+                let result = lua
+                    .registry_value::<LuaFunction>(&key)
+                    .and_then(|callback| callback.call::<()>(to_lua_value(lua, &status)?))
+                    .and(lua.remove_registry_value(key));
+                if let Err(err) = result {
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+        if let Some(mut callbacks) = lua.app_data_mut::<JobCallbacks>() {
+            // Jobs started by the callbacks were added in the meantime
+            pending.append(&mut callbacks.0);
+            callbacks.0 = pending;
+        }
+        run_post_command_hook(lua);
+
+        match first_error {
+            Some(err) => Err(err),
+            None => Ok(count),
+        }
+    })?;
+    exports.set("dispatch_completions", dispatch_completions)?;
+
+    // Register set_post_command_hook function: a function called as
+    // hook(subcommand, { subcommand, ok, outcome, error }) after every subcommand, on the
+    // main thread: when a direct call returns, and from dispatch_completions for jobs.
+    // nil removes it
+    let set_post_command_hook = lua.create_function(move |lua, hook: Option<LuaFunction>| {
+        if let Some(previous) = lua.remove_app_data::<LuaPostCommandHook>() {
+            lua.remove_registry_value(previous.0)?;
+        }
+        queue_finished(hook.is_some());
+        if let Some(hook) = hook {
+            lua.set_app_data(LuaPostCommandHook(lua.create_registry_value(hook)?));
+        }
+        Ok(())
+    })?;
+    exports.set("set_post_command_hook", set_post_command_hook)?;
+
+    // Register search_start function: cancellable background search for crate pickers
+    // Each call supersedes the previous search; poll search_status(id) for the results.
+    // The id is a job's, for job_status and job_cancel too
+    let search_start = lua.create_function(
+        move |lua, (args, opts): (Option<Vec<String>>, Option<LuaTable>)| {
+            let options = match opts {
+                Some(opts) => CommandOptions::from_lua(LuaValue::Table(opts), lua)?,
+                None => CommandOptions::from_lua(LuaValue::Nil, lua)?,
+            };
+            Ok(search::start(options, args.unwrap_or_default()))
+        },
+    )?;
+    exports.set("search_start", search_start)?;
+
+    let search_status =
+        lua.create_function(move |lua, id: u64| to_lua_value(lua, &search::status(id)))?;
+    exports.set("search_status", search_status)?;
+
+    let search_cancel = lua.create_function(move |_, id: u64| Ok(search::cancel(id)))?;
+    exports.set("search_cancel", search_cancel)?;
 
     // Register interrupt function
+    // Stops the foreground command, whose result then has `interrupted` set, and
+    // forgets its input channel so later input isn't silently sent to a program
+    // that is no longer running; returns whether a command was running
     let interrupt = lua.create_function(move |_, _: ()| {
-        // TODO: Implement interrupt functionality
-        Ok(())
+        Ok(interrupt_foreground(&FOREGROUND_CANCEL, &INPUT_SENDER))
     })?;
     exports.set("interrupt", interrupt)?;
 
     // Register send_input function for interactive mode
+    // Returns true, or false and the reason the input could not be delivered
     let send_input = lua.create_function(move |_, input: String| {
-        if let Some(sender) = INPUT_SENDER.lock().unwrap().as_ref() {
-            // 非同期でメッセージを送信（エラーは無視）
-            let _ = sender.try_send(input);
+        match send_to(&INPUT_SENDER, InputMessage::Data(input.into_bytes())) {
+            Ok(()) => Ok((true, None)),
+            Err(reason) => Ok((false, Some(reason))),
         }
-        Ok(())
     })?;
     exports.set("send_input", send_input)?;
 
+    // Register send_bytes function: forward raw bytes (including control characters)
+    // Returns whether the bytes were queued for the running command
+    let send_bytes = lua.create_function(move |_, data: LuaString| {
+        Ok(send_to(&INPUT_SENDER, InputMessage::Data(data.as_bytes().to_vec())).is_ok())
+    })?;
+    exports.set("send_bytes", send_bytes)?;
+
+    // Register close_input function: signal EOF on the running command's stdin
+    let close_input =
+        lua.create_function(move |_, _: ()| Ok(send_to(&INPUT_SENDER, InputMessage::Eof).is_ok()))?;
+    exports.set("close_input", close_input)?;
+
+    // Register capabilities function: { version, features = { streaming = true, ... } }
+    // for feature detection by the plugin
+    let capabilities = lua.create_function(move |lua, _: ()| to_lua_value(lua, &capabilities()))?;
+    exports.set("capabilities", capabilities)?;
+
     Ok(exports)
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
     use crate::cargo_nvim;
-    use mlua::Lua;
+
+    #[test]
+    fn test_input_after_interrupt_reports_no_active_job() {
+        // A local slot stands in for INPUT_SENDER, which concurrent tests also use
+        let slot = Mutex::new(None);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        *slot.lock().unwrap() = Some(tx);
+
+        assert_eq!(send_to(&slot, InputMessage::Data(b"1\n".to_vec())), Ok(()));
+        assert_eq!(rx.try_recv().unwrap(), InputMessage::Data(b"1\n".to_vec()));
+
+        detach_input(&slot);
+        assert_eq!(
+            send_to(&slot, InputMessage::Data(b"2\n".to_vec())),
+            Err("no active job")
+        );
+    }
+
+    #[test]
+    fn test_interrupt_without_command_is_a_no_op() {
+        // Local slots stand in for FOREGROUND_CANCEL and INPUT_SENDER
+        let cancel_slot = Mutex::new(None);
+        let input_slot = Mutex::new(None);
+        assert!(!interrupt_foreground(&cancel_slot, &input_slot));
+
+        let cancel = CancelToken::default();
+        *cancel_slot.lock().unwrap() = Some(cancel.clone());
+        let (tx, _rx) = mpsc::unbounded_channel();
+        *input_slot.lock().unwrap() = Some(tx);
+        assert!(interrupt_foreground(&cancel_slot, &input_slot));
+        assert!(cancel.is_cancelled());
+        assert!(input_slot.lock().unwrap().is_none());
+
+        // Once the command has returned, its slot is emptied and interrupting again is
+        // harmless
+        cancel_slot.lock().unwrap().take();
+        assert!(!interrupt_foreground(&cancel_slot, &input_slot));
+    }
+
+    #[test]
+    fn test_on_output_gets_both_streams_in_order() {
+        let lua = Lua::new();
+        let opts: LuaTable = lua
+            .load(
+                r#"
+                lines = {}
+                return { on_output = function(line, stream)
+                    table.insert(lines, stream .. ": " .. line)
+                end }
+                "#,
+            )
+            .eval()
+            .unwrap();
+        let callbacks = EventCallbacks::from_opts(Some(&opts)).unwrap();
+        assert!(!callbacks.is_empty());
+        for event in [
+            Event::Stderr {
+                line: "   Compiling app v0.1.0".to_string(),
+            },
+            Event::Stdout {
+                line: "hello".to_string(),
+            },
+        ] {
+            callbacks.deliver(&lua, &event).unwrap();
+        }
+        let lines: Vec<String> = lua.globals().get("lines").unwrap();
+        assert_eq!(
+            lines,
+            vec!["stderr:    Compiling app v0.1.0", "stdout: hello"]
+        );
+    }
 
     #[test]
     fn test_module_registration() {
@@ -255,7 +975,111 @@ mod tests {
 
         assert!(table.contains_key("build").unwrap());
         assert!(table.contains_key("test").unwrap());
+        assert!(table.contains_key("test_doc").unwrap());
         assert!(table.contains_key("check").unwrap());
+        assert!(table.contains_key("help_options").unwrap());
+        assert!(table.contains_key("is_installed").unwrap());
+        assert!(table.contains_key("run_sequence").unwrap());
+        assert!(table.contains_key("send_bytes").unwrap());
+        assert!(table.contains_key("close_input").unwrap());
+        assert!(table.contains_key("check_fast").unwrap());
+        assert!(table.contains_key("check_fast_status").unwrap());
+        assert!(table.contains_key("artifact_dir").unwrap());
+        assert!(table.contains_key("set_target_dirs").unwrap());
+        assert!(table.contains_key("set_cwd").unwrap());
+        assert!(table.contains_key("set_toolchain").unwrap());
+        assert!(table.contains_key("set_cargo_bin").unwrap());
+        assert!(table.contains_key("set_env").unwrap());
+        assert!(table.contains_key("target_dir_for").unwrap());
+        assert!(table.contains_key("tree_diff").unwrap());
+        assert!(table.contains_key("check_then_build").unwrap());
+        assert!(table.contains_key("search_start").unwrap());
+        assert!(table.contains_key("compile_file").unwrap());
+        assert!(table.contains_key("start_job").unwrap());
+        assert!(table.contains_key("start_interactive").unwrap());
+
+        lua.globals().set("cargo", table).unwrap();
+        let streaming: bool = lua
+            .load("return cargo.capabilities().features.streaming")
+            .eval()
+            .unwrap();
+        assert!(streaming);
+    }
+
+    #[test]
+    fn test_job_completion_callback() {
+        let lua = Lua::new();
+        let table = cargo_nvim(&lua).unwrap();
+        lua.globals().set("cargo", table).unwrap();
+
+        let id: u64 = lua
+            .load(
+                r#"
+                lines = 0
+                return cargo.start_job("help", {}, {
+                    on_stdout = function() lines = lines + 1 end,
+                    on_complete = function(result) finished = result.status end,
+                })
+                "#,
+            )
+            .eval()
+            .unwrap();
+        let deadline = std::time::Instant::now() + Duration::from_secs(60);
+        while !jobs::status(id).is_finished() && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        let dispatched: usize = lua
+            .load("return cargo.dispatch_completions()")
+            .eval()
+            .unwrap();
+        assert_eq!(dispatched, 1);
+        assert_eq!(lua.globals().get::<String>("finished").unwrap(), "done");
+        // Streamed lines are delivered along with the result
+        assert!(lua.globals().get::<usize>("lines").unwrap() > 0);
+        // Each callback runs once
+        let dispatched: usize = lua
+            .load("return cargo.dispatch_completions()")
+            .eval()
+            .unwrap();
+        assert_eq!(dispatched, 0);
+    }
+
+    #[test]
+    fn test_post_command_hook() {
+        let lua = Lua::new();
+        let table = cargo_nvim(&lua).unwrap();
+        lua.globals().set("cargo", table).unwrap();
+
+        let (help_ok, build_failed): (bool, bool) = lua
+            .load(
+                r#"
+                seen = {}
+                cargo.set_post_command_hook(function(subcommand, result)
+                    table.insert(seen, subcommand .. "=" .. tostring(result.ok))
+                    if result.error then failed_with = result.error end
+                    error("hook error")
+                end)
+                local help_ok = pcall(cargo.help)
+                local build_ok = pcall(cargo.build, { "--invalid-flag" })
+                cargo.set_post_command_hook(nil)
+                pcall(cargo.help)
+                return help_ok, not build_ok
+                "#,
+            )
+            .eval()
+            .unwrap();
+        // The hook's error is not the command's
+        assert!(help_ok && build_failed);
+        // Commands of other tests running meanwhile may be seen too
+        let seen: Vec<String> = lua.globals().get("seen").unwrap();
+        assert!(seen.contains(&"help=true".to_string()), "{:?}", seen);
+        assert!(seen.contains(&"build=false".to_string()), "{:?}", seen);
+        assert!(lua
+            .globals()
+            .get::<String>("failed_with")
+            .unwrap()
+            .contains("--invalid-flag"));
     }
 
     #[test]
@@ -266,4 +1090,90 @@ mod tests {
         let result: mlua::Result<String> = build_fn.call(["--invalid-flag"]);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_stream_callbacks() {
+        let lua = Lua::new();
+        let table = cargo_nvim(&lua).unwrap();
+        lua.globals().set("cargo", table).unwrap();
+
+        let (stdout, stderr, events): (usize, usize, usize) = lua
+            .load(
+                r#"
+                local stdout, stderr, events = 0, 0, 0
+                local opts = {
+                    on_stdout = function(line) stdout = stdout + 1 end,
+                    on_stderr = function(line) stderr = stderr + 1 end,
+                    on_event = function(ev) events = events + 1 end,
+                }
+                cargo.help({}, opts)
+                pcall(cargo.build, { "--invalid-flag" }, opts)
+                return stdout, stderr, events
+                "#,
+            )
+            .eval()
+            .unwrap();
+
+        assert!(stdout > 0);
+        assert!(stderr > 0);
+        // The combined callback still sees every line
+        assert_eq!(events, stdout + stderr);
+    }
+
+    #[test]
+    fn test_failure_is_raised_with_details() {
+        let lua = Lua::new();
+        let table = cargo_nvim(&lua).unwrap();
+        lua.globals().set("cargo", table).unwrap();
+
+        let (ok, kind, message, exit_code, kept): (bool, String, String, i64, bool) = lua
+            .load(
+                r#"
+                local ok, err = pcall(cargo.build, { "--invalid-flag" })
+                local output = cargo.help()
+                return ok, err.kind, tostring(err), err.details.exit_code,
+                    type(output) == "string"
+                "#,
+            )
+            .eval()
+            .unwrap();
+        assert!(!ok);
+        assert_eq!(kind, "command_failed");
+        assert!(
+            message.starts_with("[command_failed] cargo build failed"),
+            "{}",
+            message
+        );
+        assert_eq!(exit_code, 1);
+        // Successful calls still return their values
+        assert!(kept);
+    }
+
+    #[test]
+    fn test_run_sequence_reports_steps() {
+        let lua = Lua::new();
+        let table = cargo_nvim(&lua).unwrap();
+        lua.globals().set("cargo", table).unwrap();
+
+        let (count, events): (usize, String) = lua
+            .load(
+                r#"
+                local seen = {}
+                local results = cargo.run_sequence(
+                    { { subcommand = "help" }, { subcommand = "build", args = { "--invalid-flag" } } },
+                    { on_event = function(ev)
+                        if ev.type ~= "stdout" and ev.type ~= "stderr" then
+                            table.insert(seen, ev.type .. ev.index)
+                        end
+                    end }
+                )
+                return #results, table.concat(seen, ",")
+                "#,
+            )
+            .eval()
+            .unwrap();
+
+        assert_eq!(count, 2);
+        assert_eq!(events, "step_start0,step_finish0,step_start1,step_finish1");
+    }
 }
@@ -0,0 +1,201 @@
+// src/manifest_error.rs
+//! Errors in a `Cargo.toml` that cargo couldn't read, with the location it reports so
+//! the manifest can be opened at the offending line
+//!
+//! Current cargo reports them like rustc diagnostics:
+//!
+//! ```text
+//! error: invalid float, expected nothing
+//!  --> a/Cargo.toml:3:14
+//! ```
+//!
+//! Older releases wrap the TOML error in `failed to parse manifest at` and `Caused by:`
+//! blocks, with the position as `TOML parse error at line 3, column 14`.
+
+use crate::quickfix::QuickfixEntry;
+use regex::Regex;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::LazyLock;
+
+/// A manifest cargo couldn't parse or load
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ManifestError {
+    /// Path of the manifest, as cargo printed it (relative to where it ran, or absolute)
+    pub file: String,
+    /// 1-based; missing when cargo didn't point at a position (e.g. a manifest without
+    /// targets)
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    pub message: String,
+}
+
+impl ManifestError {
+    /// `file` taken from `dir` when it's relative; cargo prints paths outside of `dir`
+    /// as `../..`, which are resolved when the file exists
+    pub fn resolve(mut self, dir: &Path) -> Self {
+        let path = dir.join(&self.file);
+        let path = path.canonicalize().unwrap_or(path);
+        self.file = path.to_string_lossy().into_owned();
+        self
+    }
+
+    pub fn to_quickfix(&self) -> QuickfixEntry {
+        QuickfixEntry::error(
+            &self.file,
+            self.line.unwrap_or(1),
+            self.column.unwrap_or(1),
+            &self.message,
+        )
+    }
+}
+
+impl std::fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.file)?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+static TOML_POSITION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"TOML parse error at line (\d+), column (\d+)").unwrap());
+
+// Lines of a source snippet: `  |`, `3 | version = 0.1.0"`, `  |    ^`
+static SNIPPET_LINE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\d+\s*)?\|").unwrap());
+
+/// The first manifest error in cargo's output, if any
+pub fn parse_manifest_error(output: &str) -> Option<ManifestError> {
+    let lines: Vec<&str> = output.lines().collect();
+    lines.iter().enumerate().find_map(|(i, line)| {
+        let line = line.trim_start();
+        if let Some(path) = line
+            .strip_prefix("error: failed to parse manifest at `")
+            .and_then(|rest| rest.strip_suffix('`'))
+        {
+            return Some(caused_by(path, &lines[i + 1..]));
+        }
+        let message = line.strip_prefix("error: ")?;
+        let location = lines.get(i + 1)?.trim_start().strip_prefix("--> ")?;
+        let mut parts = location.rsplitn(3, ':');
+        let column = parts.next()?.parse().ok()?;
+        let line = parts.next()?.parse().ok()?;
+        let file = parts.next()?;
+        (Path::new(file).file_name()? == "Cargo.toml").then(|| ManifestError {
+            file: file.to_string(),
+            line: Some(line),
+            column: Some(column),
+            message: message.to_string(),
+        })
+    })
+}
+
+// The older format: the reason and position follow in `Caused by:` blocks
+fn caused_by(file: &str, rest: &[&str]) -> ManifestError {
+    let position = rest.iter().find_map(|line| TOML_POSITION.captures(line));
+    let message = rest
+        .iter()
+        .map(|line| line.trim())
+        .take_while(|line| !line.starts_with("error:") && !line.starts_with("warning:"))
+        .find(|line| {
+            !line.is_empty()
+                && *line != "Caused by:"
+                && *line != "could not parse input as TOML"
+                && !TOML_POSITION.is_match(line)
+                && !SNIPPET_LINE.is_match(line)
+        })
+        .unwrap_or("failed to parse manifest");
+    ManifestError {
+        file: file.to_string(),
+        line: position.as_ref().and_then(|c| c[1].parse().ok()),
+        column: position.as_ref().and_then(|c| c[2].parse().ok()),
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_current_format() {
+        let output = "\
+error: invalid float, expected nothing
+ --> a/Cargo.toml:3:14
+  |
+3 | version = 0.1.0\"
+  |              ^^^
+error: failed to load manifest for workspace member `/tmp/ws/a`
+referenced by workspace at `/tmp/ws/Cargo.toml`
+";
+        let error = parse_manifest_error(output).unwrap();
+        assert_eq!(
+            error,
+            ManifestError {
+                file: "a/Cargo.toml".to_string(),
+                line: Some(3),
+                column: Some(14),
+                message: "invalid float, expected nothing".to_string(),
+            }
+        );
+        let error = error.resolve(Path::new("/tmp/ws"));
+        assert_eq!(error.file, "/tmp/ws/a/Cargo.toml");
+        assert_eq!(
+            error.to_string(),
+            "/tmp/ws/a/Cargo.toml:3:14: invalid float, expected nothing"
+        );
+        assert_eq!(error.to_quickfix().lnum, 3);
+
+        // A compile error isn't a manifest error
+        let compile = "error[E0308]: mismatched types\n --> src/lib.rs:2:5\n";
+        assert_eq!(parse_manifest_error(compile), None);
+        assert_eq!(parse_manifest_error("error: could not compile `app`"), None);
+    }
+
+    #[test]
+    fn test_caused_by_format() {
+        let output = "\
+error: failed to parse manifest at `/tmp/app/Cargo.toml`
+
+Caused by:
+  could not parse input as TOML
+
+Caused by:
+  TOML parse error at line 3, column 11
+    |
+  3 | version = 0.1.0\"
+    |           ^
+  invalid string
+  expected `\"`, `'`
+";
+        let error = parse_manifest_error(output).unwrap();
+        assert_eq!(error.file, "/tmp/app/Cargo.toml");
+        assert_eq!((error.line, error.column), (Some(3), Some(11)));
+        assert_eq!(error.message, "invalid string");
+        // Absolute paths are kept
+        assert_eq!(
+            error.resolve(Path::new("/elsewhere")).file,
+            "/tmp/app/Cargo.toml"
+        );
+
+        let no_targets = "\
+error: failed to parse manifest at `/tmp/app/Cargo.toml`
+
+Caused by:
+  no targets specified in the manifest
+  either src/lib.rs, src/main.rs, a [lib] section, or [[bin]] section must be present
+";
+        let error = parse_manifest_error(no_targets).unwrap();
+        assert_eq!(error.line, None);
+        assert_eq!(error.message, "no targets specified in the manifest");
+        assert_eq!(
+            error.to_string(),
+            "/tmp/app/Cargo.toml: no targets specified in the manifest"
+        );
+    }
+}
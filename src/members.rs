@@ -0,0 +1,192 @@
+// src/members.rs
+//! The workspace members a command acts on when no package is selected, worked out the
+//! way cargo does so the result can say what was built
+use crate::metadata::Metadata;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// A workspace member and the manifest to pass as `manifest_path` to run in it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WorkspaceMember {
+    pub name: String,
+    pub version: String,
+    pub manifest_path: String,
+    /// Whether cargo acts on it from the workspace root without a package selection
+    pub default_member: bool,
+}
+
+/// Members of the workspace, in metadata order
+pub fn list_workspace_members(metadata: &Metadata) -> Vec<WorkspaceMember> {
+    metadata
+        .packages
+        .iter()
+        .filter(|p| metadata.workspace_members.contains(&p.name))
+        .map(|package| WorkspaceMember {
+            name: package.name.clone(),
+            version: package.version.clone(),
+            manifest_path: package.manifest_path.clone(),
+            default_member: metadata.workspace_default_members.contains(&package.name),
+        })
+        .collect()
+}
+
+/// Whether `args` select packages themselves (`--package`, `--workspace`, ...)
+pub fn has_package_selection(args: &[&str]) -> bool {
+    args.iter().take_while(|a| **a != "--").any(|arg| {
+        matches!(*arg, "-p" | "--package" | "--workspace" | "--all")
+            || arg.starts_with("--package=")
+            || (arg.starts_with("-p") && !arg.starts_with("--"))
+    })
+}
+
+/// The manifest cargo uses when started in `dir` without `--manifest-path`: the closest
+/// `Cargo.toml` in `dir` or a parent
+pub fn find_manifest(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|d| d.join("Cargo.toml"))
+        .find(|manifest| manifest.is_file())
+}
+
+/// The package of `manifest` when it isn't a workspace root (no `[workspace]` table):
+/// cargo acts on that package alone, so its name is all there is to know
+pub fn package_of_manifest(manifest: &Path) -> Option<String> {
+    let table: toml::Table = std::fs::read_to_string(manifest).ok()?.parse().ok()?;
+    if table.contains_key("workspace") {
+        return None;
+    }
+    let name = table.get("package")?.get("name")?.as_str()?;
+    Some(name.to_string())
+}
+
+/// Members cargo acts on for `manifest` when no package is selected
+/// From a member's own manifest that is the member; from the workspace root it's the
+/// default members, falling back to the root package or every member (a virtual
+/// manifest) for cargo releases that don't report default members
+pub fn targeted_members(metadata: &Metadata, manifest: &Path) -> Vec<String> {
+    let manifest = canonical(manifest);
+    let is_root = metadata
+        .workspace_root
+        .as_deref()
+        .is_some_and(|root| canonical(&Path::new(root).join("Cargo.toml")) == manifest);
+    let package_of = |manifest: &Path| {
+        metadata
+            .packages
+            .iter()
+            .filter(|p| metadata.workspace_members.contains(&p.name))
+            .find(|p| canonical(Path::new(&p.manifest_path)) == manifest)
+            .map(|p| p.name.clone())
+    };
+
+    if !is_root {
+        return package_of(&manifest).into_iter().collect();
+    }
+    if !metadata.workspace_default_members.is_empty() {
+        return metadata.workspace_default_members.clone();
+    }
+    match package_of(&manifest) {
+        Some(root_package) => vec![root_package],
+        None => metadata.workspace_members.clone(),
+    }
+}
+
+// Symlinked checkouts would otherwise never match the paths cargo reports
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::ScratchCrate;
+    use crate::PackageInfo;
+
+    fn package(name: &str, manifest_path: &str) -> PackageInfo {
+        PackageInfo {
+            name: name.to_string(),
+            version: "0.1.0".to_string(),
+            manifest_path: manifest_path.to_string(),
+            targets: Vec::new(),
+            features: Default::default(),
+            default_features: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_has_package_selection() {
+        assert!(!has_package_selection(&[]));
+        assert!(!has_package_selection(&["--release", "--", "-p", "x"]));
+        assert!(has_package_selection(&["-p", "app"]));
+        assert!(has_package_selection(&["-papp"]));
+        assert!(has_package_selection(&["--package=app"]));
+        assert!(has_package_selection(&["--workspace", "--exclude", "app"]));
+    }
+
+    #[test]
+    fn test_targeted_members() {
+        let mut metadata = Metadata {
+            packages: vec![
+                package("cli", "/ws/cli/Cargo.toml"),
+                package("core", "/ws/core/Cargo.toml"),
+                package("serde", "/registry/serde/Cargo.toml"),
+            ],
+            workspace_members: vec!["cli".to_string(), "core".to_string()],
+            workspace_default_members: vec!["cli".to_string()],
+            workspace_root: Some("/ws".to_string()),
+            target_directory: None,
+        };
+        let root = Path::new("/ws/Cargo.toml");
+
+        assert_eq!(targeted_members(&metadata, root), vec!["cli"]);
+        // In a member's directory cargo acts on that member
+        assert_eq!(
+            targeted_members(&metadata, Path::new("/ws/core/Cargo.toml")),
+            vec!["core"]
+        );
+
+        // Cargo without default members in its metadata: a virtual manifest acts on all
+        metadata.workspace_default_members.clear();
+        assert_eq!(targeted_members(&metadata, root), vec!["cli", "core"]);
+        metadata.packages.push(package("app", "/ws/Cargo.toml"));
+        metadata.workspace_members.push("app".to_string());
+        assert_eq!(targeted_members(&metadata, root), vec!["app"]);
+    }
+
+    #[test]
+    fn test_package_of_manifest() {
+        let scratch = ScratchCrate::new("package_of_manifest", &[]);
+        let manifest = scratch.path("Cargo.toml");
+        // A workspace root needs the metadata
+        assert_eq!(package_of_manifest(&manifest), None);
+        std::fs::write(
+            &manifest,
+            "[package]\nname = \"member\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        assert_eq!(package_of_manifest(&manifest).as_deref(), Some("member"));
+        assert_eq!(
+            package_of_manifest(&scratch.path("missing/Cargo.toml")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_list_workspace_members() {
+        let metadata = Metadata {
+            packages: vec![
+                package("cli", "/ws/cli/Cargo.toml"),
+                package("serde", "/registry/serde/Cargo.toml"),
+                package("core", "/ws/core/Cargo.toml"),
+            ],
+            workspace_members: vec!["cli".to_string(), "core".to_string()],
+            workspace_default_members: vec!["cli".to_string()],
+            workspace_root: Some("/ws".to_string()),
+            target_directory: None,
+        };
+        let members = list_workspace_members(&metadata);
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0].manifest_path, "/ws/cli/Cargo.toml");
+        assert!(members[0].default_member);
+        assert_eq!(members[1].name, "core");
+        assert!(!members[1].default_member);
+    }
+}
@@ -0,0 +1,449 @@
+// src/messages.rs
+//! Models for the JSON messages cargo prints with `--message-format=json`
+
+use crate::diagnostics::Diagnostic;
+use crate::events::Event;
+use serde::Deserialize;
+use std::collections::HashSet;
+
+/// A single line of cargo's JSON output, keyed by its `reason`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+pub enum CargoMessage {
+    CompilerArtifact(Artifact),
+    CompilerMessage(CompilerMessage),
+    BuildFinished(BuildFinished),
+    #[serde(other)]
+    Other,
+}
+
+/// A `compiler-artifact` message
+#[derive(Debug, Clone, Deserialize)]
+pub struct Artifact {
+    pub target: Target,
+    /// Path of the produced executable, for binary, example and test targets
+    pub executable: Option<String>,
+    /// `true` when the artifact was reused from a previous build
+    pub fresh: bool,
+}
+
+/// A `compiler-message` message, wrapping a rustc diagnostic
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompilerMessage {
+    /// Package of the target being compiled, as a package ID spec
+    #[serde(default)]
+    pub package_id: Option<String>,
+    pub message: Diagnostic,
+}
+
+/// The `build-finished` message, cargo's own verdict on the build
+#[derive(Debug, Clone, Deserialize)]
+pub struct BuildFinished {
+    pub success: bool,
+}
+
+/// The target an artifact was built for
+#[derive(Debug, Clone, Deserialize)]
+pub struct Target {
+    pub name: String,
+}
+
+impl CargoMessage {
+    /// Parse a line of cargo output, returning `None` for non-JSON lines
+    pub fn parse(line: &str) -> Option<Self> {
+        if !line.starts_with('{') {
+            return None;
+        }
+        serde_json::from_str(line).ok()
+    }
+
+    /// Event to publish for this message, if any
+    pub fn to_event(&self) -> Option<Event> {
+        match self {
+            CargoMessage::CompilerArtifact(artifact) => {
+                let path = artifact.executable.clone()?;
+                Some(Event::Artifact {
+                    target: artifact.target.name.clone(),
+                    path,
+                    fresh: artifact.fresh,
+                })
+            }
+            CargoMessage::CompilerMessage(message) => Some(Event::Diagnostic {
+                quickfix: message.message.to_quickfix(),
+                suggestions: message.message.suggestions(),
+                diagnostic: message.message.clone(),
+            }),
+            CargoMessage::BuildFinished(_) | CargoMessage::Other => None,
+        }
+    }
+}
+
+/// Message format whose diagnostics carry both the structured data and rustc's
+/// rendered text (with colors), so one build gives a quickfix list and human output
+pub const RENDERED_MESSAGE_FORMAT: &str = "--message-format=json-diagnostic-rendered-ansi";
+
+/// Whether `args` ask cargo for JSON messages (`--message-format=json...` or
+/// `--message-format json...`), before any `--`
+pub fn is_json_message_format(args: &[&str]) -> bool {
+    let args: Vec<&str> = args.iter().copied().take_while(|a| *a != "--").collect();
+    args.iter().enumerate().any(|(i, arg)| {
+        let format = match arg.strip_prefix("--message-format") {
+            Some(rest) => rest.strip_prefix('=').or(args.get(i + 1).copied()),
+            None => None,
+        };
+        format.is_some_and(|format| format.starts_with("json"))
+    })
+}
+
+impl CompilerMessage {
+    /// Lines rustc would have printed for this diagnostic
+    pub fn rendered_lines(&self) -> impl Iterator<Item = &str> {
+        self.message.rendered.as_deref().unwrap_or_default().lines()
+    }
+
+    /// Whether the diagnostic is of one of `packages` (by name); every one is when the
+    /// list is empty, and ones whose package is unknown are kept
+    pub fn of_packages(&self, packages: &[String]) -> bool {
+        packages.is_empty()
+            || self
+                .package_id
+                .as_deref()
+                .and_then(package_name)
+                .is_none_or(|name| packages.iter().any(|p| p == name))
+    }
+}
+
+/// Name of the package in a package ID: `path+file:///ws/cli#my-cli@0.1.0`,
+/// `registry+https://...#serde@1.0.0`, `path+file:///ws/app#0.1.0` (the name is the
+/// directory's when they match) or the older `my-cli 0.1.0 (path+file:///ws/cli)`
+pub fn package_name(package_id: &str) -> Option<&str> {
+    if let Some((name, rest)) = package_id.split_once(' ') {
+        return rest.contains('(').then_some(name);
+    }
+    let (url, fragment) = package_id.split_once('#')?;
+    match fragment.split_once('@') {
+        Some((name, _)) => Some(name),
+        None => url
+            .split(['?', '#'])
+            .next()?
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next(),
+    }
+}
+
+/// Output with cargo's JSON messages replaced by what cargo prints without them: the
+/// rendered text of each diagnostic, once; artifacts and `build-finished` are dropped
+pub fn render_messages(output: &str) -> String {
+    let mut seen: HashSet<Diagnostic> = HashSet::new();
+    let mut rendered = String::new();
+    for line in output.lines() {
+        match CargoMessage::parse(line) {
+            None => {
+                rendered.push_str(line);
+                rendered.push('\n');
+            }
+            Some(CargoMessage::CompilerMessage(message)) => {
+                if !seen.insert(message.message.clone()) {
+                    continue;
+                }
+                for line in message.rendered_lines() {
+                    rendered.push_str(line);
+                    rendered.push('\n');
+                }
+            }
+            Some(_) => {}
+        }
+    }
+    rendered
+}
+
+/// The `success` of the last `build-finished` message, if cargo printed one
+pub fn build_finished(output: &str) -> Option<bool> {
+    output
+        .lines()
+        .rev()
+        .find_map(|line| match CargoMessage::parse(line) {
+            Some(CargoMessage::BuildFinished(finished)) => Some(finished.success),
+            _ => None,
+        })
+}
+
+/// Status lines cargo prints as it starts work on a package
+const PROGRESS_ACTIONS: &[&str] = &["Compiling", "Checking", "Documenting"];
+
+/// Progress event for a status line like `Documenting my-lib v0.1.0 (/tmp/ws/my-lib)`
+pub fn progress_event(line: &str) -> Option<Event> {
+    let (action, rest) = line.trim_start().split_once(' ')?;
+    if !PROGRESS_ACTIONS.contains(&action) {
+        return None;
+    }
+    let mut words = rest.split_whitespace();
+    let package = words.next()?;
+    let version = words.next()?.strip_prefix('v')?;
+    Some(Event::Progress {
+        action: action.to_string(),
+        package: package.to_string(),
+        version: version.to_string(),
+    })
+}
+
+/// Tracks cargo's download status lines across a command's output
+///
+/// Without a terminal cargo prints `Downloading crates ...`, then `Downloaded <crate>
+/// v<version>` as each one arrives and `Downloaded N crates (1.2MB) in 0.5s` at the end
+#[derive(Debug, Default)]
+pub struct DownloadProgress {
+    current: usize,
+    total: Option<usize>,
+}
+
+impl DownloadProgress {
+    /// Downloading event for a download status line
+    pub fn update(&mut self, line: &str) -> Option<Event> {
+        let (action, rest) = line.trim_start().split_once(' ')?;
+        let mut words = rest.split_whitespace();
+        let first = words.next()?;
+        let count = first
+            .parse::<usize>()
+            .ok()
+            .filter(|_| matches!(words.next(), Some("crate" | "crates")));
+        match (action, count) {
+            ("Downloading", Some(total)) => self.total = Some(total),
+            ("Downloading", None) if first == "crates" => {}
+            ("Downloaded", Some(total)) => {
+                self.total = Some(total);
+                self.current = total;
+            }
+            ("Downloaded", None) if words.next()?.starts_with('v') => self.current += 1,
+            _ => return None,
+        }
+        Some(Event::Downloading {
+            current: self.current,
+            total: self.total,
+        })
+    }
+}
+
+/// Whether the output shows a build with nothing to do
+///
+/// Cargo prints only the `Finished` line when everything is up to date; any
+/// `Compiling`/`Checking`/`Documenting` line or a non-fresh JSON artifact means work was done.
+pub fn is_cached_build(output: &str) -> bool {
+    let mut finished = false;
+    for line in output.lines() {
+        let status = line.trim_start();
+        if status.starts_with("Compiling ")
+            || status.starts_with("Checking ")
+            || status.starts_with("Documenting ")
+        {
+            return false;
+        }
+        if status.starts_with("Finished ") {
+            finished = true;
+        }
+        if let Some(CargoMessage::CompilerArtifact(artifact)) = CargoMessage::parse(line) {
+            if !artifact.fresh {
+                return false;
+            }
+        }
+    }
+    finished
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_json_message_format() {
+        assert!(is_json_message_format(&["--message-format=json"]));
+        assert!(is_json_message_format(&[
+            "--message-format",
+            "json-diagnostic-short"
+        ]));
+        assert!(is_json_message_format(&[RENDERED_MESSAGE_FORMAT]));
+        assert!(!is_json_message_format(&["--message-format=short"]));
+        assert!(!is_json_message_format(&["--message-format"]));
+        assert!(!is_json_message_format(&["--", "--message-format=json"]));
+    }
+
+    const BIN_ARTIFACT: &str = r#"{"reason":"compiler-artifact","package_id":"path+file:///tmp/app#0.1.0","manifest_path":"/tmp/app/Cargo.toml","target":{"kind":["bin"],"crate_types":["bin"],"name":"app","src_path":"/tmp/app/src/main.rs","edition":"2021","doc":true,"doctest":false,"test":true},"profile":{"opt_level":"0","debuginfo":2,"debug_assertions":true,"overflow_checks":true,"test":false},"features":[],"filenames":["/tmp/app/target/debug/app"],"executable":"/tmp/app/target/debug/app","fresh":true}"#;
+
+    const LIB_ARTIFACT: &str = r#"{"reason":"compiler-artifact","package_id":"registry+https://github.com/rust-lang/crates.io-index#cfg-if@1.0.0","target":{"kind":["lib"],"crate_types":["lib"],"name":"cfg_if","src_path":"/x/src/lib.rs"},"filenames":["/tmp/app/target/debug/deps/libcfg_if.rlib"],"executable":null,"fresh":false}"#;
+
+    #[test]
+    fn test_package_name() {
+        assert_eq!(
+            package_name("path+file:///ws/cli#my-cli@0.1.0"),
+            Some("my-cli")
+        );
+        assert_eq!(package_name("path+file:///tmp/app#0.1.0"), Some("app"));
+        assert_eq!(
+            package_name("registry+https://github.com/rust-lang/crates.io-index#cfg-if@1.0.0"),
+            Some("cfg-if")
+        );
+        assert_eq!(
+            package_name("my-cli 0.1.0 (path+file:///ws/cli)"),
+            Some("my-cli")
+        );
+        assert_eq!(package_name("not an id"), None);
+
+        let message = |package_id: Option<&str>| CompilerMessage {
+            package_id: package_id.map(str::to_string),
+            message: serde_json::from_str(
+                r#"{"message":"m","level":"warning","code":null,"rendered":null}"#,
+            )
+            .unwrap(),
+        };
+        let only = ["app".to_string()];
+        assert!(message(Some("path+file:///tmp/app#0.1.0")).of_packages(&only));
+        assert!(!message(Some("registry+https://x#cfg-if@1.0.0")).of_packages(&only));
+        assert!(message(Some("registry+https://x#cfg-if@1.0.0")).of_packages(&[]));
+        assert!(message(None).of_packages(&only));
+    }
+
+    #[test]
+    fn test_render_messages() {
+        let diagnostic = r#"{"reason":"compiler-message","message":{"rendered":"\u001b[33mwarning\u001b[0m: unused variable: `x`\n --> src/lib.rs:1:5\n","level":"warning","message":"unused variable: `x`","code":null,"spans":[]}}"#;
+        let output = format!(
+            "   Compiling app v0.1.0 (/tmp/app)\n{}\n{}\n{}\n{}\n",
+            LIB_ARTIFACT, diagnostic, diagnostic, r#"{"reason":"build-finished","success":true}"#
+        );
+        assert_eq!(
+            render_messages(&output),
+            "   Compiling app v0.1.0 (/tmp/app)\n\u{1b}[33mwarning\u{1b}[0m: unused variable: `x`\n --> src/lib.rs:1:5\n"
+        );
+    }
+
+    #[test]
+    fn test_executable_artifact_event() {
+        let message = CargoMessage::parse(BIN_ARTIFACT).unwrap();
+        assert_eq!(
+            message.to_event(),
+            Some(Event::Artifact {
+                target: "app".to_string(),
+                path: "/tmp/app/target/debug/app".to_string(),
+                fresh: true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_non_executable_and_non_json_lines() {
+        let message = CargoMessage::parse(LIB_ARTIFACT).unwrap();
+        assert!(message.to_event().is_none());
+
+        let message = CargoMessage::parse(r#"{"reason":"build-script-executed"}"#);
+        assert!(matches!(message, Some(CargoMessage::Other)));
+
+        assert!(CargoMessage::parse("   Compiling app v0.1.0").is_none());
+    }
+
+    #[test]
+    fn test_compiler_message_event() {
+        let line = r#"{"reason":"compiler-message","package_id":"path+file:///tmp/app#0.1.0","target":{"kind":["bin"],"name":"app"},"message":{"rendered":"warning: unused variable: `x`\n","children":[],"level":"warning","message":"unused variable: `x`","spans":[{"file_name":"src/main.rs","line_start":2,"column_start":9,"is_primary":true}],"code":{"code":"unused_variables","explanation":null}}}"#;
+        match CargoMessage::parse(line).unwrap().to_event() {
+            Some(Event::Diagnostic {
+                diagnostic,
+                quickfix,
+                suggestions,
+            }) => {
+                assert!(suggestions.is_empty());
+                assert_eq!(diagnostic.level, "warning");
+                let quickfix = quickfix.unwrap();
+                assert_eq!((quickfix.lnum, quickfix.col), (2, 9));
+                assert_eq!(quickfix.text, "[unused_variables] unused variable: `x`");
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_build_finished() {
+        let output = format!(
+            "{}\n{}\n   Running `target/debug/app`",
+            BIN_ARTIFACT, r#"{"reason":"build-finished","success":false}"#
+        );
+        assert_eq!(build_finished(&output), Some(false));
+        assert_eq!(
+            build_finished(r#"{"reason":"build-finished","success":true}"#),
+            Some(true)
+        );
+        assert_eq!(build_finished("    Finished `dev` profile"), None);
+    }
+
+    #[test]
+    fn test_download_progress() {
+        let mut progress = DownloadProgress::default();
+        let downloading = |current, total| Some(Event::Downloading { current, total });
+        assert_eq!(progress.update("    Updating crates.io index"), None);
+        assert_eq!(
+            progress.update(" Downloading crates ..."),
+            downloading(0, None)
+        );
+        assert_eq!(
+            progress.update("  Downloaded serde v1.0.228"),
+            downloading(1, None)
+        );
+        assert_eq!(
+            progress.update("  Downloaded itoa v1.0.15 (registry `crates-io`)"),
+            downloading(2, None)
+        );
+        assert_eq!(
+            progress.update("  Downloaded 2 crates (101.5 KB) in 0.31s"),
+            downloading(2, Some(2))
+        );
+        assert_eq!(progress.update("   Compiling serde v1.0.228"), None);
+
+        let mut progress = DownloadProgress::default();
+        assert_eq!(
+            progress.update(" Downloading 3 crates"),
+            downloading(0, Some(3))
+        );
+        assert_eq!(
+            progress.update("  Downloaded libc v0.2.175"),
+            downloading(1, Some(3))
+        );
+        assert_eq!(
+            progress.update("  Downloaded 1 crate (790.1 KB) in 0.2s"),
+            downloading(1, Some(1))
+        );
+        // Not a status line
+        assert_eq!(progress.update("Downloaded files are cached"), None);
+    }
+
+    #[test]
+    fn test_progress_event() {
+        assert_eq!(
+            progress_event(" Documenting my-lib v0.1.0 (/tmp/ws/my-lib)"),
+            Some(Event::Progress {
+                action: "Documenting".to_string(),
+                package: "my-lib".to_string(),
+                version: "0.1.0".to_string(),
+            })
+        );
+        assert!(progress_event("   Compiling serde v1.0.228").is_some());
+        assert!(progress_event("    Finished `dev` profile [unoptimized] target(s)").is_none());
+        assert!(progress_event("Compiling the project failed").is_none());
+    }
+
+    #[test]
+    fn test_cached_build_detection() {
+        let finished = "    Finished `dev` profile [unoptimized + debuginfo] target(s) in 0.03s";
+        assert!(is_cached_build(finished));
+        assert!(is_cached_build(&format!("{}\n{}", BIN_ARTIFACT, finished)));
+
+        // Fresh artifacts alongside a stale one still mean work was done
+        assert!(!is_cached_build(&format!(
+            "{}\n{}\n{}",
+            BIN_ARTIFACT, LIB_ARTIFACT, finished
+        )));
+        assert!(!is_cached_build(&format!(
+            "    Checking app v0.1.0 (/tmp/app)\n{}",
+            finished
+        )));
+        // Without a `Finished` line nothing can be said about the build
+        assert!(!is_cached_build("error: could not find `Cargo.toml`"));
+    }
+}
@@ -0,0 +1,148 @@
+// src/metadata.rs
+//! Packages, targets and features from `cargo metadata --format-version 1`
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Packages described by `cargo metadata`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Metadata {
+    pub packages: Vec<PackageInfo>,
+    /// Names of the packages that are members of the workspace
+    pub workspace_members: Vec<String>,
+    /// Members a command without `--package`/`--workspace` acts on from the workspace
+    /// root: `default-members`, else the root package, else every member. Empty when
+    /// cargo is older than 1.71, which doesn't report them
+    pub workspace_default_members: Vec<String>,
+    /// Directory of the workspace's root manifest
+    pub workspace_root: Option<String>,
+    /// Where cargo puts build output, with `CARGO_TARGET_DIR` and config applied
+    pub target_directory: Option<String>,
+}
+
+/// A package with what a feature picker needs
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PackageInfo {
+    pub name: String,
+    pub version: String,
+    pub manifest_path: String,
+    pub targets: Vec<TargetInfo>,
+    /// Feature name -> the features and dependencies it enables (`dep:serde`,
+    /// `serde/derive`, ...); `default` isn't included
+    pub features: BTreeMap<String, Vec<String>>,
+    /// What the `default` feature enables, i.e. what is on without `--no-default-features`
+    pub default_features: Vec<String>,
+}
+
+/// A build target of a package
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TargetInfo {
+    pub name: String,
+    /// `lib`, `bin`, `example`, `test`, `bench`, `custom-build`, ...
+    pub kind: Vec<String>,
+    #[serde(default)]
+    pub src_path: String,
+    /// Features the target is only built with (`required-features` in the manifest)
+    #[serde(default, rename(deserialize = "required-features"))]
+    pub required_features: Vec<String>,
+}
+
+// The parts of cargo's output that are read
+#[derive(Deserialize)]
+struct RawMetadata {
+    packages: Vec<RawPackage>,
+    #[serde(default)]
+    workspace_members: Vec<String>,
+    #[serde(default)]
+    workspace_default_members: Vec<String>,
+    workspace_root: Option<String>,
+    target_directory: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawPackage {
+    id: String,
+    name: String,
+    version: String,
+    manifest_path: String,
+    #[serde(default)]
+    targets: Vec<TargetInfo>,
+    #[serde(default)]
+    features: BTreeMap<String, Vec<String>>,
+}
+
+impl Metadata {
+    /// Parse the JSON line of the output; other lines (warnings) are skipped
+    pub fn from_output(output: &str) -> Option<Self> {
+        let raw: RawMetadata = output
+            .lines()
+            .filter(|line| line.starts_with('{'))
+            .find_map(|line| serde_json::from_str(line).ok())?;
+
+        let names = |ids: &[String]| {
+            raw.packages
+                .iter()
+                .filter(|p| ids.contains(&p.id))
+                .map(|p| p.name.clone())
+                .collect()
+        };
+        let workspace_members = names(&raw.workspace_members);
+        let workspace_default_members = names(&raw.workspace_default_members);
+        let packages = raw
+            .packages
+            .into_iter()
+            .map(|p| {
+                let mut features = p.features;
+                let default_features = features.remove("default").unwrap_or_default();
+                PackageInfo {
+                    name: p.name,
+                    version: p.version,
+                    manifest_path: p.manifest_path,
+                    targets: p.targets,
+                    features,
+                    default_features,
+                }
+            })
+            .collect();
+        Some(Self {
+            packages,
+            workspace_members,
+            workspace_default_members,
+            workspace_root: raw.workspace_root,
+            target_directory: raw.target_directory,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: &str = r#"warning: unused manifest key: package.unknown
+{"packages":[{"name":"app","version":"0.1.0","id":"path+file:///tmp/app#0.1.0","license":null,"source":null,"dependencies":[{"name":"serde","req":"^1","optional":true}],"targets":[{"kind":["lib"],"crate_types":["lib"],"name":"app","src_path":"/tmp/app/src/lib.rs"},{"kind":["bin"],"crate_types":["bin"],"name":"cli","src_path":"/tmp/app/src/bin/cli.rs","required-features":["json"]}],"features":{"default":["json"],"json":["dep:serde","serde?/derive"],"serde":["dep:serde"]},"manifest_path":"/tmp/app/Cargo.toml","edition":"2021"}],"workspace_members":["path+file:///tmp/app#0.1.0"],"workspace_default_members":["path+file:///tmp/app#0.1.0"],"resolve":null,"target_directory":"/tmp/app/target","version":1,"workspace_root":"/tmp/app","metadata":null}"#;
+
+    #[test]
+    fn test_parse_metadata_features() {
+        let metadata = Metadata::from_output(OUTPUT).unwrap();
+        assert_eq!(metadata.workspace_members, vec!["app"]);
+        assert_eq!(metadata.workspace_default_members, vec!["app"]);
+        assert_eq!(metadata.workspace_root.as_deref(), Some("/tmp/app"));
+        assert_eq!(
+            metadata.target_directory.as_deref(),
+            Some("/tmp/app/target")
+        );
+
+        let package = &metadata.packages[0];
+        assert_eq!(package.default_features, vec!["json"]);
+        assert_eq!(
+            package.features.keys().collect::<Vec<_>>(),
+            vec!["json", "serde"]
+        );
+        assert_eq!(package.features["json"], vec!["dep:serde", "serde?/derive"]);
+        assert_eq!(package.targets[1].name, "cli");
+        assert_eq!(package.targets[1].kind, vec!["bin"]);
+        assert_eq!(package.targets[1].required_features, vec!["json"]);
+        assert!(package.targets[0].required_features.is_empty());
+
+        assert!(Metadata::from_output("error: could not find `Cargo.toml`").is_none());
+    }
+}
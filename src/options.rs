@@ -0,0 +1,1645 @@
+// src/options.rs
+use crate::artifacts::validate_component;
+use crate::event_log::EventLogTarget;
+use crate::messages::RENDERED_MESSAGE_FORMAT;
+use crate::profile::Profiler;
+use crate::search::MAX_LIMIT;
+use crate::signal::{StopSignal, DEFAULT_KILL_TIMEOUT};
+use crate::stream::DEFAULT_OUTPUT_YIELD;
+use crate::test_report::TestFormat;
+use crate::tree::validate_package_spec;
+use mlua::prelude::*;
+use mlua::DeserializeOptions;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::process::Command as TokioCommand;
+
+/// Per-invocation options passed from Lua as a table
+/// Missing keys fall back to the built-in defaults
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct CommandOptions {
+    /// Directory the command runs in, instead of Neovim's current directory (or the one
+    /// set with `set_cwd`); the project config is looked up from there too
+    pub cwd: Option<String>,
+    /// Manifest of the project to run in (`--manifest-path <path>`), for every subcommand
+    /// that takes one; relative to `cwd`
+    pub manifest_path: Option<String>,
+    /// Arguments as one string, e.g. `--features "a b" --lib`, split the way a shell
+    /// would (quotes and backslash escapes) and added after `args`; the list of `args`
+    /// is passed as is and remains the exact way to give arguments
+    pub raw_args_string: Option<String>,
+    /// Override the default timeout for this command (seconds); 0 disables it
+    pub timeout_secs: Option<u64>,
+    /// `false` disables the timeout, whatever `timeout_secs` says
+    pub timeout: Option<bool>,
+    /// Grace period given to each signal when the command is stopped by its timeout or
+    /// cancelled, before the next one (milliseconds, 2000 by default)
+    pub kill_timeout_ms: Option<u64>,
+    /// Signal the command is stopped with first (`"SIGINT"` or `"SIGTERM"`): SIGINT by
+    /// default for run, so the program's Ctrl-C handler runs, SIGTERM otherwise
+    pub stop_signal: Option<StopSignal>,
+    /// Least time between two reads of the command's output (milliseconds, 5 by
+    /// default); lines printed meanwhile are read together. 0 reads every line as it
+    /// comes, at the cost of CPU when a build prints thousands of them
+    pub output_yield_ms: Option<u64>,
+    /// Lines of output kept in the result: past it, the oldest are dropped (counted in
+    /// `details.dropped_lines`), while callbacks still see every line. Unlimited by
+    /// default, except for tree, which keeps the last 10000
+    pub max_output_lines: Option<usize>,
+    /// Arguments for the tool behind cargo, added after a `--` separator (reusing one in
+    /// the arguments): the test harness (test, bench), the program (run), rustc lint
+    /// flags (clippy), rustfmt (fmt), nextest's filters (nextest) or the test binaries
+    /// (llvm_cov); an error for other subcommands
+    pub tool_args: Vec<String>,
+    /// clippy: fail on any warning (`-- -D warnings`)
+    pub deny_warnings: bool,
+    /// clippy: lints to warn on (`-- -W <lint>`)
+    pub warn: Vec<String>,
+    /// clippy: lints to allow (`-- -A <lint>`)
+    pub allow: Vec<String>,
+    /// Compiling commands (see `command_args`): cargo's own parallelism (`--jobs <N>`)
+    pub jobs: Option<i64>,
+    /// vendor: additional manifests to sync and vendor (`--sync <manifest>`)
+    pub sync: Vec<String>,
+    /// Prompt pattern (regex) -> input sent automatically when an output line matches
+    pub responses: BTreeMap<String, String>,
+    /// tree: show the crates depending on this package (`--invert <spec>`)
+    pub invert: Option<String>,
+    /// Append events as newline-delimited JSON to this file
+    pub event_log: Option<String>,
+    /// Write events as newline-delimited JSON to this open file descriptor
+    pub event_fd: Option<i32>,
+    /// run: when the program panics without a backtrace, run it again once with
+    /// `RUST_BACKTRACE=1`
+    pub backtrace_on_panic: bool,
+    /// run: directory the program runs in, relative to `cwd`; cargo still runs in `cwd`
+    /// (reading its config and building from there). Set through a runner for the target
+    /// (`--config target.<triple>.runner`), so it replaces a runner configured for that
+    /// triple; Unix only
+    pub run_dir: Option<String>,
+    /// run: start the program under a profiler (`perf`, `dtrace` or `samply`), also
+    /// through a runner for the target; the profile goes to `<target dir>/profiles/`
+    pub profile_with: Option<Profiler>,
+    /// run jobs: stop a running job for the same target before starting (on by default)
+    pub replace: Option<bool>,
+    /// Force interactive mode on (`true`) or off (`false`) instead of detecting it from the
+    /// output; off means the normal timeout applies
+    pub interactive: Option<bool>,
+    /// check_fast and diagnostic events: keep only error-level diagnostics
+    pub errors_only: bool,
+    /// Diagnostics from JSON output (check_fast, `rendered_diagnostics`, diagnostic
+    /// events): keep only those of these packages, e.g. `{ "my-crate" }`, while the
+    /// whole workspace is still built; all are kept when empty
+    pub only_packages: Vec<String>,
+    /// Compiling commands (see `command_args`): build with
+    /// `--message-format=json-diagnostic-rendered-ansi`, filling `quickfix` from the
+    /// JSON while output and stderr events get rustc's rendered text
+    pub rendered_diagnostics: bool,
+    /// Config overrides for this invocation (`--config key=value`), e.g.
+    /// `net.git-fetch-with-cli=true`
+    pub config: Vec<String>,
+    /// Run through a wrapper: `cross` replaces `cargo`, `sccache` is set as `RUSTC_WRAPPER`
+    pub wrapper: Option<String>,
+    /// Run cargo (and the rustc/rustfmt it needs) through this command, e.g.
+    /// `["nix", "develop", "-c"]` or `["direnv", "exec", "."]`, so a managed environment
+    /// is active; the cargo invocation is appended to it
+    pub shell_prefix: Option<Vec<String>>,
+    /// rustup toolchain to run with, like `cargo +<toolchain>` (e.g. `"nightly"` or
+    /// `"+nightly"`); set as `RUSTUP_TOOLCHAIN` for cargo and the tools it runs. Empty
+    /// means none, and without one the toolchain set with `set_toolchain` applies
+    pub toolchain: Option<String>,
+    /// Variables removed from the inherited environment of every process started,
+    /// e.g. `{ "RUSTFLAGS", "CARGO_BUILD_RUSTFLAGS" }`; variables the plugin sets itself
+    /// are applied afterwards
+    pub strip_env: Vec<String>,
+    /// Variables set for every process started, e.g. `{ RUST_BACKTRACE = "full" }`, or
+    /// removed with `false` (`{ RUSTFLAGS = false }`); on top of those set with `set_env`,
+    /// and applied after `strip_env`
+    pub env: BTreeMap<String, EnvValue>,
+    /// Have cargo print colors (`CARGO_TERM_COLOR=always`), so the output returned keeps
+    /// its ANSI escapes for a terminal buffer; everything read from it (prompts,
+    /// diagnostics, `details`) is read without them
+    pub color: bool,
+    /// search: number of results (`--limit <N>`), capped at cargo's maximum of 100
+    pub limit: Option<i64>,
+    /// publish/search/install: wait and retry when the registry rate limits the request
+    pub respect_rate_limit: bool,
+    /// publish/add/search/install: registry to use instead of crates.io (`--registry <name>`)
+    pub registry: Option<String>,
+    /// publish: token for the registry, passed through the environment
+    pub token: Option<Token>,
+    /// fmt: format only these files, running rustfmt on them directly
+    pub files: Vec<String>,
+    /// build: write cargo's timing report (`--timings`) and return the slowest crates
+    pub timings: bool,
+    /// build: return the size of each binary and library built, raw and gzipped
+    pub report_size: bool,
+    /// llvm_cov: also write the HTML report and return its path
+    pub html: bool,
+    /// test: structured report to return in `test_report` (`"junit"`)
+    pub format: Option<TestFormat>,
+    /// test: run the doctests as a step of their own, after the other tests, and report
+    /// them apart (`doctests`, `doctest_output`)
+    pub split_doctests: bool,
+    /// doc: workspace member whose index `doc.index` points at (package or crate name);
+    /// run_example: member the example belongs to
+    pub package: Option<String>,
+    /// artifact_dir: profile whose artifacts to locate (`dev` by default)
+    pub profile: Option<String>,
+    /// Compiling commands (see `command_args`): features to enable (`--features <a,b>`);
+    /// `member/feature` enables a feature of another workspace member, which is how to
+    /// pick features per package with `--workspace`
+    pub features: Vec<String>,
+    /// Compiling commands (see `command_args`): enable every feature of the selected
+    /// packages (`--all-features`)
+    pub all_features: bool,
+    /// Compiling commands (see `command_args`): target triple (`--target <triple>`);
+    /// also used by artifact_dir
+    pub target: Option<String>,
+    /// Compiling commands and artifact_dir: target directory (`--target-dir <dir>`),
+    /// overriding the one set for the command's category and `CARGO_TARGET_DIR`
+    pub target_dir: Option<String>,
+}
+
+/// First cargo release with `--config key=value` on stable
+pub const CONFIG_MIN_CARGO_VERSION: (u32, u32) = (1, 63);
+
+/// Lines of output tree keeps unless `max_output_lines` is set; the tree itself is
+/// parsed as it is read, so it is complete whatever the output kept
+pub const TREE_OUTPUT_LINES: usize = 10_000;
+
+// Directory commands run in when they have no `cwd` option, set with `set_cwd`
+static DEFAULT_CWD: Mutex<Option<String>> = Mutex::new(None);
+
+// Toolchain of commands without a `toolchain` option, set with `set_toolchain`
+static DEFAULT_TOOLCHAIN: Mutex<Option<String>> = Mutex::new(None);
+
+// The toolchain name in `+nightly` or `nightly`; `None` when empty
+fn toolchain_name(toolchain: &str) -> Option<&str> {
+    let name = toolchain.trim();
+    let name = name.strip_prefix('+').unwrap_or(name);
+    (!name.is_empty()).then_some(name)
+}
+
+/// Set the toolchain of commands without a `toolchain` option (`"nightly"` or
+/// `"+nightly"`), or clear it with `None` or `""`; returns the name kept
+pub fn set_default_toolchain(toolchain: Option<&str>) -> LuaResult<Option<String>> {
+    let name = toolchain.and_then(toolchain_name);
+    if let Some(name) = name {
+        validate_component("toolchain", name)?;
+    }
+    let name = name.map(str::to_string);
+    *DEFAULT_TOOLCHAIN.lock().unwrap() = name.clone();
+    Ok(name)
+}
+
+// Program run for cargo, set with `set_cargo_bin`
+static CARGO_BIN: Mutex<Option<String>> = Mutex::new(None);
+
+/// Set the program run for cargo, instead of `cargo` from `PATH`: a path (e.g. a rustup
+/// shim outside `PATH`), made absolute, or a program name looked up in `PATH`; `None`
+/// or `""` goes back to `cargo`. Returns the program kept
+/// The versions and subcommands read from the previous one are forgotten.
+pub fn set_cargo_bin(path: Option<&str>) -> LuaResult<Option<String>> {
+    let bin = match path.map(str::trim).filter(|path| !path.is_empty()) {
+        None => None,
+        Some(name) if !name.contains(std::path::MAIN_SEPARATOR) && !name.contains('/') => {
+            Some(name.to_string())
+        }
+        Some(path) => {
+            let absolute = std::env::current_dir().unwrap_or_default().join(path);
+            if !absolute.is_file() {
+                return Err(cargo_bin_not_found(path, "no such file"));
+            }
+            Some(absolute.to_string_lossy().into_owned())
+        }
+    };
+    *CARGO_BIN.lock().unwrap() = bin.clone();
+    crate::compat::forget();
+    crate::installed::forget();
+    Ok(bin)
+}
+
+/// The error for a cargo set with `set_cargo_bin` that can't be found
+pub fn cargo_bin_not_found(bin: &str, details: &str) -> LuaError {
+    crate::error::Error::NotFound {
+        program: bin.to_string(),
+        details: format!(
+            "{} (set with set_cargo_bin; set_cargo_bin(nil) goes back to cargo)",
+            details
+        ),
+    }
+    .into()
+}
+
+/// The program set with `set_cargo_bin`, if any
+pub fn cargo_bin() -> Option<String> {
+    CARGO_BIN.lock().unwrap().clone()
+}
+
+// The program started for `program`: the cargo set with `set_cargo_bin` for `cargo`
+fn program_for(program: &str, cargo_bin: Option<&str>) -> String {
+    match cargo_bin {
+        Some(bin) if program == "cargo" => bin.to_string(),
+        _ => program.to_string(),
+    }
+}
+
+/// Set the directory commands run in when they have no `cwd` option, or clear it with
+/// `None`; returns it made absolute
+/// It must be a directory with a `Cargo.toml` in it or a parent, where cargo finds one.
+pub fn set_default_cwd(dir: Option<&str>) -> LuaResult<Option<String>> {
+    let Some(dir) = dir else {
+        *DEFAULT_CWD.lock().unwrap() = None;
+        return Ok(None);
+    };
+    let path = std::env::current_dir().unwrap_or_default().join(dir);
+    if !path.is_dir() {
+        return Err(LuaError::RuntimeError(format!(
+            "Invalid cwd: {:?} is not a directory",
+            dir
+        )));
+    }
+    if !path.ancestors().any(|dir| dir.join("Cargo.toml").is_file()) {
+        return Err(LuaError::RuntimeError(format!(
+            "Invalid cwd: no Cargo.toml in {:?} or its parents",
+            dir
+        )));
+    }
+    let path = path.canonicalize().unwrap_or(path);
+    let path = path.to_string_lossy().into_owned();
+    *DEFAULT_CWD.lock().unwrap() = Some(path.clone());
+    Ok(Some(path))
+}
+
+/// A variable of the `env` option
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum EnvValue {
+    /// Set to this value
+    Set(String),
+    /// `false` removes it; `true` is an error
+    Unset(bool),
+}
+
+// Variables of commands, under their `env` option, set with `set_env`
+static DEFAULT_ENV: Mutex<BTreeMap<String, EnvValue>> = Mutex::new(BTreeMap::new());
+
+// Fails for a variable that can't be set: an empty name, `=` or NUL in it, or `true`
+fn validate_env(name: &str, value: &EnvValue) -> LuaResult<()> {
+    if name.is_empty() || name.contains(['=', '\0']) {
+        return Err(LuaError::RuntimeError(format!(
+            "Invalid env: {:?} is not a variable name",
+            name
+        )));
+    }
+    match value {
+        EnvValue::Set(value) if value.contains('\0') => Err(LuaError::RuntimeError(format!(
+            "Invalid env: the value of {} contains a NUL byte",
+            name
+        ))),
+        EnvValue::Unset(true) => Err(LuaError::RuntimeError(format!(
+            "Invalid env: {} = true (expected a string, or false to remove it)",
+            name
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Set the variables of every command, under its `env` option (`false` removes one),
+/// replacing those set before; `None` clears them
+pub fn set_default_env(env: Option<BTreeMap<String, EnvValue>>) -> LuaResult<()> {
+    let env = env.unwrap_or_default();
+    for (name, value) in &env {
+        validate_env(name, value)?;
+    }
+    *DEFAULT_ENV.lock().unwrap() = env;
+    Ok(())
+}
+
+/// A registry token; never shown in debug output
+#[derive(Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Token(String);
+
+impl std::fmt::Debug for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Token(<redacted>)")
+    }
+}
+
+/// What decides which cargo a command starts and what it reports beyond its arguments,
+/// to cache what was read from it (its version, its subcommands)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CargoKey {
+    /// Directory cargo runs in (a `rust-toolchain.toml` there picks the toolchain)
+    pub dir: Option<PathBuf>,
+    /// The program set with `set_cargo_bin`
+    pub bin: Option<String>,
+    pub toolchain: Option<String>,
+    pub shell_prefix: Option<Vec<String>>,
+    /// Variables removed (`None`) or set, in the order they are applied
+    pub env: Vec<(String, Option<String>)>,
+}
+
+/// How a command is launched once the wrapper option is applied
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invocation {
+    pub program: &'static str,
+    pub env: Vec<(&'static str, &'static str)>,
+}
+
+// Subcommands that take `--manifest-path`
+const MANIFEST_PATH_COMMANDS: &[&str] = &[
+    "build", "check", "clippy", "test", "bench", "run", "doc", "fix", "rustc", "clean", "update",
+    "tree", "vendor", "metadata", "add", "remove", "publish", "fmt", "nextest", "llvm-cov",
+    "fetch",
+];
+
+// Wrappers that take cargo's place and accept the same subcommands
+const REPLACEMENT_WRAPPERS: &[&str] = &["cross"];
+// Subcommands whose arguments after `--` go to another tool
+const TOOL_ARGS_COMMANDS: &[&str] = &[
+    "test", "bench", "run", "clippy", "fmt", "nextest", "llvm-cov",
+];
+
+// Wrappers that sit in front of rustc and are enabled through `RUSTC_WRAPPER`
+const RUSTC_WRAPPERS: &[&str] = &["sccache"];
+
+impl CommandOptions {
+    /// Timeout requested by the caller, if any
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_secs
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs)
+    }
+
+    /// Whether the caller asked for no timeout (`timeout = false` or `timeout_secs = 0`)
+    pub fn timeout_disabled(&self) -> bool {
+        self.timeout == Some(false) || self.timeout_secs == Some(0)
+    }
+
+    /// A command for `program`, run through the shell prefix when one is set
+    /// Also returns the program that is actually started, for spawn errors; `cargo` is
+    /// the one set with `set_cargo_bin`, if any.
+    /// The variables in `strip_env` are removed from its environment, and it runs in `cwd`.
+    /// The key of the cargo `command("cargo")` starts in `dir`, with the environment of
+    /// `strip_env`, `color` and `env` (which includes the variables set with `set_env`)
+    pub fn cargo_key(&self, dir: Option<PathBuf>) -> CargoKey {
+        let stripped = self.strip_env.iter().map(|name| (name.clone(), None));
+        let color = self
+            .color
+            .then(|| ("CARGO_TERM_COLOR".to_string(), Some("always".to_string())));
+        let env = self.env.iter().map(|(name, value)| match value {
+            EnvValue::Set(value) => (name.clone(), Some(value.clone())),
+            EnvValue::Unset(_) => (name.clone(), None),
+        });
+        CargoKey {
+            dir,
+            bin: cargo_bin(),
+            toolchain: self.toolchain.clone(),
+            shell_prefix: self.shell_prefix.clone(),
+            env: stripped.chain(color).chain(env).collect(),
+        }
+    }
+
+    pub fn command(&self, program: &str) -> LuaResult<(TokioCommand, String)> {
+        let program = &program_for(program, CARGO_BIN.lock().unwrap().as_deref());
+        let (mut cmd, launcher) = match self.shell_prefix.as_deref().map(<[String]>::split_first) {
+            None => (TokioCommand::new(program), program.to_string()),
+            Some(Some((launcher, rest))) if !launcher.is_empty() => {
+                let mut cmd = TokioCommand::new(launcher);
+                cmd.args(rest).arg(program);
+                (cmd, launcher.clone())
+            }
+            Some(_) => {
+                return Err(LuaError::RuntimeError(
+                    "Invalid shell_prefix: expected a non-empty array starting with a program"
+                        .to_string(),
+                ))
+            }
+        };
+        for name in &self.strip_env {
+            cmd.env_remove(name);
+        }
+        if self.color {
+            cmd.env("CARGO_TERM_COLOR", "always");
+        }
+        for (name, value) in &self.env {
+            validate_env(name, value)?;
+            match value {
+                EnvValue::Set(value) => cmd.env(name, value),
+                EnvValue::Unset(_) => cmd.env_remove(name),
+            };
+        }
+        if let Some(toolchain) = self.toolchain.as_deref().and_then(toolchain_name) {
+            validate_component("toolchain", toolchain)?;
+            cmd.env("RUSTUP_TOOLCHAIN", toolchain);
+        }
+        if let Some(cwd) = &self.cwd {
+            // Checked here, since spawning in a missing directory reads like a missing program
+            if !Path::new(cwd).is_dir() {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid cwd: {:?} is not a directory",
+                    cwd
+                )));
+            }
+            cmd.current_dir(cwd);
+        }
+        Ok((cmd, launcher))
+    }
+
+    /// Grace period before a stopped command is killed with SIGKILL
+    pub fn kill_timeout(&self) -> Duration {
+        self.kill_timeout_ms
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_KILL_TIMEOUT)
+    }
+
+    /// Least time between two reads of a command's output
+    pub fn output_yield(&self) -> Duration {
+        self.output_yield_ms
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_OUTPUT_YIELD)
+    }
+
+    /// Lines of output a `subcommand` keeps, if limited
+    pub fn max_output_lines(&self, subcommand: &str) -> Option<usize> {
+        self.max_output_lines
+            .or((subcommand == "tree").then_some(TREE_OUTPUT_LINES))
+    }
+
+    /// Signal a stopped `subcommand` gets first
+    pub fn stop_signal(&self, subcommand: &str) -> StopSignal {
+        self.stop_signal.unwrap_or(if subcommand == "run" {
+            StopSignal::Int
+        } else {
+            StopSignal::Term
+        })
+    }
+
+    /// Destination of the NDJSON event log, if one was requested
+    pub fn event_log_target(&self) -> LuaResult<Option<EventLogTarget>> {
+        match (&self.event_log, self.event_fd) {
+            (Some(_), Some(_)) => Err(LuaError::RuntimeError(
+                "event_log and event_fd can't be used together".to_string(),
+            )),
+            (Some(path), None) => Ok(Some(EventLogTarget::File(path.clone()))),
+            (None, Some(fd)) => Ok(Some(EventLogTarget::Fd(fd))),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Program and environment to launch, according to the wrapper option
+    ///
+    /// Only known wrappers are accepted, so the option can't be used to run an
+    /// arbitrary program.
+    pub fn invocation(&self) -> LuaResult<Invocation> {
+        let Some(wrapper) = self.wrapper.as_deref() else {
+            return Ok(Invocation {
+                program: "cargo",
+                env: Vec::new(),
+            });
+        };
+
+        if let Some(&program) = REPLACEMENT_WRAPPERS.iter().find(|&&w| w == wrapper) {
+            Ok(Invocation {
+                program,
+                env: Vec::new(),
+            })
+        } else if let Some(&rustc_wrapper) = RUSTC_WRAPPERS.iter().find(|&&w| w == wrapper) {
+            Ok(Invocation {
+                program: "cargo",
+                env: vec![("RUSTC_WRAPPER", rustc_wrapper)],
+            })
+        } else {
+            Err(LuaError::RuntimeError(format!(
+                "Unsupported wrapper: {:?} (expected one of: {})",
+                wrapper,
+                REPLACEMENT_WRAPPERS
+                    .iter()
+                    .chain(RUSTC_WRAPPERS)
+                    .copied()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )))
+        }
+    }
+
+    /// The complete arguments of `subcommand`: `args` with the options that apply to it
+    ///
+    /// | options | subcommands |
+    /// |---|---|
+    /// | `jobs`, `features` | build, check, clippy, test, bench, run, doc, fix |
+    /// | `all_features`, `target` | the same |
+    /// | `timings` | build |
+    /// | `deny_warnings`, `warn`, `allow` (after `--`) | clippy |
+    /// | `registry` | add, publish, install, search |
+    /// | `limit` | search |
+    /// | `invert` | tree |
+    /// | `sync` | vendor |
+    /// | `tool_args` (after `--`) | test, bench, run, clippy, fmt, nextest, llvm-cov |
+    ///
+    /// Flags cargo takes once (`--jobs`, `--all-features`, `--registry`, `--limit`) are
+    /// left out when `args` already has them, so explicit arguments win; repeatable ones
+    /// (`--features`, `--target`, `--sync`) are added to those in `args`. Other options
+    /// (`target_dir`, `config`, `cwd`, ...) apply to every subcommand and are handled
+    /// when spawning.
+    pub fn command_args(&self, subcommand: &str, args: &[&str]) -> LuaResult<Vec<String>> {
+        let args = self.with_manifest_path(subcommand, args)?;
+        let args: &[&str] = &args.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+        let full_args = match subcommand {
+            "build" if self.timings => {
+                let mut timed = vec!["--timings"];
+                timed.extend_from_slice(args);
+                self.build_args(&timed)?
+            }
+            "build" | "check" | "test" | "bench" | "run" | "doc" | "fix" => {
+                self.build_args(args)?
+            }
+            "clippy" => {
+                let args = self.build_args(args)?;
+                let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+                self.clippy_args(&args)?
+            }
+            "nextest" => match args.split_first() {
+                Some((&"run", rest)) => std::iter::once("run".to_string())
+                    .chain(self.nextest_run_args(rest)?)
+                    .collect(),
+                _ => args.iter().map(|s| s.to_string()).collect(),
+            },
+            "add" | "publish" | "install" => self.registry_args(args)?,
+            "search" => self.search_args(args)?,
+            "tree" => self.tree_args(args)?,
+            "vendor" => self.vendor_args(args),
+            _ => args.iter().map(|s| s.to_string()).collect(),
+        };
+        self.with_tool_args(subcommand, full_args)
+    }
+
+    /// Append `tool_args` to `args`, after its `--` separator or a new one
+    pub fn with_tool_args(
+        &self,
+        subcommand: &str,
+        mut args: Vec<String>,
+    ) -> LuaResult<Vec<String>> {
+        if self.tool_args.is_empty() {
+            return Ok(args);
+        }
+        if !TOOL_ARGS_COMMANDS.contains(&subcommand) {
+            return Err(LuaError::RuntimeError(format!(
+                "Invalid tool_args: cargo {} passes no arguments to another tool (tool_args \
+                 applies to {})",
+                subcommand,
+                TOOL_ARGS_COMMANDS.join(", ")
+            )));
+        }
+        if !args.iter().any(|a| a == "--") {
+            args.push("--".to_string());
+        }
+        args.extend(self.tool_args.iter().cloned());
+        Ok(args)
+    }
+
+    /// `args` followed by the words of `raw_args_string`
+    pub fn with_raw_args(&self, args: &[&str]) -> LuaResult<Vec<String>> {
+        let mut full_args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        if let Some(raw) = &self.raw_args_string {
+            let words = shell_words::split(raw).map_err(|e| {
+                LuaError::RuntimeError(format!("Invalid raw_args_string: {:?}: {}", raw, e))
+            })?;
+            full_args.extend(words);
+        }
+        Ok(full_args)
+    }
+
+    /// Insert `--manifest-path <path>` into `args` for subcommands that take it, before any
+    /// `--` separator; the manifest, given either way, must exist
+    pub fn with_manifest_path(&self, subcommand: &str, args: &[&str]) -> LuaResult<Vec<String>> {
+        let mut full_args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        if !MANIFEST_PATH_COMMANDS.contains(&subcommand) {
+            return Ok(full_args);
+        }
+        let before_separator: Vec<&str> = args.iter().copied().take_while(|a| *a != "--").collect();
+        let explicit = before_separator.iter().enumerate().find_map(|(i, arg)| {
+            match arg.strip_prefix("--manifest-path")? {
+                "" => before_separator.get(i + 1).copied(),
+                rest => rest.strip_prefix('='),
+            }
+        });
+        let Some(manifest) = explicit.or(self.manifest_path.as_deref()) else {
+            return Ok(full_args);
+        };
+        let resolved = match &self.cwd {
+            Some(cwd) => Path::new(cwd).join(manifest),
+            None => Path::new(manifest).to_path_buf(),
+        };
+        if !resolved.is_file() {
+            return Err(LuaError::RuntimeError(format!(
+                "Invalid manifest_path: {:?} does not exist",
+                manifest
+            )));
+        }
+        if explicit.is_none() {
+            let at = before_separator.len();
+            full_args.splice(
+                at..at,
+                ["--manifest-path".to_string(), manifest.to_string()],
+            );
+        }
+        Ok(full_args)
+    }
+
+    /// Insert `--jobs <N>` into `args`, before any `--` separator so cargo reads it
+    pub fn jobs_args(&self, args: &[&str]) -> LuaResult<Vec<String>> {
+        if has_flag(args, "--jobs") || has_flag(args, "-j") {
+            return Ok(args.iter().map(|s| s.to_string()).collect());
+        }
+        self.with_jobs_flag(args, "--jobs")
+    }
+
+    // `jobs` as `flag`, before any `--` separator
+    fn with_jobs_flag(&self, args: &[&str], flag: &str) -> LuaResult<Vec<String>> {
+        let mut full_args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        if let Some(jobs) = self.jobs {
+            if jobs < 1 {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid jobs value: {} (expected a positive integer)",
+                    jobs
+                )));
+            }
+            let at = args.iter().position(|a| *a == "--").unwrap_or(args.len());
+            full_args.splice(at..at, [flag.to_string(), jobs.to_string()]);
+        }
+        Ok(full_args)
+    }
+
+    // `cargo nextest run` takes cargo's build options, but its `-j`/`--jobs` is the
+    // number of test threads: `jobs` goes to `--build-jobs`, and the message format is
+    // left out (nextest's `--message-format` is its own)
+    fn nextest_run_args(&self, args: &[&str]) -> LuaResult<Vec<String>> {
+        let full_args = if has_flag(args, "--build-jobs") {
+            args.iter().map(|s| s.to_string()).collect()
+        } else {
+            self.with_jobs_flag(args, "--build-jobs")?
+        };
+        let build = Self {
+            jobs: None,
+            rendered_diagnostics: false,
+            ..self.clone()
+        };
+        build.build_args(&full_args.iter().map(|s| s.as_str()).collect::<Vec<_>>())
+    }
+
+    /// Insert the options of compiling commands (`--jobs`, `--features`, `--all-features`,
+    /// `--target`, the message format) into `args`, before any `--` separator
+    pub fn build_args(&self, args: &[&str]) -> LuaResult<Vec<String>> {
+        let mut full_args = self.jobs_args(args)?;
+        let mut extra = Vec::new();
+        if self.rendered_diagnostics && !has_flag(args, "--message-format") {
+            extra.push(RENDERED_MESSAGE_FORMAT.to_string());
+        }
+        if !self.features.is_empty() {
+            for feature in &self.features {
+                validate_feature_name(feature)?;
+            }
+            extra.push("--features".to_string());
+            extra.push(self.features.join(","));
+        }
+        if self.all_features && !has_flag(args, "--all-features") {
+            extra.push("--all-features".to_string());
+        }
+        if let Some(target) = &self.target {
+            validate_component("target", target)?;
+            extra.push("--target".to_string());
+            extra.push(target.clone());
+        }
+        let at = full_args
+            .iter()
+            .position(|a| a == "--")
+            .unwrap_or(full_args.len());
+        full_args.splice(at..at, extra);
+        Ok(full_args)
+    }
+
+    /// Prepend `--registry <name>` to `args` when a registry is set
+    pub fn registry_args(&self, args: &[&str]) -> LuaResult<Vec<String>> {
+        let mut full_args = Vec::new();
+        let explicit = has_flag(args, "--registry") || has_flag(args, "--index");
+        if let Some(registry) = self.registry.as_ref().filter(|_| !explicit) {
+            validate_registry_name(registry)?;
+            full_args.push("--registry".to_string());
+            full_args.push(registry.clone());
+        }
+        full_args.extend(args.iter().map(|s| s.to_string()));
+        Ok(full_args)
+    }
+
+    /// Append `--limit <N>` for the `limit` option, capped at what cargo accepts
+    pub fn search_args(&self, args: &[&str]) -> LuaResult<Vec<String>> {
+        let mut full_args = self.registry_args(args)?;
+        if let Some(limit) = self.limit.filter(|_| !has_flag(args, "--limit")) {
+            if limit < 1 {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid limit value: {} (expected a positive integer)",
+                    limit
+                )));
+            }
+            full_args.push("--limit".to_string());
+            full_args.push(limit.min(MAX_LIMIT).to_string());
+        }
+        Ok(full_args)
+    }
+
+    /// `--config key=value` flags for the `config` option, placed before the subcommand
+    pub fn config_args(&self) -> LuaResult<Vec<String>> {
+        let mut args = Vec::new();
+        for entry in &self.config {
+            validate_config_override(entry)?;
+            args.push("--config".to_string());
+            args.push(entry.clone());
+        }
+        Ok(args)
+    }
+
+    /// Environment carrying the `token` option: `CARGO_REGISTRIES_<NAME>_TOKEN` for the
+    /// selected registry, or `CARGO_REGISTRY_TOKEN` for crates.io
+    pub fn registry_token_env(&self) -> LuaResult<Vec<(String, String)>> {
+        let Some(Token(token)) = &self.token else {
+            return Ok(Vec::new());
+        };
+        let name = match &self.registry {
+            Some(registry) => {
+                validate_registry_name(registry)?;
+                format!(
+                    "CARGO_REGISTRIES_{}_TOKEN",
+                    registry.to_ascii_uppercase().replace('-', "_")
+                )
+            }
+            None => "CARGO_REGISTRY_TOKEN".to_string(),
+        };
+        Ok(vec![(name, token.clone())])
+    }
+
+    /// Prepend `--invert <spec>` to the tree `args` when `invert` is set
+    pub fn tree_args(&self, args: &[&str]) -> LuaResult<Vec<String>> {
+        let mut full_args = Vec::new();
+        if let Some(spec) = &self.invert {
+            validate_package_spec(spec)?;
+            full_args.push("--invert".to_string());
+            full_args.push(spec.clone());
+        }
+        full_args.extend(args.iter().map(|s| s.to_string()));
+        Ok(full_args)
+    }
+
+    /// Append a `--sync` flag for each extra manifest to the vendor `args`
+    pub fn vendor_args(&self, args: &[&str]) -> Vec<String> {
+        let mut full_args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        for manifest in &self.sync {
+            full_args.push("--sync".to_string());
+            full_args.push(manifest.clone());
+        }
+        full_args
+    }
+
+    /// Append the clippy lint options to `args`
+    ///
+    /// Lint flags are rustc flags, so they must follow the `--` separator; an existing
+    /// separator is reused. Allows come after warns so a single lint can be carved out
+    /// of a warned group, and `-D warnings` comes last.
+    pub fn clippy_args(&self, args: &[&str]) -> LuaResult<Vec<String>> {
+        let mut lint_args = Vec::new();
+        for (flag, lints) in [("-W", &self.warn), ("-A", &self.allow)] {
+            for lint in lints {
+                validate_lint_name(lint)?;
+                lint_args.push(flag.to_string());
+                lint_args.push(lint.clone());
+            }
+        }
+        if self.deny_warnings {
+            lint_args.push("-D".to_string());
+            lint_args.push("warnings".to_string());
+        }
+
+        let mut full_args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        if !lint_args.is_empty() {
+            if !args.contains(&"--") {
+                full_args.push("--".to_string());
+            }
+            full_args.extend(lint_args);
+        }
+        Ok(full_args)
+    }
+}
+
+// Whether `flag` (as `--flag value`, `--flag=value` or `-j4`) is among the cargo
+// arguments, i.e. before any `--`
+fn has_flag(args: &[&str], flag: &str) -> bool {
+    args.iter().take_while(|a| **a != "--").any(|a| {
+        a.strip_prefix(flag)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('=') || !flag.starts_with("--"))
+    })
+}
+
+// Lint names are plain paths like `clippy::pedantic` or `dead_code`; anything else
+// (e.g. a value starting with `-`) would be read by rustc as a separate flag
+fn validate_lint_name(lint: &str) -> LuaResult<()> {
+    let valid = !lint.is_empty()
+        && !lint.starts_with('-')
+        && lint
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == ':');
+    if valid {
+        Ok(())
+    } else {
+        Err(LuaError::RuntimeError(format!(
+            "Invalid lint name: {:?}",
+            lint
+        )))
+    }
+}
+
+// Registry names are keys of `[registries]` in cargo's config and become part of an
+// environment variable name
+fn validate_registry_name(name: &str) -> LuaResult<()> {
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(LuaError::RuntimeError(format!(
+            "Invalid registry name: {:?}",
+            name
+        )))
+    }
+}
+
+// A dotted config key (segments may be quoted, e.g. `target."cfg(unix)".runner`) and a
+// value; cargo would also take a file path, which isn't accepted here
+fn validate_config_override(entry: &str) -> LuaResult<()> {
+    let valid = entry.split_once('=').is_some_and(|(key, _)| {
+        let key = key.trim();
+        !key.is_empty()
+            && !key.starts_with(['-', '.'])
+            && !key.ends_with('.')
+            && key.chars().all(|c| {
+                c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '"' | '\'' | '(' | ')')
+            })
+    });
+    if valid {
+        Ok(())
+    } else {
+        Err(LuaError::RuntimeError(format!(
+            "Invalid config override: {:?} (expected key=value)",
+            entry
+        )))
+    }
+}
+
+// Feature names, optionally prefixed by the package they belong to: a workspace member
+// (`cli/json`) or a dependency (`serde/derive`); the slash is passed to cargo as is
+fn validate_feature_name(feature: &str) -> LuaResult<()> {
+    let (package, name) = match feature.split_once('/') {
+        Some((package, name)) => (Some(package), name),
+        None => (None, feature),
+    };
+    let valid_package = package.is_none_or(|p| {
+        !p.is_empty()
+            && p.chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'))
+    });
+    let valid = valid_package
+        && !name.is_empty()
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(LuaError::RuntimeError(format!(
+            "Invalid feature name: {:?} (expected feature or package/feature)",
+            feature
+        )))
+    }
+}
+
+/// Options from Lua; `nil` gives the defaults. Without a `cwd` or `toolchain`, the
+/// ones set with `set_cwd` and `set_toolchain` apply
+impl FromLua for CommandOptions {
+    fn from_lua(value: LuaValue, lua: &Lua) -> LuaResult<Self> {
+        let mut options: Self = match value {
+            LuaValue::Nil => Self::default(),
+            // Callbacks share the table with plain options, so skip values serde can't read
+            value => lua.from_value_with(
+                value,
+                DeserializeOptions::new().deny_unsupported_types(false),
+            )?,
+        };
+        if options.cwd.is_none() {
+            options.cwd = DEFAULT_CWD.lock().unwrap().clone();
+        }
+        let mut env = DEFAULT_ENV.lock().unwrap().clone();
+        env.append(&mut options.env);
+        options.env = env;
+        options.toolchain = match options.toolchain.as_deref().map(toolchain_name) {
+            Some(Some(name)) => Some(name.to_string()),
+            _ => DEFAULT_TOOLCHAIN.lock().unwrap().clone(),
+        };
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_default_cwd_checks_directory() {
+        let err = set_default_cwd(Some("no/such/dir"))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("is not a directory"), "{}", err);
+
+        let dir = std::env::temp_dir().join(format!("no_manifest_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let err = set_default_cwd(dir.to_str()).unwrap_err().to_string();
+        assert!(err.contains("no Cargo.toml"), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Concurrent tests read options from Lua, so nothing is set here
+        assert_eq!(set_default_cwd(None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_options_from_lua_table() {
+        let lua = Lua::new();
+        let table: LuaValue = lua
+            .load("return { timeout_secs = 600, on_event = function() end }")
+            .eval()
+            .unwrap();
+        let options = CommandOptions::from_lua(table, &lua).unwrap();
+        assert_eq!(options.timeout(), Some(Duration::from_secs(600)));
+        assert!(!options.timeout_disabled());
+        for disabled in [
+            "{ timeout_secs = 0 }",
+            "{ timeout = false, timeout_secs = 60 }",
+        ] {
+            let table: LuaValue = lua.load(format!("return {}", disabled)).eval().unwrap();
+            let options = CommandOptions::from_lua(table, &lua).unwrap();
+            assert!(options.timeout_disabled(), "{}", disabled);
+        }
+        assert_eq!(options.stop_signal("run"), StopSignal::Int);
+        assert_eq!(options.stop_signal("build"), StopSignal::Term);
+
+        let table: LuaValue = lua
+            .load(r#"return { stop_signal = "SIGTERM" }"#)
+            .eval()
+            .unwrap();
+        let options = CommandOptions::from_lua(table, &lua).unwrap();
+        assert_eq!(options.stop_signal("run"), StopSignal::Term);
+    }
+
+    #[test]
+    fn test_clippy_lint_args() {
+        let options = CommandOptions {
+            deny_warnings: true,
+            warn: vec!["clippy::pedantic".to_string()],
+            allow: vec!["clippy::module_name_repetitions".to_string()],
+            ..Default::default()
+        };
+
+        let args = options.clippy_args(&["--all-targets"]).unwrap();
+        assert_eq!(
+            args,
+            vec![
+                "--all-targets",
+                "--",
+                "-W",
+                "clippy::pedantic",
+                "-A",
+                "clippy::module_name_repetitions",
+                "-D",
+                "warnings"
+            ]
+        );
+
+        // An existing separator is reused rather than duplicated
+        let args = options.clippy_args(&["--", "-W", "dead_code"]).unwrap();
+        assert_eq!(args.iter().filter(|a| *a == "--").count(), 1);
+        assert_eq!(&args[..3], &["--", "-W", "dead_code"]);
+
+        // Without lint options the args are passed through unchanged
+        let args = CommandOptions::default().clippy_args(&["--fix"]).unwrap();
+        assert_eq!(args, vec!["--fix"]);
+    }
+
+    #[test]
+    fn test_invalid_lint_name_rejected() {
+        let options = CommandOptions {
+            allow: vec!["--cap-lints".to_string()],
+            ..Default::default()
+        };
+        assert!(options.clippy_args(&[]).is_err());
+    }
+
+    #[test]
+    fn test_jobs_args() {
+        let options = CommandOptions {
+            jobs: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.jobs_args(&["--release"]).unwrap(),
+            vec!["--release", "--jobs", "2"]
+        );
+        assert_eq!(
+            options.jobs_args(&["--", "--nocapture"]).unwrap(),
+            vec!["--jobs", "2", "--", "--nocapture"]
+        );
+
+        for jobs in [0, -4] {
+            let options = CommandOptions {
+                jobs: Some(jobs),
+                ..Default::default()
+            };
+            assert!(options.jobs_args(&[]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_build_args() {
+        let options = CommandOptions {
+            jobs: Some(4),
+            features: vec!["serde".to_string(), "tokio/full".to_string()],
+            target: Some("wasm32-unknown-unknown".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            options
+                .build_args(&["--release", "--", "--nocapture"])
+                .unwrap(),
+            vec![
+                "--release",
+                "--jobs",
+                "4",
+                "--features",
+                "serde,tokio/full",
+                "--target",
+                "wasm32-unknown-unknown",
+                "--",
+                "--nocapture"
+            ]
+        );
+
+        let options = CommandOptions {
+            features: vec!["--all-features".to_string()],
+            ..Default::default()
+        };
+        assert!(options.build_args(&[]).is_err());
+    }
+
+    #[test]
+    fn test_workspace_member_features() {
+        let options = CommandOptions {
+            features: vec!["cli/json".to_string(), "core/serde-1".to_string()],
+            all_features: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            options
+                .build_args(&["--workspace", "--", "--nocapture"])
+                .unwrap(),
+            vec![
+                "--workspace",
+                "--features",
+                "cli/json,core/serde-1",
+                "--all-features",
+                "--",
+                "--nocapture"
+            ]
+        );
+        // Given once
+        assert_eq!(
+            options.build_args(&["--all-features"]).unwrap(),
+            vec!["--all-features", "--features", "cli/json,core/serde-1"]
+        );
+
+        for invalid in [
+            "cli/",
+            "/json",
+            "a/b/c",
+            "cli/-json",
+            "c li/json",
+            "cli/js on",
+        ] {
+            let options = CommandOptions {
+                features: vec![invalid.to_string()],
+                ..Default::default()
+            };
+            assert!(options.build_args(&[]).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_cargo_key() {
+        let options = CommandOptions {
+            toolchain: Some("stable".to_string()),
+            ..Default::default()
+        };
+        let dir = Some(PathBuf::from("/work"));
+        assert_eq!(
+            options.cargo_key(dir.clone()),
+            options.cargo_key(dir.clone())
+        );
+
+        // Another PATH can mean another cargo, with its own version and tools
+        let mut with_env = options.clone();
+        with_env.env.insert(
+            "PATH".to_string(),
+            EnvValue::Set("/opt/rust/bin".to_string()),
+        );
+        let key = with_env.cargo_key(dir.clone());
+        assert_ne!(key, options.cargo_key(dir.clone()));
+        assert_eq!(
+            key.env,
+            vec![("PATH".to_string(), Some("/opt/rust/bin".to_string()))]
+        );
+        let stripped = CommandOptions {
+            strip_env: vec!["RUSTUP_TOOLCHAIN".to_string()],
+            ..options.clone()
+        };
+        assert_ne!(stripped.cargo_key(dir.clone()), options.cargo_key(dir));
+    }
+
+    #[test]
+    fn test_command_args() {
+        let options = CommandOptions {
+            jobs: Some(2),
+            features: vec!["json".to_string()],
+            deny_warnings: true,
+            registry: Some("corp".to_string()),
+            limit: Some(5),
+            timings: true,
+            ..Default::default()
+        };
+        let args =
+            |subcommand: &str, args: &[&str]| options.command_args(subcommand, args).unwrap();
+
+        assert_eq!(
+            args("run", &["--", "x"]),
+            vec!["--jobs", "2", "--features", "json", "--", "x"]
+        );
+        assert_eq!(
+            args("build", &[]),
+            vec!["--timings", "--jobs", "2", "--features", "json"]
+        );
+        assert_eq!(
+            args("clippy", &[]),
+            vec!["--jobs", "2", "--features", "json", "--", "-D", "warnings"]
+        );
+        assert_eq!(
+            args("install", &["ripgrep"]),
+            vec!["--registry", "corp", "ripgrep"]
+        );
+        assert_eq!(
+            args("nextest", &["run", "--lib"]),
+            vec!["run", "--lib", "--build-jobs", "2", "--features", "json"]
+        );
+        // nextest's `-j` is its test threads, not cargo's jobs
+        assert_eq!(
+            args("nextest", &["run", "-j", "1"]),
+            vec!["run", "-j", "1", "--build-jobs", "2", "--features", "json"]
+        );
+        let nextest = CommandOptions {
+            rendered_diagnostics: true,
+            target: Some("x86_64-unknown-linux-gnu".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            nextest.command_args("nextest", &["run"]).unwrap(),
+            vec!["run", "--target", "x86_64-unknown-linux-gnu"]
+        );
+        // Only the options of the subcommand apply
+        assert_eq!(args("update", &["-p", "serde"]), vec!["-p", "serde"]);
+
+        // Flags cargo takes once: the explicit argument wins
+        assert_eq!(args("check", &["-j4"]), vec!["-j4", "--features", "json"]);
+        assert_eq!(
+            args(
+                "search",
+                &["serde", "--limit=50", "--index", "sparse+https://x/"]
+            ),
+            vec!["serde", "--limit=50", "--index", "sparse+https://x/"]
+        );
+        // ... unless it only follows `--`
+        assert_eq!(
+            args("test", &["--", "--jobs"]),
+            vec!["--jobs", "2", "--features", "json", "--", "--jobs"]
+        );
+    }
+
+    #[test]
+    fn test_registry_args_and_token() {
+        let lua = Lua::new();
+        let table: LuaValue = lua
+            .load(r#"return { registry = "my-corp", token = "s3cret" }"#)
+            .eval()
+            .unwrap();
+        let options = CommandOptions::from_lua(table, &lua).unwrap();
+
+        assert_eq!(
+            options.registry_args(&["serde"]).unwrap(),
+            vec!["--registry", "my-corp", "serde"]
+        );
+        assert_eq!(
+            options.registry_token_env().unwrap(),
+            vec![(
+                "CARGO_REGISTRIES_MY_CORP_TOKEN".to_string(),
+                "s3cret".to_string()
+            )]
+        );
+        // The token never shows up when options are logged
+        assert!(!format!("{:?}", options).contains("s3cret"));
+
+        for name in ["--index", "corp registry", "1st", ""] {
+            let options = CommandOptions {
+                registry: Some(name.to_string()),
+                ..Default::default()
+            };
+            assert!(options.registry_args(&[]).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_config_args() {
+        let options = CommandOptions {
+            config: vec![
+                "net.git-fetch-with-cli=true".to_string(),
+                "target.\"cfg(unix)\".runner=\"valgrind\"".to_string(),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            options.config_args().unwrap(),
+            vec![
+                "--config",
+                "net.git-fetch-with-cli=true",
+                "--config",
+                "target.\"cfg(unix)\".runner=\"valgrind\"",
+            ]
+        );
+
+        for entry in [
+            "net.git-fetch-with-cli",
+            "=true",
+            "--offline=true",
+            ".cargo/config.toml",
+        ] {
+            let options = CommandOptions {
+                config: vec![entry.to_string()],
+                ..Default::default()
+            };
+            assert!(options.config_args().is_err(), "{:?}", entry);
+        }
+    }
+
+    #[test]
+    fn test_search_args() {
+        let options = CommandOptions {
+            limit: Some(500),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.search_args(&["serde"]).unwrap(),
+            vec!["serde", "--limit", "100"]
+        );
+
+        let options = CommandOptions {
+            limit: Some(0),
+            ..Default::default()
+        };
+        assert!(options.search_args(&["serde"]).is_err());
+    }
+
+    #[test]
+    fn test_vendor_sync_args() {
+        let options = CommandOptions {
+            sync: vec![
+                "tools/Cargo.toml".to_string(),
+                "xtask/Cargo.toml".to_string(),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            options.vendor_args(&["third_party"]),
+            vec![
+                "third_party",
+                "--sync",
+                "tools/Cargo.toml",
+                "--sync",
+                "xtask/Cargo.toml"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_manifest_path_args() {
+        let options = CommandOptions {
+            manifest_path: Some("Cargo.toml".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            options
+                .command_args("test", &["--lib", "--", "--nocapture"])
+                .unwrap(),
+            vec![
+                "--lib",
+                "--manifest-path",
+                "Cargo.toml",
+                "--",
+                "--nocapture"
+            ]
+        );
+        // Given in the arguments, it isn't repeated; subcommands without it are left alone
+        assert_eq!(
+            options
+                .with_manifest_path("build", &["--manifest-path=Cargo.toml"])
+                .unwrap(),
+            vec!["--manifest-path=Cargo.toml"]
+        );
+        assert_eq!(
+            options.with_manifest_path("new", &["app"]).unwrap(),
+            vec!["app"]
+        );
+
+        let missing = CommandOptions {
+            manifest_path: Some("no/such/Cargo.toml".to_string()),
+            ..Default::default()
+        };
+        let err = missing.command_args("check", &[]).unwrap_err();
+        assert!(err.to_string().contains("Invalid manifest_path"));
+        assert!(CommandOptions::default()
+            .with_manifest_path("check", &["--manifest-path", "no/such/Cargo.toml"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_tool_args() {
+        let options = CommandOptions {
+            tool_args: vec!["--nocapture".to_string(), "--test-threads=1".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            options.command_args("test", &["--lib"]).unwrap(),
+            vec!["--lib", "--", "--nocapture", "--test-threads=1"]
+        );
+        // An existing separator is reused, after what it already passes
+        assert_eq!(
+            options.command_args("run", &["--", "serve"]).unwrap(),
+            vec!["--", "serve", "--nocapture", "--test-threads=1"]
+        );
+        let clippy = CommandOptions {
+            deny_warnings: true,
+            tool_args: vec!["-W".to_string(), "clippy::pedantic".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            clippy.command_args("clippy", &[]).unwrap(),
+            vec!["--", "-D", "warnings", "-W", "clippy::pedantic"]
+        );
+
+        let err = options.command_args("build", &[]).unwrap_err();
+        assert!(err.to_string().contains("Invalid tool_args"), "{}", err);
+        // Without tool_args, nothing is added
+        assert_eq!(
+            CommandOptions::default().command_args("test", &[]).unwrap(),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_raw_args_string() {
+        let raw = |raw: &str| CommandOptions {
+            raw_args_string: Some(raw.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            raw(r#"--features "a b" --lib"#)
+                .with_raw_args(&["--release"])
+                .unwrap(),
+            vec!["--release", "--features", "a b", "--lib"]
+        );
+        assert_eq!(
+            raw(r#"--bin 'my app' -- it\'s "say \"hi\"" a\ b"#)
+                .with_raw_args(&[])
+                .unwrap(),
+            vec!["--bin", "my app", "--", "it's", "say \"hi\"", "a b"]
+        );
+        assert!(raw("--features 'a b").with_raw_args(&[]).is_err());
+        // Structured args are never split
+        assert_eq!(
+            CommandOptions::default()
+                .with_raw_args(&["--features", "a b"])
+                .unwrap(),
+            vec!["--features", "a b"]
+        );
+    }
+
+    #[test]
+    fn test_wrapper_invocation() {
+        let with_wrapper = |wrapper: &str| CommandOptions {
+            wrapper: Some(wrapper.to_string()),
+            ..Default::default()
+        };
+
+        let plain = CommandOptions::default().invocation().unwrap();
+        assert_eq!(plain.program, "cargo");
+        assert!(plain.env.is_empty());
+
+        let cross = with_wrapper("cross").invocation().unwrap();
+        assert_eq!(cross.program, "cross");
+        assert!(cross.env.is_empty());
+
+        let sccache = with_wrapper("sccache").invocation().unwrap();
+        assert_eq!(sccache.program, "cargo");
+        assert_eq!(sccache.env, vec![("RUSTC_WRAPPER", "sccache")]);
+
+        assert!(with_wrapper("rm -rf /; cross").invocation().is_err());
+        assert!(with_wrapper("/usr/bin/cross").invocation().is_err());
+    }
+
+    #[test]
+    fn test_shell_prefix_command() {
+        let (cmd, launcher) = CommandOptions::default().command("cargo").unwrap();
+        assert_eq!(cmd.as_std().get_program(), "cargo");
+        assert_eq!(launcher, "cargo");
+
+        let prefixed = |prefix: &[&str]| CommandOptions {
+            shell_prefix: Some(prefix.iter().map(|s| s.to_string()).collect()),
+            ..Default::default()
+        };
+        let (cmd, launcher) = prefixed(&["nix", "develop", "-c"])
+            .command("cargo")
+            .unwrap();
+        assert_eq!(cmd.as_std().get_program(), "nix");
+        assert_eq!(
+            cmd.as_std().get_args().collect::<Vec<_>>(),
+            vec!["develop", "-c", "cargo"]
+        );
+        assert_eq!(launcher, "nix");
+
+        assert!(prefixed(&[]).command("cargo").is_err());
+        assert!(prefixed(&["", "-c"]).command("cargo").is_err());
+
+        let in_dir = |cwd: &str| CommandOptions {
+            cwd: Some(cwd.to_string()),
+            ..Default::default()
+        };
+        let (cmd, _) = in_dir("/").command("cargo").unwrap();
+        assert_eq!(cmd.as_std().get_current_dir(), Some(Path::new("/")));
+        let err = in_dir("/cargo-nvim-no-such-dir")
+            .command("cargo")
+            .unwrap_err();
+        assert!(err.to_string().contains("is not a directory"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_strip_env() {
+        let child_env = |strip_env: &[&str]| {
+            let options = CommandOptions {
+                strip_env: strip_env.iter().map(|s| s.to_string()).collect(),
+                ..Default::default()
+            };
+            let (mut cmd, _) = options.command("env").unwrap();
+            async move { String::from_utf8(cmd.output().await.unwrap().stdout).unwrap() }
+        };
+        let inherited = child_env(&[]).await;
+        assert!(inherited.lines().any(|l| l.starts_with("PATH=")));
+        assert!(inherited.lines().any(|l| l.starts_with("HOME=")));
+
+        let stripped = child_env(&["HOME", "CARGO_NVIM_UNSET"]).await;
+        assert!(
+            !stripped.lines().any(|l| l.starts_with("HOME=")),
+            "{}",
+            stripped
+        );
+        assert!(stripped.lines().any(|l| l.starts_with("PATH=")));
+    }
+
+    #[tokio::test]
+    async fn test_env() {
+        let lua = Lua::new();
+        let table: LuaValue = lua
+            .load(r#"return { env = { CARGO_NVIM_SET = "full", HOME = false } }"#)
+            .eval()
+            .unwrap();
+        let options = CommandOptions::from_lua(table, &lua).unwrap();
+        let (mut cmd, _) = options.command("env").unwrap();
+        let env = String::from_utf8(cmd.output().await.unwrap().stdout).unwrap();
+        assert!(env.lines().any(|l| l == "CARGO_NVIM_SET=full"), "{}", env);
+        assert!(!env.lines().any(|l| l.starts_with("HOME=")), "{}", env);
+        assert!(env.lines().any(|l| l.starts_with("PATH=")));
+
+        for invalid in [r#"{ ["A=B"] = "1" }"#, "{ RUST_BACKTRACE = true }"] {
+            let table: LuaValue = lua
+                .load(format!("return {{ env = {} }}", invalid))
+                .eval()
+                .unwrap();
+            let options = CommandOptions::from_lua(table, &lua).unwrap();
+            let err = options.command("env").unwrap_err().to_string();
+            assert!(err.contains("Invalid env"), "{}", err);
+        }
+
+        // Concurrent tests read options from Lua, so nothing valid is set here
+        let unset = BTreeMap::from([("RUSTFLAGS".to_string(), EnvValue::Unset(true))]);
+        assert!(set_default_env(Some(unset)).is_err());
+        set_default_env(None).unwrap();
+    }
+
+    #[test]
+    fn test_toolchain_env() {
+        let options = CommandOptions {
+            toolchain: Some("nightly-2026-01-01".to_string()),
+            // Set by the plugin after the inherited environment is stripped
+            strip_env: vec!["RUSTUP_TOOLCHAIN".to_string()],
+            ..Default::default()
+        };
+        let (cmd, _) = options.command("cargo").unwrap();
+        let toolchain = cmd
+            .as_std()
+            .get_envs()
+            .find(|(name, _)| *name == "RUSTUP_TOOLCHAIN")
+            .and_then(|(_, value)| value);
+        assert_eq!(toolchain, Some(std::ffi::OsStr::new("nightly-2026-01-01")));
+
+        let options = CommandOptions {
+            toolchain: Some("nightly; rm".to_string()),
+            ..Default::default()
+        };
+        assert!(options.command("cargo").is_err());
+
+        // `+nightly` as typed after cargo, and an empty one as none
+        let env = |toolchain: &str| {
+            let options = CommandOptions {
+                toolchain: Some(toolchain.to_string()),
+                ..Default::default()
+            };
+            let (cmd, _) = options.command("cargo").unwrap();
+            let value = cmd
+                .as_std()
+                .get_envs()
+                .find(|(name, _)| *name == "RUSTUP_TOOLCHAIN")
+                .and_then(|(_, value)| value)
+                .map(|value| value.to_string_lossy().into_owned());
+            value
+        };
+        assert_eq!(env("+nightly").as_deref(), Some("nightly"));
+        assert_eq!(env(""), None);
+    }
+
+    #[test]
+    fn test_cargo_bin() {
+        assert_eq!(program_for("cargo", None), "cargo");
+        assert_eq!(
+            program_for("cargo", Some("/opt/rust/cargo")),
+            "/opt/rust/cargo"
+        );
+        assert_eq!(program_for("cross", Some("/opt/rust/cargo")), "cross");
+
+        // Concurrent tests run cargo, so only a missing binary is set here
+        let err = set_cargo_bin(Some("no/such/cargo"))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("[not_found] no/such/cargo is not installed"),
+            "{}",
+            err
+        );
+        assert!(err.contains("set_cargo_bin(nil)"), "{}", err);
+        assert_eq!(set_cargo_bin(Some("")).unwrap(), None);
+    }
+
+    #[test]
+    fn test_set_default_toolchain_checks_name() {
+        assert!(set_default_toolchain(Some("+nightly; rm")).is_err());
+        // Concurrent tests read options from Lua, so nothing is set here
+        assert_eq!(set_default_toolchain(Some(" + ")).unwrap(), None);
+        assert_eq!(set_default_toolchain(None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_missing_options_use_defaults() {
+        let lua = Lua::new();
+        let options = CommandOptions::from_lua(LuaValue::Nil, &lua).unwrap();
+        assert_eq!(options.timeout(), None);
+        assert_eq!(options.output_yield(), DEFAULT_OUTPUT_YIELD);
+    }
+}
@@ -0,0 +1,151 @@
+// src/outcome.rs
+use crate::add::AddedDependency;
+use crate::clean::CleanResult;
+use crate::compile_failure::CompileFailure;
+use crate::coverage::CoverageReport;
+use crate::diagnostics::Suggestion;
+use crate::doc::DocResult;
+use crate::doctest::DoctestResult;
+use crate::metadata::Metadata;
+use crate::panic::PanicInfo;
+use crate::profile::ProfileOutput;
+use crate::quickfix::{ErrorLocation, QuickfixEntry};
+use crate::scaffold::CreatedPackage;
+use crate::search::SearchResult;
+use crate::signal::Termination;
+use crate::size::ArtifactSize;
+use crate::test_report::TestReport;
+use crate::timings::TimingReport;
+use crate::tree::DependencyTree;
+use crate::unit_graph::UnitGraph;
+use crate::vendor::VendorResult;
+use serde::Serialize;
+
+/// Result of a cargo command
+/// Exported to Lua as `output, interactive, details` where `details` holds every field
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CommandOutcome {
+    pub output: String,
+    /// `output` with its colors, with the `color` option; it takes the place of `output`
+    /// once everything was read from that
+    #[serde(skip)]
+    pub ansi_output: Option<String>,
+    /// The lines of `output` that came from stdout (for `run`, the program's output), as
+    /// printed
+    pub stdout: String,
+    /// The lines of `output` that came from stderr, such as cargo's `Compiling` and
+    /// `Finished` lines and rustc's diagnostics
+    pub stderr: String,
+    pub interactive: bool,
+    /// Lines dropped from the start of `output` by `max_output_lines`
+    pub dropped_lines: usize,
+    /// Whether the command succeeded: cargo's `build-finished` verdict for build/check/clippy
+    /// with JSON output, the exit status otherwise
+    pub success: bool,
+    /// Exit code of cargo (e.g. 101 when a build or a test failed), which for `run` is the
+    /// program's; missing when it was killed by a signal, stopped by the user, or
+    /// never spawned
+    pub exit_code: Option<i32>,
+    /// `success` of cargo's `build-finished` JSON message, when it printed one
+    pub build_finished: Option<bool>,
+    /// `true` when the user stopped the command (`interrupt`, `job_cancel`); distinct
+    /// from a timeout or a failed build, and the output up to that point is kept
+    pub interrupted: bool,
+    /// How the process went down when it was interrupted
+    pub termination: Option<Termination>,
+    /// Signal that killed cargo or one of its processes (e.g. rustc), if any
+    pub signal: Option<i32>,
+    /// `rustc --version` of the toolchain the command ran under, e.g.
+    /// `rustc 1.95.0 (59807616e 2026-04-14)`
+    pub toolchain: Option<String>,
+    /// `true` when the build was a no-op because everything was up to date
+    pub cached: bool,
+    /// Panic reported by the program (`cargo run`)
+    pub panic: Option<PanicInfo>,
+    /// `true` when the program was run again with `RUST_BACKTRACE=1` after a panic
+    /// (`cargo run` with `backtrace_on_panic`)
+    pub backtrace_rerun: bool,
+    /// Directory the program ran in (`cargo run`): `run_dir` when set, otherwise the
+    /// directory cargo ran in
+    pub program_dir: Option<String>,
+    /// Profile written by the profiler (`cargo run` with `profile_with`)
+    pub profile: Option<ProfileOutput>,
+    /// Warnings reported by the compiler (build/check/clippy)
+    pub warning_count: usize,
+    /// Workspace members cargo acted on, for compiling commands run without a package
+    /// selection (`default-members` from the workspace root)
+    pub members: Option<Vec<String>>,
+    /// Crates that failed to compile, with their error counts, from cargo's
+    /// `could not compile` lines
+    pub compile_failures: Vec<CompileFailure>,
+    /// Locations worth jumping to, ready for `setqflist`
+    pub quickfix: Vec<QuickfixEntry>,
+    /// Fixes rustc is sure of, for code actions (JSON diagnostics: `check_fast` and
+    /// `rendered_diagnostics`)
+    pub suggestions: Vec<Suggestion>,
+    /// The first error of `quickfix` in source order, to jump to
+    pub first_error: Option<ErrorLocation>,
+    /// Directory measured and the bytes freed (`cargo clean`)
+    pub clean: Option<CleanResult>,
+    /// Index to open and the index of each workspace member (`cargo doc`)
+    pub doc: Option<DocResult>,
+    /// Suites and cases in the shape of a JUnit report (`cargo test` with `format`)
+    pub test_report: Option<TestReport>,
+    /// Line, region and function coverage (`cargo llvm-cov`)
+    pub coverage: Option<CoverageReport>,
+    /// Per-doctest results (`cargo test --doc`)
+    pub doctests: Vec<DoctestResult>,
+    /// Output of the doctest step, kept apart from `output` (`cargo test` with
+    /// `split_doctests`)
+    pub doctest_output: Option<String>,
+    /// Dependencies added, with their available and enabled features (`cargo add`)
+    pub added: Vec<AddedDependency>,
+    /// Package created, with the file to open (`cargo new`, `cargo init`)
+    pub created: Option<CreatedPackage>,
+    /// Parsed dependency tree (`cargo tree`)
+    pub tree: Option<DependencyTree>,
+    /// Crates found and the count beyond the limit (`cargo search`)
+    pub search: Option<SearchResult>,
+    /// Packages with their targets and features (`cargo metadata`)
+    pub metadata: Option<Metadata>,
+    /// Vendor directory and config snippet (`cargo vendor`)
+    pub vendor: Option<VendorResult>,
+    /// Compilation units and their dependencies (`unit_graph`)
+    pub unit_graph: Option<UnitGraph>,
+    /// Timing report location and per-crate durations (`cargo build` with `timings`)
+    pub timings: Option<TimingReport>,
+    /// Size of each binary and library built (`cargo build` with `report_size`)
+    pub artifact_sizes: Vec<ArtifactSize>,
+    /// Files rustfmt changed (`cargo fmt` with `files`)
+    pub formatted: Vec<String>,
+}
+
+impl CommandOutcome {
+    /// Create an outcome with just the output and interactive flag
+    pub fn new(output: String, interactive: bool) -> Self {
+        Self {
+            output,
+            interactive,
+            ..Default::default()
+        }
+    }
+
+    /// Apply `edit` to the output, to each stream alone, and to the output with colors
+    /// if kept
+    pub fn edit_output(&mut self, edit: impl Fn(&str) -> String) {
+        self.output = edit(&self.output);
+        self.stdout = edit(&self.stdout);
+        self.stderr = edit(&self.stderr);
+        if let Some(ansi_output) = &mut self.ansi_output {
+            *ansi_output = edit(ansi_output);
+        }
+    }
+
+    /// The outcome with its output in color, when it was kept
+    pub fn with_colors(mut self) -> Self {
+        if let Some(ansi_output) = self.ansi_output.take() {
+            self.output = ansi_output;
+        }
+        self
+    }
+}
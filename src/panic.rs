@@ -0,0 +1,212 @@
+// src/panic.rs
+//! Extraction of panic messages and locations from program output
+
+use crate::quickfix::QuickfixEntry;
+use serde::Serialize;
+
+/// A panic reported by a Rust program
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PanicInfo {
+    pub thread: String,
+    pub message: String,
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+    /// First backtrace frame in user code, when a backtrace was printed
+    pub frame: Option<BacktraceFrame>,
+}
+
+/// A backtrace frame with a source location
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BacktraceFrame {
+    pub function: String,
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl PanicInfo {
+    /// Quickfix entry pointing at the panic location
+    pub fn to_quickfix(&self) -> QuickfixEntry {
+        QuickfixEntry::error(&self.file, self.line, self.column, &self.message)
+    }
+}
+
+/// Find the first panic in the output
+///
+/// Handles the pre-1.73 single-line format
+/// `thread 'main' panicked at 'msg', src/main.rs:10:5`, the newer two-line format
+/// `thread 'main' panicked at src/main.rs:10:5:` followed by the message, and the
+/// variant that adds a thread id (`thread 'main' (1234) panicked at ...`).
+pub fn parse_panic(output: &str) -> Option<PanicInfo> {
+    let lines: Vec<&str> = output.lines().collect();
+
+    for (idx, line) in lines.iter().enumerate() {
+        let Some(rest) = line.trim_start().strip_prefix("thread '") else {
+            continue;
+        };
+        let Some(at) = rest.find(" panicked at ") else {
+            continue;
+        };
+        let thread = rest[..at]
+            .split('\'')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let after = &rest[at + " panicked at ".len()..];
+
+        let parsed = if let Some(quoted) = after.strip_prefix('\'') {
+            // Old format: 'message', file:line:col
+            quoted.rfind("', ").and_then(|end| {
+                let (file, line, column) = parse_location(&quoted[end + 3..])?;
+                Some((quoted[..end].to_string(), file, line, column))
+            })
+        } else {
+            // New format: location on this line, message on the following lines
+            parse_location(after.trim_end().trim_end_matches(':')).map(|(file, line, column)| {
+                let message = lines[idx + 1..]
+                    .iter()
+                    .take_while(|l| {
+                        !l.trim().is_empty()
+                            && !l.starts_with("note: ")
+                            && !l.starts_with("stack backtrace:")
+                    })
+                    .copied()
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                (message, file, line, column)
+            })
+        };
+
+        if let Some((message, file, line, column)) = parsed {
+            return Some(PanicInfo {
+                thread,
+                message,
+                file,
+                line,
+                column,
+                frame: parse_first_user_frame(&lines[idx + 1..]),
+            });
+        }
+    }
+
+    None
+}
+
+/// Whether the output includes a backtrace (the program ran with `RUST_BACKTRACE` set)
+pub fn has_backtrace(output: &str) -> bool {
+    output.lines().any(|l| l.starts_with("stack backtrace:"))
+}
+
+/// Parse `file:line[:column]`
+fn parse_location(location: &str) -> Option<(String, u32, u32)> {
+    let (rest, last) = location.trim().rsplit_once(':')?;
+    let last = last.parse().ok()?;
+    match rest.rsplit_once(':') {
+        Some((file, line)) => match line.parse() {
+            Ok(line) => Some((file.to_string(), line, last)),
+            Err(_) => Some((rest.to_string(), last, 1)),
+        },
+        None => Some((rest.to_string(), last, 1)),
+    }
+}
+
+/// Find the first backtrace frame located outside the standard library and dependencies
+fn parse_first_user_frame(lines: &[&str]) -> Option<BacktraceFrame> {
+    let start = lines
+        .iter()
+        .position(|l| l.starts_with("stack backtrace:"))?;
+
+    let mut function = None;
+    for line in &lines[start + 1..] {
+        let trimmed = line.trim();
+        if let Some(location) = trimmed.strip_prefix("at ") {
+            let Some(name) = function.take() else {
+                continue;
+            };
+            let is_external = location.starts_with("/rustc/")
+                || location.contains("/.cargo/registry/")
+                || location.contains("/.rustup/toolchains/");
+            if is_external {
+                continue;
+            }
+            if let Some((file, line, column)) = parse_location(location) {
+                let file = file.strip_prefix("./").unwrap_or(&file).to_string();
+                return Some(BacktraceFrame {
+                    function: name,
+                    file,
+                    line,
+                    column,
+                });
+            }
+        } else if let Some((index, name)) = trimmed.split_once(": ") {
+            if index.chars().all(|c| c.is_ascii_digit()) {
+                function = Some(name.to_string());
+            } else {
+                break;
+            }
+        } else {
+            break;
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_old_panic_format() {
+        let output = "thread 'main' panicked at 'called `Option::unwrap()` on a `None` value', src/main.rs:10:5\nnote: run with `RUST_BACKTRACE=1`";
+        let panic = parse_panic(output).unwrap();
+        assert_eq!(panic.thread, "main");
+        assert_eq!(panic.message, "called `Option::unwrap()` on a `None` value");
+        assert_eq!(
+            (panic.file.as_str(), panic.line, panic.column),
+            ("src/main.rs", 10, 5)
+        );
+        assert!(panic.frame.is_none());
+    }
+
+    #[test]
+    fn test_parse_new_panic_format_with_backtrace() {
+        let output = "\
+   Compiling app v0.1.0
+thread 'main' (9273) panicked at src/main.rs:2:30:
+value 'x' missing
+stack backtrace:
+   0: __rustc::rust_begin_unwind
+             at /rustc/5980761/library/std/src/panicking.rs:689:5
+   1: core::option::expect_failed
+             at /rustc/5980761/library/core/src/option.rs:2245:5
+   2: app::helper
+             at ./src/main.rs:2:30
+   3: app::main
+             at ./src/main.rs:1:39
+note: Some details are omitted, run with `RUST_BACKTRACE=full` for a verbose backtrace.
+";
+        let panic = parse_panic(output).unwrap();
+        assert_eq!(panic.thread, "main");
+        assert_eq!(panic.message, "value 'x' missing");
+        assert_eq!((panic.line, panic.column), (2, 30));
+        assert_eq!(
+            panic.frame,
+            Some(BacktraceFrame {
+                function: "app::helper".to_string(),
+                file: "src/main.rs".to_string(),
+                line: 2,
+                column: 30,
+            })
+        );
+
+        let entry = panic.to_quickfix();
+        assert_eq!((entry.filename.as_str(), entry.lnum), ("src/main.rs", 2));
+    }
+
+    #[test]
+    fn test_no_panic() {
+        assert!(parse_panic("Hello, world!\n").is_none());
+    }
+}
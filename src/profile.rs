@@ -0,0 +1,207 @@
+// src/profile.rs
+//! Running the program under a profiler (`profile_with`)
+//!
+//! The profiler goes in front of the program as part of the runner cargo starts it
+//! with, so `cargo run` still builds first and picks the binary; the profile is written
+//! to `<target dir>/profiles/`.
+
+use crate::error::Error;
+use mlua::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// dtrace splits its `-c` command on whitespace, without quoting, so the command goes in
+// a script, each word quoted, which dtrace starts and which execs the program (keeping
+// the pid dtrace traces); `$0` is the output file
+const DTRACE_RUNNER: &str = r#"out=$0
+script=$(mktemp) || exit 1
+printf 'exec' > "$script"
+for word; do
+    printf " '%s'" "$(printf '%s' "$word" | sed "s/'/'\\\\''/g")" >> "$script"
+done
+dtrace -q -x ustackframes=100 \
+    -n 'profile-997 /pid == $target/ { @[ustack()] = count(); }' \
+    -o "$out" -c "sh $script"
+status=$?
+rm -f "$script"
+exit $status"#;
+
+/// A profiler to run the program under
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Profiler {
+    /// `perf record -g`, on Linux; writes a `perf.data` file
+    Perf,
+    /// DTrace sampling user stacks at 997 Hz, on macOS, FreeBSD and illumos; writes the
+    /// aggregated stacks, ready for flamegraph tools. It usually needs root
+    Dtrace,
+    /// `samply record --save-only`, on Linux and macOS; writes a Firefox Profiler file
+    Samply,
+}
+
+/// The profile a run wrote
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProfileOutput {
+    /// `perf`, `dtrace` or `samply`
+    pub profiler: String,
+    /// Where the profile is, once the program has exited
+    pub path: String,
+}
+
+impl Profiler {
+    pub fn name(self) -> &'static str {
+        match self {
+            Profiler::Perf => "perf",
+            Profiler::Dtrace => "dtrace",
+            Profiler::Samply => "samply",
+        }
+    }
+
+    /// Flag that makes the profiler print its version, to see whether it is installed
+    pub fn version_flag(self) -> &'static str {
+        match self {
+            Profiler::Dtrace => "-V",
+            _ => "--version",
+        }
+    }
+
+    pub fn install_hint(self) -> &'static str {
+        match self {
+            Profiler::Perf => "install perf, e.g. the linux-tools package of your distribution",
+            Profiler::Dtrace => "DTrace ships with macOS, FreeBSD and illumos",
+            Profiler::Samply => "install it with 'cargo install samply'",
+        }
+    }
+
+    // Whether the profiler runs on this platform
+    fn supported(self) -> bool {
+        match self {
+            Profiler::Perf => cfg!(target_os = "linux"),
+            Profiler::Dtrace => cfg!(any(
+                target_os = "macos",
+                target_os = "freebsd",
+                target_os = "illumos"
+            )),
+            Profiler::Samply => cfg!(any(target_os = "linux", target_os = "macos")),
+        }
+    }
+
+    /// Fails with the platforms it runs on when it doesn't run on this one
+    pub fn check_platform(self) -> LuaResult<()> {
+        if self.supported() {
+            return Ok(());
+        }
+        let platforms = match self {
+            Profiler::Perf => "Linux",
+            Profiler::Dtrace => "macOS, FreeBSD and illumos",
+            Profiler::Samply => "Linux and macOS",
+        };
+        Err(LuaError::RuntimeError(format!(
+            "Invalid profile_with: {} is only supported on {} (this is {})",
+            self.name(),
+            platforms,
+            std::env::consts::OS
+        )))
+    }
+
+    /// Extension of the profile file
+    pub fn extension(self) -> &'static str {
+        match self {
+            Profiler::Perf => "perf.data",
+            Profiler::Dtrace => "stacks",
+            Profiler::Samply => "json.gz",
+        }
+    }
+
+    /// The runner words that start the program (appended by cargo, with its arguments)
+    /// under the profiler, writing to `output`
+    pub fn runner(self, output: &Path) -> Vec<String> {
+        let output = output.to_string_lossy().into_owned();
+        let words: Vec<&str> = match self {
+            Profiler::Perf => vec!["perf", "record", "-g", "-o", &output, "--"],
+            Profiler::Samply => vec!["samply", "record", "--save-only", "-o", &output, "--"],
+            Profiler::Dtrace => vec!["sh", "-c", DTRACE_RUNNER, &output],
+        };
+        words.into_iter().map(str::to_string).collect()
+    }
+}
+
+/// The error for a profiler that isn't installed
+pub fn profiler_not_installed(profiler: Profiler) -> LuaError {
+    Error::NotFound {
+        program: profiler.name().to_string(),
+        details: format!("Please {}", profiler.install_hint()),
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runner_and_platforms() {
+        let output = Path::new("/tmp/target/profiles/run-1.perf.data");
+        assert_eq!(
+            Profiler::Perf.runner(output),
+            vec![
+                "perf",
+                "record",
+                "-g",
+                "-o",
+                "/tmp/target/profiles/run-1.perf.data",
+                "--"
+            ]
+        );
+        let dtrace = Profiler::Dtrace.runner(Path::new("/tmp/out.stacks"));
+        assert_eq!(dtrace[..2], ["sh", "-c"]);
+        assert_eq!(dtrace[3], "/tmp/out.stacks");
+
+        let profilers = [Profiler::Perf, Profiler::Dtrace, Profiler::Samply];
+        #[cfg(target_os = "linux")]
+        {
+            assert!(Profiler::Perf.check_platform().is_ok());
+            let err = Profiler::Dtrace.check_platform().unwrap_err();
+            assert!(
+                err.to_string().contains("only supported on macOS"),
+                "{}",
+                err
+            );
+        }
+        // Every profiler runs somewhere
+        assert!(profilers.iter().any(|p| p.check_platform().is_ok()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_dtrace_runner_keeps_words() {
+        // A stand-in that, like dtrace, runs its `-c` command split on whitespace
+        let bin = std::env::temp_dir().join(format!("cargo-nvim-dtrace-{}", std::process::id()));
+        std::fs::create_dir_all(&bin).unwrap();
+        std::fs::write(
+            bin.join("dtrace"),
+            "#!/bin/sh\nwhile [ $# -gt 0 ]; do\n  [ \"$1\" = -c ] && exec $2\n  shift\ndone\n",
+        )
+        .unwrap();
+        std::process::Command::new("chmod")
+            .args(["+x", &bin.join("dtrace").to_string_lossy()])
+            .status()
+            .unwrap();
+
+        let runner = Profiler::Dtrace.runner(Path::new("/tmp/out.stacks"));
+        let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+        let output = std::process::Command::new(&runner[0])
+            .args(&runner[1..])
+            .args(["printf", "%s|", "two words", "it's", "$HOME"])
+            .env("PATH", path)
+            .output()
+            .unwrap();
+        let _ = std::fs::remove_dir_all(&bin);
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "two words|it's|$HOME|",
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
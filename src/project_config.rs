@@ -0,0 +1,152 @@
+// src/project_config.rs
+//! Per-project defaults read from `.cargo-nvim.toml`
+//!
+//! The file is looked up from the working directory upwards, so it can be committed at
+//! the workspace root. Its values only fill in options the caller left unset. Each file
+//! is parsed once, and again only after it has been modified.
+
+use crate::options::CommandOptions;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+// When and how large a file was, to tell whether it changed since it was read
+type Stamp = Option<(Option<SystemTime>, u64)>;
+
+// Parsed files by path, with the stamp they were read at
+type Loaded = BTreeMap<PathBuf, (Stamp, Result<ProjectConfig, String>)>;
+
+static LOADED: Mutex<Loaded> = Mutex::new(BTreeMap::new());
+
+/// Name of the project config file
+pub const FILE_NAME: &str = ".cargo-nvim.toml";
+
+/// Contents of `.cargo-nvim.toml`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// Timeouts in seconds per subcommand, with `default` for the others
+    pub timeouts: BTreeMap<String, u64>,
+    /// Features enabled for compiling commands
+    pub features: Vec<String>,
+    /// Target triple for compiling commands
+    pub target: Option<String>,
+    /// Subcommands that may not be run in this project (e.g. `publish`)
+    pub denied_commands: Vec<String>,
+}
+
+impl ProjectConfig {
+    /// Find and parse the config for `dir`
+    ///
+    /// Returns the path read along with the config, `Ok(None)` when no file exists,
+    /// and an error message when the file can't be read or parsed.
+    pub fn find(dir: &Path) -> Result<Option<(PathBuf, Self)>, (PathBuf, String)> {
+        let Some(path) = dir
+            .ancestors()
+            .map(|d| d.join(FILE_NAME))
+            .find(|p| p.is_file())
+        else {
+            return Ok(None);
+        };
+        let stamp = std::fs::metadata(&path)
+            .ok()
+            .map(|m| (m.modified().ok(), m.len()));
+        let mut loaded = LOADED.lock().unwrap();
+        let parsed = match loaded.get(&path) {
+            Some((at, parsed)) if stamp.is_some() && *at == stamp => parsed.clone(),
+            _ => {
+                let parsed = std::fs::read_to_string(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|contents| toml::from_str(&contents).map_err(|e| e.to_string()));
+                loaded.insert(path.clone(), (stamp, parsed.clone()));
+                parsed
+            }
+        };
+        match parsed {
+            Ok(config) => Ok(Some((path, config))),
+            Err(message) => Err((path, message)),
+        }
+    }
+
+    /// Whether `subcommand` is denied by this config
+    pub fn denies(&self, subcommand: &str) -> bool {
+        self.denied_commands.iter().any(|c| c == subcommand)
+    }
+
+    /// Fill in the options the caller didn't set with this config's values
+    pub fn apply(&self, subcommand: &str, options: &mut CommandOptions) {
+        if options.timeout_secs.is_none() {
+            options.timeout_secs = self
+                .timeouts
+                .get(subcommand)
+                .or_else(|| self.timeouts.get("default"))
+                .copied();
+        }
+        if options.features.is_empty() {
+            options.features = self.features.clone();
+        }
+        if options.target.is_none() {
+            options.target = self.target.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::ScratchCrate;
+
+    const CONFIG: &str = r#"
+features = ["serde"]
+target = "x86_64-unknown-linux-gnu"
+denied_commands = ["publish"]
+
+[timeouts]
+default = 60
+test = 900
+"#;
+
+    #[test]
+    fn test_config_fills_unset_options() {
+        let config: ProjectConfig = toml::from_str(CONFIG).unwrap();
+        assert!(config.denies("publish"));
+        assert!(!config.denies("build"));
+
+        let mut options = CommandOptions::default();
+        config.apply("test", &mut options);
+        assert_eq!(options.timeout_secs, Some(900));
+        assert_eq!(options.features, vec!["serde"]);
+        assert_eq!(options.target.as_deref(), Some("x86_64-unknown-linux-gnu"));
+
+        // Explicit options win
+        let mut options = CommandOptions {
+            timeout_secs: Some(5),
+            features: vec!["tokio".to_string()],
+            ..Default::default()
+        };
+        config.apply("build", &mut options);
+        assert_eq!(options.timeout_secs, Some(5));
+        assert_eq!(options.features, vec!["tokio"]);
+    }
+
+    #[test]
+    fn test_find_config_in_parent_directory() {
+        let scratch = ScratchCrate::new("config", &[(FILE_NAME, CONFIG)]);
+        let root = Path::new(&scratch.manifest_path())
+            .parent()
+            .unwrap()
+            .to_path_buf();
+
+        let (path, config) = ProjectConfig::find(&root.join("src")).unwrap().unwrap();
+        assert_eq!(path, root.join(FILE_NAME));
+        assert_eq!(config.timeouts.get("default"), Some(&60));
+
+        // Unknown keys and bad syntax make the file malformed
+        std::fs::write(root.join(FILE_NAME), "timeout = 3\n").unwrap();
+        assert!(ProjectConfig::find(&root).is_err());
+        std::fs::write(root.join(FILE_NAME), "features = [\n").unwrap();
+        assert!(ProjectConfig::find(&root).is_err());
+    }
+}
@@ -0,0 +1,80 @@
+// src/quickfix.rs
+use serde::Serialize;
+
+/// An entry shaped like Neovim's quickfix items (`:help setqflist()`)
+/// so the Lua side can pass a list of them straight to `vim.fn.setqflist`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct QuickfixEntry {
+    pub filename: String,
+    pub lnum: u32,
+    pub col: u32,
+    pub text: String,
+    /// `E` for errors, `W` for warnings, `N` for notes
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+impl QuickfixEntry {
+    pub fn new(filename: &str, lnum: u32, col: u32, text: &str, kind: &str) -> Self {
+        Self {
+            filename: filename.to_string(),
+            lnum,
+            col,
+            text: text.to_string(),
+            kind: kind.to_string(),
+        }
+    }
+
+    /// Create an error entry
+    pub fn error(filename: &str, lnum: u32, col: u32, text: &str) -> Self {
+        Self::new(filename, lnum, col, text, "E")
+    }
+}
+
+/// A location to put the cursor on
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ErrorLocation {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+/// The first error among `entries` in source order (by file, then line and column), to
+/// jump to after a failed build; `None` when there are no errors
+pub fn first_error(entries: &[QuickfixEntry]) -> Option<ErrorLocation> {
+    entries
+        .iter()
+        .filter(|entry| entry.kind == "E")
+        .min_by(|a, b| (&a.filename, a.lnum, a.col).cmp(&(&b.filename, b.lnum, b.col)))
+        .map(|entry| ErrorLocation {
+            file: entry.filename.clone(),
+            line: entry.lnum,
+            column: entry.col,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_error() {
+        let entries = vec![
+            QuickfixEntry::new("src/a.rs", 1, 1, "unused variable", "W"),
+            QuickfixEntry::error("src/main.rs", 3, 9, "mismatched types"),
+            QuickfixEntry::error("src/lib.rs", 40, 5, "cannot find value"),
+            QuickfixEntry::error("src/lib.rs", 12, 20, "expected `;`"),
+            QuickfixEntry::error("src/lib.rs", 12, 7, "borrow of moved value"),
+        ];
+        assert_eq!(
+            first_error(&entries),
+            Some(ErrorLocation {
+                file: "src/lib.rs".to_string(),
+                line: 12,
+                column: 7,
+            })
+        );
+        assert_eq!(first_error(&entries[..1]), None);
+        assert_eq!(first_error(&[]), None);
+    }
+}
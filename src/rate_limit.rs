@@ -0,0 +1,146 @@
+// src/rate_limit.rs
+//! Detection of registry rate limiting in cargo output
+//!
+//! crates.io answers with HTTP 429 when too many requests are made, and publishing
+//! too many new crates in a short time is refused with a date to try again after.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Commands that talk to the registry and may be rate limited
+pub const REGISTRY_COMMANDS: &[&str] = &["publish", "search", "install"];
+
+/// Retries made with `respect_rate_limit` before giving up
+pub const MAX_RETRIES: u32 = 3;
+
+// Wait used when the registry gave no hint
+const DEFAULT_WAIT: Duration = Duration::from_secs(30);
+// Longer waits are reported rather than waited out: the caller is blocked meanwhile
+const MAX_WAIT: Duration = Duration::from_secs(300);
+
+/// A rate-limit response found in a command's output
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimit {
+    /// Seconds to wait before retrying, when the registry said
+    pub retry_after_secs: Option<u64>,
+}
+
+impl RateLimit {
+    /// How long to wait before retrying; `None` when that's too long to wait out
+    pub fn wait(&self) -> Option<Duration> {
+        let wait = self
+            .retry_after_secs
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_WAIT);
+        (wait <= MAX_WAIT).then_some(wait)
+    }
+}
+
+/// Look for a rate-limit response in the output of a failed registry command
+pub fn detect_rate_limit(output: &str, now: SystemTime) -> Option<RateLimit> {
+    let lower = output.to_lowercase();
+    let limited = lower.contains("status 429")
+        || lower.contains("got 429")
+        || lower.contains("too many requests")
+        || lower.contains("published too many");
+    if !limited {
+        return None;
+    }
+
+    Some(RateLimit {
+        retry_after_secs: lower.lines().find_map(|line| retry_after(line, now)),
+    })
+}
+
+// `Retry-After: <seconds>` (headers cargo prints for failed requests) or crates.io's
+// "try again after <HTTP date>"
+fn retry_after(line: &str, now: SystemTime) -> Option<u64> {
+    if let Some((_, rest)) = line.split_once("retry-after:") {
+        return rest.split_whitespace().next()?.parse().ok();
+    }
+    let (_, rest) = line.split_once("try again after ")?;
+    let date: Vec<&str> = rest.split_whitespace().take(6).collect();
+    let at = parse_http_date(&date.join(" "))?;
+    let now = now.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    Some(at.saturating_sub(now))
+}
+
+// `Mon, 21 Oct 2024 12:34:56 GMT`, in lowercase, to seconds since the epoch
+fn parse_http_date(date: &str) -> Option<u64> {
+    let mut parts = date.split_whitespace().skip(1);
+    let day: u64 = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let year: u64 = parts.next()?.parse().ok()?;
+    let mut time = parts.next()?.split(':').map(|n| n.parse::<u64>().ok());
+    let (hours, minutes, seconds) = (time.next()??, time.next()??, time.next()??);
+
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let month = MONTHS.iter().position(|m| *m == month)? as u64 + 1;
+    if year < 1970 || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Days since the epoch for a proleptic Gregorian date, with years starting in March
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = y / 400;
+    let year_of_era = y % 400;
+    let day_of_year = (153 * m + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = (era * 146_097 + day_of_era).checked_sub(719_468)?;
+
+    Some(days * 86_400 + hours * 3_600 + minutes * 60 + seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOO_MANY_CRATES: &str = "\
+    Uploading tt v0.1.0 (/tmp/tt)
+error: failed to publish to registry at https://crates.io
+
+Caused by:
+  the remote server responded with an error (status 429 Too Many Requests): You have published too many new crates in a short period of time. Please try again after Mon, 21 Oct 2024 12:34:56 GMT or email help@crates.io to have your limit increased.
+";
+
+    #[test]
+    fn test_try_again_after_date() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_729_514_096 - 90);
+        let limit = detect_rate_limit(TOO_MANY_CRATES, now).unwrap();
+        assert_eq!(limit.retry_after_secs, Some(90));
+        assert_eq!(limit.wait(), Some(Duration::from_secs(90)));
+
+        // A date already passed means retrying right away
+        let later = UNIX_EPOCH + Duration::from_secs(1_729_514_096 + 5);
+        let limit = detect_rate_limit(TOO_MANY_CRATES, later).unwrap();
+        assert_eq!(limit.retry_after_secs, Some(0));
+    }
+
+    #[test]
+    fn test_retry_after_header() {
+        let output = "error: failed to retrieve search results from the registry at https://crates.io\n\nCaused by:\n  failed to get successful HTTP response from `https://crates.io/api/v1/crates?q=serde`, got 429\n  debug headers:\n  Retry-After: 45\n";
+        let limit = detect_rate_limit(output, SystemTime::now()).unwrap();
+        assert_eq!(limit.retry_after_secs, Some(45));
+
+        // Without a hint the default wait applies; very long waits aren't waited out
+        let limit = RateLimit {
+            retry_after_secs: None,
+        };
+        assert_eq!(limit.wait(), Some(DEFAULT_WAIT));
+        let limit = RateLimit {
+            retry_after_secs: Some(3_600),
+        };
+        assert_eq!(limit.wait(), None);
+    }
+
+    #[test]
+    fn test_other_failures_are_not_rate_limits() {
+        let output = "error: failed to publish to registry at https://crates.io\n\nCaused by:\n  the remote server responded with an error (status 403 Forbidden): this crate exists but you don't seem to be an owner.\n";
+        assert_eq!(detect_rate_limit(output, SystemTime::now()), None);
+    }
+}
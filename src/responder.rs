@@ -0,0 +1,88 @@
+// src/responder.rs
+//! Automatic answers to known prompts
+use mlua::prelude::*;
+use regex::Regex;
+use std::collections::{BTreeMap, HashSet};
+
+/// Matches output lines against prompt patterns and picks the response to send
+#[derive(Debug, Clone, Default)]
+pub struct AutoResponder {
+    rules: Vec<(Regex, String)>,
+    // Prompts already answered; a prompt that comes back means the answer was
+    // rejected, and answering it again would loop forever
+    answered: HashSet<String>,
+}
+
+impl AutoResponder {
+    /// Compile a `pattern -> response` map, rejecting invalid patterns
+    pub fn new(responses: &BTreeMap<String, String>) -> LuaResult<Self> {
+        let rules = responses
+            .iter()
+            .map(|(pattern, response)| {
+                Regex::new(pattern)
+                    .map(|regex| (regex, response.clone()))
+                    .map_err(|e| {
+                        LuaError::RuntimeError(format!(
+                            "Invalid prompt pattern {:?}: {}",
+                            pattern, e
+                        ))
+                    })
+            })
+            .collect::<LuaResult<_>>()?;
+        Ok(Self {
+            rules,
+            answered: HashSet::new(),
+        })
+    }
+
+    /// Response for an output line, if it matches a prompt not answered before
+    pub fn respond(&mut self, line: &str) -> Option<String> {
+        if self.rules.is_empty() {
+            return None;
+        }
+        let response = self
+            .rules
+            .iter()
+            .find(|(regex, _)| regex.is_match(line))
+            .map(|(_, response)| response.clone())?;
+        self.answered
+            .insert(line.trim().to_string())
+            .then_some(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn responder(rules: &[(&str, &str)]) -> AutoResponder {
+        let map = rules
+            .iter()
+            .map(|(p, r)| (p.to_string(), r.to_string()))
+            .collect();
+        AutoResponder::new(&map).unwrap()
+    }
+
+    #[test]
+    fn test_responds_once_per_prompt() {
+        let mut responder = responder(&[("Overwrite\\?", "y\n")]);
+        assert_eq!(responder.respond("Compiling app"), None);
+        assert_eq!(
+            responder.respond("Overwrite? [y/N]").as_deref(),
+            Some("y\n")
+        );
+        // Asked again: the answer didn't work, leave it for the user
+        assert_eq!(responder.respond("Overwrite? [y/N]"), None);
+        // A different prompt matching the same pattern is still answered
+        assert_eq!(
+            responder.respond("Overwrite? src/lib.rs [y/N]").as_deref(),
+            Some("y\n")
+        );
+    }
+
+    #[test]
+    fn test_invalid_pattern_rejected() {
+        let map = BTreeMap::from([("(".to_string(), "y\n".to_string())]);
+        assert!(AutoResponder::new(&map).is_err());
+    }
+}
@@ -0,0 +1,132 @@
+// src/scaffold.rs
+//! The package `cargo new` or `cargo init` created, so the plugin can open its main
+//! source file right away
+//!
+//! Cargo only names the kind of package (and, for `new`, its name):
+//!
+//! ```text
+//!     Creating binary (application) `my-app` package
+//!     Creating library package
+//! ```
+//!
+//! so the directory comes from the arguments, and the entry file is looked up in it.
+
+use crate::artifacts::flag_values;
+use serde::Serialize;
+use std::path::Path;
+
+/// A package created by `cargo new` or `cargo init`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CreatedPackage {
+    /// `bin` or `lib`
+    pub kind: String,
+    /// Package name: `--name` when given, which may differ from the directory's
+    pub name: String,
+    /// Directory of the package
+    pub path: String,
+    /// The file to start editing: `src/main.rs` or `src/lib.rs`, or the existing file
+    /// `cargo init` picked up (e.g. `main.rs` at the root)
+    pub entry_file: String,
+}
+
+// Flags of new and init that take a value, so their values aren't taken for the path
+const VALUE_FLAGS: &[&str] = &[
+    "--name",
+    "--vcs",
+    "--edition",
+    "--registry",
+    "--color",
+    "--config",
+    "-Z",
+];
+
+/// The path argument of `cargo new` or `cargo init` (`.` when there is none)
+pub fn init_path<'a>(args: &[&'a str]) -> &'a str {
+    let mut args = args.iter().take_while(|a| **a != "--");
+    while let Some(arg) = args.next() {
+        if VALUE_FLAGS.contains(arg) {
+            args.next();
+        } else if !arg.starts_with('-') {
+            return arg;
+        }
+    }
+    "."
+}
+
+/// The package in `output`, created in `dir` with `args`
+pub fn parse_created(output: &str, dir: &Path, args: &[&str]) -> Option<CreatedPackage> {
+    let line = output.lines().find_map(|line| {
+        let line = line.trim_start();
+        line.strip_prefix("Creating ")
+            .or_else(|| line.strip_prefix("Created "))
+            .filter(|rest| rest.ends_with(" package"))
+    })?;
+    let (kind, entries): (&str, &[&str]) = if line.starts_with("binary") {
+        ("bin", &["src/main.rs", "main.rs"])
+    } else if line.starts_with("library") {
+        ("lib", &["src/lib.rs", "lib.rs"])
+    } else {
+        return None;
+    };
+
+    let name = match line.split('`').nth(1) {
+        Some(name) => name.to_string(),
+        None => match flag_values(args, "--name").last() {
+            Some(name) => name.to_string(),
+            None => dir.file_name()?.to_string_lossy().into_owned(),
+        },
+    };
+    let entry_file = entries
+        .iter()
+        .map(|entry| dir.join(entry))
+        .find(|path| path.is_file())
+        .unwrap_or_else(|| dir.join(entries[0]));
+    Some(CreatedPackage {
+        kind: kind.to_string(),
+        name,
+        path: dir.to_string_lossy().into_owned(),
+        entry_file: entry_file.to_string_lossy().into_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_path() {
+        assert_eq!(init_path(&["--lib", "--name", "x", "ws/app"]), "ws/app");
+        assert_eq!(init_path(&["--vcs", "none", "--name=x"]), ".");
+        assert_eq!(init_path(&[]), ".");
+    }
+
+    #[test]
+    fn test_parse_created() {
+        let dir = std::env::temp_dir().join(format!("cargo-nvim-scaffold-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // cargo init kept a main.rs at the root
+        std::fs::write(dir.join("main.rs"), "fn main() {}\n").unwrap();
+
+        let output = "    Creating binary (application) `real_name` package\n\
+                      note: see more `Cargo.toml` keys and their definitions at https://doc.rust-lang.org/cargo/reference/manifest.html\n";
+        let created = parse_created(output, &dir, &["--name", "real_name"]).unwrap();
+        assert_eq!(created.kind, "bin");
+        assert_eq!(created.name, "real_name");
+        assert_eq!(created.path, dir.to_string_lossy());
+        assert_eq!(created.entry_file, dir.join("main.rs").to_string_lossy());
+
+        // init doesn't name the package; older releases say `Created`
+        let created =
+            parse_created("     Created library package\n", &dir, &["--name=my-lib"]).unwrap();
+        assert_eq!(
+            (created.kind.as_str(), created.name.as_str()),
+            ("lib", "my-lib")
+        );
+        assert_eq!(created.entry_file, dir.join("src/lib.rs").to_string_lossy());
+        let created = parse_created("    Creating library package\n", &dir, &[]).unwrap();
+        assert_eq!(created.name, dir.file_name().unwrap().to_string_lossy());
+
+        assert_eq!(parse_created("error: destination exists", &dir, &[]), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,184 @@
+// src/search.rs
+//! Parsing of `cargo search` results, and searches run in the background
+//!
+//! A background search is a job (see `jobs`) that supersedes the previous search, so a
+//! crate picker can search on every keystroke, and it can be cancelled while the
+//! registry is slow to answer. Results are polled by id.
+
+use crate::jobs::{self, JobStatus};
+use crate::options::CommandOptions;
+use serde::Serialize;
+use std::sync::Mutex;
+
+/// Most results cargo returns for one search
+pub const MAX_LIMIT: i64 = 100;
+
+/// Crates found by `cargo search`
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SearchResult {
+    pub crates: Vec<SearchHit>,
+    /// Matching crates beyond the limit ("... and N crates more")
+    pub more: u64,
+}
+
+/// One crate of the search results
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchHit {
+    pub name: String,
+    pub version: String,
+    pub description: Option<String>,
+}
+
+/// Parse lines like `serde = "1.0.228"    # A serialization framework`
+/// `limit` caps the number of crates kept, in case cargo printed more
+pub fn parse_search_output(output: &str, limit: Option<usize>) -> SearchResult {
+    let mut result = SearchResult::default();
+    for line in output.lines() {
+        if let Some(rest) = line.strip_prefix("... and ") {
+            if let Some(count) = rest.split(' ').next().and_then(|n| n.parse().ok()) {
+                result.more = count;
+            }
+            continue;
+        }
+        let Some((name, rest)) = line.split_once(" = \"") else {
+            continue;
+        };
+        let Some((version, rest)) = rest.split_once('"') else {
+            continue;
+        };
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            continue;
+        }
+        let description = rest
+            .trim()
+            .strip_prefix('#')
+            .map(|d| d.trim().to_string())
+            .filter(|d| !d.is_empty());
+        result.crates.push(SearchHit {
+            name: name.to_string(),
+            version: version.to_string(),
+            description,
+        });
+    }
+    if let Some(limit) = limit {
+        if result.crates.len() > limit {
+            result.more += (result.crates.len() - limit) as u64;
+            result.crates.truncate(limit);
+        }
+    }
+    result
+}
+
+/// State of a background search, as returned to Lua
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SearchStatus {
+    /// Still waiting for the registry
+    Pending,
+    /// A newer search was started before this one finished
+    Superseded,
+    Cancelled,
+    Done {
+        crates: Vec<SearchHit>,
+        more: u64,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+// The latest search, which supersedes the others
+static LATEST: Mutex<Option<u64>> = Mutex::new(None);
+
+/// Start a search as a background job, cancelling any earlier search; returns its id,
+/// which is also a job id (`jobs::status`, `jobs::cancel`)
+pub fn start(options: CommandOptions, args: Vec<String>) -> u64 {
+    let mut latest = LATEST.lock().unwrap();
+    if let Some(previous) = *latest {
+        jobs::cancel(previous);
+    }
+    let id = jobs::start("search".to_string(), args, options, false);
+    *latest = Some(id);
+    id
+}
+
+/// Stop the search with the given id; returns whether it was still running
+pub fn cancel(id: u64) -> bool {
+    jobs::cancel(id)
+}
+
+/// Current state of the search with the given id
+pub fn status(id: u64) -> SearchStatus {
+    let latest = *LATEST.lock().unwrap();
+    match jobs::status(id) {
+        _ if latest.is_some_and(|latest| id < latest) => SearchStatus::Superseded,
+        JobStatus::Running => SearchStatus::Pending,
+        JobStatus::Cancelled { .. } => SearchStatus::Cancelled,
+        JobStatus::Done { outcome } => {
+            let search = outcome.search.unwrap_or_default();
+            SearchStatus::Done {
+                crates: search.crates,
+                more: search.more,
+            }
+        }
+        JobStatus::Failed { error } => SearchStatus::Failed { error },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: &str = "\
+serde = \"1.0.228\"                    # A generic serialization/deserialization framework
+serde_json = \"1.0.145\"               # A JSON serialization file format
+serde-nothing = \"0.1.0\"
+... and 7269 crates more (use --limit N to see more)
+note: to learn more about a package, run `cargo info <name>`
+";
+
+    #[test]
+    fn test_parse_search_output() {
+        let result = parse_search_output(OUTPUT, None);
+        assert_eq!(result.crates.len(), 3);
+        assert_eq!(
+            result.crates[0],
+            SearchHit {
+                name: "serde".to_string(),
+                version: "1.0.228".to_string(),
+                description: Some("A generic serialization/deserialization framework".to_string()),
+            }
+        );
+        assert_eq!(result.crates[2].description, None);
+        assert_eq!(result.more, 7269);
+
+        let limited = parse_search_output(OUTPUT, Some(1));
+        assert_eq!(limited.crates.len(), 1);
+        assert_eq!(limited.more, 7271);
+    }
+
+    #[test]
+    fn test_cancel_search() {
+        // An unreachable registry keeps the search waiting until it is cancelled
+        let options = CommandOptions {
+            config: vec![
+                "registries.blackhole.index=\"sparse+http://10.255.255.1/\"".to_string(),
+                "http.timeout=30".to_string(),
+            ],
+            registry: Some("blackhole".to_string()),
+            ..Default::default()
+        };
+        let id = start(options.clone(), vec!["serde".to_string()]);
+        assert_eq!(status(id), SearchStatus::Pending);
+        // A newer search supersedes it
+        let newer = start(options, vec!["tokio".to_string()]);
+        assert_eq!(status(id), SearchStatus::Superseded);
+        assert!(cancel(newer));
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(20);
+        while status(newer) == SearchStatus::Pending && std::time::Instant::now() < deadline {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        assert_eq!(status(newer), SearchStatus::Cancelled);
+        assert!(!cancel(newer));
+    }
+}
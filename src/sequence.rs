@@ -0,0 +1,272 @@
+// src/sequence.rs
+use crate::events::Event;
+use crate::options::CommandOptions;
+use crate::outcome::CommandOutcome;
+use crate::quickfix::{ErrorLocation, QuickfixEntry};
+use crate::CargoCommands;
+use mlua::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// One step of a command sequence, e.g. `{ subcommand = "clippy", args = { "--all" } }`
+#[derive(Debug, Clone, Deserialize)]
+pub struct SequenceStep {
+    pub subcommand: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub opts: CommandOptions,
+}
+
+/// Result of a single step of a sequence
+#[derive(Debug, Clone, Serialize)]
+pub struct StepResult {
+    pub index: usize,
+    pub subcommand: String,
+    pub success: bool,
+    pub output: String,
+    pub interactive: bool,
+    /// `true` when the user interrupted the step; the remaining steps are skipped
+    pub interrupted: bool,
+    /// Error message when the step failed
+    pub error: Option<String>,
+}
+
+/// Result of `check_then_build`: the last stage that ran and how it went
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckThenBuild {
+    /// `check`, or `build` once the check passed and the build was requested
+    pub stage: String,
+    pub success: bool,
+    /// Diagnostics of the check (`check_fast` style)
+    pub quickfix: Vec<QuickfixEntry>,
+    /// The first error of `quickfix` in source order
+    pub first_error: Option<ErrorLocation>,
+    /// Output of the build
+    pub output: String,
+    /// `true` when the user interrupted the stage
+    pub interrupted: bool,
+    /// Error message when the stage failed without diagnostics
+    pub error: Option<String>,
+}
+
+impl CargoCommands {
+    /// Check first and build only when the check passes, so compile errors are found
+    /// without paying for a full build; with `build` unset only the check runs
+    /// The stages are reported as sequence steps (`step_start`/`step_finish` events)
+    pub async fn check_then_build(&self, args: &[&str], build: bool) -> CheckThenBuild {
+        self.emit(Event::StepStart {
+            index: 0,
+            subcommand: "check".to_string(),
+        });
+        let check = self.staged_check(args).await;
+        let mut result = match check {
+            Ok(outcome) => CheckThenBuild {
+                stage: "check".to_string(),
+                success: outcome.success,
+                first_error: outcome.first_error,
+                quickfix: outcome.quickfix,
+                output: String::new(),
+                interrupted: outcome.interrupted,
+                error: None,
+            },
+            Err(err) => CheckThenBuild {
+                stage: "check".to_string(),
+                success: false,
+                quickfix: Vec::new(),
+                first_error: None,
+                output: String::new(),
+                interrupted: false,
+                error: Some(err.to_string()),
+            },
+        };
+        self.emit(Event::StepFinish {
+            index: 0,
+            subcommand: "check".to_string(),
+            success: result.success,
+        });
+        if !result.success || !build {
+            return result;
+        }
+
+        self.emit(Event::StepStart {
+            index: 1,
+            subcommand: "build".to_string(),
+        });
+        result.stage = "build".to_string();
+        match self.run_subcommand("build", args).await {
+            Ok(outcome) => {
+                result.success = outcome.success;
+                result.output = outcome.output;
+                result.interrupted = outcome.interrupted;
+            }
+            Err(err) => {
+                result.success = false;
+                result.error = Some(err.to_string());
+            }
+        }
+        self.emit(Event::StepFinish {
+            index: 1,
+            subcommand: "build".to_string(),
+            success: result.success,
+        });
+        result
+    }
+
+    // The check stage, with the project config applied as for a `check` step
+    async fn staged_check(&self, args: &[&str]) -> LuaResult<CommandOutcome> {
+        self.with_project_config("check")?
+            .cargo_check_fast(args)
+            .await
+    }
+
+    /// Run steps in order, returning one result per executed step
+    /// When `stop_on_failure` is set, the remaining steps are skipped after the first failure;
+    /// they always are after an interrupted step
+    pub async fn run_sequence(
+        &self,
+        steps: &[SequenceStep],
+        stop_on_failure: bool,
+    ) -> Vec<StepResult> {
+        let mut results = Vec::with_capacity(steps.len());
+
+        for (index, step) in steps.iter().enumerate() {
+            self.emit(Event::StepStart {
+                index,
+                subcommand: step.subcommand.clone(),
+            });
+
+            let args: Vec<&str> = step.args.iter().map(|s| s.as_str()).collect();
+            let result = self
+                .with_options(step.opts.clone())
+                .run_subcommand(&step.subcommand, &args)
+                .await;
+
+            let step_result = match result {
+                Ok(outcome) => StepResult {
+                    index,
+                    subcommand: step.subcommand.clone(),
+                    success: !outcome.interrupted,
+                    output: outcome.output,
+                    interactive: outcome.interactive,
+                    interrupted: outcome.interrupted,
+                    error: None,
+                },
+                Err(err) => StepResult {
+                    index,
+                    subcommand: step.subcommand.clone(),
+                    success: false,
+                    output: String::new(),
+                    interactive: false,
+                    interrupted: false,
+                    error: Some(err.to_string()),
+                },
+            };
+
+            self.emit(Event::StepFinish {
+                index,
+                subcommand: step.subcommand.clone(),
+                success: step_result.success,
+            });
+
+            let failed = !step_result.success;
+            let interrupted = step_result.interrupted;
+            results.push(step_result);
+            if interrupted || (failed && stop_on_failure) {
+                break;
+            }
+        }
+
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::ScratchCrate;
+    use tokio::sync::mpsc;
+
+    fn step(subcommand: &str, args: &[&str]) -> SequenceStep {
+        SequenceStep {
+            subcommand: subcommand.to_string(),
+            args: args.iter().map(|s| s.to_string()).collect(),
+            opts: CommandOptions::default(),
+        }
+    }
+
+    #[test]
+    fn test_sequence_stops_on_failure() {
+        let cargo_commands = CargoCommands::new().unwrap();
+        let steps = vec![
+            step("help", &[]),
+            step("build", &["--invalid-flag"]),
+            step("help", &[]),
+        ];
+
+        let results = cargo_commands.execute(cargo_commands.run_sequence(&steps, true));
+        assert_eq!(results.len(), 2);
+        assert!(results[0].success);
+        assert!(!results[1].success);
+        assert!(results[1].error.is_some());
+
+        let results = cargo_commands.execute(cargo_commands.run_sequence(&steps, false));
+        assert_eq!(results.len(), 3);
+        assert!(results[2].success);
+    }
+
+    #[test]
+    fn test_check_then_build() {
+        let scratch = ScratchCrate::new(
+            "check_then_build",
+            &[("src/lib.rs", "pub fn f() -> i32 {\n    \"s\"\n}\n")],
+        );
+        let manifest = scratch.manifest_path();
+        let args = ["--manifest-path", manifest.as_str()];
+        let cargo_commands = CargoCommands::new().unwrap();
+
+        // Compile errors stop at the check, with its diagnostics
+        let result = cargo_commands.execute(cargo_commands.check_then_build(&args, true));
+        assert_eq!(result.stage, "check");
+        assert!(!result.success);
+        assert_eq!(result.quickfix[0].lnum, 2);
+
+        std::fs::write(
+            std::path::Path::new(&manifest)
+                .parent()
+                .unwrap()
+                .join("src/lib.rs"),
+            "pub fn f() -> i32 {\n    1\n}\n",
+        )
+        .unwrap();
+        let result = cargo_commands.execute(cargo_commands.check_then_build(&args, false));
+        assert_eq!(result.stage, "check");
+        assert!(result.success);
+
+        let result = cargo_commands.execute(cargo_commands.check_then_build(&args, true));
+        assert_eq!(result.stage, "build");
+        assert!(result.success, "{:?}", result.error);
+    }
+
+    #[test]
+    fn test_sequence_emits_step_boundaries() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let cargo_commands = CargoCommands::new().unwrap().with_events(tx);
+        let steps = vec![step("help", &[]), step("unknown-subcommand", &[])];
+
+        cargo_commands.execute(cargo_commands.run_sequence(&steps, false));
+        drop(cargo_commands);
+
+        let mut boundaries = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            match event {
+                Event::StepStart { index, .. } => boundaries.push(("start", index)),
+                Event::StepFinish { index, .. } => boundaries.push(("finish", index)),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            boundaries,
+            vec![("start", 0), ("finish", 0), ("start", 1), ("finish", 1)]
+        );
+    }
+}
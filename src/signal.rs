@@ -0,0 +1,208 @@
+// src/signal.rs
+//! Detecting processes killed by a signal, such as a build stopped by the OOM killer
+//!
+//! Cargo itself is rarely the one killed: when rustc or a build script dies, cargo
+//! exits normally and names the signal in its output, so both places are checked.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::process::ExitStatus;
+use std::time::Duration;
+use tokio::process::Child;
+
+/// Signal number of SIGKILL, which on Linux usually means the OOM killer
+pub const SIGKILL: i32 = 9;
+
+/// The signal that killed the process, if it didn't exit on its own
+#[cfg(unix)]
+pub fn exit_signal(status: &ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(not(unix))]
+pub fn exit_signal(_status: &ExitStatus) -> Option<i32> {
+    None
+}
+
+/// Grace period between SIGTERM and SIGKILL when a command is stopped
+pub const DEFAULT_KILL_TIMEOUT: Duration = Duration::from_millis(2000);
+
+/// Signal a command is stopped with first, before escalating
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum StopSignal {
+    /// Like Ctrl-C in a terminal, so a server's shutdown handler runs; followed by
+    /// SIGTERM if the program doesn't exit within the grace period
+    #[serde(rename = "SIGINT", alias = "sigint")]
+    Int,
+    #[serde(rename = "SIGTERM", alias = "sigterm")]
+    Term,
+}
+
+/// How a stopped command went down
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Termination {
+    /// Exited within the grace period after SIGINT or SIGTERM
+    Graceful,
+    /// Still running when the grace period ran out, so it was killed with SIGKILL
+    Forced,
+}
+
+impl fmt::Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Termination::Graceful => write!(f, "it exited gracefully"),
+            Termination::Forced => write!(f, "it was force-killed"),
+        }
+    }
+}
+
+/// Stop a child started in a process group of its own, together with everything it
+/// started (the program behind `cargo run`, rustc, build scripts): `first` (then SIGTERM,
+/// when that was SIGINT), so programs can flush files and close sockets, each with
+/// `grace` to take effect, then SIGKILL
+pub async fn terminate_process_group(
+    child: &mut Child,
+    first: StopSignal,
+    grace: Duration,
+) -> Termination {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        let group = pid as libc::pid_t;
+        let signals: &[libc::c_int] = match first {
+            StopSignal::Int => &[libc::SIGINT, libc::SIGTERM],
+            StopSignal::Term => &[libc::SIGTERM],
+        };
+        let mut exited = false;
+        for &signal in signals {
+            // SAFETY: killpg only sends a signal; the group id is the child's own pid
+            unsafe {
+                libc::killpg(group, signal);
+            }
+            if tokio::time::timeout(grace, child.wait()).await.is_ok() {
+                exited = true;
+                break;
+            }
+        }
+        // Whatever ignored the signals goes now, even when cargo itself exited
+        unsafe {
+            libc::killpg(group, libc::SIGKILL);
+        }
+        if exited {
+            return Termination::Graceful;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (first, grace);
+    child.kill().await.ok();
+    Termination::Forced
+}
+
+/// The signal cargo reports for a child that died, e.g.
+/// ``process didn't exit successfully: `rustc ...` (signal: 9, SIGKILL: kill)``
+pub fn parse_signal(output: &str) -> Option<i32> {
+    output
+        .lines()
+        .filter(|line| line.contains("didn't exit successfully: "))
+        .find_map(|line| {
+            let (_, rest) = line.rsplit_once("(signal: ")?;
+            rest.split(',').next()?.parse().ok()
+        })
+}
+
+/// Conventional name of a signal number, for the common fatal ones
+pub fn signal_name(signal: i32) -> Option<&'static str> {
+    match signal {
+        2 => Some("SIGINT"),
+        4 => Some("SIGILL"),
+        6 => Some("SIGABRT"),
+        SIGKILL => Some("SIGKILL"),
+        11 => Some("SIGSEGV"),
+        15 => Some("SIGTERM"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_signal() {
+        let output = "   Compiling big v0.1.0 (/tmp/big)\n\
+error: could not compile `big` (lib)\n\
+\n\
+Caused by:\n  process didn't exit successfully: `/usr/bin/rustc --crate-name big --edition=2021 src/lib.rs` (signal: 9, SIGKILL: kill)\n";
+        assert_eq!(parse_signal(output), Some(SIGKILL));
+        assert_eq!(
+            parse_signal(
+                "error: process didn't exit successfully: `target/debug/app` (exit status: 1)"
+            ),
+            None
+        );
+        assert_eq!(signal_name(11), Some("SIGSEGV"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exit_signal() {
+        let status = std::process::Command::new("sh")
+            .args(["-c", "kill -9 $$"])
+            .status()
+            .unwrap();
+        assert_eq!(exit_signal(&status), Some(SIGKILL));
+
+        let status = std::process::Command::new("sh")
+            .args(["-c", "exit 1"])
+            .status()
+            .unwrap();
+        assert_eq!(exit_signal(&status), None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_terminate_process_group() {
+        let spawn = |script: &str| {
+            tokio::process::Command::new("sh")
+                .args(["-c", script])
+                .process_group(0)
+                .spawn()
+                .unwrap()
+        };
+        let grace = Duration::from_millis(300);
+
+        let mut child = spawn("sleep 30");
+        assert_eq!(
+            terminate_process_group(&mut child, StopSignal::Term, grace).await,
+            Termination::Graceful
+        );
+
+        // The ignored SIGTERM is inherited by sleep, so only SIGKILL stops them
+        let mut child = spawn("trap '' TERM; sleep 30; :");
+        // Give the shell time to set up the trap
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(
+            terminate_process_group(&mut child, StopSignal::Term, grace).await,
+            Termination::Forced
+        );
+        assert!(child.try_wait().unwrap().is_some());
+
+        // SIGINT reaches a Ctrl-C handler; the background sleep ignores it and is swept
+        let mut child = spawn("trap 'exit 3' INT; sleep 30 & wait");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(
+            terminate_process_group(&mut child, StopSignal::Int, grace).await,
+            Termination::Graceful
+        );
+        assert_eq!(child.wait().await.unwrap().code(), Some(3));
+
+        // A program ignoring SIGINT still gets SIGTERM before it would be killed
+        let mut child = spawn("trap '' INT; sleep 30");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(
+            terminate_process_group(&mut child, StopSignal::Int, grace).await,
+            Termination::Graceful
+        );
+    }
+}
@@ -0,0 +1,181 @@
+// src/size.rs
+//! Sizes of the binaries and libraries a build produced, raw and gzipped, for targets
+//! where size matters (wasm, embedded)
+use crate::metadata::Metadata;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Size of one artifact
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ArtifactSize {
+    /// Path of the file
+    pub artifact: String,
+    pub bytes: u64,
+    /// Size after gzip at the default level, roughly what a web server would send
+    pub gzip_bytes: u64,
+}
+
+/// Files the bin, cdylib and staticlib targets of `packages` produce in `dir`, named
+/// the way cargo names them for `triple` (the host when `None`)
+pub fn artifact_files(
+    metadata: &Metadata,
+    packages: &[String],
+    dir: &Path,
+    triple: Option<&str>,
+) -> Vec<PathBuf> {
+    let platform = Platform::of(triple);
+    metadata
+        .packages
+        .iter()
+        .filter(|p| packages.contains(&p.name))
+        .flat_map(|p| &p.targets)
+        .flat_map(|target| {
+            target
+                .kind
+                .iter()
+                .filter_map(|kind| platform.file_name(kind, &target.name))
+        })
+        .map(|name| dir.join(name))
+        .collect()
+}
+
+/// Size of `path` and of its gzipped contents; `None` when it can't be read
+pub fn measure(path: &Path) -> Option<ArtifactSize> {
+    let contents = std::fs::read(path).ok()?;
+    let mut encoder = GzEncoder::new(ByteCounter(0), Compression::default());
+    encoder.write_all(&contents).ok()?;
+    let gzip_bytes = encoder.finish().ok()?.0;
+    Some(ArtifactSize {
+        artifact: path.to_string_lossy().into_owned(),
+        bytes: contents.len() as u64,
+        gzip_bytes,
+    })
+}
+
+// Counts what is written instead of keeping it
+struct ByteCounter(u64);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// File naming conventions of the platforms cargo builds for
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Platform {
+    Wasm,
+    WindowsMsvc,
+    WindowsGnu,
+    Apple,
+    Unix,
+}
+
+impl Platform {
+    fn of(triple: Option<&str>) -> Self {
+        match triple {
+            Some(t) if t.starts_with("wasm") => Self::Wasm,
+            Some(t) if t.contains("windows") && t.ends_with("msvc") => Self::WindowsMsvc,
+            Some(t) if t.contains("windows") => Self::WindowsGnu,
+            Some(t) if t.contains("apple") => Self::Apple,
+            Some(_) => Self::Unix,
+            None if cfg!(all(windows, target_env = "msvc")) => Self::WindowsMsvc,
+            None if cfg!(windows) => Self::WindowsGnu,
+            None if cfg!(target_vendor = "apple") => Self::Apple,
+            None => Self::Unix,
+        }
+    }
+
+    fn file_name(self, kind: &str, name: &str) -> Option<String> {
+        // Library files use the crate name, which has `_` for `-`
+        let lib = name.replace('-', "_");
+        let file = match (kind, self) {
+            ("bin", Self::Wasm) => format!("{}.wasm", name),
+            ("bin", Self::WindowsMsvc | Self::WindowsGnu) => format!("{}.exe", name),
+            ("bin", _) => name.to_string(),
+            ("cdylib", Self::Wasm) => format!("{}.wasm", lib),
+            ("cdylib", Self::WindowsMsvc | Self::WindowsGnu) => format!("{}.dll", lib),
+            ("cdylib", Self::Apple) => format!("lib{}.dylib", lib),
+            ("cdylib", _) => format!("lib{}.so", lib),
+            ("staticlib", Self::WindowsMsvc) => format!("{}.lib", lib),
+            ("staticlib", _) => format!("lib{}.a", lib),
+            _ => return None,
+        };
+        Some(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PackageInfo, TargetInfo};
+
+    #[test]
+    fn test_artifact_files() {
+        let target = |name: &str, kind: &[&str]| TargetInfo {
+            name: name.to_string(),
+            kind: kind.iter().map(|k| k.to_string()).collect(),
+            src_path: String::new(),
+            required_features: Vec::new(),
+        };
+        let metadata = Metadata {
+            packages: vec![PackageInfo {
+                name: "web-app".to_string(),
+                version: "0.1.0".to_string(),
+                manifest_path: "/ws/Cargo.toml".to_string(),
+                targets: vec![
+                    target("web-app", &["cdylib", "rlib"]),
+                    target("cli", &["bin"]),
+                    target("build-script-build", &["custom-build"]),
+                ],
+                features: Default::default(),
+                default_features: Vec::new(),
+            }],
+            workspace_members: vec!["web-app".to_string()],
+            workspace_default_members: Vec::new(),
+            workspace_root: None,
+            target_directory: None,
+        };
+        let packages = ["web-app".to_string()];
+        let files = |triple| artifact_files(&metadata, &packages, Path::new("out"), Some(triple));
+
+        assert_eq!(
+            files("wasm32-unknown-unknown"),
+            vec![
+                PathBuf::from("out/web_app.wasm"),
+                PathBuf::from("out/cli.wasm")
+            ]
+        );
+        assert_eq!(
+            files("x86_64-pc-windows-msvc"),
+            vec![
+                PathBuf::from("out/web_app.dll"),
+                PathBuf::from("out/cli.exe")
+            ]
+        );
+        assert_eq!(
+            files("aarch64-apple-darwin")[0],
+            PathBuf::from("out/libweb_app.dylib")
+        );
+        assert!(artifact_files(&metadata, &[], Path::new("out"), None).is_empty());
+    }
+
+    #[test]
+    fn test_measure() {
+        let path = std::env::temp_dir().join(format!("cargo-nvim-measure-{}", std::process::id()));
+        std::fs::write(&path, vec![b'a'; 10_000]).unwrap();
+        let size = measure(&path).unwrap();
+        assert_eq!(size.bytes, 10_000);
+        assert!(size.gzip_bytes > 0 && size.gzip_bytes < 200);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(measure(&path), None);
+    }
+}
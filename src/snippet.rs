@@ -0,0 +1,151 @@
+// src/snippet.rs
+//! Compiling a single scratch file with rustc, for trying out a snippet
+//!
+//! This doesn't involve cargo or a project: the file is handed to rustc directly and
+//! the binary is written to a temporary directory. Only the binary of the latest
+//! snippet is kept; the previous one is removed when the next is compiled.
+
+use crate::diagnostics::Diagnostic;
+use crate::error::spawn_error;
+use crate::quickfix::QuickfixEntry;
+use mlua::prelude::*;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use tokio::process::Command as TokioCommand;
+
+/// Edition used when the caller doesn't choose one
+pub const DEFAULT_EDITION: &str = "2021";
+
+// Output directory of the latest snippet, removed when the next one is compiled
+static LAST_SNIPPET_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+static SNIPPET_COUNT: AtomicU32 = AtomicU32::new(0);
+
+/// Result of compiling a snippet
+/// A snippet that doesn't compile is reported here rather than as an error
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SnippetResult {
+    pub success: bool,
+    /// Path of the compiled binary, when compilation succeeded
+    pub binary: Option<String>,
+    /// Diagnostics as rustc would print them
+    pub output: String,
+    pub quickfix: Vec<QuickfixEntry>,
+}
+
+/// Compile `file` into a binary with rustc
+/// `args` are passed to rustc (e.g. `-O`)
+pub async fn compile_snippet(
+    file: &str,
+    edition: Option<&str>,
+    args: &[&str],
+) -> LuaResult<SnippetResult> {
+    let source = Path::new(file);
+    if !source.is_file() {
+        return Err(LuaError::RuntimeError(format!("No such file: {}", file)));
+    }
+    let stem = source
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("snippet");
+
+    let dir = std::env::temp_dir()
+        .join("cargo-nvim-snippets")
+        .join(format!(
+            "{}-{}",
+            std::process::id(),
+            SNIPPET_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+    std::fs::create_dir_all(&dir).map_err(|e| {
+        LuaError::RuntimeError(format!("Failed to create {}: {}", dir.display(), e))
+    })?;
+    let binary = dir.join(format!("{}{}", stem, std::env::consts::EXE_SUFFIX));
+
+    let result = TokioCommand::new("rustc")
+        .args(["--edition", edition.unwrap_or(DEFAULT_EDITION)])
+        .arg("--error-format=json")
+        .arg("-o")
+        .arg(&binary)
+        .args(args)
+        .arg(source)
+        .output()
+        .await;
+    let result = match result {
+        Ok(result) => result,
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&dir);
+            return Err(spawn_error("rustc", e));
+        }
+    };
+
+    // rustc prints one JSON diagnostic per line on stderr
+    let diagnostics: Vec<Diagnostic> = String::from_utf8_lossy(&result.stderr)
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    let output = diagnostics
+        .iter()
+        .filter_map(|d| d.rendered.as_deref())
+        .collect();
+    let quickfix = diagnostics.iter().filter_map(|d| d.to_quickfix()).collect();
+
+    let success = result.status.success() && binary.is_file();
+    let previous = if success {
+        LAST_SNIPPET_DIR.lock().unwrap().replace(dir)
+    } else {
+        Some(dir)
+    };
+    if let Some(previous) = previous {
+        let _ = std::fs::remove_dir_all(previous);
+    }
+
+    Ok(SnippetResult {
+        success,
+        binary: success.then(|| binary.to_string_lossy().into_owned()),
+        output,
+        quickfix,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::ScratchCrate;
+
+    #[test]
+    fn test_compile_snippet() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        // Only its directory is used: the snippets are compiled with rustc alone
+        let scratch = ScratchCrate::new(
+            "snippet",
+            &[
+                ("good.rs", "fn main() {\n    println!(\"hi\");\n}\n"),
+                ("bad.rs", "fn main() {\n    let _x: i32 = \"s\";\n}\n"),
+            ],
+        );
+
+        let good = scratch.path("good.rs").to_string_lossy().into_owned();
+        let built = runtime.block_on(compile_snippet(&good, None, &[])).unwrap();
+        assert!(built.success, "{}", built.output);
+        let binary = built.binary.unwrap();
+        let run = std::process::Command::new(&binary).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&run.stdout), "hi\n");
+
+        let bad = scratch.path("bad.rs").to_string_lossy().into_owned();
+        let failed = runtime
+            .block_on(compile_snippet(&bad, Some("2024"), &[]))
+            .unwrap();
+        assert!(!failed.success);
+        assert!(failed.binary.is_none());
+        assert_eq!(failed.quickfix[0].kind, "E");
+        assert_eq!(failed.quickfix[0].lnum, 2);
+        assert!(failed.output.contains("mismatched types"));
+
+        // The latest successful snippet is kept until the next one replaces it
+        assert!(Path::new(&binary).is_file());
+        let rebuilt = runtime.block_on(compile_snippet(&good, None, &[])).unwrap();
+        assert!(!Path::new(&binary).exists());
+        assert!(Path::new(&rebuilt.binary.unwrap()).is_file());
+    }
+}
@@ -0,0 +1,912 @@
+// src/stream.rs
+//! The core every command runs on
+//!
+//! `spawn_and_stream` starts a process in a process group of its own and returns a
+//! `JobHandle` right away: its stdout and stderr are read line by line into an event
+//! stream (with the progress, download and diagnostic events parsed from them), input
+//! is forwarded to its stdin, and `kill` stops the whole group. `JobHandle::wait` is the
+//! blocking adapter: it applies the timeout and cancellation and collects the output.
+
+use crate::ansi::strip_ansi;
+use crate::cancel::CancelToken;
+use crate::diagnostics::Diagnostic;
+use crate::events::Event;
+use crate::lines::LossyLines;
+use crate::lua_exports::InputMessage;
+use crate::messages::{progress_event, CargoMessage, DownloadProgress};
+use crate::responder::AutoResponder;
+use crate::signal::{exit_signal, terminate_process_group, StopSignal, Termination};
+use crate::tree::{TreeBuilder, TreeNode};
+use std::collections::{HashSet, VecDeque};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command as TokioCommand};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+/// Interactive commands get this many times the normal timeout, as they wait on the user
+pub const INTERACTIVE_TIMEOUT_FACTOR: u32 = 3;
+
+/// Timeout of a command whose timeout is disabled: a century, which never comes
+pub const NO_TIMEOUT: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+/// Least time between two wakes of the output reader, unless set with `output_yield_ms`
+pub const DEFAULT_OUTPUT_YIELD: Duration = Duration::from_millis(5);
+
+/// How long `wait` gives the output to be read once the process has exited, in case
+/// a process it started still holds the pipes open
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How a process is run by `spawn_and_stream`
+pub struct StreamOptions {
+    /// Time the process gets; see `INTERACTIVE_TIMEOUT_FACTOR`
+    pub timeout: Duration,
+    /// Whether the process is treated as interactive from the start
+    pub interactive: bool,
+    /// Whether prompts in the output switch it to interactive
+    pub detect_interactive: bool,
+    /// Give the process a stdin pipe fed through `JobHandle::input`; `/dev/null` otherwise
+    pub stdin: bool,
+    /// Answers sent to prompts as they are read
+    pub responder: AutoResponder,
+    /// Produce the event stream; without it the output is only collected
+    pub events: bool,
+    /// Keep only error-level diagnostic events
+    pub errors_only: bool,
+    /// Keep only the diagnostic events of these packages; all when empty
+    pub only_packages: Vec<String>,
+    /// Diagnostics are published as their rendered text (on stderr) instead of JSON lines
+    pub rendered: bool,
+    /// Least time between two wakes of the output reader; lines printed meanwhile are
+    /// handled together. Zero wakes for every line
+    pub output_yield: Duration,
+    /// First signal `kill` sends, and the grace period before the next one
+    pub stop_signal: StopSignal,
+    pub kill_timeout: Duration,
+    /// Parse stdout as `cargo tree` output while it is read, publishing a `tree_node`
+    /// event for each package
+    pub tree: bool,
+    /// Keep only the last lines of output; earlier ones are dropped as new ones are read
+    pub max_output_lines: Option<usize>,
+    /// The output has colors: it is kept without its escape sequences, and as printed
+    /// in `Finished::ansi_output`
+    pub color: bool,
+}
+
+/// A running process started by `spawn_and_stream`
+pub struct JobHandle {
+    child: Child,
+    reader: JoinHandle<ReadOutput>,
+    stdin_task: JoinHandle<()>,
+    input: mpsc::UnboundedSender<InputMessage>,
+    events: Option<mpsc::UnboundedReceiver<Event>>,
+    interactive: Arc<AtomicBool>,
+    timeout: Duration,
+    stop_signal: StopSignal,
+    kill_timeout: Duration,
+}
+
+/// How a process ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Exit {
+    /// It exited on its own; `code` is its exit code, missing when a signal (`signal`)
+    /// killed it
+    Exited {
+        success: bool,
+        code: Option<i32>,
+        signal: Option<i32>,
+    },
+    /// It ran out of time and was stopped
+    TimedOut(Termination),
+    /// The cancel token fired and it was stopped
+    Cancelled(Termination),
+}
+
+/// Result of `JobHandle::wait`
+#[derive(Debug)]
+pub struct Finished {
+    /// stdout and stderr lines, interleaved as they were read
+    pub output: String,
+    /// The lines of `output` read from stdout
+    pub stdout: String,
+    /// The lines of `output` read from stderr
+    pub stderr: String,
+    /// `output` as printed, with its colors, when `color` was set
+    pub ansi_output: Option<String>,
+    /// Lines dropped from the start of `output` by `max_output_lines`
+    pub dropped_lines: usize,
+    /// The packages of a `tree` run, read up to the end (or until it was stopped)
+    pub tree: Option<Vec<TreeNode>>,
+    /// Whether the process was treated as interactive by the end
+    pub interactive: bool,
+    pub exit: Exit,
+}
+
+/// Start `cmd` with piped output in a process group of its own
+pub fn spawn_and_stream(
+    mut cmd: TokioCommand,
+    options: StreamOptions,
+) -> std::io::Result<JobHandle> {
+    cmd.stdin(if options.stdin {
+        Stdio::piped()
+    } else {
+        Stdio::null()
+    })
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped());
+    // A group of its own, so a kill also reaches the programs it started
+    #[cfg(unix)]
+    cmd.process_group(0);
+    let mut child = cmd.spawn()?;
+
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+    let stdin = child.stdin.take();
+
+    let (input, input_rx) = mpsc::unbounded_channel::<InputMessage>();
+    let stdin_task = tokio::spawn(async move {
+        if let Some(stdin) = stdin {
+            forward_input(stdin, input_rx).await;
+        }
+    });
+
+    let (events_tx, events) = match options.events {
+        true => {
+            let (tx, rx) = mpsc::unbounded_channel();
+            (Some(tx), Some(rx))
+        }
+        false => (None, None),
+    };
+    let interactive = Arc::new(AtomicBool::new(options.interactive));
+    let reader = tokio::spawn(read_output(
+        LossyLines::new(BufReader::new(stdout)),
+        LossyLines::new(BufReader::new(stderr)),
+        ReadState {
+            events: events_tx,
+            auto_input: input.clone(),
+            interactive: interactive.clone(),
+            timeout: options.timeout,
+            detect_interactive: options.detect_interactive,
+            responder: options.responder,
+            errors_only: options.errors_only,
+            only_packages: options.only_packages,
+            rendered: options.rendered,
+            output_yield: options.output_yield,
+            tree: options.tree,
+            max_output_lines: options.max_output_lines,
+            color: options.color,
+        },
+    ));
+
+    Ok(JobHandle {
+        child,
+        reader,
+        stdin_task,
+        input,
+        events,
+        interactive,
+        timeout: options.timeout,
+        stop_signal: options.stop_signal,
+        kill_timeout: options.kill_timeout,
+    })
+}
+
+impl JobHandle {
+    /// The event stream: output lines and the events parsed from them, in order; it
+    /// ends once the output has been read. `None` without `events`, or once taken
+    pub fn take_events(&mut self) -> Option<mpsc::UnboundedReceiver<Event>> {
+        self.events.take()
+    }
+
+    /// Sender for the process's standard input
+    pub fn input(&self) -> mpsc::UnboundedSender<InputMessage> {
+        self.input.clone()
+    }
+
+    /// Whether the process is treated as interactive by now
+    pub fn is_interactive(&self) -> bool {
+        self.interactive.load(Ordering::Relaxed)
+    }
+
+    /// Stop the process group: the stop signal first, escalating after the kill timeout
+    pub async fn kill(&mut self) -> Termination {
+        terminate_process_group(&mut self.child, self.stop_signal, self.kill_timeout).await
+    }
+
+    /// Wait for the process to exit, stopping it at its deadline or once `cancel`
+    /// fires, and collect its output
+    pub async fn wait(mut self, cancel: Option<&CancelToken>) -> Finished {
+        let exit = tokio::select! {
+            status = self.child.wait() => match status {
+                Ok(status) => Exit::Exited {
+                    success: status.success(),
+                    code: status.code(),
+                    signal: exit_signal(&status),
+                },
+                Err(_) => Exit::Exited {
+                    success: false,
+                    code: None,
+                    signal: None,
+                },
+            },
+            _ = wait_deadline(self.timeout, &self.interactive) => Exit::TimedOut(self.kill().await),
+            _ = wait_cancelled(cancel) => Exit::Cancelled(self.kill().await),
+        };
+
+        let read = match tokio::time::timeout(OUTPUT_DRAIN_TIMEOUT, &mut self.reader).await {
+            Ok(Ok(read)) => read,
+            _ => {
+                // Ends the event stream too
+                self.reader.abort();
+                ReadOutput::default()
+            }
+        };
+        self.stdin_task.abort();
+        Finished {
+            output: read.output,
+            stdout: read.stdout,
+            stderr: read.stderr,
+            ansi_output: read.ansi_output,
+            dropped_lines: read.dropped_lines,
+            tree: read.tree,
+            interactive: self.is_interactive(),
+            exit,
+        }
+    }
+}
+
+// What the output reader needs besides the streams
+struct ReadState {
+    events: Option<mpsc::UnboundedSender<Event>>,
+    auto_input: mpsc::UnboundedSender<InputMessage>,
+    interactive: Arc<AtomicBool>,
+    timeout: Duration,
+    detect_interactive: bool,
+    responder: AutoResponder,
+    errors_only: bool,
+    only_packages: Vec<String>,
+    rendered: bool,
+    output_yield: Duration,
+    tree: bool,
+    max_output_lines: Option<usize>,
+    color: bool,
+}
+
+// What the output reader returns
+#[derive(Default)]
+struct ReadOutput {
+    output: String,
+    stdout: String,
+    stderr: String,
+    ansi_output: Option<String>,
+    dropped_lines: usize,
+    tree: Option<Vec<TreeNode>>,
+}
+
+// Lines of output kept, up to an optional limit past which the oldest are dropped
+// Each is marked with whether it came from stderr, and with `color` has its escape
+// sequences stripped, the line as printed kept next to it
+struct OutputLines {
+    lines: VecDeque<(bool, String, Option<String>)>,
+    max_lines: Option<usize>,
+    dropped: usize,
+    color: bool,
+}
+
+impl OutputLines {
+    fn push(&mut self, stderr: bool, line: String) {
+        let entry = match self.color {
+            true => (stderr, strip_ansi(&line).into_owned(), Some(line)),
+            false => (stderr, line, None),
+        };
+        self.lines.push_back(entry);
+        if self.max_lines.is_some_and(|max| self.lines.len() > max) {
+            self.lines.pop_front();
+            self.dropped += 1;
+        }
+    }
+
+    // Both streams interleaved, then stdout and stderr alone, and both interleaved as
+    // printed with `color`
+    fn into_strings(self) -> (String, String, String, Option<String>) {
+        let (mut output, mut stdout, mut stderr) = (String::new(), String::new(), String::new());
+        let mut ansi_output = self.color.then(String::new);
+        for (is_stderr, line, printed) in self.lines {
+            let stream = if is_stderr { &mut stderr } else { &mut stdout };
+            for text in [&mut output, stream] {
+                text.push_str(&line);
+                text.push('\n');
+            }
+            if let (Some(ansi_output), Some(printed)) = (&mut ansi_output, printed) {
+                ansi_output.push_str(&printed);
+                ansi_output.push('\n');
+            }
+        }
+        (output, stdout, stderr, ansi_output)
+    }
+}
+
+// A line that asks for input, whichever stream it is printed on; given without colors
+fn is_prompt(line: &str) -> bool {
+    line.contains("? [Y/n]") || line.contains("Enter password:") || line.ends_with("? ")
+}
+
+// The output reader's progress
+struct Reader {
+    state: ReadState,
+    output: OutputLines,
+    tree: Option<TreeBuilder>,
+    seen_diagnostics: HashSet<Diagnostic>,
+    downloads: DownloadProgress,
+    is_interactive: bool,
+}
+
+impl Reader {
+    fn emit(&self, event: Event) {
+        if let Some(events) = &self.state.events {
+            // The receiver may be gone; events are best-effort
+            let _ = events.send(event);
+        }
+    }
+
+    // Lines are matched without their colors, and published and kept as printed
+    fn stdout_line(&mut self, line: String) {
+        let plain = strip_ansi(&line);
+        let message = CargoMessage::parse(&plain);
+        // Detect interactive mode based on specific patterns; cargo's
+        // JSON messages (whose rendered text has `--> `) are no prompts
+        if message.is_none()
+            && (is_prompt(&plain)
+                || plain.contains("> ")
+                || plain.contains("[1/3]")
+                // Empty line may indicate interactive mode
+                || plain.trim().is_empty())
+        {
+            self.detected_interactive();
+        }
+
+        // Answer known prompts without waiting for the user
+        if let Some(response) = self.state.responder.respond(&plain) {
+            let _ = self
+                .state
+                .auto_input
+                .send(InputMessage::Data(response.into_bytes()));
+        }
+
+        if let Some(tree) = &mut self.tree {
+            if let Some((depth, node)) = tree.push_line(&plain) {
+                self.emit(Event::TreeNode { depth, node });
+            }
+        }
+        if self.state.events.is_none() {
+            self.output.push(false, line);
+            return;
+        }
+        let mut display = Vec::new();
+        // JSON messages may carry events of their own (e.g. new executables)
+        if let Some(event) = message.as_ref().and_then(|m| m.to_event()) {
+            // With several targets rustc reports the same diagnostic once per target
+            if let Event::Diagnostic { diagnostic, .. } = &event {
+                let of_packages = match &message {
+                    Some(CargoMessage::CompilerMessage(message)) => {
+                        message.of_packages(&self.state.only_packages)
+                    }
+                    _ => true,
+                };
+                if of_packages
+                    && (!self.state.errors_only || diagnostic.is_error())
+                    && self.seen_diagnostics.insert(diagnostic.clone())
+                {
+                    if let Some(CargoMessage::CompilerMessage(message)) = &message {
+                        display.extend(message.rendered_lines().map(str::to_string));
+                    }
+                    self.emit(event);
+                }
+            } else {
+                self.emit(event);
+            }
+        }
+        if self.state.rendered && message.is_some() {
+            // Where cargo prints diagnostics without JSON
+            for line in display {
+                self.emit(Event::Stderr { line });
+            }
+        } else {
+            self.emit(Event::Stdout { line: line.clone() });
+        }
+        self.output.push(false, line);
+    }
+
+    fn stderr_line(&mut self, line: String) {
+        let plain = strip_ansi(&line);
+        // Only unmistakable prompts: cargo's own lines and rustc's diagnostics (with
+        // their `--> ` and blank lines) go to stderr too
+        if is_prompt(&plain) {
+            self.detected_interactive();
+        }
+        if let Some(response) = self.state.responder.respond(&plain) {
+            let _ = self
+                .state
+                .auto_input
+                .send(InputMessage::Data(response.into_bytes()));
+        }
+
+        if self.state.events.is_some() {
+            if let Some(event) = progress_event(&plain).or_else(|| self.downloads.update(&plain)) {
+                self.emit(event);
+            }
+            self.emit(Event::Stderr { line: line.clone() });
+        }
+        self.output.push(true, line);
+    }
+
+    fn detected_interactive(&mut self) {
+        if !self.is_interactive && self.state.detect_interactive {
+            self.is_interactive = true;
+            self.state.interactive.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn finish(self) -> ReadOutput {
+        let dropped_lines = self.output.dropped;
+        let (output, stdout, stderr, ansi_output) = self.output.into_strings();
+        ReadOutput {
+            dropped_lines,
+            output,
+            stdout,
+            stderr,
+            ansi_output,
+            tree: self.tree.and_then(TreeBuilder::finish),
+        }
+    }
+
+    // Time the process gets as of now: interactive ones get the extended timeout
+    fn time_limit(&self) -> Duration {
+        if self.is_interactive {
+            self.state.timeout * INTERACTIVE_TIMEOUT_FACTOR
+        } else {
+            self.state.timeout
+        }
+    }
+}
+
+// Read both streams until they close or the process runs out of time, returning the
+// output; the lines are published as they are read
+//
+// The loop only wakes for output or the deadline. Each wake handles every line already
+// read, then sleeps out the rest of `output_yield`, so a process printing line by line
+// is read in batches instead of keeping a thread busy
+async fn read_output<O, E>(
+    mut stdout: LossyLines<O>,
+    mut stderr: LossyLines<E>,
+    state: ReadState,
+) -> ReadOutput
+where
+    O: tokio::io::AsyncBufRead + Unpin,
+    E: tokio::io::AsyncBufRead + Unpin,
+{
+    let start_time = Instant::now();
+    let mut reader = Reader {
+        is_interactive: state.interactive.load(Ordering::Relaxed),
+        output: OutputLines {
+            lines: VecDeque::new(),
+            max_lines: state.max_output_lines,
+            dropped: 0,
+            color: state.color,
+        },
+        tree: state.tree.then(TreeBuilder::default),
+        state,
+        seen_diagnostics: HashSet::new(),
+        downloads: DownloadProgress::default(),
+    };
+    let mut time_limit = reader.time_limit();
+    let deadline = tokio::time::sleep_until(start_time + time_limit);
+    tokio::pin!(deadline);
+
+    // Output reading loop, until both streams are closed
+    let mut stdout_open = true;
+    let mut stderr_open = true;
+    while stdout_open || stderr_open {
+        let woke = Instant::now();
+        tokio::select! {
+            line = stdout.next_line(), if stdout_open => match line {
+                Ok(Some(line)) => reader.stdout_line(line),
+                // EOF; stderr may still have lines to read
+                Ok(None) | Err(_) => stdout_open = false,
+            },
+            line = stderr.next_line(), if stderr_open => match line {
+                Ok(Some(line)) => reader.stderr_line(line),
+                Ok(None) | Err(_) => stderr_open = false,
+            },
+            _ = &mut deadline => return reader.finish(),
+        }
+
+        // Lines that are ready already are handled in the same wake
+        loop {
+            tokio::select! {
+                biased;
+                line = stdout.next_line(), if stdout_open => match line {
+                    Ok(Some(line)) => reader.stdout_line(line),
+                    Ok(None) | Err(_) => stdout_open = false,
+                },
+                line = stderr.next_line(), if stderr_open => match line {
+                    Ok(Some(line)) => reader.stderr_line(line),
+                    Ok(None) | Err(_) => stderr_open = false,
+                },
+                _ = std::future::ready(()) => break,
+            }
+        }
+
+        // A prompt extends the deadline
+        if reader.time_limit() != time_limit {
+            time_limit = reader.time_limit();
+            deadline.as_mut().reset(start_time + time_limit);
+        }
+        if start_time.elapsed() >= time_limit {
+            return reader.finish();
+        }
+
+        if (stdout_open || stderr_open) && !reader.state.output_yield.is_zero() {
+            tokio::time::sleep_until(woke + reader.state.output_yield).await;
+        }
+    }
+
+    reader.finish()
+}
+
+// Resolves when a process has run out of time: after `timeout`, or the extended
+// timeout when it is interactive by then
+async fn wait_deadline(timeout: Duration, interactive: &AtomicBool) {
+    tokio::time::sleep(timeout).await;
+    if interactive.load(Ordering::Relaxed) {
+        tokio::time::sleep(timeout * (INTERACTIVE_TIMEOUT_FACTOR - 1)).await;
+    }
+}
+
+/// Resolves when the token is cancelled; never without a token
+pub async fn wait_cancelled(cancel: Option<&CancelToken>) {
+    match cancel {
+        Some(cancel) => cancel.cancelled().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Write queued input to the child's stdin until EOF is requested or the channel closes
+/// Dropping the writer closes the pipe, which the program observes as end-of-file
+///
+/// The queue is unbounded, so input sent faster than the program reads it (a pasted
+/// block of lines) waits here rather than being dropped; whatever is queued by the
+/// time a write starts goes out in one write, in order.
+async fn forward_input<W: AsyncWrite + Unpin>(
+    mut stdin: W,
+    mut rx: mpsc::UnboundedReceiver<InputMessage>,
+) {
+    while let Some(message) = rx.recv().await {
+        let mut eof = false;
+        let mut data = match message {
+            InputMessage::Data(data) => data,
+            InputMessage::Eof => break,
+        };
+        while let Ok(message) = rx.try_recv() {
+            match message {
+                InputMessage::Data(more) => data.extend(more),
+                InputMessage::Eof => {
+                    eof = true;
+                    break;
+                }
+            }
+        }
+        match stdin.write_all(&data).await {
+            Ok(_) => {
+                if let Err(e) = stdin.flush().await {
+                    eprintln!("Failed to flush stdin: {}", e);
+                    break;
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to write to stdin: {}", e);
+                break;
+            }
+        }
+        if eof {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn options(timeout: Duration) -> StreamOptions {
+        StreamOptions {
+            timeout,
+            interactive: false,
+            detect_interactive: false,
+            stdin: true,
+            responder: AutoResponder::new(&BTreeMap::new()).unwrap(),
+            events: true,
+            errors_only: false,
+            only_packages: Vec::new(),
+            rendered: false,
+            output_yield: DEFAULT_OUTPUT_YIELD,
+            stop_signal: StopSignal::Term,
+            kill_timeout: Duration::from_millis(500),
+            tree: false,
+            max_output_lines: None,
+            color: false,
+        }
+    }
+
+    fn sh(script: &str) -> TokioCommand {
+        let mut cmd = TokioCommand::new("sh");
+        cmd.args(["-c", script]);
+        cmd
+    }
+
+    #[test]
+    fn test_forward_input_bytes_and_eof() {
+        use tokio::io::AsyncReadExt;
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let (writer, mut reader) = tokio::io::duplex(64);
+            let (tx, rx) = mpsc::unbounded_channel();
+            let forwarder = tokio::spawn(forward_input(writer, rx));
+
+            tx.send(InputMessage::Data(b"ab\x03".to_vec())).unwrap();
+            tx.send(InputMessage::Data(b"c\n".to_vec())).unwrap();
+            tx.send(InputMessage::Eof).unwrap();
+            // Not written: it comes after the EOF
+            tx.send(InputMessage::Data(b"late".to_vec())).unwrap();
+            forwarder.await.unwrap();
+
+            // read_to_end only returns once the writer has been dropped (EOF)
+            let mut received = Vec::new();
+            reader.read_to_end(&mut received).await.unwrap();
+            assert_eq!(received, b"ab\x03c\n");
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_stream_events_input_and_exit() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let script = "read name; echo \"hi $name\"; echo '   Compiling app v0.1.0' >&2; exit 3";
+            let mut handle =
+                spawn_and_stream(sh(script), options(Duration::from_secs(30))).unwrap();
+            let mut events = handle.take_events().unwrap();
+            assert!(handle.take_events().is_none());
+            handle
+                .input()
+                .send(InputMessage::Data(b"cargo\n".to_vec()))
+                .unwrap();
+
+            let finished = handle.wait(None).await;
+            assert_eq!(
+                finished.exit,
+                Exit::Exited {
+                    success: false,
+                    code: Some(3),
+                    signal: None
+                }
+            );
+            assert!(
+                finished.output.contains("hi cargo\n"),
+                "{}",
+                finished.output
+            );
+
+            let mut received = Vec::new();
+            while let Some(event) = events.recv().await {
+                received.push(event);
+            }
+            assert!(received.contains(&Event::Stdout {
+                line: "hi cargo".to_string()
+            }));
+            assert!(received
+                .iter()
+                .any(|event| matches!(event, Event::Progress { package, .. } if package == "app")));
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_streams_are_kept_apart() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let detecting = || StreamOptions {
+                detect_interactive: true,
+                ..options(Duration::from_secs(30))
+            };
+            let script = "echo '   Compiling app v0.1.0' >&2; echo 'error --> here' >&2; \
+                          echo result; printf 'Your guess? \\n' >&2";
+            let finished = spawn_and_stream(sh(script), detecting())
+                .unwrap()
+                .wait(None)
+                .await;
+            assert_eq!(finished.stdout, "result\n");
+            assert_eq!(
+                finished.stderr,
+                "   Compiling app v0.1.0\nerror --> here\nYour guess? \n"
+            );
+            assert_eq!(finished.output.lines().count(), 4);
+            // A prompt on stderr counts; rustc's `--> ` there doesn't
+            assert!(finished.interactive);
+
+            let finished = spawn_and_stream(sh("echo 'error --> here' >&2"), detecting())
+                .unwrap()
+                .wait(None)
+                .await;
+            assert!(!finished.interactive);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_colors_are_kept_apart() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let colored = StreamOptions {
+                detect_interactive: true,
+                color: true,
+                ..options(Duration::from_secs(30))
+            };
+            let script = "printf '\\033[1m\\033[32m   Compiling\\033[0m app v0.1.0\\n' >&2; \
+                          printf 'Proceed? [Y/n\\033[0m]\\n' >&2";
+            let mut handle = spawn_and_stream(sh(script), colored).unwrap();
+            let mut events = handle.take_events().unwrap();
+            let finished = handle.wait(None).await;
+            assert_eq!(finished.output, "   Compiling app v0.1.0\nProceed? [Y/n]\n");
+            assert_eq!(
+                finished.ansi_output.as_deref(),
+                Some("\x1b[1m\x1b[32m   Compiling\x1b[0m app v0.1.0\nProceed? [Y/n\x1b[0m]\n")
+            );
+            // The prompt is found through the escape sequence in it
+            assert!(finished.interactive);
+
+            let mut received = Vec::new();
+            while let Some(event) = events.recv().await {
+                received.push(event);
+            }
+            assert!(received
+                .iter()
+                .any(|event| matches!(event, Event::Progress { package, .. } if package == "app")));
+            assert!(received.contains(&Event::Stderr {
+                line: "\x1b[1m\x1b[32m   Compiling\x1b[0m app v0.1.0".to_string()
+            }));
+
+            let finished = spawn_and_stream(sh(script), options(Duration::from_secs(30)))
+                .unwrap()
+                .wait(None)
+                .await;
+            assert_eq!(finished.ansi_output, None);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_pasted_input_is_written_in_order() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            // Sent all at once, before the program reads any of it
+            let handle = spawn_and_stream(
+                sh("sleep 0.2; cat | tail -n 2; echo done"),
+                options(Duration::from_secs(30)),
+            )
+            .unwrap();
+            let input = handle.input();
+            for i in 1..=2000 {
+                input
+                    .send(InputMessage::Data(format!("line {}\n", i).into_bytes()))
+                    .unwrap();
+            }
+            input.send(InputMessage::Eof).unwrap();
+
+            let finished = handle.wait(None).await;
+            assert_eq!(finished.output, "line 1999\nline 2000\ndone\n");
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_lines_read_in_batches_are_all_kept() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            for output_yield in [Duration::ZERO, Duration::from_millis(20)] {
+                let mut options = options(Duration::from_secs(30));
+                options.output_yield = output_yield;
+                let mut handle =
+                    spawn_and_stream(sh("seq 1 5000; seq 1 5000 >&2"), options).unwrap();
+                let mut events = handle.take_events().unwrap();
+                let finished = handle.wait(None).await;
+                assert_eq!(finished.output.lines().count(), 10_000);
+                let mut stdout = Vec::new();
+                while let Some(event) = events.recv().await {
+                    if let Event::Stdout { line } = event {
+                        stdout.push(line);
+                    }
+                }
+                // In order, within a stream
+                let expected: Vec<String> = (1..=5000).map(|i| i.to_string()).collect();
+                assert_eq!(stdout, expected);
+            }
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tree_is_parsed_past_the_output_kept() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let mut options = options(Duration::from_secs(30));
+            options.tree = true;
+            options.max_output_lines = Some(2);
+            let script = "echo '    Locking 3 packages' >&2; echo 'app v0.1.0 (/tmp/app)'; \
+                          echo '├── serde v1.0.229'; echo '└── log v0.4.29'";
+            let mut handle = spawn_and_stream(sh(script), options).unwrap();
+            let mut events = handle.take_events().unwrap();
+            let finished = handle.wait(None).await;
+
+            assert_eq!(finished.dropped_lines, 2);
+            assert_eq!(finished.output.lines().count(), 2);
+            // The oldest go first; stderr may be read before or after stdout
+            assert!(finished.output.contains("└── log v0.4.29\n"));
+            assert!(!finished.output.contains("app v0.1.0"));
+            let roots = finished.tree.unwrap();
+            assert_eq!(roots[0].children.len(), 2);
+
+            let mut nodes = Vec::new();
+            while let Some(event) = events.recv().await {
+                if let Event::TreeNode { depth, node } = event {
+                    nodes.push((depth, node.name));
+                }
+            }
+            assert_eq!(
+                nodes,
+                vec![
+                    (0, "app".to_string()),
+                    (1, "serde".to_string()),
+                    (1, "log".to_string())
+                ]
+            );
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_timeout_and_cancel_stop_the_process() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let handle = spawn_and_stream(
+                sh("echo started; sleep 30"),
+                options(Duration::from_millis(300)),
+            )
+            .unwrap();
+            let finished = handle.wait(None).await;
+            assert!(
+                matches!(finished.exit, Exit::TimedOut(_)),
+                "{:?}",
+                finished.exit
+            );
+            assert_eq!(finished.output, "started\n");
+
+            let cancel = CancelToken::default();
+            let handle =
+                spawn_and_stream(sh("sleep 30"), options(Duration::from_secs(30))).unwrap();
+            let canceller = cancel.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                canceller.cancel();
+            });
+            let finished = handle.wait(Some(&cancel)).await;
+            assert_eq!(finished.exit, Exit::Cancelled(Termination::Graceful));
+        });
+    }
+}
@@ -0,0 +1,383 @@
+// src/test_report.rs
+//! Test results as a JUnit-shaped report, for exporting to other tooling
+//!
+//! cargo-nextest writes a JUnit file of its own, which is read back when it's installed;
+//! otherwise libtest's plain output is parsed, which has no per-test durations.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+
+/// Structured report requested with the `format` option of test
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestFormat {
+    Junit,
+}
+
+/// Every suite of a test run, with totals
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TestReport {
+    /// Where the results come from: `nextest` (its JUnit file) or `libtest` (the output)
+    pub source: String,
+    pub suites: Vec<TestSuite>,
+    pub tests: usize,
+    pub failures: usize,
+    pub skipped: usize,
+    /// Seconds, summed over the suites
+    pub time: f64,
+    /// What the report lacks with the tooling available, e.g. per-test times
+    pub limitations: Vec<String>,
+    /// The report as JUnit XML
+    pub junit: String,
+}
+
+/// One test binary: a crate's unit tests, an integration test file or its doctests
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TestSuite {
+    /// e.g. `unittests src/lib.rs`, `tests/api.rs` or `Doc-tests my_crate`, as cargo
+    /// names them; nextest's binary id with nextest
+    pub name: String,
+    pub tests: usize,
+    pub failures: usize,
+    pub skipped: usize,
+    pub time: f64,
+    pub cases: Vec<TestCase>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TestCase {
+    pub name: String,
+    /// `passed`, `failed` or `skipped`
+    pub status: String,
+    /// Seconds; only known with nextest
+    pub time: Option<f64>,
+    /// Failure output (the panic and what the test printed), for failed tests
+    pub message: Option<String>,
+}
+
+impl TestCase {
+    fn new(name: &str, status: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            status: status.to_string(),
+            time: None,
+            message: None,
+        }
+    }
+}
+
+impl TestSuite {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            tests: 0,
+            failures: 0,
+            skipped: 0,
+            time: 0.0,
+            cases: Vec::new(),
+        }
+    }
+
+    // Counts from the cases
+    fn tally(mut self) -> Self {
+        self.tests = self.cases.len();
+        self.failures = self.cases.iter().filter(|c| c.status == "failed").count();
+        self.skipped = self.cases.iter().filter(|c| c.status == "skipped").count();
+        self
+    }
+}
+
+impl TestReport {
+    pub fn new(source: &str, suites: Vec<TestSuite>, limitations: Vec<String>) -> Self {
+        let suites: Vec<TestSuite> = suites.into_iter().map(TestSuite::tally).collect();
+        let mut report = Self {
+            source: source.to_string(),
+            tests: suites.iter().map(|s| s.tests).sum(),
+            failures: suites.iter().map(|s| s.failures).sum(),
+            skipped: suites.iter().map(|s| s.skipped).sum(),
+            time: suites.iter().map(|s| s.time).sum(),
+            suites,
+            limitations,
+            junit: String::new(),
+        };
+        report.junit = report.to_junit();
+        report
+    }
+
+    fn to_junit(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(&format!(
+            "<testsuites name=\"cargo test\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n",
+            self.tests, self.failures, self.skipped, self.time
+        ));
+        for suite in &self.suites {
+            xml.push_str(&format!(
+                "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n",
+                escape(&suite.name),
+                suite.tests,
+                suite.failures,
+                suite.skipped,
+                suite.time
+            ));
+            for case in &suite.cases {
+                let time = case
+                    .time
+                    .map(|t| format!(" time=\"{:.3}\"", t))
+                    .unwrap_or_default();
+                xml.push_str(&format!(
+                    "    <testcase name=\"{}\" classname=\"{}\"{}",
+                    escape(&case.name),
+                    escape(&suite.name),
+                    time
+                ));
+                match case.status.as_str() {
+                    "failed" => {
+                        let message = case.message.as_deref().unwrap_or_default();
+                        let first_line = message.lines().next().unwrap_or("test failed");
+                        xml.push_str(&format!(
+                            ">\n      <failure message=\"{}\">{}</failure>\n    </testcase>\n",
+                            escape(first_line),
+                            escape(message)
+                        ));
+                    }
+                    "skipped" => xml.push_str(">\n      <skipped/>\n    </testcase>\n"),
+                    _ => xml.push_str("/>\n"),
+                }
+            }
+            xml.push_str("  </testsuite>\n");
+        }
+        xml.push_str("</testsuites>\n");
+        xml
+    }
+}
+
+/// Suites from libtest's output: `Running ...`/`Doc-tests ...` headers, `test <name> ... ok`
+/// lines, the `---- <name> stdout ----` failure sections and each `test result:` line
+pub fn parse_libtest(output: &str) -> Vec<TestSuite> {
+    let mut suites: Vec<TestSuite> = Vec::new();
+    let mut failure: Option<(String, Vec<&str>)> = None;
+    let flush = |suites: &mut Vec<TestSuite>, failure: &mut Option<(String, Vec<&str>)>| {
+        if let Some((name, body)) = failure.take() {
+            let case = suites
+                .last_mut()
+                .and_then(|s| s.cases.iter_mut().find(|c| c.name == name));
+            if let Some(case) = case {
+                case.message = Some(body.join("\n").trim().to_string());
+            }
+        }
+    };
+
+    for line in output.lines() {
+        let trimmed = line.trim();
+        if let Some(header) = trimmed.strip_prefix("Running ") {
+            flush(&mut suites, &mut failure);
+            // Drop the binary path: `unittests src/lib.rs (target/debug/deps/app-1a2b)`
+            let name = match header.rfind(" (") {
+                Some(at) if header.ends_with(')') => &header[..at],
+                _ => header,
+            };
+            suites.push(TestSuite::new(name));
+        } else if trimmed.starts_with("Doc-tests ") {
+            flush(&mut suites, &mut failure);
+            suites.push(TestSuite::new(trimmed));
+        } else if let Some(name) = line
+            .strip_prefix("---- ")
+            .and_then(|l| l.strip_suffix(" stdout ----"))
+        {
+            flush(&mut suites, &mut failure);
+            failure = Some((name.to_string(), Vec::new()));
+        } else if line == "failures:" {
+            flush(&mut suites, &mut failure);
+        } else if let Some((_, body)) = failure.as_mut() {
+            body.push(line);
+        } else if let Some(result) = line.strip_prefix("test result: ") {
+            let time = result
+                .rsplit_once("finished in ")
+                .and_then(|(_, t)| t.trim().strip_suffix('s')?.parse().ok());
+            if let (Some(suite), Some(time)) = (suites.last_mut(), time) {
+                suite.time = time;
+            }
+        } else if let Some((name, status)) = line
+            .strip_prefix("test ")
+            .and_then(|rest| rest.rsplit_once(" ... "))
+        {
+            let status = match status.trim() {
+                "ok" => "passed",
+                "FAILED" => "failed",
+                s if s.starts_with("ignored") => "skipped",
+                _ => continue,
+            };
+            if suites.is_empty() {
+                suites.push(TestSuite::new("tests"));
+            }
+            if let Some(suite) = suites.last_mut() {
+                suite.cases.push(TestCase::new(name, status));
+            }
+        }
+    }
+    flush(&mut suites, &mut failure);
+    suites.retain(|s| !s.cases.is_empty());
+    suites
+}
+
+static SUITE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?s)<testsuite\b([^>]*?)(?:/>|>(.*?)</testsuite>)"#).unwrap());
+static CASE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?s)<testcase\b([^>]*?)(?:/>|>(.*?)</testcase>)"#).unwrap());
+static FAILURE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?s)<(?:failure|error)\b([^>]*?)(?:/>|>(.*?)</(?:failure|error)>)"#).unwrap()
+});
+static SYSTEM_ERR: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?s)<system-err>(.*?)</system-err>"#).unwrap());
+static ATTRIBUTE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"([\w-]+)="([^"]*)""#).unwrap());
+
+/// Suites from a JUnit file such as the one nextest writes
+pub fn parse_junit(xml: &str) -> Vec<TestSuite> {
+    SUITE
+        .captures_iter(xml)
+        .map(|suite| {
+            let attrs = &suite[1];
+            let body = suite.get(2).map_or("", |m| m.as_str());
+            let mut result = TestSuite::new(&attribute(attrs, "name").unwrap_or_default());
+            result.cases = CASE
+                .captures_iter(body)
+                .map(|case| {
+                    let attrs = &case[1];
+                    let body = case.get(2).map_or("", |m| m.as_str());
+                    let name = attribute(attrs, "name").unwrap_or_default();
+                    let mut result = TestCase::new(&name, "passed");
+                    result.time = attribute(attrs, "time").and_then(|t| t.parse().ok());
+                    if let Some(failure) = FAILURE.captures(body) {
+                        result.status = "failed".to_string();
+                        let text = failure.get(2).map(|m| unescape(m.as_str()));
+                        let stderr = SYSTEM_ERR.captures(body).map(|c| unescape(&c[1]));
+                        let message = [attribute(&failure[1], "message"), text.or(stderr)]
+                            .into_iter()
+                            .flatten()
+                            .filter(|part| !part.trim().is_empty())
+                            .collect::<Vec<_>>()
+                            .join("\n");
+                        result.message = Some(message);
+                    } else if body.contains("<skipped") {
+                        result.status = "skipped".to_string();
+                    }
+                    result
+                })
+                .collect();
+            result.time = attribute(attrs, "time")
+                .and_then(|t| t.parse().ok())
+                .unwrap_or_else(|| result.cases.iter().filter_map(|c| c.time).sum());
+            result
+        })
+        .collect()
+}
+
+fn attribute(attrs: &str, name: &str) -> Option<String> {
+    ATTRIBUTE
+        .captures_iter(attrs)
+        .find(|c| &c[1] == name)
+        .map(|c| unescape(&c[2]))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn unescape(text: &str) -> String {
+    let text = text
+        .trim()
+        .trim_start_matches("<![CDATA[")
+        .trim_end_matches("]]>");
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&#10;", "\n")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIBTEST: &str = "\
+   Compiling app v0.1.0 (/tmp/app)
+    Finished `test` profile [unoptimized + debuginfo] target(s) in 0.50s
+     Running unittests src/lib.rs (target/debug/deps/app-1a2b3c)
+
+running 3 tests
+test tests::adds ... ok
+test tests::slow ... ignored, takes a minute
+test tests::breaks ... FAILED
+
+failures:
+
+---- tests::breaks stdout ----
+
+thread 'tests::breaks' panicked at src/lib.rs:12:9:
+assertion `left == right` failed
+
+failures:
+    tests::breaks
+
+test result: FAILED. 1 passed; 1 failed; 1 ignored; 0 measured; 0 filtered out; finished in 0.25s
+
+error: test failed, to rerun pass `--lib`
+";
+
+    #[test]
+    fn test_parse_libtest() {
+        let report = TestReport::new("libtest", parse_libtest(LIBTEST), Vec::new());
+        assert_eq!(report.suites.len(), 1);
+        let suite = &report.suites[0];
+        assert_eq!(suite.name, "unittests src/lib.rs");
+        assert_eq!((suite.tests, suite.failures, suite.skipped), (3, 1, 1));
+        assert_eq!(suite.time, 0.25);
+        assert_eq!(suite.cases[1].status, "skipped");
+        let failed = &suite.cases[2];
+        assert_eq!(failed.name, "tests::breaks");
+        assert!(failed
+            .message
+            .as_deref()
+            .unwrap()
+            .starts_with("thread 'tests::breaks' panicked"));
+        assert_eq!(report.failures, 1);
+
+        assert!(report.junit.contains(
+            "<testcase name=\"tests::breaks\" classname=\"unittests src/lib.rs\">\n      \
+             <failure message=\"thread 'tests::breaks' panicked at src/lib.rs:12:9:\">"
+        ));
+        assert!(report.junit.contains("<skipped/>"));
+    }
+
+    #[test]
+    fn test_parse_junit() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="nextest-run" tests="2" failures="1" errors="0" time="0.042">
+    <testsuite name="app" tests="2" disabled="0" errors="0" failures="1" time="0.040">
+        <testcase name="tests::adds" classname="app" time="0.001">
+        </testcase>
+        <testcase name="tests::breaks" classname="app" time="0.039">
+            <failure message="thread &apos;tests::breaks&apos; panicked at src/lib.rs:12:9" type="test failure"/>
+            <system-err>left: 1 &lt; right</system-err>
+        </testcase>
+    </testsuite>
+</testsuites>
+"#;
+        let report = TestReport::new("nextest", parse_junit(xml), Vec::new());
+        assert_eq!(report.tests, 2);
+        assert_eq!(report.time, 0.04);
+        let cases = &report.suites[0].cases;
+        assert_eq!(cases[0].status, "passed");
+        assert_eq!(cases[0].time, Some(0.001));
+        assert_eq!(cases[1].status, "failed");
+        assert_eq!(
+            cases[1].message.as_deref(),
+            Some("thread 'tests::breaks' panicked at src/lib.rs:12:9\nleft: 1 < right")
+        );
+    }
+}
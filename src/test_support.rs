@@ -0,0 +1,46 @@
+// src/test_support.rs
+//! Helpers shared by tests that need a real crate to run cargo against
+use std::path::PathBuf;
+
+/// A throwaway crate in the temp directory, removed on drop
+pub struct ScratchCrate {
+    dir: PathBuf,
+}
+
+impl ScratchCrate {
+    /// Create a crate named `name` with the given source files (e.g. `src/lib.rs`)
+    pub fn new(name: &str, files: &[(&str, &str)]) -> Self {
+        let dir = std::env::temp_dir().join(format!("cargo-nvim-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        // An empty workspace table keeps cargo from looking for a parent workspace
+        std::fs::write(
+            dir.join("Cargo.toml"),
+            format!(
+                "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[workspace]\n",
+                name
+            ),
+        )
+        .unwrap();
+        for (path, contents) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        Self { dir }
+    }
+
+    /// A file of the crate, e.g. `src/lib.rs`
+    pub fn path(&self, file: &str) -> PathBuf {
+        self.dir.join(file)
+    }
+
+    pub fn manifest_path(&self) -> String {
+        self.dir.join("Cargo.toml").to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for ScratchCrate {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
@@ -0,0 +1,164 @@
+// src/timings.rs
+//! Build timing reports (`cargo build --timings`)
+//!
+//! Current cargo only accepts a bare `--timings` and writes an HTML report; the
+//! `--timings=json,html` form was an unstable, since removed, variant. The report embeds
+//! the per-unit data as a JSON array, which is where durations are read from.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// First cargo release with `--timings` on stable
+pub const MIN_CARGO_VERSION: (u32, u32) = (1, 60);
+
+/// Where the timing report was written and what took longest
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TimingReport {
+    /// Path of the HTML report, when it could be found
+    pub html_path: Option<String>,
+    /// Build time per crate, slowest first
+    pub crates: Vec<CrateTiming>,
+}
+
+/// Time spent building one crate, summed over its units (lib, build script, ...)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CrateTiming {
+    #[serde(rename = "crate")]
+    pub name: String,
+    pub duration_ms: u64,
+}
+
+// An entry of the report's `UNIT_DATA` array
+#[derive(Deserialize)]
+struct Unit {
+    name: String,
+    duration: f64,
+}
+
+impl TimingReport {
+    /// Locate the report of a build and read its durations
+    /// Cargo names the report in its output; `target_dir` is the fallback
+    pub fn from_build(output: &str, target_dir: &Path) -> Self {
+        let html_path = output
+            .lines()
+            .find_map(|l| l.trim().strip_prefix("Timing report saved to "))
+            .map(|p| p.trim().to_string())
+            .or_else(|| {
+                let path = target_dir.join("cargo-timings").join("cargo-timing.html");
+                path.is_file().then(|| path.to_string_lossy().into_owned())
+            });
+        let crates = html_path
+            .as_deref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .map(|html| parse_unit_data(&html))
+            .unwrap_or_default();
+        Self { html_path, crates }
+    }
+}
+
+/// Per-crate durations from the `const UNIT_DATA = [...]` of an HTML report
+pub fn parse_unit_data(html: &str) -> Vec<CrateTiming> {
+    let Some((_, data)) = html.split_once("const UNIT_DATA = ") else {
+        return Vec::new();
+    };
+    // The array is followed by the rest of the script; only the first value is read
+    let Some(Ok(units)) = serde_json::Deserializer::from_str(data)
+        .into_iter::<Vec<Unit>>()
+        .next()
+    else {
+        return Vec::new();
+    };
+
+    let mut crates: Vec<CrateTiming> = Vec::new();
+    for unit in units {
+        let duration_ms = (unit.duration * 1000.0).round() as u64;
+        match crates.iter_mut().find(|c| c.name == unit.name) {
+            Some(timing) => timing.duration_ms += duration_ms,
+            None => crates.push(CrateTiming {
+                name: unit.name,
+                duration_ms,
+            }),
+        }
+    }
+    crates.sort_by_key(|c| std::cmp::Reverse(c.duration_ms));
+    crates
+}
+
+/// `(major, minor)` from `cargo --version` output, e.g. `cargo 1.95.0 (f2d3ce0bd 2026-03-21)`
+pub fn parse_cargo_version(output: &str) -> Option<(u32, u32)> {
+    let version = output.trim().strip_prefix("cargo ")?.split(' ').next()?;
+    let mut parts = version.split(['.', '-']);
+    Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPORT: &str = r#"<script>
+const UNIT_DATA = [
+  {
+    "i": 0,
+    "name": "proc-macro2",
+    "version": "1.0.107",
+    "mode": "run-custom-build",
+    "target": " build script (run)",
+    "start": 0.0,
+    "duration": 0.25,
+    "unblocked_units": []
+  },
+  {
+    "i": 1,
+    "name": "tt",
+    "version": "0.1.0",
+    "mode": "todo",
+    "target": " tt \"bin\"",
+    "start": 0.3,
+    "duration": 0.1,
+    "unblocked_units": []
+  },
+  {
+    "i": 2,
+    "name": "proc-macro2",
+    "version": "1.0.107",
+    "mode": "todo",
+    "target": "",
+    "start": 0.25,
+    "duration": 0.5004,
+    "unblocked_units": []
+  }
+];
+const CONCURRENCY_DATA = [];
+</script>"#;
+
+    #[test]
+    fn test_parse_unit_data() {
+        assert_eq!(
+            parse_unit_data(REPORT),
+            vec![
+                CrateTiming {
+                    name: "proc-macro2".to_string(),
+                    duration_ms: 750,
+                },
+                CrateTiming {
+                    name: "tt".to_string(),
+                    duration_ms: 100,
+                },
+            ]
+        );
+        assert!(parse_unit_data("<html></html>").is_empty());
+    }
+
+    #[test]
+    fn test_parse_cargo_version() {
+        assert_eq!(
+            parse_cargo_version("cargo 1.95.0 (f2d3ce0bd 2026-03-21)\n"),
+            Some((1, 95))
+        );
+        assert_eq!(
+            parse_cargo_version("cargo 1.97.0-nightly (4d1f98451 2026-05-15)"),
+            Some((1, 97))
+        );
+        assert_eq!(parse_cargo_version("cross 0.2.5"), None);
+    }
+}
@@ -0,0 +1,51 @@
+// src/toolchain.rs
+//! Toolchains rustup was asked for but doesn't have, e.g. with the `toolchain` option,
+//! a `rust-toolchain.toml` or `RUSTUP_TOOLCHAIN`. rustup reports them as
+//!
+//! ```text
+//! error: toolchain 'nightly-x86_64-unknown-linux-gnu' is not installed
+//! ```
+//!
+//! (`override toolchain '...'` in older releases) before cargo even starts.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+static NOT_INSTALLED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^error: (?:override )?toolchain '([^']+)' is not installed").unwrap()
+});
+
+/// The toolchain rustup found missing in a command's output, if any
+pub fn missing_toolchain(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find_map(|line| NOT_INSTALLED.captures(line.trim_start()))
+        .map(|captures| captures[1].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_toolchain() {
+        let output = "\
+error: toolchain 'nightly-x86_64-unknown-linux-gnu' is not installed
+help: run `rustup toolchain install nightly-x86_64-unknown-linux-gnu` to install it
+";
+        assert_eq!(
+            missing_toolchain(output).as_deref(),
+            Some("nightly-x86_64-unknown-linux-gnu")
+        );
+        let older = "error: override toolchain 'nightly' is not installed\n\
+                     info: the RUSTUP_TOOLCHAIN environment variable specifies an uninstalled toolchain\n";
+        assert_eq!(missing_toolchain(older).as_deref(), Some("nightly"));
+
+        // A program's own output mentioning toolchains is left alone
+        assert_eq!(
+            missing_toolchain("note: toolchain 'stable' is not installed on CI"),
+            None
+        );
+        assert_eq!(missing_toolchain("error: could not compile `app`"), None);
+    }
+}
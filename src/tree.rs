@@ -0,0 +1,554 @@
+// src/tree.rs
+//! Parsing of `cargo tree` output into nested nodes
+use mlua::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A package in the dependency tree
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TreeNode {
+    pub name: String,
+    pub version: String,
+    /// Path or git source, for packages not from a registry
+    pub source: Option<String>,
+    /// `normal`, `build` or `dev`: how the parent depends on this package
+    /// (in an inverted tree, how this package depends on its parent)
+    pub kind: String,
+    pub proc_macro: bool,
+    /// `true` for `(*)` entries whose dependencies were already listed elsewhere
+    pub duplicate: bool,
+    #[serde(default)]
+    pub children: Vec<TreeNode>,
+}
+
+/// Parsed `cargo tree` result
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DependencyTree {
+    #[serde(default)]
+    pub roots: Vec<TreeNode>,
+    /// For `invert` queries: the direct dependencies of local packages that pull the
+    /// inverted crate in (the crate itself when it is a direct dependency)
+    #[serde(default)]
+    pub pulled_in_by: Vec<String>,
+}
+
+/// Changes between two dependency trees, each list sorted by name
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TreeDiff {
+    pub added: Vec<CrateVersion>,
+    pub removed: Vec<CrateVersion>,
+    pub version_changed: Vec<VersionChange>,
+}
+
+/// A package at a specific version
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CrateVersion {
+    pub name: String,
+    pub version: String,
+}
+
+/// A package whose version differs between the two trees
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VersionChange {
+    pub name: String,
+    pub from: String,
+    pub to: String,
+}
+
+impl DependencyTree {
+    /// Parse the default (indented) output format; `None` when it doesn't look like a tree,
+    /// e.g. with a custom `--format` or `--prefix`
+    pub fn from_output(output: &str, inverted: bool) -> Option<Self> {
+        let mut builder = TreeBuilder::default();
+        for line in output.lines() {
+            builder.push_line(line);
+        }
+        Some(Self::from_roots(builder.finish()?, inverted))
+    }
+
+    /// The tree of `roots`, as parsed by a `TreeBuilder`
+    pub fn from_roots(roots: Vec<TreeNode>, inverted: bool) -> Self {
+        let pulled_in_by = if inverted {
+            let mut names = Vec::new();
+            for root in &roots {
+                collect_pulled_in_by(root, None, &mut names);
+            }
+            names
+        } else {
+            Vec::new()
+        };
+        Self {
+            roots,
+            pulled_in_by,
+        }
+    }
+
+    /// What changed from this tree to `after`
+    ///
+    /// A package present in both at different versions is a version change. When several
+    /// versions of it are involved, old and new versions are paired in semver order (so
+    /// 0.9 comes before 0.10) and the rest count as added or removed.
+    pub fn diff(&self, after: &DependencyTree) -> TreeDiff {
+        let before = self.versions();
+        let after = after.versions();
+        let mut diff = TreeDiff::default();
+
+        let names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+        for name in names {
+            let empty = BTreeSet::new();
+            let old = before.get(name).unwrap_or(&empty);
+            let new = after.get(name).unwrap_or(&empty);
+            let mut removed: Vec<&String> = old.difference(new).collect();
+            let mut added: Vec<&String> = new.difference(old).collect();
+            removed.sort_by_cached_key(|version| semver_key(version));
+            added.sort_by_cached_key(|version| semver_key(version));
+
+            // Only a package that was present both before and after can change version
+            let pairs = if old.is_empty() || new.is_empty() {
+                0
+            } else {
+                removed.len().min(added.len())
+            };
+            for (from, to) in removed.iter().zip(&added).take(pairs) {
+                diff.version_changed.push(VersionChange {
+                    name: name.clone(),
+                    from: from.to_string(),
+                    to: to.to_string(),
+                });
+            }
+            for version in &removed[pairs..] {
+                diff.removed.push(CrateVersion {
+                    name: name.clone(),
+                    version: version.to_string(),
+                });
+            }
+            for version in &added[pairs..] {
+                diff.added.push(CrateVersion {
+                    name: name.clone(),
+                    version: version.to_string(),
+                });
+            }
+        }
+        diff
+    }
+
+    // Every version of every package anywhere in the tree
+    fn versions(&self) -> BTreeMap<String, BTreeSet<String>> {
+        let mut versions: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        let mut stack: Vec<&TreeNode> = self.roots.iter().collect();
+        while let Some(node) = stack.pop() {
+            versions
+                .entry(node.name.clone())
+                .or_default()
+                .insert(node.version.clone());
+            stack.extend(&node.children);
+        }
+        versions
+    }
+}
+
+// Orders versions by semver, those that don't parse first and by their text
+fn semver_key(version: &str) -> (Option<semver::Version>, String) {
+    (semver::Version::parse(version).ok(), version.to_string())
+}
+
+// Walk the inverted tree until reaching a local package; the node walked through just
+// before it is the top-level dependency responsible
+fn collect_pulled_in_by(node: &TreeNode, previous: Option<&TreeNode>, names: &mut Vec<String>) {
+    if node.is_local() {
+        if let Some(previous) = previous {
+            if !names.contains(&previous.name) {
+                names.push(previous.name.clone());
+            }
+            return;
+        }
+    }
+    for child in &node.children {
+        collect_pulled_in_by(child, Some(node), names);
+    }
+}
+
+impl TreeNode {
+    // Workspace members and path dependencies are listed with their directory (`/ws/app`,
+    // `C:\ws\app`, `\\server\ws\app`) or as a `path+file://` source id; git sources are
+    // URLs, and packages of other registries say which one
+    fn is_local(&self) -> bool {
+        self.source.as_deref().is_some_and(|source| {
+            let bytes = source.as_bytes();
+            let windows_drive = bytes.len() >= 3
+                && bytes[0].is_ascii_alphabetic()
+                && bytes[1] == b':'
+                && matches!(bytes[2], b'\\' | b'/');
+            source.starts_with('/')
+                || source.starts_with("\\\\")
+                || source.starts_with("path+file://")
+                || windows_drive
+        })
+    }
+}
+
+/// Validate the crate spec passed to `cargo tree -i`: a name, optionally `@version`
+pub fn validate_package_spec(spec: &str) -> LuaResult<()> {
+    let (name, version) = match spec.split_once('@') {
+        Some((name, version)) => (name, Some(version)),
+        None => (spec, None),
+    };
+    let valid_name = !name.is_empty()
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    let valid_version = version.is_none_or(|v| {
+        !v.is_empty()
+            && v.chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+'))
+    });
+    if valid_name && valid_version {
+        Ok(())
+    } else {
+        Err(LuaError::RuntimeError(format!(
+            "Invalid package name: {:?}",
+            spec
+        )))
+    }
+}
+
+/// Parser fed one line of `cargo tree` output at a time, so the tree can be shown while
+/// it is still being printed
+#[derive(Debug, Default)]
+pub struct TreeBuilder {
+    // Nodes on the current path, with their depth; popped into their parent when done
+    stack: Vec<(usize, TreeNode)>,
+    // Dependency kind headers, with the depth they apply below
+    kinds: Vec<(usize, String)>,
+    roots: Vec<TreeNode>,
+    // Set once a line doesn't fit a tree; the rest of the output is ignored
+    invalid: bool,
+}
+
+impl TreeBuilder {
+    /// Add a line; returns the package it starts, with its depth (the package is a
+    /// child of the last one returned at the depth above)
+    pub fn push_line(&mut self, line: &str) -> Option<(usize, TreeNode)> {
+        if self.invalid {
+            return None;
+        }
+        let text = line.trim_start_matches(['│', '├', '└', '─', ' ', '\u{a0}']);
+        if text.is_empty() {
+            return None;
+        }
+        let prefix = &line[..line.len() - text.len()];
+        let depth = prefix.chars().count() / 4;
+
+        if let Some(kind) = text
+            .strip_prefix('[')
+            .and_then(|t| t.strip_suffix("-dependencies]"))
+        {
+            self.kinds.retain(|(d, _)| *d < depth);
+            self.kinds.push((depth, kind.to_string()));
+            return None;
+        }
+
+        // Status lines (`Updating crates.io index`) and warnings are mixed into the
+        // output; only lines under a connector or at the root are packages
+        let is_entry = depth == 0 || prefix.ends_with("── ");
+        let mut node = match parse_node(text) {
+            Some(node) if is_entry => node,
+            _ if is_entry && depth > 0 => {
+                self.invalid = true;
+                return None;
+            }
+            _ => return None,
+        };
+        self.kinds.retain(|(d, _)| *d < depth);
+        if let Some((_, kind)) = self
+            .kinds
+            .last()
+            .filter(|(d, _)| depth > 0 && *d == depth - 1)
+        {
+            node.kind = kind.clone();
+        }
+
+        // Close nodes that are not ancestors of this one
+        while self.stack.last().is_some_and(|(d, _)| *d >= depth) {
+            close_node(&mut self.stack, &mut self.roots);
+        }
+        if depth != self.stack.len() {
+            self.invalid = true;
+            return None;
+        }
+        self.stack.push((depth, node.clone()));
+        Some((depth, node))
+    }
+
+    /// The packages read, nested; `None` when the output didn't look like a tree
+    pub fn finish(mut self) -> Option<Vec<TreeNode>> {
+        if self.invalid {
+            return None;
+        }
+        while !self.stack.is_empty() {
+            close_node(&mut self.stack, &mut self.roots);
+        }
+        (!self.roots.is_empty()).then_some(self.roots)
+    }
+}
+
+fn close_node(stack: &mut Vec<(usize, TreeNode)>, roots: &mut Vec<TreeNode>) {
+    if let Some((_, node)) = stack.pop() {
+        match stack.last_mut() {
+            Some((_, parent)) => parent.children.push(node),
+            None => roots.push(node),
+        }
+    }
+}
+
+// `name vX.Y.Z [(source)] [(proc-macro)] [(*)]`
+fn parse_node(text: &str) -> Option<TreeNode> {
+    let (name, rest) = text.split_once(' ')?;
+    // Package names start with a letter; anything else is a custom prefix or format
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    let (version, mut rest) = rest.split_once(' ').unwrap_or((rest, ""));
+    let version = version.strip_prefix('v')?;
+
+    let mut node = TreeNode {
+        name: name.to_string(),
+        version: version.to_string(),
+        source: None,
+        kind: "normal".to_string(),
+        proc_macro: false,
+        duplicate: false,
+        children: Vec::new(),
+    };
+    while let Some(group) = rest.trim_start().strip_prefix('(') {
+        let (inner, after) = group.split_once(')')?;
+        match inner {
+            "*" => node.duplicate = true,
+            "proc-macro" => node.proc_macro = true,
+            source => node.source = Some(source.to_string()),
+        }
+        rest = after;
+    }
+    rest.trim().is_empty().then_some(node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TREE: &str = "\
+     Locking 18 packages to latest Rust 1.95.0 compatible versions
+tt v0.1.0 (/tmp/tt)
+├── serde v1.0.229
+│   └── serde_derive v1.0.229 (proc-macro)
+│       └── proc-macro2 v1.0.107
+└── serde_json v1.0.154
+    └── memchr v2.8.3
+[build-dependencies]
+└── cc v1.8.0
+[dev-dependencies]
+└── regex v1.13.1
+    ├── aho-corasick v1.1.5
+    │   └── memchr v2.8.3
+    └── memchr v2.8.3
+";
+
+    const INVERTED: &str = "\
+memchr v2.8.3
+├── aho-corasick v1.1.5
+│   ├── regex v1.13.1
+│   │   [dev-dependencies]
+│   │   └── tt v0.1.0 (/tmp/tt)
+│   └── regex-automata v0.4.18
+│       └── regex v1.13.1 (*)
+├── regex v1.13.1 (*)
+└── serde_json v1.0.154
+    └── tt v0.1.0 (/tmp/tt)
+";
+
+    #[test]
+    fn test_parse_tree() {
+        let tree = DependencyTree::from_output(TREE, false).unwrap();
+        assert_eq!(tree.roots.len(), 1);
+        let root = &tree.roots[0];
+        assert_eq!(root.source.as_deref(), Some("/tmp/tt"));
+
+        let children: Vec<(&str, &str)> = root
+            .children
+            .iter()
+            .map(|c| (c.name.as_str(), c.kind.as_str()))
+            .collect();
+        assert_eq!(
+            children,
+            vec![
+                ("serde", "normal"),
+                ("serde_json", "normal"),
+                ("cc", "build"),
+                ("regex", "dev")
+            ]
+        );
+
+        let derive = &root.children[0].children[0];
+        assert!(derive.proc_macro);
+        assert_eq!(derive.children[0].name, "proc-macro2");
+        // Kinds apply to the header's entries, not to their dependencies
+        assert_eq!(root.children[3].children[0].kind, "normal");
+    }
+
+    #[test]
+    fn test_builder_returns_packages_as_they_are_read() {
+        let mut builder = TreeBuilder::default();
+        let mut read = Vec::new();
+        for line in TREE.lines() {
+            if let Some((depth, node)) = builder.push_line(line) {
+                assert!(node.children.is_empty());
+                read.push((depth, node.name, node.kind));
+            }
+        }
+        assert_eq!(read.len(), 11);
+        assert_eq!(read[0], (0, "tt".to_string(), "normal".to_string()));
+        assert_eq!(
+            read[3],
+            (3, "proc-macro2".to_string(), "normal".to_string())
+        );
+        assert_eq!(read[6], (1, "cc".to_string(), "build".to_string()));
+        assert_eq!(
+            builder.finish().unwrap(),
+            DependencyTree::from_output(TREE, false).unwrap().roots
+        );
+
+        // Once a line breaks the tree, nothing more is returned
+        let mut builder = TreeBuilder::default();
+        assert!(builder.push_line("tt v0.1.0").is_some());
+        assert!(builder.push_line("│       └── deep v1.0.0").is_none());
+        assert!(builder.push_line("└── serde v1.0.229").is_none());
+        assert_eq!(builder.finish(), None);
+    }
+
+    #[test]
+    fn test_inverted_tree_reports_top_level_dependencies() {
+        let tree = DependencyTree::from_output(INVERTED, true).unwrap();
+        let root = &tree.roots[0];
+        assert_eq!(root.name, "memchr");
+        assert!(root.children[1].duplicate);
+        assert_eq!(root.children[0].children[0].children[0].kind, "dev");
+        assert_eq!(tree.pulled_in_by, vec!["regex", "serde_json"]);
+
+        // A direct dependency is pulled in by itself
+        let tree =
+            DependencyTree::from_output("serde v1.0.229\n└── tt v0.1.0 (/tmp/tt)\n", true).unwrap();
+        assert_eq!(tree.pulled_in_by, vec!["serde"]);
+    }
+
+    #[test]
+    fn test_is_local() {
+        let with_source = |source: &str| TreeNode {
+            name: "app".to_string(),
+            version: "0.1.0".to_string(),
+            source: Some(source.to_string()),
+            kind: "normal".to_string(),
+            proc_macro: false,
+            duplicate: false,
+            children: Vec::new(),
+        };
+        for local in [
+            "/tmp/ws/app",
+            "C:\\Users\\me\\ws\\app",
+            "d:/ws/app",
+            "\\\\server\\ws\\app",
+            "path+file:///tmp/ws/app#0.1.0",
+        ] {
+            assert!(with_source(local).is_local(), "{}", local);
+        }
+        for remote in [
+            "https://github.com/rust-lang/regex#b2e6a0c7",
+            "registry `my-registry`",
+        ] {
+            assert!(!with_source(remote).is_local(), "{}", remote);
+        }
+    }
+
+    #[test]
+    fn test_tree_diff() {
+        let before = DependencyTree::from_output(TREE, false).unwrap();
+        let after = DependencyTree::from_output(
+            "\
+tt v0.1.0 (/tmp/tt)
+├── serde v1.0.230
+│   └── serde_derive v1.0.230 (proc-macro)
+│       └── proc-macro2 v1.0.107
+├── serde_json v1.0.154
+│   └── memchr v2.8.3
+└── itoa v1.0.15
+[build-dependencies]
+└── cc v1.8.0
+",
+            false,
+        )
+        .unwrap();
+
+        let diff = before.diff(&after);
+        let names = |list: &[CrateVersion]| -> Vec<String> {
+            list.iter()
+                .map(|c| format!("{}@{}", c.name, c.version))
+                .collect()
+        };
+        assert_eq!(names(&diff.added), vec!["itoa@1.0.15"]);
+        assert_eq!(
+            names(&diff.removed),
+            vec!["aho-corasick@1.1.5", "regex@1.13.1"]
+        );
+        assert_eq!(
+            diff.version_changed,
+            vec![
+                VersionChange {
+                    name: "serde".to_string(),
+                    from: "1.0.229".to_string(),
+                    to: "1.0.230".to_string(),
+                },
+                VersionChange {
+                    name: "serde_derive".to_string(),
+                    from: "1.0.229".to_string(),
+                    to: "1.0.230".to_string(),
+                },
+            ]
+        );
+        assert_eq!(after.diff(&after), TreeDiff::default());
+
+        // Versions are paired by semver, not as text where 0.10.0 sorts before 0.9.0
+        let two_versions = |first: &str, second: &str| {
+            DependencyTree::from_output(
+                &format!(
+                    "tt v0.1.0 (/tmp/tt)\n├── rand v{}\n└── rand v{}\n",
+                    first, second
+                ),
+                false,
+            )
+            .unwrap()
+        };
+        let diff = two_versions("0.9.0", "0.10.0").diff(&two_versions("0.9.1", "1.0.0"));
+        let changes: Vec<(&str, &str)> = diff
+            .version_changed
+            .iter()
+            .map(|change| (change.from.as_str(), change.to.as_str()))
+            .collect();
+        assert_eq!(changes, vec![("0.9.0", "0.9.1"), ("0.10.0", "1.0.0")]);
+    }
+
+    #[test]
+    fn test_custom_format_is_not_parsed() {
+        assert!(
+            DependencyTree::from_output("0tt v0.1.0 (/tmp/tt)\n1serde v1.0.229\n", false).is_none()
+        );
+    }
+
+    #[test]
+    fn test_validate_package_spec() {
+        assert!(validate_package_spec("serde").is_ok());
+        assert!(validate_package_spec("proc-macro2@1.0.107").is_ok());
+        assert!(validate_package_spec("--all").is_err());
+        assert!(validate_package_spec("serde; rm").is_err());
+        assert!(validate_package_spec("serde@").is_err());
+    }
+}
@@ -0,0 +1,97 @@
+// src/unit_graph.rs
+//! Cargo's unit graph (`cargo build --unit-graph -Z unstable-options`, nightly only): every
+//! compilation unit of a build and what each depends on, for tooling that draws or
+//! analyzes builds. The format is unstable; only the fields that have been stable in
+//! practice are typed, and each unit's profile is passed through as is.
+
+use serde::{Deserialize, Serialize};
+
+/// The unit graph, as cargo prints it (format `version` 1)
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct UnitGraph {
+    pub version: u32,
+    pub units: Vec<Unit>,
+    /// Indices into `units` of the units the command asked for
+    pub roots: Vec<usize>,
+}
+
+/// One compilation: a target of a package, built in one mode for one platform
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Unit {
+    pub pkg_id: String,
+    pub target: UnitTarget,
+    /// Profile settings (opt_level, debuginfo, ...), unchanged
+    pub profile: serde_json::Value,
+    /// Target triple, or `None` for the host
+    pub platform: Option<String>,
+    /// `build`, `test`, `check`, `doc`, `run-custom-build`, ...
+    pub mode: String,
+    pub features: Vec<String>,
+    pub dependencies: Vec<UnitDependency>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct UnitTarget {
+    pub name: String,
+    pub kind: Vec<String>,
+    pub crate_types: Vec<String>,
+    pub src_path: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct UnitDependency {
+    /// Index into `units`
+    pub index: usize,
+    pub extern_crate_name: String,
+}
+
+/// The unit graph in a command's output: the line of JSON cargo prints to stdout
+pub fn parse_unit_graph(output: &str) -> Option<UnitGraph> {
+    output
+        .lines()
+        .filter(|line| line.starts_with("{\"version\""))
+        .find_map(|line| serde_json::from_str(line).ok())
+}
+
+/// Whether `cargo --version` output is of a toolchain that accepts `-Z` flags
+pub fn is_nightly(version: &str) -> bool {
+    version
+        .split_whitespace()
+        .nth(1)
+        .is_some_and(|v| v.ends_with("-nightly") || v.ends_with("-dev"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_unit_graph() {
+        let output = r#"{"version":1,"units":[{"pkg_id":"path+file:///tmp/app#0.1.0","target":{"kind":["bin"],"crate_types":["bin"],"name":"app","src_path":"/tmp/app/src/main.rs","edition":"2021","doc":true,"doctest":false,"test":true},"profile":{"name":"dev","opt_level":"0","debuginfo":2},"platform":null,"mode":"build","features":["default"],"dependencies":[{"index":1,"extern_crate_name":"itoa","public":false,"noprelude":false}]},{"pkg_id":"registry+https://github.com/rust-lang/crates.io-index#itoa@1.0.15","target":{"kind":["lib"],"crate_types":["lib"],"name":"itoa","src_path":"/r/itoa/src/lib.rs"},"profile":{"name":"dev"},"platform":null,"mode":"build","features":[],"dependencies":[]}],"roots":[0]}
+warning: unused manifest key: package.foo"#;
+        let graph = parse_unit_graph(output).unwrap();
+        assert_eq!(graph.version, 1);
+        assert_eq!(graph.roots, vec![0]);
+        let root = &graph.units[0];
+        assert_eq!(root.target.name, "app");
+        assert_eq!(root.profile["opt_level"], "0");
+        assert_eq!(root.dependencies[0].extern_crate_name, "itoa");
+        assert_eq!(
+            graph.units[root.dependencies[0].index].target.kind,
+            vec!["lib"]
+        );
+
+        assert_eq!(
+            parse_unit_graph("error: the `-Z` flag is only accepted on the nightly channel"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_is_nightly() {
+        assert!(is_nightly("cargo 1.97.0-nightly (4d1f98451 2026-05-15)"));
+        assert!(is_nightly("cargo 1.98.0-dev"));
+        assert!(!is_nightly("cargo 1.95.0 (f2d3ce0bd 2026-03-21)"));
+        assert!(!is_nightly("cargo 1.96.0-beta.3 (aa1b2c3d4 2026-04-30)"));
+    }
+}
@@ -0,0 +1,88 @@
+// src/vendor.rs
+//! Extraction of the source replacement config printed by `cargo vendor`
+use serde::Serialize;
+
+/// Result of `cargo vendor`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VendorResult {
+    /// Directory the crates were vendored into
+    pub vendor_dir: String,
+    /// Config to add to `.cargo/config.toml`, absent when nothing was vendored
+    pub config_snippet: Option<String>,
+}
+
+/// Collect the vendor directory and the `[source]` config snippet from the output
+///
+/// Cargo prints the snippet to stdout while progress goes to stderr; both end up in
+/// the combined output, so the snippet is taken as the run of TOML lines starting at
+/// the first `[source.` table.
+pub fn parse_vendor_output(output: &str, args: &[&str]) -> VendorResult {
+    let snippet: Vec<&str> = output
+        .lines()
+        .skip_while(|l| !l.starts_with("[source."))
+        .take_while(|l| l.trim().is_empty() || l.starts_with('[') || l.contains(" = "))
+        .collect();
+    let config_snippet = (!snippet.is_empty()).then(|| snippet.join("\n").trim().to_string());
+
+    // The snippet names the directory exactly as cargo resolved it
+    let vendor_dir = snippet
+        .iter()
+        .find_map(|l| l.strip_prefix("directory = "))
+        .map(|dir| dir.trim().trim_matches('"').to_string())
+        .unwrap_or_else(|| vendor_path_arg(args).unwrap_or("vendor").to_string());
+
+    VendorResult {
+        vendor_dir,
+        config_snippet,
+    }
+}
+
+// The positional `[path]` argument, skipping flags and their values
+fn vendor_path_arg<'a>(args: &[&'a str]) -> Option<&'a str> {
+    let mut iter = args.iter();
+    while let Some(&arg) = iter.next() {
+        match arg {
+            "-s" | "--sync" | "--manifest-path" | "--color" | "-Z" | "--config" => {
+                iter.next();
+            }
+            _ if arg.starts_with('-') => {}
+            _ => return Some(arg),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_vendor_snippet() {
+        let output = "\
+   Vendoring serde v1.0.210 (/home/user/.cargo/registry/src/serde-1.0.210) to third_party/serde
+To use vendored sources, add this to your .cargo/config.toml for this project:
+
+[source.crates-io]
+replace-with = \"vendored-sources\"
+
+[source.vendored-sources]
+directory = \"third_party\"
+";
+        let result = parse_vendor_output(output, &["--sync", "other/Cargo.toml", "third_party"]);
+        assert_eq!(result.vendor_dir, "third_party");
+        assert_eq!(
+            result.config_snippet.as_deref(),
+            Some("[source.crates-io]\nreplace-with = \"vendored-sources\"\n\n[source.vendored-sources]\ndirectory = \"third_party\"")
+        );
+    }
+
+    #[test]
+    fn test_nothing_vendored() {
+        let result = parse_vendor_output(
+            "There is no dependency to vendor in this project.\n",
+            &["--sync", "a/Cargo.toml", "deps"],
+        );
+        assert_eq!(result.vendor_dir, "deps");
+        assert!(result.config_snippet.is_none());
+    }
+}